@@ -0,0 +1,723 @@
+//! Description of the exported ABI, and detection of breaking changes between two versions.
+//!
+//! The ABI is described by a `.abi` file listing every exported symbol on its own line (e.g.
+//! `fn app_free(app: *mut App)`), sorted by kind and name, after a `version` line holding the
+//! version of the crate. The file of a release can be committed and used as a baseline to check
+//! the following versions.
+
+use bitflags::BitFlags;
+use common::{
+    append_output, check_no_mangle, check_repr_c, is_extern, parse_attr, version_parts, wrong_item,
+    Lang, Outputs,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+/// Generates a `<lib_name>.abi` file describing the exported symbols.
+pub struct LangAbi {
+    lib_name: String,
+    version: String,
+}
+
+impl LangAbi {
+    pub fn new() -> Self {
+        LangAbi {
+            lib_name: "backend".to_owned(),
+            version: "0.0.0".to_owned(),
+        }
+    }
+
+    /// Set the name of the native library, used to name the output file.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}.abi", self.lib_name)
+    }
+
+    fn append(&self, symbol: String, outputs: &mut Outputs) {
+        append_output(format!("{}\n", symbol), &self.output_name(), outputs);
+    }
+}
+
+impl Default for LangAbi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangAbi {
+    fn name(&self) -> &'static str {
+        "abi"
+    }
+
+    fn crate_version(&mut self, _name: &str, version: &str) {
+        self.version = version.to_string();
+    }
+
+    /// Describe `pub const NAME: Type = value;` as `const NAME: Type = value`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        match item.node {
+            ast::ItemKind::Const(ref ty, ref expr) => self.append(
+                format!(
+                    "const {}: {} = {}",
+                    item.ident.name.as_str(),
+                    pprust::ty_to_string(ty),
+                    pprust::expr_to_string(expr)
+                ),
+                outputs,
+            ),
+            _ => return Err(wrong_item(item, "parse_const")),
+        }
+
+        Ok(())
+    }
+
+    /// Describe `pub type A = B;` as `type A = B`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        match item.node {
+            ast::ItemKind::Ty(ref ty, ref generics) => {
+                // Generic aliases are not exported.
+                if generics.is_parameterized() {
+                    return Ok(());
+                }
+
+                self.append(
+                    format!(
+                        "type {} = {}",
+                        item.ident.name.as_str(),
+                        pprust::ty_to_string(ty)
+                    ),
+                    outputs,
+                );
+            }
+            _ => return Err(wrong_item(item, "parse_ty")),
+        }
+
+        Ok(())
+    }
+
+    /// Describe a `#[repr(C)]` enum as `enum Name { A = 0; B = 1 }`.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (repr_c, _) = parse_attr(&item.attrs, check_repr_c, |_| None);
+        if !repr_c {
+            return Ok(());
+        }
+
+        let definition = match item.node {
+            ast::ItemKind::Enum(ref definition, _) => definition,
+            _ => return Err(wrong_item(item, "parse_enum")),
+        };
+
+        // Variants without an explicit discriminant take the next value.
+        let mut next = 0;
+        let variants: Vec<_> = definition
+            .variants
+            .iter()
+            .map(|var| {
+                if !var.node.data.is_unit() {
+                    return pprust::variant_to_string(var);
+                }
+
+                let value = var.node
+                    .disr_expr
+                    .as_ref()
+                    .map(|expr| pprust::expr_to_string(expr))
+                    .unwrap_or_else(|| next.to_string());
+                next = value.parse::<i64>().map(|value| value + 1).unwrap_or(next + 1);
+
+                format!("{} = {}", var.node.name.name.as_str(), value)
+            })
+            .collect();
+
+        self.append(
+            format!(
+                "enum {} {{ {} }}",
+                item.ident.name.as_str(),
+                variants.join("; ")
+            ),
+            outputs,
+        );
+
+        Ok(())
+    }
+
+    /// Describe a `#[repr(C)]` struct as `struct Name { a: A; b: B }`.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (repr_c, _) = parse_attr(&item.attrs, check_repr_c, |_| None);
+        if !repr_c {
+            return Ok(());
+        }
+
+        let variants = match item.node {
+            ast::ItemKind::Struct(ref variants, _) => variants,
+            _ => return Err(wrong_item(item, "parse_struct")),
+        };
+
+        let fields: Vec<_> = variants
+            .fields()
+            .iter()
+            .map(|field| match field.ident {
+                Some(ident) => format!(
+                    "{}: {}",
+                    ident.name.as_str(),
+                    pprust::ty_to_string(&field.ty)
+                ),
+                None => pprust::ty_to_string(&field.ty),
+            })
+            .collect();
+
+        self.append(
+            format!(
+                "struct {} {{ {} }}",
+                item.ident.name.as_str(),
+                fields.join("; ")
+            ),
+            outputs,
+        );
+
+        Ok(())
+    }
+
+    /// Describe a `bitflags!` flag set as `flags Name: u32 { A = 0x1; B = 0x2 }`.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let values: Vec<_> = flags
+            .flags
+            .iter()
+            .map(|flag| format!("{} = {:#x}", flag.name, flag.value))
+            .collect();
+
+        self.append(
+            format!(
+                "flags {}: {} {{ {} }}",
+                flags.name,
+                pprust::ty_to_string(&flags.ty),
+                values.join("; ")
+            ),
+            outputs,
+        );
+
+        Ok(())
+    }
+
+    /// Describe a `#[no_mangle] pub extern "C" fn` as `fn name(a: A) -> R`.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (no_mangle, _) = parse_attr(&item.attrs, check_no_mangle, |_| None);
+        if !no_mangle {
+            return Ok(());
+        }
+
+        match item.node {
+            ast::ItemKind::Fn(ref fn_decl, _, _, abi, _, _) => {
+                if !is_extern(abi) {
+                    return Ok(());
+                }
+
+                let args: Vec<_> = fn_decl
+                    .inputs
+                    .iter()
+                    .map(|arg| {
+                        format!(
+                            "{}: {}",
+                            pprust::pat_to_string(&arg.pat),
+                            pprust::ty_to_string(&arg.ty)
+                        )
+                    })
+                    .collect();
+                let output = match fn_decl.output {
+                    ast::FunctionRetTy::Default(..) => String::new(),
+                    ast::FunctionRetTy::Ty(ref ty) => {
+                        format!(" -> {}", pprust::ty_to_string(ty))
+                    }
+                };
+
+                self.append(
+                    format!(
+                        "fn {}({}){}",
+                        item.ident.name.as_str(),
+                        args.join(", "),
+                        output
+                    ),
+                    outputs,
+                );
+            }
+            _ => return Err(wrong_item(item, "parse_fn")),
+        }
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+        let mut symbols: Vec<_> = body.lines().collect();
+        symbols.sort();
+        symbols.dedup();
+
+        let mut abi = format!("version {}\n", self.version);
+        for symbol in symbols {
+            abi.push_str(symbol);
+            abi.push('\n');
+        }
+
+        let _ = outputs.insert(self.output_name(), abi);
+
+        Ok(())
+    }
+}
+
+/// A change of the exported ABI.
+#[derive(Debug, PartialEq)]
+pub enum AbiChange {
+    /// A new symbol, which is a compatible change.
+    Added(String),
+    /// A symbol which doesn't exist anymore.
+    Removed(String),
+    /// A symbol whose signature or layout changed.
+    Changed { old: String, new: String },
+}
+
+impl AbiChange {
+    /// Whether the change breaks the users of the previous version.
+    pub fn is_breaking(&self) -> bool {
+        match *self {
+            AbiChange::Added(..) => false,
+            AbiChange::Removed(..) | AbiChange::Changed { .. } => true,
+        }
+    }
+}
+
+impl Display for AbiChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            AbiChange::Added(ref symbol) => write!(f, "added `{}`", symbol),
+            AbiChange::Removed(ref symbol) => write!(f, "removed `{}`", symbol),
+            AbiChange::Changed { ref old, ref new } => {
+                write!(f, "changed `{}` into `{}`", old, new)
+            }
+        }
+    }
+}
+
+/// The changes between the ABI of two versions of the crate.
+#[derive(Debug)]
+pub struct AbiReport {
+    pub old_version: String,
+    pub new_version: String,
+    pub changes: Vec<AbiChange>,
+}
+
+impl AbiReport {
+    /// Compare a baseline description (`.abi` file) with the current one.
+    pub fn new(baseline: &str, current: &str) -> Self {
+        let (old_version, old) = parse_abi(baseline);
+        let (new_version, new) = parse_abi(current);
+
+        let mut changes = Vec::new();
+        for (key, old_symbol) in &old {
+            match new.get(key) {
+                None => changes.push(AbiChange::Removed(old_symbol.to_string())),
+                Some(new_symbol) if new_symbol != old_symbol => changes.push(AbiChange::Changed {
+                    old: old_symbol.to_string(),
+                    new: new_symbol.to_string(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for (key, new_symbol) in &new {
+            if !old.contains_key(key) {
+                changes.push(AbiChange::Added(new_symbol.to_string()));
+            }
+        }
+
+        AbiReport {
+            old_version: old_version.to_string(),
+            new_version: new_version.to_string(),
+            changes,
+        }
+    }
+
+    /// Whether any of the changes is breaking.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(AbiChange::is_breaking)
+    }
+
+    /// Render the changes as a Markdown section for a changelog, listing the added, removed and
+    /// changed symbols (down to the fields, variants and flags of structs, enums and flag sets).
+    pub fn changelog(&self) -> String {
+        let mut added = String::new();
+        let mut removed = String::new();
+        let mut changed = String::new();
+
+        for change in &self.changes {
+            match *change {
+                AbiChange::Added(ref symbol) => {
+                    added.push_str(&format!("- {}: `{}`\n", describe(symbol), symbol))
+                }
+                AbiChange::Removed(ref symbol) => {
+                    removed.push_str(&format!("- {}\n", describe(symbol)))
+                }
+                AbiChange::Changed { ref old, ref new } => match member_changes(old, new) {
+                    Some(members) => {
+                        changed.push_str(&format!("- {}:\n", describe(old)));
+                        for member in members {
+                            changed.push_str(&format!("  - {}\n", member));
+                        }
+                    }
+                    None => changed.push_str(&format!(
+                        "- {}: `{}` into `{}`\n",
+                        describe(old),
+                        old,
+                        new
+                    )),
+                },
+            }
+        }
+
+        let mut changelog = format!(
+            "### ABI changes from {} to {}\n",
+            self.old_version, self.new_version
+        );
+        if self.changes.is_empty() {
+            changelog.push_str("\nNo changes.\n");
+        }
+        for &(title, ref list) in &[("Added", added), ("Removed", removed), ("Changed", changed)] {
+            if !list.is_empty() {
+                changelog.push_str(&format!("\n#### {}\n\n{}", title, list));
+            }
+        }
+
+        changelog
+    }
+
+    /// Fail if there are breaking changes but the version wasn't bumped accordingly (i.e. the
+    /// major version, or the minor version for `0.x` versions).
+    pub fn check(&self) -> Result<(), Error> {
+        if !self.is_breaking() || is_breaking_bump(&self.old_version, &self.new_version) {
+            return Ok(());
+        }
+
+        let changes: Vec<_> = self.changes
+            .iter()
+            .filter(|change| change.is_breaking())
+            .map(|change| format!("  {}", change))
+            .collect();
+
+        Err(Error {
+            level: Level::Error,
+            span: None,
+            message: format!(
+                "breaking ABI changes since {} require a major version bump (found {}):\n{}",
+                self.old_version,
+                self.new_version,
+                changes.join("\n")
+            ),
+            help: None,
+        })
+    }
+}
+
+/// Parse a `.abi` file into its version and its symbols indexed by kind and name.
+fn parse_abi(abi: &str) -> (&str, BTreeMap<(&str, &str), &str>) {
+    let mut version = "0.0.0";
+    let mut symbols = BTreeMap::new();
+
+    for line in abi.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with("version ") {
+            version = line["version ".len()..].trim();
+            continue;
+        }
+
+        let _ = symbols.insert(symbol_key(line), line);
+    }
+
+    (version, symbols)
+}
+
+/// The kind and the name of a symbol (e.g. `("fn", "app_free")`).
+fn symbol_key(symbol: &str) -> (&str, &str) {
+    let kind = symbol.split(' ').next().unwrap_or("");
+    let rest = symbol[kind.len()..].trim_left();
+    (kind, identifier(rest))
+}
+
+/// The identifier at the start of `text`.
+fn identifier(text: &str) -> &str {
+    let end = text.find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .unwrap_or_else(|| text.len());
+    &text[..end]
+}
+
+/// Describe a symbol for humans (e.g. ``function `app_free` ``).
+fn describe(symbol: &str) -> String {
+    let (kind, name) = symbol_key(symbol);
+    let kind = match kind {
+        "fn" => "function",
+        "const" => "constant",
+        "type" => "type alias",
+        "flags" => "flag set",
+        kind => kind,
+    };
+
+    format!("{} `{}`", kind, name)
+}
+
+/// The added, removed and changed members (fields, variants or flags) of a struct, an enum or a
+/// flag set. Returns `None` if the symbol has no members or if something else changed.
+fn member_changes(old: &str, new: &str) -> Option<Vec<String>> {
+    let noun = match symbol_key(old).0 {
+        "struct" => "field",
+        "enum" => "variant",
+        "flags" => "flag",
+        _ => return None,
+    };
+
+    let (old_head, old_members) = members(old)?;
+    let (new_head, new_members) = members(new)?;
+    if old_head != new_head {
+        return None;
+    }
+
+    let name = |member: &str| -> String {
+        match member.find(": ").or_else(|| member.find(" = ")) {
+            Some(end) => member[..end].to_string(),
+            None => identifier(member).to_string(),
+        }
+    };
+    let find = |members: &[&str], member: &str| -> Option<String> {
+        members
+            .iter()
+            .find(|other| name(other) == name(member))
+            .map(|other| other.to_string())
+    };
+
+    let mut changes = Vec::new();
+    for &member in &old_members {
+        match find(&new_members, member) {
+            None => changes.push(format!("removed {} `{}`", noun, member)),
+            Some(ref new_member) if new_member != member => changes.push(format!(
+                "changed {} `{}` into `{}`",
+                noun, member, new_member
+            )),
+            Some(_) => {}
+        }
+    }
+    for &member in &new_members {
+        if find(&old_members, member).is_none() {
+            changes.push(format!("added {} `{}`", noun, member));
+        }
+    }
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes)
+    }
+}
+
+/// Split a symbol with members (e.g. `struct Point { x: i32; y: i32 }`) into its head and its
+/// members.
+fn members(symbol: &str) -> Option<(&str, Vec<&str>)> {
+    let start = symbol.find('{')?;
+    let end = symbol.rfind('}')?;
+    let members = symbol[start + 1..end]
+        .split(';')
+        .map(str::trim)
+        .filter(|member| !member.is_empty())
+        .collect();
+
+    Some((symbol[..start].trim(), members))
+}
+
+/// Whether going from `old` to `new` is allowed to break compatibility, following the cargo
+/// flavour of semver (`0.x` versions are compatible within the same minor version).
+fn is_breaking_bump(old: &str, new: &str) -> bool {
+    let old = version_parts(old);
+    let new = version_parts(new);
+
+    match (old, new) {
+        ((0, 0, old_patch), (0, 0, new_patch)) => new_patch > old_patch,
+        ((0, old_minor, _), (0, new_minor, _)) => new_minor > old_minor,
+        ((old_major, _, _), (new_major, _, _)) => new_major > old_major,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse;
+
+    #[test]
+    fn description() {
+        let source = "
+            pub const MAX_LEN: u32 = 16;
+
+            #[repr(C)]
+            pub enum Status {
+                Ok,
+                Failed = 4,
+                Unknown,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                y: i32,
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_free(point: *mut Point) {}
+
+            #[no_mangle]
+            pub extern \"C\" fn point_len(point: *const Point) -> usize {}
+        ";
+
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut lang = LangAbi::new();
+        lang.crate_version("points", "0.3.1");
+        let mut outputs = Outputs::default();
+        unwrap!(parse::parse_mod(
+            &mut lang,
+            &krate.module,
+            &["ffi".to_string()],
+            &Default::default(),
+            &mut outputs,
+        ));
+        unwrap!(lang.finalise_output(&mut outputs));
+
+        assert_eq!(
+            outputs["backend.abi"],
+            "version 0.3.1\n\
+             const MAX_LEN: u32 = 16\n\
+             enum Status { Ok = 0; Failed = 4; Unknown = 5 }\n\
+             fn point_free(point: *mut Point)\n\
+             fn point_len(point: *const Point) -> usize\n\
+             struct Point { x: i32; y: i32 }\n"
+        );
+    }
+
+    #[test]
+    fn report() {
+        let baseline = "\
+            # Generated by safe_bindgen\n\
+            version 0.3.1\n\
+            fn point_free(point: *mut Point)\n\
+            fn point_len(point: *const Point) -> usize\n\
+            struct Point { x: i32; y: i32 }\n";
+        let current = "\
+            version 0.3.2\n\
+            fn point_len(point: *const Point) -> usize\n\
+            fn point_new() -> *mut Point\n\
+            struct Point { x: i64; y: i64 }\n";
+
+        let report = AbiReport::new(baseline, current);
+        assert_eq!(
+            report.changes,
+            vec![
+                AbiChange::Removed("fn point_free(point: *mut Point)".to_string()),
+                AbiChange::Changed {
+                    old: "struct Point { x: i32; y: i32 }".to_string(),
+                    new: "struct Point { x: i64; y: i64 }".to_string(),
+                },
+                AbiChange::Added("fn point_new() -> *mut Point".to_string()),
+            ]
+        );
+        assert!(report.is_breaking());
+        assert!(report.check().is_err());
+
+        let report = AbiReport::new(baseline, &current.replace("0.3.2", "0.4.0"));
+        assert!(report.check().is_ok());
+
+        // Compatible changes only require a patch version.
+        let report = AbiReport::new(baseline, &format!("{}fn point_clone()\n", baseline));
+        assert!(!report.is_breaking());
+        assert!(report.check().is_ok());
+    }
+
+    #[test]
+    fn changelog() {
+        let baseline = "\
+            version 0.3.1\n\
+            enum Status { Ok = 0; Failed = 1 }\n\
+            fn point_free(point: *mut Point)\n\
+            fn point_len(point: *const Point) -> usize\n\
+            struct Point { x: i32; y: i32 }\n";
+        let current = "\
+            version 0.4.0\n\
+            enum Status { Ok = 0; Failed = 1; Pending = 2 }\n\
+            fn point_len(point: *const Point) -> u32\n\
+            fn point_new() -> *mut Point\n\
+            struct Point { x: i64; z: i32 }\n";
+
+        assert_eq!(
+            AbiReport::new(baseline, current).changelog(),
+            "### ABI changes from 0.3.1 to 0.4.0\n\n\
+             #### Added\n\n\
+             - function `point_new`: `fn point_new() -> *mut Point`\n\n\
+             #### Removed\n\n\
+             - function `point_free`\n\n\
+             #### Changed\n\n\
+             - enum `Status`:\n  \
+             - added variant `Pending = 2`\n\
+             - function `point_len`: `fn point_len(point: *const Point) -> usize` into \
+             `fn point_len(point: *const Point) -> u32`\n\
+             - struct `Point`:\n  \
+             - changed field `x: i32` into `x: i64`\n  \
+             - removed field `y: i32`\n  \
+             - added field `z: i32`\n"
+        );
+    }
+
+    #[test]
+    fn breaking_bump() {
+        assert!(is_breaking_bump("1.2.3", "2.0.0"));
+        assert!(!is_breaking_bump("1.2.3", "1.3.0"));
+        assert!(is_breaking_bump("0.2.3", "0.3.0"));
+        assert!(!is_breaking_bump("0.2.3", "0.2.4"));
+        assert!(is_breaking_bump("0.0.1", "0.0.2"));
+    }
+}
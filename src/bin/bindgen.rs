@@ -4,8 +4,13 @@ extern crate clap;
 extern crate jni;
 
 use jni::signature::{JavaType, Primitive};
-use safe_bindgen::{Bindgen, LangC, LangCSharp, LangJava};
+use safe_bindgen::{
+    AbiReport, Bindgen, LangAbi, LangC, LangCSharp, LangCython, LangD, LangGir, LangHaskell,
+    LangJava, LangJs, LangMan, LangMarkdown, LangRuby, LangZig, LineEnding,
+};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
 
 fn main() {
     let matches = clap::App::new("bindgen")
@@ -25,17 +30,100 @@ fn main() {
                 .short("-l")
                 .long("--lang")
                 .takes_value(true)
-                .required(true)
+                .required_unless_one(&["CHECK_ABI", "ABI_CHANGELOG", "EXPORT_REPORT"])
                 .help("target language")
-                .possible_values(&["csharp", "java", "c"]),
+                .possible_values(&[
+                    "csharp", "java", "c", "cython", "zig", "d", "haskell", "ruby", "js", "gir",
+                    "abi", "markdown", "man",
+                ]),
         )
         .arg(
             clap::Arg::with_name("LIB")
                 .long("--lib")
                 .takes_value(true)
-                .required(true)
+                .required_unless_one(&["CHECK_ABI", "ABI_CHANGELOG", "EXPORT_REPORT"])
                 .help("name of the native library to link"),
         )
+        .arg(
+            clap::Arg::with_name("TIMESTAMP")
+                .long("--timestamp")
+                .help("include the generation time in the banner"),
+        )
+        .arg(
+            clap::Arg::with_name("CRLF")
+                .long("--crlf")
+                .help("end the lines of the output files with CRLF"),
+        )
+        .arg(
+            clap::Arg::with_name("BOM")
+                .long("--bom")
+                .help("start the output files with a UTF-8 byte order mark"),
+        )
+        .arg(
+            clap::Arg::with_name("STDOUT")
+                .long("--stdout")
+                .help("write the generated files to stdout instead of the output directory"),
+        )
+        .arg(
+            clap::Arg::with_name("DRY_RUN")
+                .long("--dry-run")
+                .help("run all the checks without writing any file"),
+        )
+        .arg(
+            clap::Arg::with_name("PROFILE")
+                .long("--profile")
+                .help("print the time spent in each phase and the number of items to stderr"),
+        )
+        .arg(
+            clap::Arg::with_name("INCLUDE")
+                .long("--include")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("only generate bindings for items matching this glob pattern"),
+        )
+        .arg(
+            clap::Arg::with_name("EXCLUDE")
+                .long("--exclude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("skip items matching this glob pattern"),
+        )
+        .arg(
+            clap::Arg::with_name("TARGET")
+                .long("--target")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("generate the bindings for this target triple, into a subdirectory"),
+        )
+        .arg(
+            clap::Arg::with_name("CHECK_ABI")
+                .long("--check-abi")
+                .takes_value(true)
+                .value_name("BASELINE")
+                .help(
+                    "compare the ABI with a baseline generated with `--lang abi`, failing on \
+                     breaking changes unless the version was bumped",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("ABI_CHANGELOG")
+                .long("--abi-changelog")
+                .takes_value(true)
+                .number_of_values(2)
+                .value_names(&["OLD", "NEW"])
+                .help("print the changes between two ABI descriptions as a changelog section"),
+        )
+        .arg(
+            clap::Arg::with_name("EXPORT_REPORT")
+                .long("--export-report")
+                .help(
+                    "list the exported functions referencing skipped types and the types not \
+                     used by any exported function",
+                ),
+        )
         .arg(
             clap::Arg::with_name("OUTPUT")
                 .index(1)
@@ -43,14 +131,100 @@ fn main() {
         )
         .get_matches();
 
+    if let Some(mut paths) = matches.values_of("ABI_CHANGELOG") {
+        let read = |path: &str| {
+            let mut contents = String::new();
+            let _ = File::open(path)
+                .and_then(|mut file| file.read_to_string(&mut contents))
+                .unwrap_or_else(|error| panic!("could not read {}: {}", path, error));
+            contents
+        };
+        let old = read(paths.next().unwrap());
+        let new = read(paths.next().unwrap());
+        print!("{}", AbiReport::new(&old, &new).changelog());
+        return;
+    }
+
     let mut bindgen = Bindgen::new().expect("cargo manifest could not be read");
-    let lang = matches.value_of("LANG").unwrap();
-    let lib = matches.value_of("LIB").unwrap();
 
     if let Some(file) = matches.value_of("FILE") {
         bindgen.source_file(&file);
     }
 
+    if matches.is_present("TIMESTAMP") {
+        bindgen.timestamp(true);
+    }
+
+    if matches.is_present("CRLF") {
+        bindgen.line_endings(LineEnding::CrLf);
+    }
+
+    if matches.is_present("BOM") {
+        bindgen.byte_order_mark(true);
+    }
+
+    if matches.is_present("STDOUT") {
+        bindgen.stdout(true);
+    }
+
+    if matches.is_present("DRY_RUN") {
+        bindgen.dry_run(true);
+    }
+
+    if matches.is_present("PROFILE") {
+        bindgen.profile(true);
+    }
+
+    for pattern in matches.values_of("INCLUDE").into_iter().flat_map(|values| values) {
+        bindgen.include(pattern);
+    }
+
+    for pattern in matches.values_of("EXCLUDE").into_iter().flat_map(|values| values) {
+        bindgen.exclude(pattern);
+    }
+
+    let targets: Vec<_> = matches
+        .values_of("TARGET")
+        .into_iter()
+        .flat_map(|values| values)
+        .collect();
+    if !targets.is_empty() {
+        bindgen.targets(&targets);
+    }
+
+    if let Some(baseline) = matches.value_of("CHECK_ABI") {
+        match bindgen.check_abi(baseline) {
+            Ok(report) => {
+                for change in &report.changes {
+                    println!("{}", change);
+                }
+            }
+            Err(errors) => {
+                for error in &errors {
+                    bindgen.print_error(error);
+                }
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("EXPORT_REPORT") {
+        match bindgen.export_report() {
+            Ok(report) => print!("{}", report),
+            Err(errors) => {
+                for error in &errors {
+                    bindgen.print_error(error);
+                }
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let lang = matches.value_of("LANG").unwrap();
+    let lib = matches.value_of("LIB").unwrap();
+
     let output_dir = if let Some(output) = matches.value_of("OUTPUT") {
         output.to_string()
     } else {
@@ -58,6 +232,11 @@ fn main() {
     };
 
     match lang {
+        "abi" => {
+            let mut lang = LangAbi::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
         "c" => {
             let mut lang = LangC::new();
             lang.set_lib_name(lib);
@@ -68,6 +247,11 @@ fn main() {
             lang.set_lib_name(lib);
             bindgen.run_build(&mut lang, &output_dir)
         }
+        "cython" => {
+            let mut lang = LangCython::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
         "java" => {
             let mut type_map = HashMap::new();
             type_map.insert(
@@ -136,6 +320,46 @@ fn main() {
             java.set_lib_name(lib);
             bindgen.run_build(&mut java, &output_dir);
         }
+        "d" => {
+            let mut lang = LangD::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "gir" => {
+            let mut lang = LangGir::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "haskell" => {
+            let mut lang = LangHaskell::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "man" => {
+            let mut lang = LangMan::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "markdown" => {
+            let mut lang = LangMarkdown::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "ruby" => {
+            let mut lang = LangRuby::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "js" => {
+            let mut lang = LangJs::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
+        "zig" => {
+            let mut lang = LangZig::new();
+            lang.set_lib_name(lib);
+            bindgen.run_build(&mut lang, &output_dir)
+        }
         _ => unreachable!(),
     }
 }
@@ -0,0 +1,207 @@
+//! Recognition of flag sets declared with the `bitflags!` macro.
+
+use std::collections::HashMap;
+use syntax::ast;
+use syntax::parse::parser::Parser;
+use syntax::parse::token;
+use syntax::parse::{self, PResult, ParseSess};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::keywords;
+use Error;
+use Level;
+
+/// A flag set declared as
+///
+/// ```ignore
+/// bitflags! {
+///     pub struct Flags: u32 {
+///         const READ = 0b01;
+///         const WRITE = 0b10;
+///     }
+/// }
+/// ```
+pub struct BitFlags {
+    pub attrs: Vec<ast::Attribute>,
+    pub public: bool,
+    pub name: String,
+    /// Underlying integer type.
+    pub ty: P<ast::Ty>,
+    pub flags: Vec<BitFlag>,
+}
+
+pub struct BitFlag {
+    pub attrs: Vec<ast::Attribute>,
+    pub name: String,
+    pub value: i64,
+}
+
+impl BitFlags {
+    /// Parse a `bitflags!` invocation. Returns `None` if the item is not one.
+    pub fn from_item(item: &ast::Item) -> Option<Result<Self, Error>> {
+        let is_bitflags = match item.node {
+            ast::ItemKind::Mac(ref mac) => pprust::path_to_string(&mac.node.path) == "bitflags",
+            _ => false,
+        };
+        if !is_bitflags {
+            return None;
+        }
+
+        // Re-parse the body of the macro (`bitflags! { ... }`).
+        let source = pprust::item_to_string(item);
+        let body = match (source.find('{'), source.rfind('}')) {
+            (Some(start), Some(end)) if start < end => source[start + 1..end].to_string(),
+            _ => return None,
+        };
+
+        let session = ParseSess::new();
+        let mut parser = parse::new_parser_from_source_str(&session, "bitflags".into(), body);
+
+        Some(parse_body(&mut parser).map_err(|mut err| {
+            err.cancel();
+            Error {
+                level: Level::Error,
+                span: Some(item.span),
+                message: "bindgen can not handle this `bitflags!` invocation".into(),
+                help: None,
+            }
+        }))
+    }
+
+    /// Convert the flag set into an item carrying its name, visibility and attributes (and the
+    /// original macro invocation), so it can be filtered like any other item.
+    pub fn to_item(&self, item: &ast::Item) -> ast::Item {
+        ast::Item {
+            ident: ast::Ident::from_str(&self.name),
+            attrs: self.attrs.clone(),
+            id: item.id,
+            node: item.node.clone(),
+            vis: if self.public {
+                ast::Visibility::Public
+            } else {
+                ast::Visibility::Inherited
+            },
+            span: item.span,
+        }
+    }
+}
+
+fn parse_body<'a>(parser: &mut Parser<'a>) -> PResult<'a, BitFlags> {
+    let attrs = parser.parse_outer_attributes()?;
+    let public = parser.eat_keyword(keywords::Pub);
+    parser.expect_keyword(keywords::Struct)?;
+    let name = parser.parse_ident()?.name.as_str().to_string();
+    parser.expect(&token::Colon)?;
+    let ty = parser.parse_ty()?;
+    parser.expect(&token::OpenDelim(token::Brace))?;
+
+    let mut flags = Vec::new();
+    let mut values = HashMap::new();
+
+    while !parser.eat(&token::CloseDelim(token::Brace)) {
+        let attrs = parser.parse_outer_attributes()?;
+        parser.expect_keyword(keywords::Const)?;
+        let name = parser.parse_ident()?.name.as_str().to_string();
+        parser.expect(&token::Eq)?;
+        let expr = parser.parse_expr()?;
+
+        // Older versions of `bitflags` separate the flags with commas.
+        if !parser.eat(&token::Semi) {
+            let _ = parser.eat(&token::Comma);
+        }
+
+        // Flags whose value can not be computed are skipped.
+        if let Some(value) = eval(&expr, &values) {
+            let _ = values.insert(name.clone(), value);
+            flags.push(BitFlag { attrs, name, value });
+        }
+    }
+
+    Ok(BitFlags {
+        attrs,
+        public,
+        name,
+        ty,
+        flags,
+    })
+}
+
+/// Compute the value of a flag, which can refer to the flags declared before it (e.g.
+/// `Self::READ.bits | Self::WRITE.bits`).
+fn eval(expr: &ast::Expr, values: &HashMap<String, i64>) -> Option<i64> {
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Int(value, _) => Some(value as i64),
+            _ => None,
+        },
+        ast::ExprKind::Paren(ref expr) => eval(expr, values),
+        ast::ExprKind::Binary(op, ref lhs, ref rhs) => {
+            let lhs = eval(lhs, values)?;
+            let rhs = eval(rhs, values)?;
+
+            match op.node {
+                ast::BinOpKind::BitOr => Some(lhs | rhs),
+                ast::BinOpKind::BitAnd => Some(lhs & rhs),
+                ast::BinOpKind::BitXor => Some(lhs ^ rhs),
+                ast::BinOpKind::Shl => lhs.checked_shl(rhs as u32),
+                ast::BinOpKind::Shr => lhs.checked_shr(rhs as u32),
+                ast::BinOpKind::Add => lhs.checked_add(rhs),
+                ast::BinOpKind::Sub => lhs.checked_sub(rhs),
+                _ => None,
+            }
+        }
+        ast::ExprKind::Path(None, ref path) => path.segments
+            .last()
+            .and_then(|segment| values.get(&*segment.identifier.name.as_str()))
+            .cloned(),
+        ast::ExprKind::Field(ref expr, ref field) if field.node.name == "bits" => {
+            eval(expr, values)
+        }
+        ast::ExprKind::MethodCall(ref method, _, ref args)
+            if method.node.name == "bits" && args.len() == 1 =>
+        {
+            eval(&args[0], values)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let source = "
+            bitflags! {
+                /// Permissions.
+                pub struct Permissions: u32 {
+                    const READ = 0b0001;
+                    const WRITE = 1 << 1;
+                    const ALL = Self::READ.bits | Self::WRITE.bits();
+                    const UNKNOWN = some_fn();
+                }
+            }
+        ";
+
+        let session = ParseSess::new();
+        let krate = unwrap!(parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let flags = unwrap!(unwrap!(BitFlags::from_item(&krate.module.items[0])));
+        assert!(flags.public);
+        assert_eq!(flags.name, "Permissions");
+        assert_eq!(pprust::ty_to_string(&flags.ty), "u32");
+        assert_eq!(flags.attrs.len(), 1);
+
+        let flags: Vec<_> = flags
+            .flags
+            .iter()
+            .map(|flag| (flag.name.as_str(), flag.value))
+            .collect();
+        assert_eq!(flags, vec![("READ", 1), ("WRITE", 2), ("ALL", 3)]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Compatibility with the configuration files of cbindgen (`cbindgen.toml`), for the options with
+//! an equivalent here.
+
+use lang_c::LangC;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use toml;
+use Bindgen;
+use Error;
+use Level;
+
+/// Options of a `cbindgen.toml` which can be applied to the generation of C headers.
+#[derive(Debug, Default, PartialEq)]
+pub struct CbindgenConfig {
+    /// Headers included with `#include "..."`.
+    pub includes: Vec<String>,
+    /// Headers included with `#include <...>`.
+    pub sys_includes: Vec<String>,
+    /// Text prepended to the headers.
+    pub header: Option<String>,
+    /// Text appended to the headers.
+    pub trailer: Option<String>,
+    /// Items not to generate bindings for (`export.exclude`).
+    pub exclude: Vec<String>,
+    /// Options (`table.key`) which have no equivalent and are ignored, such as the rename rules
+    /// or another `style` than `"both"`.
+    pub ignored: Vec<String>,
+}
+
+impl CbindgenConfig {
+    /// Read the options of a `cbindgen.toml` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut source = String::new();
+        let _ = fs::File::open(path).and_then(|mut file| file.read_to_string(&mut source))?;
+        Self::parse(&source)
+    }
+
+    /// Parse the options of the contents of a `cbindgen.toml`.
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let value = source.parse::<toml::Value>().map_err(|error| Error {
+            level: Level::Error,
+            span: None,
+            message: format!("could not parse the cbindgen configuration: {}", error),
+            help: None,
+        })?;
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return Ok(Self::default()),
+        };
+
+        let mut config = CbindgenConfig::default();
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("includes", value) => config.includes = strings(value),
+                ("sys_includes", value) => config.sys_includes = strings(value),
+                ("header", value) => config.header = value.as_str().map(String::from),
+                ("trailer", value) => config.trailer = value.as_str().map(String::from),
+                ("language", value) if value.as_str() == Some("C") => {}
+                ("style", value) if value.as_str() == Some("both") => {}
+                (name, &toml::Value::Table(ref options)) => {
+                    for (key, value) in options {
+                        match (name, key.as_str(), value) {
+                            ("export", "exclude", value) => config.exclude = strings(value),
+                            // Enum variants are always prefixed with the name of the enum.
+                            ("enum", "prefix_with_name", &toml::Value::Boolean(true)) => {}
+                            _ => config.ignored.push(format!("{}.{}", name, key)),
+                        }
+                    }
+                }
+                (name, _) => config.ignored.push(name.to_string()),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Apply the options to the generation of the C headers.
+    pub fn apply(&self, bindgen: &mut Bindgen, lang: &mut LangC) {
+        let mut includes = String::new();
+        for include in &self.sys_includes {
+            includes.push_str(&format!("#include <{}>\n", include));
+        }
+        for include in &self.includes {
+            includes.push_str(&format!("#include \"{}\"\n", include));
+        }
+        if !includes.is_empty() {
+            lang.add_custom_code(&includes);
+        }
+
+        for pattern in &self.exclude {
+            let _ = bindgen.exclude(pattern.as_str());
+        }
+
+        if self.header.is_some() || self.trailer.is_some() {
+            let header = self.header.clone();
+            let trailer = self.trailer.clone();
+            let _ = bindgen.post_process(move |lang, path, contents| {
+                if lang != "c" || !path.ends_with(".h") {
+                    return Ok(());
+                }
+                if let Some(ref header) = header {
+                    contents.insert_str(0, &format!("{}\n", header));
+                }
+                if let Some(ref trailer) = trailer {
+                    contents.push_str(&format!("{}\n", trailer));
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Returns the strings of an array.
+fn strings(value: &toml::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_else(Vec::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = unwrap!(CbindgenConfig::parse(
+            "language = \"C\"\n\
+             header = \"/* Copyright MaidSafe.net limited */\"\n\
+             sys_includes = [\"stdio.h\"]\n\
+             includes = [\"safe_core.h\"]\n\
+             style = \"tag\"\n\
+             [export]\n\
+             exclude = [\"test_*\"]\n\
+             [export.rename]\n\
+             App = \"SafeApp\"\n\
+             [enum]\n\
+             prefix_with_name = true\n"
+        ));
+
+        assert_eq!(
+            config,
+            CbindgenConfig {
+                includes: vec!["safe_core.h".to_string()],
+                sys_includes: vec!["stdio.h".to_string()],
+                header: Some("/* Copyright MaidSafe.net limited */".to_string()),
+                trailer: None,
+                exclude: vec!["test_*".to_string()],
+                ignored: vec!["export.rename".to_string(), "style".to_string()],
+            }
+        );
+    }
+}
@@ -1,10 +1,14 @@
 //! Functions common for all target languages.
 
-use std::collections::hash_map::{Entry, HashMap};
+use bitflags::BitFlags;
+use lang_c::const_value;
+use std::collections::HashMap;
 use syntax::abi::Abi;
 use syntax::ast;
 use syntax::print::pprust;
+use syntax::ptr::P;
 use Error;
+use Level;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FilterMode {
@@ -17,6 +21,12 @@ pub type Outputs = HashMap<String, String>;
 
 /// Target language support
 pub trait Lang {
+    /// Short name of the target language (e.g. `"c"`), passed to post-processing hooks.
+    fn name(&self) -> &'static str;
+
+    /// Receive the name and version of the crate the bindings are generated for.
+    fn crate_version(&mut self, _name: &str, _version: &str) {}
+
     /// Convert a Rust constant (`pub const NAME: Type = value;`) into a target
     /// language constant.
     fn parse_const(
@@ -58,6 +68,17 @@ pub trait Lang {
         Ok(())
     }
 
+    /// Convert a flag set declared with `bitflags!` into target language flags.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        _flags: &BitFlags,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Convert a Rust function declaration into a target language function declaration.
     fn parse_fn(
         &mut self,
@@ -68,6 +89,10 @@ pub trait Lang {
         Ok(())
     }
 
+    /// Receive an opaque handle type of the registry (see `Bindgen::opaque_handle`) and the
+    /// function freeing it, if any, before the items are parsed.
+    fn add_handle(&mut self, _name: &str, _free_fn: Option<&str>) {}
+
     /// Add extra and custom code after the code generation part is done.
     fn finalise_output(&mut self, _outputs: &mut Outputs) -> Result<(), Error> {
         Ok(())
@@ -76,12 +101,32 @@ pub trait Lang {
 
 /// Append or create new output file
 pub fn append_output(text: String, file: &str, o: &mut Outputs) {
-    match o.entry(file.to_string()) {
-        Entry::Occupied(o) => o.into_mut().push_str(&text),
-        Entry::Vacant(v) => {
-            let _ = v.insert(text);
-        }
+    // Only allocate the file name the first time.
+    if let Some(output) = o.get_mut(file) {
+        output.push_str(&text);
+        return;
     }
+
+    let _ = o.insert(file.to_string(), text);
+}
+
+/// Split a semver version string into its major, minor and patch numbers.
+///
+/// Missing or non-numeric components (e.g. pre-release suffixes) are ignored.
+pub fn version_parts(version: &str) -> (u64, u64, u64) {
+    let mut parts = version.split('.').map(|part| {
+        part.chars()
+            .take_while(|ch| ch.is_digit(10))
+            .collect::<String>()
+            .parse()
+            .unwrap_or(0)
+    });
+
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
 }
 
 /// Check the attribute is `#[no_mangle]`.
@@ -98,6 +143,61 @@ pub fn is_user_data_arg(arg: &ast::Arg) -> bool {
         && pprust::ty_to_string(&*arg.ty) == "*mut c_void"
 }
 
+/// Retrieve the name of the user data parameter given by a `#[cheddar(user_data = "ctx")]`
+/// attribute.
+pub fn retrieve_user_data(attr: &ast::Attribute) -> Option<String> {
+    cheddar_value(attr, "user_data").and_then(|lit| match lit.node {
+        ast::LitKind::Str(ref name, _) => Some(name.as_str().to_string()),
+        _ => None,
+    })
+}
+
+/// Rename the parameters declared with `#[cheddar(user_data = "ctx")]` (in the function and its
+/// callbacks) to `user_data`, so the managed backends recognise the user data + callback pattern
+/// and expose the callback as a single argument.
+///
+/// Returns `None` if the function has no such attribute.
+pub fn rename_user_data(item: &ast::Item) -> Option<ast::Item> {
+    let (_, name) = parse_attr(&item.attrs, |_| false, retrieve_user_data);
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut item = item.clone();
+    if let ast::ItemKind::Fn(ref mut decl, ..) = item.node {
+        *decl = P(rename_user_data_args((**decl).clone(), &name));
+    }
+
+    Some(item)
+}
+
+fn rename_user_data_args(mut decl: ast::FnDecl, name: &str) -> ast::FnDecl {
+    for arg in &mut decl.inputs {
+        if pprust::pat_to_string(&*arg.pat) == name
+            && pprust::ty_to_string(&*arg.ty) == "*mut c_void"
+        {
+            arg.pat = arg.pat.clone().map(|mut pat| {
+                if let ast::PatKind::Ident(_, ref mut ident, _) = pat.node {
+                    ident.node = ast::Ident::from_str("user_data");
+                }
+                pat
+            });
+        }
+
+        // The user data is passed back as a parameter of the callbacks.
+        arg.ty = arg.ty.clone().map(|mut ty| {
+            if let ast::TyKind::BareFn(ref mut bare_fn) = ty.node {
+                let mut callback = (**bare_fn).clone();
+                callback.decl = P(rename_user_data_args((*callback.decl).clone(), name));
+                *bare_fn = P(callback);
+            }
+            ty
+        });
+    }
+
+    decl
+}
+
 /// Check the function argument is `result: *const FfiResult`
 pub fn is_result_arg(arg: &ast::Arg) -> bool {
     pprust::pat_to_string(&*arg.pat) == "result"
@@ -112,17 +212,81 @@ pub fn is_ptr_len_arg(ty: &ast::Ty, arg_name: &str) -> bool {
 
 /// Detect array ptrs and skip the length args - e.g. for a case of
 /// `ptr: *const u8, ptr_len: usize` we're going to skip the `len` part.
-pub fn is_array_arg(arg: &ast::Arg, next_arg: Option<&ast::Arg>) -> bool {
+///
+/// `slices` lists the pairs declared explicitly with `#[cheddar(slice(..))]`.
+pub fn is_array_arg(
+    arg: &ast::Arg,
+    next_arg: Option<&ast::Arg>,
+    slices: &[(String, String)],
+) -> bool {
     if let ast::TyKind::Ptr(..) = arg.ty.node {
+        let next_arg = match next_arg {
+            Some(next_arg) => next_arg,
+            None => return false,
+        };
+        let arg_name = pprust::pat_to_string(&*arg.pat);
+        let next_arg_name = pprust::pat_to_string(&*next_arg.pat);
+
         !is_result_arg(arg)
-            && next_arg
-                .map(|arg| is_ptr_len_arg(&*arg.ty, &pprust::pat_to_string(&*arg.pat)))
-                .unwrap_or(false)
+            && (is_slice(slices, &arg_name, &next_arg_name)
+                || is_ptr_len_arg(&*next_arg.ty, &next_arg_name))
     } else {
         false
     }
 }
 
+/// Check whether the parameters `ptr` and `len` are declared as a pointer/length pair.
+pub fn is_slice(slices: &[(String, String)], ptr: &str, len: &str) -> bool {
+    slices
+        .iter()
+        .any(|&(ref slice_ptr, ref slice_len)| slice_ptr == ptr && slice_len == len)
+}
+
+/// Retrieve the pointer/length parameter pairs declared with
+/// `#[cheddar(slice(ptr = "data", len = "count"))]`.
+///
+/// The pointer has to be directly followed by the length.
+pub fn retrieve_slices(attrs: &[ast::Attribute]) -> Vec<(String, String)> {
+    let mut slices = Vec::new();
+
+    for attr in attrs {
+        let items = match attr.value.node {
+            ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items,
+            _ => continue,
+        };
+
+        for item in items {
+            let fields = match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) if item.name == "slice" => {
+                    match item.node {
+                        ast::MetaItemKind::List(ref fields) => fields,
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let value = |key: &str| {
+                fields
+                    .iter()
+                    .filter_map(|field| match field.node {
+                        ast::NestedMetaItemKind::MetaItem(ref field) if field.name == key => {
+                            field.value_str()
+                        }
+                        _ => None,
+                    })
+                    .next()
+                    .map(|value| value.as_str().to_string())
+            };
+
+            if let (Some(ptr), Some(len)) = (value("ptr"), value("len")) {
+                slices.push((ptr, len));
+            }
+        }
+    }
+
+    slices
+}
+
 // TODO: Maybe it would be wise to use syntax::attr here.
 /// Loop through a list of attributes.
 ///
@@ -140,9 +304,14 @@ where
         if !check_passed {
             check_passed = check(attr);
         }
-        // If this attribute has any strings to retrieve, retrieve them.
+        // If this attribute has any strings to retrieve, retrieve them (the first one is reused
+        // as the buffer).
         if let Some(string) = retrieve(attr) {
-            retrieved_str.push_str(&string);
+            if retrieved_str.is_empty() {
+                retrieved_str = string;
+            } else {
+                retrieved_str.push_str(&string);
+            }
         }
     }
 
@@ -168,13 +337,233 @@ pub fn check_repr_c(attr: &ast::Attribute) -> bool {
     }
 }
 
+/// Layout of a struct requested by the `packed`, `packed(N)` and `align(N)` arguments of its
+/// `#[repr(..)]` attributes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReprLayout {
+    /// Maximum alignment of the fields (1 for `packed`).
+    pub packed: Option<u64>,
+    /// Minimum alignment of the struct.
+    pub align: Option<u64>,
+}
+
+/// Read the layout arguments of the `#[repr(..)]` attributes (e.g. `#[repr(C, packed)]` or
+/// `#[repr(C, align(8))]`).
+pub fn repr_layout(attrs: &[ast::Attribute]) -> ReprLayout {
+    let mut layout = ReprLayout::default();
+
+    for attr in attrs {
+        let items = match attr.value.node {
+            ast::MetaItemKind::List(ref items) if attr.name() == "repr" => items,
+            _ => continue,
+        };
+
+        for item in items {
+            let item = match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) => item,
+                _ => continue,
+            };
+            let value = match item.node {
+                ast::MetaItemKind::Word => None,
+                ast::MetaItemKind::List(ref args) => args.first().and_then(|arg| match arg.node {
+                    ast::NestedMetaItemKind::Literal(ast::Lit {
+                        node: ast::LitKind::Int(value, _),
+                        ..
+                    }) => Some(value as u64),
+                    _ => None,
+                }),
+                _ => continue,
+            };
+
+            match &*item.name.as_str() {
+                "packed" => layout.packed = Some(value.unwrap_or(1)),
+                "align" => layout.align = value,
+                _ => {}
+            }
+        }
+    }
+
+    layout
+}
+
+/// Retrieve the output file requested by a `#[cheddar(header = "file.h")]` attribute.
+pub fn retrieve_header(attr: &ast::Attribute) -> Option<String> {
+    cheddar_value(attr, "header").and_then(|lit| match lit.node {
+        ast::LitKind::Str(ref header, _) => Some(header.as_str().to_string()),
+        _ => None,
+    })
+}
+
+/// Retrieve the bitfield width requested by a `#[cheddar(bits = 3)]` attribute.
+pub fn retrieve_bits(attr: &ast::Attribute) -> Option<String> {
+    cheddar_value(attr, "bits").and_then(|lit| match lit.node {
+        ast::LitKind::Int(bits, _) => Some(bits.to_string()),
+        _ => None,
+    })
+}
+
+/// Retrieve the error code requested by a `#[cheddar(catch_panic = "ERR_PANICKED")]` attribute.
+pub fn retrieve_catch_panic(attr: &ast::Attribute) -> Option<String> {
+    cheddar_value(attr, "catch_panic").and_then(|lit| match lit.node {
+        ast::LitKind::Str(ref code, _) => Some(code.as_str().to_string()),
+        _ => None,
+    })
+}
+
+/// Check the attribute is `#[cheddar(..)]` with the flag `name` (e.g. `#[cheddar(nonnull)]`).
+pub fn has_cheddar_flag(attr: &ast::Attribute, name: &str) -> bool {
+    match attr.value.node {
+        ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => {
+            items.iter().any(|item| match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) => item.name == name,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Retrieve the function freeing the returned pointer given by a
+/// `#[cheddar(free_with = "string_free")]` attribute.
+pub fn retrieve_free_with(attr: &ast::Attribute) -> Option<String> {
+    cheddar_value(attr, "free_with").and_then(|lit| match lit.node {
+        ast::LitKind::Str(ref function, _) => Some(function.as_str().to_string()),
+        _ => None,
+    })
+}
+
+/// Compute the values of the variants of a fieldless enum, continuing from the previous value
+/// for the variants without an explicit discriminant.
+///
+/// Returns `None` if a discriminant is not an integer literal.
+pub fn enum_discriminants(variants: &[ast::Variant]) -> Option<Vec<(String, i64)>> {
+    let mut next = 0;
+
+    variants
+        .iter()
+        .map(|variant| {
+            let value = match variant.node.disr_expr {
+                Some(ref expr) => int_literal(expr)?,
+                None => next,
+            };
+            next = value + 1;
+
+            Some((variant.node.name.name.as_str().to_string(), value))
+        })
+        .collect()
+}
+
+fn int_literal(expr: &ast::Expr) -> Option<i64> {
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Int(value, _) => Some(value as i64),
+            _ => None,
+        },
+        ast::ExprKind::Unary(ast::UnOp::Neg, ref expr) => int_literal(expr).map(|value| -value),
+        _ => None,
+    }
+}
+
+/// Check the attribute is `#[derive(..)]` including `Default`.
+pub fn check_derive_default(attr: &ast::Attribute) -> bool {
+    match attr.value.node {
+        ast::MetaItemKind::List(ref items) if attr.name() == "derive" => {
+            items.iter().any(|item| match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) => item.name == "Default",
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Find the value of `key` in a `#[cheddar(key = value)]` attribute.
+fn cheddar_value(attr: &ast::Attribute, key: &str) -> Option<ast::Lit> {
+    match attr.value.node {
+        ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items
+            .iter()
+            .filter_map(|item| match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) if item.name == key => {
+                    match item.node {
+                        ast::MetaItemKind::NameValue(ref lit) => Some(lit.clone()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .next(),
+        _ => None,
+    }
+}
+
+/// Check whether the type is a primitive integer (including the `libc` ones).
+pub fn is_integer(ty: &ast::Ty) -> bool {
+    match pprust::ty_to_string(ty).trim_left_matches("libc::") {
+        "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
+        | "c_char" | "c_schar" | "c_uchar" | "c_short" | "c_ushort" | "c_int" | "c_uint"
+        | "c_long" | "c_ulong" | "c_longlong" | "c_ulonglong" => true,
+        _ => false,
+    }
+}
+
+/// Returns the pointer width (in bits) an item is restricted to by a
+/// `#[cfg(target_pointer_width = "64")]` attribute, if any.
+pub fn pointer_width(attrs: &[ast::Attribute]) -> Option<u32> {
+    attrs
+        .iter()
+        .filter_map(|attr| match attr.value.node {
+            ast::MetaItemKind::List(ref items) if attr.name() == "cfg" && items.len() == 1 => {
+                match items[0].node {
+                    ast::NestedMetaItemKind::MetaItem(ref item)
+                        if item.name == "target_pointer_width" =>
+                    {
+                        item.value_str().and_then(|width| width.as_str().parse().ok())
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .next()
+}
+
+/// Returns the value of `UINTPTR_MAX` for a pointer width (e.g. `0xFFFFFFFF` for 32 bits).
+pub fn uintptr_max(width: u32) -> String {
+    format!("0x{}", "F".repeat(width as usize / 4))
+}
+
+/// Check the attribute is `#[doc(hidden)]`.
+pub fn check_doc_hidden(attr: &ast::Attribute) -> bool {
+    match attr.value.node {
+        ast::MetaItemKind::List(ref items) if attr.name() == "doc" => {
+            items.iter().any(|item| match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) => item.name == "hidden",
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
 /// If the attribute is  a docstring, indent it the required amount and return it.
 pub fn retrieve_docstring(attr: &ast::Attribute, prepend: &str) -> Option<String> {
     match attr.value.node {
         ast::MetaItemKind::NameValue(ref val) if attr.name() == "doc" => {
             match val.node {
-                // Docstring attributes omit the trailing newline.
-                ast::LitKind::Str(ref docs, _) => Some(format!("{}{}\n", prepend, docs)),
+                // Docstring attributes omit the trailing newline. Every line of multi-line docs
+                // (block comments or `#[doc = ".."]`) is prepended, to keep them indented.
+                ast::LitKind::Str(ref docs, _) => {
+                    let docs = docs.as_str();
+                    let mut text = String::with_capacity(docs.len() + prepend.len() + 1);
+                    for line in docs.split('\n') {
+                        if !line.is_empty() {
+                            text.push_str(prepend);
+                            text.push_str(line);
+                        }
+                        text.push('\n');
+                    }
+                    Some(text)
+                }
                 _ => unreachable!("docs must be literal strings"),
             }
         }
@@ -182,6 +571,219 @@ pub fn retrieve_docstring(attr: &ast::Attribute, prepend: &str) -> Option<String
     }
 }
 
+/// If the attribute is a docstring, return it as a comment of the target language starting with
+/// `marker` (e.g. `"# "`) instead of `///`, indented the required amount.
+pub fn retrieve_doc_comment(attr: &ast::Attribute, prepend: &str, marker: &str) -> Option<String> {
+    let docs = retrieve_docstring(attr, "")?;
+    let text = docs.trim_right_matches('\n')
+        .trim_left_matches("///")
+        .trim_left_matches("//!");
+    let text = if text.starts_with(' ') { &text[1..] } else { text };
+
+    Some(
+        text.split('\n')
+            .map(|line| {
+                if line.is_empty() {
+                    format!("{}{}\n", prepend, marker.trim_right())
+                } else {
+                    format!("{}{}{}\n", prepend, marker, line)
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Collect the doc comments of an item as comments of the target language starting with `marker`
+/// (see `retrieve_doc_comment`).
+pub fn doc_comments(attrs: &[ast::Attribute], indent: &str, marker: &str) -> String {
+    parse_attr(attrs, |_| false, |attr| retrieve_doc_comment(attr, indent, marker)).1
+}
+
+/// Escape the identifier with `pattern` (e.g. `"@\"{}\""`) if it is one of the keywords of the
+/// target language.
+pub fn escape_keyword(name: &str, keywords: &[&str], pattern: &str) -> String {
+    if keywords.contains(&name) {
+        pattern.replace("{}", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Error of a `Lang` method called on an item it doesn't handle.
+pub fn wrong_item(item: &ast::Item, method: &str) -> Error {
+    Error {
+        level: Level::Bug,
+        span: Some(item.span),
+        message: format!("`{}` called on wrong `Item_`", method),
+        help: None,
+    }
+}
+
+/// A constant whose value the backends can convert.
+pub struct LiteralConst<'a> {
+    pub ty: &'a ast::Ty,
+    /// The value, rendered as in C (e.g. `"text"` or `(-1)`).
+    pub value: String,
+    /// The literal, if the value isn't an expression (e.g. `-1`).
+    pub lit: Option<&'a ast::LitKind>,
+}
+
+/// Retrieve the type and the value of `pub const NAME: Type = value;`.
+///
+/// Only literal values are supported, other constants are skipped (`Ok(None)`).
+pub fn literal_const(item: &ast::Item) -> Result<Option<LiteralConst>, Error> {
+    let (ty, expr) = match item.node {
+        ast::ItemKind::Const(ref ty, ref expr) => (ty, expr),
+        _ => return Err(wrong_item(item, "parse_const")),
+    };
+
+    Ok(const_value(expr).map(|value| LiteralConst {
+        ty: ty,
+        value: value,
+        lit: match expr.node {
+            ast::ExprKind::Lit(ref lit) => Some(&lit.node),
+            _ => None,
+        },
+    }))
+}
+
+/// Retrieve the aliased type of `pub type A = B;`.
+///
+/// Generic aliases can not yet be converted, so they are skipped (`Ok(None)`).
+pub fn alias_type(item: &ast::Item) -> Result<Option<&ast::Ty>, Error> {
+    match item.node {
+        ast::ItemKind::Ty(ref ty, ref generics) => Ok(if generics.is_parameterized() {
+            None
+        } else {
+            Some(ty)
+        }),
+        _ => Err(wrong_item(item, "parse_ty")),
+    }
+}
+
+/// A `#[repr(C)]` enum.
+pub enum ReprCEnum<'a> {
+    /// An enum with data-carrying variants, which can only be used through pointers.
+    Opaque,
+    /// A fieldless enum, with the names and values of its variants.
+    Fieldless(&'a [ast::Variant], Vec<(String, i64)>),
+}
+
+/// Retrieve the variants of a `#[repr(C)]` enum, or `None` if the enum isn't `#[repr(C)]`.
+pub fn repr_c_enum(item: &ast::Item) -> Result<Option<ReprCEnum>, Error> {
+    if !parse_attr(&item.attrs, check_repr_c, |_| None).0 {
+        return Ok(None);
+    }
+
+    let definition = match item.node {
+        ast::ItemKind::Enum(ref definition, ref generics) => {
+            if generics.is_parameterized() {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: "bindgen can not handle parameterized `#[repr(C)]` enums".into(),
+                    help: None,
+                });
+            }
+            definition
+        }
+        _ => return Err(wrong_item(item, "parse_enum")),
+    };
+
+    if definition.variants.iter().any(|var| !var.node.data.is_unit()) {
+        return Ok(Some(ReprCEnum::Opaque));
+    }
+
+    match enum_discriminants(&definition.variants) {
+        Some(values) => Ok(Some(ReprCEnum::Fieldless(&definition.variants, values))),
+        None => Err(Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: "bindgen can only handle integer literal discriminants".into(),
+            help: None,
+        }),
+    }
+}
+
+/// A `#[repr(C)]` struct.
+pub enum ReprCStruct<'a> {
+    /// A tuple struct with a single field, which can only be used through pointers.
+    Opaque,
+    /// A struct with named fields, with the name of each field.
+    Fields(Vec<(String, &'a ast::StructField)>),
+}
+
+/// Retrieve the fields of a `#[repr(C)]` struct, or `None` if the struct isn't `#[repr(C)]`.
+pub fn repr_c_struct(item: &ast::Item) -> Result<Option<ReprCStruct>, Error> {
+    if !parse_attr(&item.attrs, check_repr_c, |_| None).0 {
+        return Ok(None);
+    }
+
+    let variants = match item.node {
+        ast::ItemKind::Struct(ref variants, ref generics) => {
+            if generics.is_parameterized() {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: "bindgen can not handle parameterized `#[repr(C)]` structs".into(),
+                    help: None,
+                });
+            }
+            variants
+        }
+        _ => return Err(wrong_item(item, "parse_struct")),
+    };
+
+    if variants.is_struct() {
+        let fields = variants
+            .fields()
+            .iter()
+            .map(|field| match field.ident {
+                Some(ident) => (ident.name.as_str().to_string(), field),
+                None => unreachable!("a tuple struct snuck through"),
+            })
+            .collect();
+        Ok(Some(ReprCStruct::Fields(fields)))
+    } else if variants.is_tuple() && variants.fields().len() == 1 {
+        Ok(Some(ReprCStruct::Opaque))
+    } else {
+        Err(Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: "can not handle unit or tuple `#[repr(C)]` structs with >1 members".into(),
+            help: None,
+        })
+    }
+}
+
+/// Retrieve the declaration of a `#[no_mangle] pub extern "C" fn`, or `None` for the other
+/// functions.
+pub fn extern_fn(item: &ast::Item) -> Result<Option<&ast::FnDecl>, Error> {
+    if !parse_attr(&item.attrs, check_no_mangle, |_| None).0 {
+        return Ok(None);
+    }
+
+    match item.node {
+        ast::ItemKind::Fn(ref fn_decl, _, _, abi, ref generics, _) => {
+            if !is_extern(abi) {
+                return Ok(None);
+            }
+
+            if generics.is_parameterized() {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: "bindgen can not handle parameterized extern functions".into(),
+                    help: None,
+                });
+            }
+
+            Ok(Some(fn_decl))
+        }
+        _ => Err(wrong_item(item, "parse_fn")),
+    }
+}
+
 /// Returns whether the calling convention of the function is compatible with
 /// C (i.e. `extern "C"`).
 pub fn is_extern(abi: Abi) -> bool {
@@ -190,3 +792,147 @@ pub fn is_extern(abi: Abi) -> bool {
         _ => false,
     }
 }
+
+/// Returns the FFI-safe alternative to a standard library type (e.g. `Vec`) which can not be
+/// represented in C, or `None` if `name` isn't such a type.
+pub fn std_type_alternative(name: &str) -> Option<&'static str> {
+    match name {
+        "String" | "CString" | "OsString" | "PathBuf" => {
+            Some("pass a `*const c_char` to a nul-terminated string instead (e.g. from \
+                  `CString::into_raw`)")
+        }
+        "Vec" => Some("pass a pointer to the first element and a `usize` length instead"),
+        "Box" | "Rc" | "Arc" => Some("pass a raw pointer instead (e.g. from `Box::into_raw`)"),
+        "HashMap" | "HashSet" | "BTreeMap" | "BTreeSet" | "VecDeque" => {
+            Some("pass an opaque handle instead (a pointer to a struct owning the collection)")
+        }
+        _ => None,
+    }
+}
+
+/// Generate the outputs of `lang` for the `ffi` module of a crate made of `source`.
+#[cfg(test)]
+pub fn compile_source<L: Lang>(lang: &mut L, source: &str) -> Outputs {
+    let session = ::syntax::parse::ParseSess::new();
+    let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+        "lib.rs".to_string(),
+        source.to_string(),
+        &session,
+    ));
+
+    let mut outputs = Outputs::default();
+    unwrap!(::parse::parse_mod(
+        lang,
+        &krate.module,
+        &["ffi".to_string()],
+        &Default::default(),
+        &mut outputs,
+    ));
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse::{self, ParseSess};
+
+    fn item(source: &str) -> ast::Item {
+        let session = ParseSess::new();
+        let item = unwrap!(unwrap!(parse::parse_item_from_source_str(
+            "lib.rs".to_owned(),
+            source.to_owned(),
+            &session,
+        )));
+
+        (*item).clone()
+    }
+
+    #[test]
+    fn literal_consts() {
+        let len = item("pub const LEN: u32 = 16;");
+        let constant = unwrap!(unwrap!(literal_const(&len)));
+        assert_eq!(pprust::ty_to_string(constant.ty), "u32");
+        assert_eq!(constant.value, "16");
+        assert!(constant.lit.is_some());
+
+        let min = item("pub const MIN: i32 = -1;");
+        let constant = unwrap!(unwrap!(literal_const(&min)));
+        assert_eq!(constant.value, "(-1)");
+        assert!(constant.lit.is_none());
+
+        assert!(unwrap!(literal_const(&item("pub const MAX: u32 = LEN;"))).is_none());
+        assert!(literal_const(&item("pub type Id = u64;")).is_err());
+    }
+
+    #[test]
+    fn alias_types() {
+        let id = item("pub type Id = u64;");
+        let ty = unwrap!(unwrap!(alias_type(&id)));
+        assert_eq!(pprust::ty_to_string(ty), "u64");
+
+        assert!(unwrap!(alias_type(&item("pub type List<T> = *const T;"))).is_none());
+    }
+
+    #[test]
+    fn repr_c_enums() {
+        let source = "#[repr(C)] pub enum Status { Ok, Failed = 4, Retry }";
+        match unwrap!(repr_c_enum(&item(source))) {
+            Some(ReprCEnum::Fieldless(variants, values)) => {
+                assert_eq!(variants.len(), 3);
+                assert_eq!(
+                    values,
+                    vec![
+                        ("Ok".to_string(), 0),
+                        ("Failed".to_string(), 4),
+                        ("Retry".to_string(), 5),
+                    ]
+                );
+            }
+            _ => panic!("expected a fieldless enum"),
+        }
+
+        let source = "#[repr(C)] pub enum Value { Int(i32), Empty }";
+        match unwrap!(repr_c_enum(&item(source))) {
+            Some(ReprCEnum::Opaque) => {}
+            _ => panic!("expected an opaque enum"),
+        }
+
+        assert!(unwrap!(repr_c_enum(&item("pub enum Status { Ok }"))).is_none());
+        assert!(repr_c_enum(&item("#[repr(C)] pub enum Status { Ok = LEN }")).is_err());
+        assert!(repr_c_enum(&item("#[repr(C)] pub enum Maybe<T> { None }")).is_err());
+    }
+
+    #[test]
+    fn repr_c_structs() {
+        let source = "#[repr(C)] pub struct Point { x: i32, y: i32 }";
+        match unwrap!(repr_c_struct(&item(source))) {
+            Some(ReprCStruct::Fields(fields)) => {
+                let names: Vec<_> = fields.iter().map(|&(ref name, _)| &name[..]).collect();
+                assert_eq!(names, ["x", "y"]);
+            }
+            _ => panic!("expected a struct with fields"),
+        }
+
+        match unwrap!(repr_c_struct(&item("#[repr(C)] pub struct Handle(u64);"))) {
+            Some(ReprCStruct::Opaque) => {}
+            _ => panic!("expected an opaque struct"),
+        }
+
+        assert!(unwrap!(repr_c_struct(&item("pub struct Point { x: i32 }"))).is_none());
+        assert!(repr_c_struct(&item("#[repr(C)] pub struct Pair(u8, u8);")).is_err());
+        assert!(repr_c_struct(&item("#[repr(C)] pub struct Wrapper<T> { x: T }")).is_err());
+    }
+
+    #[test]
+    fn extern_fns() {
+        let add = item("#[no_mangle] pub extern \"C\" fn add(a: i32, b: i32) -> i32 {}");
+        let fn_decl = unwrap!(unwrap!(extern_fn(&add)));
+        assert_eq!(fn_decl.inputs.len(), 2);
+
+        assert!(unwrap!(extern_fn(&item("pub extern \"C\" fn add() {}"))).is_none());
+        assert!(unwrap!(extern_fn(&item("#[no_mangle] pub fn add() {}"))).is_none());
+        assert!(extern_fn(&item("#[no_mangle] pub extern \"C\" fn id<T>(t: T) {}")).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Settings shared by all the target languages.
+
+use docs::ThreadSafety;
+use handles::HandleRegistry;
+use lifecycle::Lifecycle;
+use order::{Order, OrderedKind};
+use parse::{ItemHooks, Unsupported};
+use reachability::Pruning;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use syntax::ast;
+use syntax::codemap;
+use syntax::fold::{self, Folder};
+use syntax::ptr::P;
+use target::Target;
+use toml;
+
+/// Settings applied while parsing the Rust source, independently of the target language.
+#[derive(Default)]
+pub struct Config {
+    /// Selects the items to generate bindings for.
+    pub filter: ItemFilter,
+    /// Hooks injecting custom text around every emitted item.
+    pub hooks: ItemHooks,
+    /// Whether to generate bindings for items marked `#[doc(hidden)]`.
+    pub include_hidden: bool,
+    /// Whether functions exported from `impl` blocks under the prefixed name (e.g.
+    /// `#[export_name = "Session_close"]`) are declared with it.
+    pub prefix_impl_fns: bool,
+    /// Whether associated constants of `impl` blocks are exported, prefixed with the type name
+    /// (e.g. `Flags_READ`).
+    pub export_assoc_consts: bool,
+    /// Emission order of the kinds of items, in source order if not listed.
+    pub order: HashMap<OrderedKind, Order>,
+    /// Naming convention of constructor/destructor pairs to group together, if any.
+    pub lifecycle: Option<Lifecycle>,
+    /// Whether the functions operating on a type are emitted after the declaration of the type.
+    pub group_methods: bool,
+    /// Thread safety of the handle types, indexed by name, if notes about it are added to the
+    /// docs of the functions taking the handles. Collected from the crate before parsing.
+    pub thread_safety: Option<RefCell<HashMap<String, ThreadSafety>>>,
+    /// Whether Rust's `char` is rejected instead of being declared as a 32-bit integer.
+    pub strict_char: bool,
+    /// Whether the strict checks are enforced regardless of the other settings (read from the
+    /// `SAFE_BINDGEN_STRICT` environment variable on each compilation): `char` is rejected and
+    /// unsupported constructs fail even if they are to be skipped.
+    pub strict: Cell<bool>,
+    /// Types to emit if only the types reachable from the exported functions are emitted.
+    pub pruning: Option<Pruning>,
+    /// Constructs whose items are skipped with a warning when the target language can't handle
+    /// them, instead of failing.
+    pub skip_unsupported: HashSet<Unsupported>,
+    /// Renderings of Rust types replacing their translation in a target language.
+    pub type_overrides: TypeOverrides,
+    /// Pointer widths (in bits) of the targets, for which the items marked
+    /// `#[cfg(target_pointer_width = "..")]` are emitted. All the items are emitted if empty.
+    pub pointer_widths: Vec<u32>,
+    /// Target the bindings are currently generated for, whose `#[cfg(..)]` attributes the items
+    /// have to match, if any.
+    pub target: RefCell<Option<Target>>,
+    /// Opaque handle types declared as handles by every target language, if the registry is
+    /// enabled.
+    pub handles: Option<HandleRegistry>,
+}
+
+/// Include/exclude lists of glob patterns (`*` matches any sequence of characters, `?` any
+/// single character) applied to item names.
+#[derive(Default)]
+pub struct ItemFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ItemFilter {
+    /// Only generate bindings for the items matching `pattern` (or any other included pattern).
+    pub fn include<T: Into<String>>(&mut self, pattern: T) {
+        self.include.push(pattern.into());
+    }
+
+    /// Skip the items matching `pattern`, even if they are included.
+    pub fn exclude<T: Into<String>>(&mut self, pattern: T) {
+        self.exclude.push(pattern.into());
+    }
+
+    /// Read the `include` and `exclude` lists from the `[package.metadata.safe_bindgen]` section
+    /// of the cargo manifest.
+    pub fn extend_from_manifest(&mut self, manifest: &toml::Value) {
+        let section = manifest
+            .get("package")
+            .and_then(|t| t.get("metadata"))
+            .and_then(|t| t.get("safe_bindgen"));
+        let patterns = |name: &str| -> Vec<String> {
+            section
+                .and_then(|t| t.get(name))
+                .and_then(|t| t.as_array())
+                .map(|patterns| {
+                    patterns
+                        .iter()
+                        .filter_map(|pattern| pattern.as_str())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_else(Vec::new)
+        };
+
+        self.include.extend(patterns("include"));
+        self.exclude.extend(patterns("exclude"));
+    }
+
+    /// Check whether bindings should be generated for the item called `name`.
+    pub fn matches(&self, name: &str) -> bool {
+        let included = self.include.is_empty()
+            || self.include
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes()));
+
+        included
+            && !self.exclude
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes()))
+    }
+}
+
+/// Renderings of Rust types (e.g. `XorName`) replacing their translation, indexed by the name of
+/// the target language (e.g. `unsigned char[32]` in C and `byte[]` in C#).
+#[derive(Default)]
+pub struct TypeOverrides {
+    types: HashMap<String, HashMap<String, String>>,
+}
+
+impl TypeOverrides {
+    /// Render the type `rust_type` as `rendered` in the outputs of the language `lang`.
+    pub fn insert<L, R, T>(&mut self, lang: L, rust_type: R, rendered: T)
+    where
+        L: Into<String>,
+        R: Into<String>,
+        T: Into<String>,
+    {
+        let _ = self.types
+            .entry(lang.into())
+            .or_insert_with(HashMap::new)
+            .insert(rust_type.into(), rendered.into());
+    }
+
+    /// Read the overrides from the `[package.metadata.safe_bindgen.types.<lang>]` tables of the
+    /// cargo manifest.
+    pub fn extend_from_manifest(&mut self, manifest: &toml::Value) {
+        let langs = manifest
+            .get("package")
+            .and_then(|t| t.get("metadata"))
+            .and_then(|t| t.get("safe_bindgen"))
+            .and_then(|t| t.get("types"))
+            .and_then(|t| t.as_table());
+
+        for (lang, types) in langs.into_iter().flat_map(|langs| langs) {
+            for (rust_type, rendered) in types.as_table().into_iter().flat_map(|types| types) {
+                if let Some(rendered) = rendered.as_str() {
+                    self.insert(lang.as_str(), rust_type.as_str(), rendered);
+                }
+            }
+        }
+    }
+
+    /// Replace the overridden types in the signature of a function, the fields of a struct or
+    /// of the variants of an enum, the type of a constant or an aliased type with their rendering
+    /// in the language `lang`.
+    pub fn apply(&self, lang: &str, item: ast::Item) -> ast::Item {
+        match self.types.get(lang) {
+            Some(types) if !types.is_empty() => override_types(item, types),
+            _ => item,
+        }
+    }
+}
+
+/// Replace the types named in `types` in the signature of a function, the fields of a struct or
+/// of the variants of an enum, the type of a constant or an aliased type with their rendering
+/// (used verbatim as the name of the type).
+pub fn override_types(item: ast::Item, types: &HashMap<String, String>) -> ast::Item {
+    let mut folder = Override { types };
+    let node = match item.node {
+        ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block) => ast::ItemKind::Fn(
+            folder.fold_fn_decl(fn_decl),
+            unsafety,
+            constness,
+            abi,
+            generics,
+            block,
+        ),
+        ast::ItemKind::Struct(variants, generics) => {
+            ast::ItemKind::Struct(folder.fold_variant_data(variants), generics)
+        }
+        ast::ItemKind::Enum(definition, generics) => ast::ItemKind::Enum(
+            ast::EnumDef {
+                variants: definition
+                    .variants
+                    .into_iter()
+                    .map(|variant| codemap::Spanned {
+                        node: ast::Variant_ {
+                            data: folder.fold_variant_data(variant.node.data),
+                            ..variant.node
+                        },
+                        span: variant.span,
+                    })
+                    .collect(),
+            },
+            generics,
+        ),
+        ast::ItemKind::Const(ty, expr) => ast::ItemKind::Const(folder.fold_ty(ty), expr),
+        ast::ItemKind::Ty(ty, generics) => ast::ItemKind::Ty(folder.fold_ty(ty), generics),
+        node => node,
+    };
+
+    ast::Item { node, ..item }
+}
+
+/// Replaces the paths of the overridden types with their rendering.
+struct Override<'a> {
+    types: &'a HashMap<String, String>,
+}
+
+impl<'a> Folder for Override<'a> {
+    fn fold_ty(&mut self, ty: P<ast::Ty>) -> P<ast::Ty> {
+        let rendered = match ty.node {
+            ast::TyKind::Path(None, ref path) => {
+                let name = path.segments
+                    .iter()
+                    .map(|segment| segment.identifier.name.as_str().to_string())
+                    .collect::<Vec<_>>()
+                    .join("::");
+                let last = path.segments
+                    .last()
+                    .map(|segment| segment.identifier.name.as_str().to_string());
+                self.types
+                    .get(&name)
+                    .or_else(|| last.and_then(|last| self.types.get(&last)))
+                    .cloned()
+            }
+            _ => None,
+        };
+
+        match rendered {
+            Some(rendered) => P(ast::Ty {
+                id: ty.id,
+                node: ast::TyKind::Path(
+                    None,
+                    ast::Path::from_ident(ty.span, ast::Ident::from_str(&rendered)),
+                ),
+                span: ty.span,
+            }),
+            None => fold::noop_fold_ty(ty, self),
+        }
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&b'*', rest)) => (0..name.len() + 1).any(|skip| glob_match(rest, &name[skip..])),
+        Some((&b'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((ch, rest)) => name.first() == Some(ch) && glob_match(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match(b"safe_*", b"safe_app"));
+        assert!(glob_match(b"safe_*", b"safe_"));
+        assert!(glob_match(b"*_free", b"app_free"));
+        assert!(glob_match(b"?_*_?", b"a_bc_d"));
+        assert!(!glob_match(b"safe_*", b"unsafe_app"));
+        assert!(!glob_match(b"app", b"app_free"));
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let mut filter = ItemFilter::default();
+        assert!(filter.matches("anything"));
+
+        filter.include("safe_*");
+        filter.exclude("*_internal");
+        assert!(filter.matches("safe_app"));
+        assert!(!filter.matches("test_app"));
+        assert!(!filter.matches("safe_app_internal"));
+    }
+
+    #[test]
+    fn manifest() {
+        let manifest = unwrap!(
+            "[package.metadata.safe_bindgen]\n\
+             exclude = [\"internal_*\", \"test_*\"]"
+                .parse::<toml::Value>()
+        );
+
+        let mut filter = ItemFilter::default();
+        filter.extend_from_manifest(&manifest);
+        assert!(filter.matches("app_free"));
+        assert!(!filter.matches("test_app"));
+    }
+
+    #[test]
+    fn type_overrides() {
+        let manifest = unwrap!(
+            "[package.metadata.safe_bindgen.types.c]\n\
+             XorName = \"unsigned char[32]\""
+                .parse::<toml::Value>()
+        );
+
+        let mut overrides = TypeOverrides::default();
+        overrides.extend_from_manifest(&manifest);
+        overrides.insert("csharp", "routing::XorName", "byte[]");
+
+        let source = "pub struct Id { name: routing::XorName, len: usize }";
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+        let item = (*krate.module.items[0]).clone();
+
+        let field_types = |item: &ast::Item| match item.node {
+            ast::ItemKind::Struct(ref variants, _) => variants
+                .fields()
+                .iter()
+                .map(|field| ::syntax::print::pprust::ty_to_string(&field.ty))
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            field_types(&overrides.apply("c", item.clone())),
+            vec!["unsigned char[32]", "usize"]
+        );
+        assert_eq!(
+            field_types(&overrides.apply("csharp", item.clone())),
+            vec!["byte[]", "usize"]
+        );
+        assert_eq!(
+            field_types(&overrides.apply("java", item)),
+            vec!["routing::XorName", "usize"]
+        );
+    }
+}
@@ -2,6 +2,7 @@
 
 use super::intermediate::*;
 use super::Context;
+use docs::{self, LinkKind};
 use inflector::Inflector;
 use output::IndentedWriter;
 use std::fmt::Write;
@@ -29,6 +30,9 @@ pub fn emit_wrapper_function_decl(
     if let Some(callback) = extract_first_callback(&fun.inputs) {
         emit_task(writer, context, &callback.inputs);
         emit!(writer, " {}Async(", name.to_pascal_case());
+    } else if context.is_error_code(&fun.output) {
+        // Errors are thrown as exceptions.
+        emit!(writer, "void {}(", name.to_pascal_case());
     } else {
         emit_type(writer, context, &fun.output, Mode::WrapperFunc);
         emit!(writer, " {}(", name.to_pascal_case());
@@ -46,6 +50,7 @@ pub fn emit_wrapper_function(
 ) {
     let callback = extract_first_callback(&fun.inputs);
     let mut has_return = false;
+    let mut has_error_code = false;
     // TODO: make sure this doesn't conflict with any arguments.
     let return_name = "ret";
 
@@ -73,6 +78,15 @@ pub fn emit_wrapper_function(
     } else {
         match fun.output {
             Type::Unit => (),
+            ref ty if context.is_error_code(ty) => {
+                emit!(writer, "var {} = ", return_name);
+                has_error_code = true;
+            }
+            // The native string is converted (and freed) after the call.
+            Type::Utf8String => {
+                emit!(writer, "var {}Ptr = ", return_name);
+                has_return = true;
+            }
             _ => {
                 emit!(writer, "var {} = ", return_name);
                 has_return = true;
@@ -88,7 +102,7 @@ pub fn emit_wrapper_function(
         }
 
         if let Some(callback) = extract_callback(ty) {
-            emit!(writer, "On");
+            emit!(writer, "DelegateOn");
             emit_callback_wrapper_name(writer, callback);
         } else {
             let name = param_name(name, index);
@@ -121,6 +135,42 @@ pub fn emit_wrapper_function(
         }
     }
 
+    if let (None, &Type::Utf8String) = (callback, &fun.output) {
+        emit!(
+            writer,
+            "var {0} = Marshal.PtrToStringUTF8({0}Ptr);\n",
+            return_name
+        );
+
+        if let Some(ref free_with) = fun.free_with {
+            emit!(
+                writer,
+                "{}({}Ptr);\n",
+                extern_function_name(free_with),
+                return_name
+            );
+        }
+    }
+
+    if let (true, &Some(ref codes)) = (has_error_code, &context.error_codes) {
+        emit!(
+            writer,
+            "if ({} != {}.{}) {{\n",
+            return_name,
+            codes.name,
+            codes.success
+        );
+        writer.indent();
+        emit!(
+            writer,
+            "throw {}Exception.FromCode({});\n",
+            codes.name,
+            return_name
+        );
+        writer.unindent();
+        emit!(writer, "}}\n");
+    }
+
     if has_return {
         emit!(writer, "return {};\n", return_name);
     }
@@ -129,6 +179,76 @@ pub fn emit_wrapper_function(
     emit!(writer, "}}\n\n");
 }
 
+/// Emit the mock implementation of a wrapper function, counting the calls, recording the last
+/// arguments and returning a result which can be set by the tests.
+pub fn emit_mock_function(
+    writer: &mut IndentedWriter,
+    context: &Context,
+    name: &str,
+    fun: &Function,
+) {
+    let method = name.to_pascal_case();
+    let callback = extract_first_callback(&fun.inputs);
+
+    let mut params = Vec::new();
+    for &(ref name, ref ty) in &fun.inputs {
+        if is_user_data(name, ty) || extract_callback(ty).is_some() {
+            continue;
+        }
+
+        let (param, field) = if name.is_empty() {
+            let index = params.len();
+            (format!("arg{}", index), format!("Arg{}", index))
+        } else {
+            (name.to_camel_case(), name.to_pascal_case())
+        };
+        params.push((param, field, ty));
+    }
+
+    emit!(writer, "public int {}Calls;\n", method);
+    for &(_, ref field, ty) in &params {
+        emit!(writer, "public ");
+        emit_type(writer, context, ty, Mode::WrapperFunc);
+        emit!(writer, " {}{};\n", method, field);
+    }
+
+    let has_result = match callback {
+        Some(callback) => callback.inputs.len() > 2,
+        None => match fun.output {
+            Type::Unit => false,
+            ref ty => !context.is_error_code(ty),
+        },
+    };
+    if has_result {
+        emit!(writer, "public ");
+        match callback {
+            Some(callback) => emit_task_result_type(writer, context, &callback.inputs),
+            None => emit_type(writer, context, &fun.output, Mode::WrapperFunc),
+        }
+        emit!(writer, " {}Result;\n", method);
+    }
+    emit!(writer, "\n");
+
+    emit_wrapper_function_decl(writer, context, "public", name, fun);
+    emit!(writer, " {{\n");
+    writer.indent();
+
+    emit!(writer, "++{}Calls;\n", method);
+    for &(ref param, ref field, _) in &params {
+        emit!(writer, "{}{} = {};\n", method, field, param);
+    }
+
+    match (callback, has_result) {
+        (Some(_), true) => emit!(writer, "return Task.FromResult({}Result);\n", method),
+        (Some(_), false) => emit!(writer, "return Task.CompletedTask;\n"),
+        (None, true) => emit!(writer, "return {}Result;\n", method),
+        (None, false) => (),
+    }
+
+    writer.unindent();
+    emit!(writer, "}}\n\n");
+}
+
 pub fn emit_function_extern_decl(
     writer: &mut IndentedWriter,
     context: &Context,
@@ -143,13 +263,19 @@ pub fn emit_function_extern_decl(
         native_name
     );
     emit!(writer, "private static extern ");
-    emit_type(writer, context, &fun.output, Mode::ExternFunc);
+    if let Type::Utf8String = fun.output {
+        // Marshaled manually, so the string is freed by the right allocator.
+        emit!(writer, "IntPtr");
+    } else {
+        emit_type(writer, context, &fun.output, Mode::ExternFunc);
+    }
     emit!(writer, " {}(", name);
     emit_native_function_params(writer, context, &fun.inputs);
     emit!(writer, ");\n\n");
 }
 
 pub fn emit_callback_delegate(writer: &mut IndentedWriter, context: &Context, callback: &Function) {
+    emit!(writer, "[UnmanagedFunctionPointer(CallingConvention.Cdecl)]\n");
     emit!(writer, "private delegate void ");
     emit_callback_wrapper_name(writer, callback);
     emit!(writer, "(");
@@ -157,8 +283,13 @@ pub fn emit_callback_delegate(writer: &mut IndentedWriter, context: &Context, ca
     emit!(writer, ");\n\n");
 }
 
+/// Emit the static method invoked by the native code, and the delegate instance passed to it.
+///
+/// The method is marked `[MonoPInvokeCallback]` as required by AOT compilers (Xamarin.iOS and
+/// Unity IL2CPP). The delegate is kept in a static field, so it is never garbage collected while
+/// the native code holds it.
 pub fn emit_callback_wrapper(writer: &mut IndentedWriter, context: &Context, callback: &Function) {
-    emit!(writer, "#if __IOS__\n");
+    emit!(writer, "#if __IOS__ || ENABLE_IL2CPP\n");
     emit!(writer, "[MonoPInvokeCallback(typeof(");
     emit_callback_wrapper_name(writer, callback);
     emit!(writer, "))]\n");
@@ -192,6 +323,14 @@ pub fn emit_callback_wrapper(writer: &mut IndentedWriter, context: &Context, cal
 
     writer.unindent();
     emit!(writer, "}}\n\n");
+
+    emit!(writer, "private static readonly ");
+    emit_callback_wrapper_name(writer, callback);
+    emit!(writer, " DelegateOn");
+    emit_callback_wrapper_name(writer, callback);
+    emit!(writer, " = On");
+    emit_callback_wrapper_name(writer, callback);
+    emit!(writer, ";\n\n");
 }
 
 pub fn emit_callback_wrapper_name(writer: &mut IndentedWriter, callback: &Function) {
@@ -214,8 +353,19 @@ pub fn emit_const(writer: &mut IndentedWriter, context: &Context, name: &str, it
 }
 
 pub fn emit_enum(writer: &mut IndentedWriter, context: &Context, name: &str, item: &Enum) {
+    if item.flags.is_some() {
+        emit!(writer, "[Flags]\n");
+    }
+
     emit!(writer, "[PublicAPI]\n");
-    emit!(writer, "public enum {} {{\n", name);
+    emit!(writer, "public enum {}", name);
+
+    if let Some(ref ty) = item.flags {
+        emit!(writer, " : ");
+        emit_type(writer, context, ty, Mode::Generic);
+    }
+
+    emit!(writer, " {{\n");
     writer.indent();
 
     for variant in &item.variants {
@@ -232,6 +382,74 @@ pub fn emit_enum(writer: &mut IndentedWriter, context: &Context, name: &str, ite
     emit!(writer, "}}\n\n");
 }
 
+/// Emit the exception thrown for the error codes, and its subclasses for each of the codes.
+pub fn emit_error_codes_exceptions(writer: &mut IndentedWriter, codes: &ErrorCodes) {
+    let base = format!("{}Exception", codes.name);
+
+    emit!(writer, "[PublicAPI]\n");
+    emit!(writer, "public class {} : Exception {{\n", base);
+    writer.indent();
+
+    emit!(writer, "public {} Code {{ get; }}\n\n", codes.name);
+
+    emit!(
+        writer,
+        "public {}({} code) : base(code.ToString()) {{\n",
+        base,
+        codes.name
+    );
+    writer.indent();
+    emit!(writer, "Code = code;\n");
+    writer.unindent();
+    emit!(writer, "}}\n\n");
+
+    emit!(
+        writer,
+        "public static {} FromCode({} code) {{\n",
+        base,
+        codes.name
+    );
+    writer.indent();
+    emit!(writer, "switch (code) {{\n");
+    writer.indent();
+
+    for error in &codes.errors {
+        emit!(writer, "case {}.{}:\n", codes.name, error);
+        writer.indent();
+        emit!(writer, "return new {}Exception();\n", error);
+        writer.unindent();
+    }
+
+    emit!(writer, "default:\n");
+    writer.indent();
+    emit!(writer, "return new {}(code);\n", base);
+    writer.unindent();
+
+    writer.unindent();
+    emit!(writer, "}}\n");
+    writer.unindent();
+    emit!(writer, "}}\n");
+
+    writer.unindent();
+    emit!(writer, "}}\n\n");
+
+    for error in &codes.errors {
+        emit!(writer, "[PublicAPI]\n");
+        emit!(writer, "public class {}Exception : {} {{\n", error, base);
+        writer.indent();
+        emit!(
+            writer,
+            "public {}Exception() : base({}.{}) {{\n",
+            error,
+            codes.name,
+            error
+        );
+        emit!(writer, "}}\n");
+        writer.unindent();
+        emit!(writer, "}}\n\n");
+    }
+}
+
 pub fn emit_normal_struct(
     writer: &mut IndentedWriter,
     context: &Context,
@@ -239,6 +457,7 @@ pub fn emit_normal_struct(
     item: &Struct,
 ) {
     emit!(writer, "[PublicAPI]\n");
+    emit_struct_layout(writer, item);
     emit!(writer, "public struct {} {{\n", name);
     writer.indent();
 
@@ -257,6 +476,7 @@ pub fn emit_native_struct(
     name: &str,
     item: &Struct,
 ) {
+    emit_struct_layout(writer, item);
     emit!(writer, "internal struct {}Native {{\n", name);
     writer.indent();
 
@@ -375,6 +595,69 @@ pub fn emit_wrapper_struct(
     emit!(writer, "}}\n\n");
 }
 
+/// Emit a `SafeHandle` holding a pointer to the opaque type, freed with `free_fn` when released
+/// if the handle owns it.
+pub fn emit_safe_handle(writer: &mut IndentedWriter, context: &Context, name: &str, free_fn: &str) {
+    emit!(writer, "public sealed class {}Handle : SafeHandle {{\n", name);
+    writer.indent();
+
+    emit_dll_name(writer, context);
+
+    emit!(writer, "private {}Handle() : base(IntPtr.Zero, true) {{\n", name);
+    emit!(writer, "}}\n\n");
+
+    emit!(
+        writer,
+        "internal {}Handle(IntPtr handle, bool ownsHandle) : base(IntPtr.Zero, ownsHandle) {{\n",
+        name
+    );
+    writer.indent();
+    emit!(writer, "SetHandle(handle);\n");
+    writer.unindent();
+    emit!(writer, "}}\n\n");
+
+    emit!(writer, "public override bool IsInvalid => handle == IntPtr.Zero;\n\n");
+
+    emit!(writer, "protected override bool ReleaseHandle() {{\n");
+    writer.indent();
+    emit!(writer, "{}(handle);\n", extern_function_name(free_fn));
+    emit!(writer, "return true;\n");
+    writer.unindent();
+    emit!(writer, "}}\n\n");
+
+    emit!(
+        writer,
+        "[DllImport(DllName, EntryPoint = \"{}\")]\n",
+        free_fn
+    );
+    emit!(
+        writer,
+        "private static extern void {}(IntPtr handle);\n",
+        extern_function_name(free_fn)
+    );
+
+    writer.unindent();
+    emit!(writer, "}}\n");
+}
+
+/// Emit the constant holding the name of the native library, used by the `[DllImport]`
+/// attributes.
+pub fn emit_dll_name(writer: &mut IndentedWriter, context: &Context) {
+    for (index, &(ref symbol, ref name)) in context.platform_lib_names.iter().enumerate() {
+        let directive = if index == 0 { "#if" } else { "#elif" };
+        emit!(writer, "{} {}\n", directive, symbol);
+        emit!(writer, "private const string DllName = \"{}\";\n", name);
+    }
+
+    if context.platform_lib_names.is_empty() {
+        emit!(writer, "private const string DllName = \"{}\";\n\n", context.lib_name);
+    } else {
+        emit!(writer, "#else\n");
+        emit!(writer, "private const string DllName = \"{}\";\n", context.lib_name);
+        emit!(writer, "#endif\n\n");
+    }
+}
+
 pub fn emit_utilities(writer: &mut IndentedWriter, context: &Context) {
     let content = include_str!("../../resources/csharp/Utils.cs.template");
     let content = content.replace("@Namespace", &context.utils_section.namespace);
@@ -383,8 +666,30 @@ pub fn emit_utilities(writer: &mut IndentedWriter, context: &Context) {
     emit!(writer, "{}", content);
 }
 
+pub fn emit_resolver(writer: &mut IndentedWriter, context: &Context) {
+    let content = include_str!("../../resources/csharp/NativeLibraryResolver.cs.template");
+    let content = content.replace("@Namespace", &context.resolver_section.namespace);
+    let content = content.replace("@Class", &context.resolver_section.class);
+    let content = content.replace("@LibName", &context.lib_name);
+
+    emit!(writer, "{}", content);
+}
+
 pub fn emit_docs(writer: &mut IndentedWriter, context: &Context, docs: &str) {
     if context.preserve_comments {
+        let docs = context.doc_sections.apply(docs);
+        let docs = docs::replace_links(&docs, |name, kind| {
+            let target = match kind {
+                LinkKind::Function => {
+                    format!("{}.{}", context.functions_section.class, name.to_pascal_case())
+                }
+                LinkKind::Constant => {
+                    format!("{}.{}", context.consts_section.class, name.to_pascal_case())
+                }
+                LinkKind::Type => name.to_string(),
+            };
+            format!("<see cref=\"{}\"/>", target)
+        });
         emit!(writer, "{}", docs);
     }
 }
@@ -412,7 +717,15 @@ fn emit_task_generic_args(
     }
 
     emit!(writer, "<");
+    emit_task_result_type(writer, context, params);
+    emit!(writer, ">");
+}
 
+fn emit_task_result_type(
+    writer: &mut IndentedWriter,
+    context: &Context,
+    params: &[(String, Type)],
+) {
     if params.len() > 3 {
         emit!(writer, "(");
     }
@@ -428,8 +741,6 @@ fn emit_task_generic_args(
     if params.len() > 3 {
         emit!(writer, ")");
     }
-
-    emit!(writer, ">");
 }
 
 fn emit_const_value(
@@ -444,7 +755,7 @@ fn emit_const_value(
         ConstValue::Char(value) => emit!(writer, "{:?}", value),
         ConstValue::Int(value) => emit!(writer, "{}", value),
         ConstValue::Float(ref value) => emit!(writer, "{}", value),
-        ConstValue::String(ref value) => emit!(writer, "{:?}", value),
+        ConstValue::String(ref value) => emit!(writer, "{}", string_literal(value)),
         ConstValue::Array(ref elements) => {
             if let Some(&Type::Array(ref ty, ..)) = ty {
                 emit!(writer, "new ");
@@ -478,7 +789,34 @@ fn emit_const_value(
 
             emit!(writer, " }}");
         }
+        ConstValue::Const(ref name) => emit_const_use(writer, context, name),
+        ConstValue::Binary(ref lhs, op, ref rhs) => {
+            emit!(writer, "(");
+            emit_const_value(writer, context, None, lhs);
+            emit!(writer, " {} ", op);
+            emit_const_value(writer, context, None, rhs);
+            emit!(writer, ")");
+        }
+    }
+}
+
+/// Render a C# string literal, escaping the quotes, backslashes, and the control and non-ASCII
+/// characters (as UTF-16 escapes).
+fn string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for unit in value.encode_utf16() {
+        match unit {
+            0x22 => literal.push_str("\\\""),
+            0x5c => literal.push_str("\\\\"),
+            0x0a => literal.push_str("\\n"),
+            0x0d => literal.push_str("\\r"),
+            0x09 => literal.push_str("\\t"),
+            0x20...0x7e => literal.push(unit as u8 as char),
+            _ => literal.push_str(&format!("\\u{:04x}", unit)),
+        }
     }
+    literal.push('"');
+    literal
 }
 
 fn emit_const_use(writer: &mut IndentedWriter, context: &Context, name: &str) {
@@ -490,6 +828,13 @@ fn emit_const_use(writer: &mut IndentedWriter, context: &Context, name: &str) {
     );
 }
 
+/// Emit the `[StructLayout]` attribute of a packed struct.
+fn emit_struct_layout(writer: &mut IndentedWriter, item: &Struct) {
+    if let Some(pack) = item.pack {
+        emit!(writer, "[StructLayout(LayoutKind.Sequential, Pack = {})]\n", pack);
+    }
+}
+
 fn emit_struct_field(
     writer: &mut IndentedWriter,
     context: &Context,
@@ -639,8 +984,8 @@ fn emit_marshal_as(
 fn unmanaged_type(ty: &Type, field: bool) -> Option<&str> {
     match *ty {
         Type::Bool => Some("U1"),
-        // TODO: consider marshaling as "LPUTF8Str", is possible and useful.
         Type::String => Some("LPStr"),
+        Type::Utf8String => Some("LPUTF8Str"),
         Type::Array(_, ArraySize::Dynamic) => Some("LPArray"),
         Type::Array(..) if field => Some("ByValArray"),
         Type::Array(..) => Some("LPArray"),
@@ -738,11 +1083,17 @@ fn emit_type(writer: &mut IndentedWriter, context: &Context, ty: &Type, mode: Mo
                 emit!(writer, "{}", LEN_TYPE)
             }
         }
-        Type::String => emit!(writer, "string"),
+        Type::String | Type::Utf8String => emit!(writer, "string"),
         Type::Pointer(ref ty) => match **ty {
             Type::Array(ref ty, ref size) => emit_array(writer, context, ty, size, mode),
             Type::User(ref name) => {
-                if mode == Mode::Callback
+                if context.has_safe_handle(name)
+                    && (mode == Mode::WrapperFunc
+                        || mode == Mode::ExternFunc
+                        || mode == Mode::Generic)
+                {
+                    emit!(writer, "{}Handle", name)
+                } else if mode == Mode::Callback
                     || mode == Mode::Const
                     || mode == Mode::NormalStruct
                     || mode == Mode::WrapperStruct
@@ -821,6 +1172,10 @@ fn emit_args(
             Type::Array(ref ty, ref size) => emit_array_use(writer, context, ty, size, &name),
             Type::Pointer(ref ty) => match **ty {
                 Type::Array(ref ty, ref size) => emit_array_use(writer, context, ty, size, &name),
+                // The pointers passed to callbacks are borrowed, so they must not be freed.
+                Type::User(ref type_name) if context.has_safe_handle(type_name) => {
+                    emit!(writer, "new {}Handle({}, false)", type_name, name);
+                }
                 Type::User(ref type_name) if context.is_native_name(type_name) => {
                     emit!(writer, "new {}(", type_name);
                     emit_pointer_use(writer, context, ty, &name, mode);
@@ -926,7 +1281,7 @@ fn emit_delegate_base_part_name(writer: &mut IndentedWriter, ty: &Type) {
         Type::U16 => emit!(writer, "UShort"),
         Type::U32 => emit!(writer, "UInt"),
         Type::U64 | Type::USize => emit!(writer, "ULong"),
-        Type::String => emit!(writer, "String"),
+        Type::String | Type::Utf8String => emit!(writer, "String"),
         Type::Pointer(ref ty) => emit_delegate_base_part_name(writer, ty),
         Type::Array(ref ty, ref size) => {
             emit_delegate_base_part_name(writer, ty);
@@ -1,7 +1,9 @@
 //! Types and utilities for the intermediate representation between the rust code
 //! and the target language code.
 
+use bitflags::BitFlags;
 use common;
+use lang_c::{const_int, is_arithmetic};
 use std::collections::BTreeMap;
 use syntax::ast;
 use syntax::print::pprust;
@@ -36,6 +38,8 @@ pub enum Type {
     U64,
     USize,
     String,
+    /// `c_char` pointer of a function marked `#[cheddar(utf8)]`.
+    Utf8String,
     Pointer(Box<Type>),
     Array(Box<Type>, ArraySize),
     Function(Box<Function>),
@@ -66,6 +70,8 @@ pub enum ArraySize {
 pub struct Function {
     pub inputs: Vec<(String, Type)>,
     pub output: Type,
+    /// Native function freeing the returned pointer.
+    pub free_with: Option<String>,
 }
 
 pub struct Snippet<T> {
@@ -87,10 +93,16 @@ pub enum ConstValue {
     String(String),
     Array(Vec<ConstValue>),
     Struct(String, BTreeMap<String, ConstValue>),
+    /// Another constant.
+    Const(String),
+    /// Arithmetic on other constants (e.g. `MAX_LEN * 2`).
+    Binary(Box<ConstValue>, &'static str, Box<ConstValue>),
 }
 
 pub struct Struct {
     pub fields: Vec<StructField>,
+    /// Maximum alignment of the fields, if the struct is `#[repr(C, packed)]`.
+    pub pack: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -103,6 +115,17 @@ pub struct StructField {
 
 pub struct Enum {
     pub variants: Vec<EnumVariant>,
+    /// Underlying integer type of a `bitflags!` flag set, emitted as a `[Flags]` enum.
+    pub flags: Option<Type>,
+}
+
+/// Enum holding the error codes returned by the library.
+pub struct ErrorCodes {
+    pub name: String,
+    /// Variant meaning success (zero).
+    pub success: String,
+    /// Variants meaning failure.
+    pub errors: Vec<String>,
 }
 
 pub struct EnumVariant {
@@ -120,17 +143,34 @@ pub fn transform_type(input: &ast::Ty) -> Option<Type> {
             transform_reference(lifetime, ty)
         }
         ast::TyKind::BareFn(ref bare_fn) => {
-            transform_function(&*bare_fn.decl).map(|fun| Type::Function(Box::new(fun)))
+            transform_function(&*bare_fn.decl, &[]).map(|fun| Type::Function(Box::new(fun)))
         }
         _ => None,
     }
 }
 
-pub fn transform_function(decl: &ast::FnDecl) -> Option<Function> {
+/// Transform a function declaration, applying its `#[cheddar(..)]` attributes:
+///
+/// - the pointer/length parameter pairs declared with `slice(..)` are turned into arrays like the
+///   `foo`/`foo_len` pairs,
+/// - the strings of functions marked `utf8` are UTF-8 encoded, and the returned one is freed with
+///   the `free_with` function.
+pub fn transform_function(decl: &ast::FnDecl, attrs: &[ast::Attribute]) -> Option<Function> {
+    let slices = common::retrieve_slices(attrs);
+    let (utf8, free_with) = common::parse_attr(
+        attrs,
+        |attr| common::has_cheddar_flag(attr, "utf8"),
+        common::retrieve_free_with,
+    );
+    let utf8_string = |ty: Type| match ty {
+        Type::String if utf8 => Type::Utf8String,
+        ty => ty,
+    };
+
     let output = match decl.output {
         ast::FunctionRetTy::Default(..) => Type::Unit,
         ast::FunctionRetTy::Ty(ref ty) => match transform_type(ty) {
-            Some(ty) => ty,
+            Some(ty) => utf8_string(ty),
             None => return None,
         },
     };
@@ -157,8 +197,9 @@ pub fn transform_function(decl: &ast::FnDecl) -> Option<Function> {
 
         if let Some(one) = one {
             if let Some(two) = two {
+                let explicit = common::is_slice(&slices, &one.0, &two.0);
                 if let Some(new_one) =
-                    transform_ptr_and_len_to_array(&one.0, &one.1, &two.0, &two.1)
+                    transform_ptr_and_len_to_array(&one.0, &one.1, &two.0, &two.1, explicit)
                 {
                     inputs.push(new_one);
                 } else {
@@ -174,7 +215,20 @@ pub fn transform_function(decl: &ast::FnDecl) -> Option<Function> {
         }
     }
 
-    Some(Function { inputs, output })
+    let inputs = inputs
+        .into_iter()
+        .map(|(name, ty)| (name, utf8_string(ty)))
+        .collect();
+
+    Some(Function {
+        inputs,
+        output,
+        free_with: if free_with.is_empty() {
+            None
+        } else {
+            Some(free_with)
+        },
+    })
 }
 
 pub fn transform_function_param(arg: &ast::Arg) -> Option<(String, Type)> {
@@ -209,7 +263,31 @@ pub fn transform_enum(variants: &[ast::Variant]) -> Option<Enum> {
         })
         .collect();
 
-    variants.map(|variants| Enum { variants })
+    variants.map(|variants| Enum {
+        variants,
+        flags: None,
+    })
+}
+
+pub fn transform_bitflags(flags: &BitFlags) -> Option<Enum> {
+    let ty = try_opt!(transform_type(&flags.ty));
+    let variants = flags
+        .flags
+        .iter()
+        .map(|flag| {
+            let (_, docs) = common::parse_attr(&flag.attrs, |_| true, retrieve_docstring);
+            EnumVariant {
+                docs,
+                name: flag.name.clone(),
+                value: Some(flag.value),
+            }
+        })
+        .collect();
+
+    Some(Enum {
+        variants,
+        flags: Some(ty),
+    })
 }
 
 pub fn transform_struct(fields: &[ast::StructField]) -> Option<Struct> {
@@ -231,6 +309,7 @@ pub fn transform_struct(fields: &[ast::StructField]) -> Option<Struct> {
 
     fields.map(|fields| Struct {
         fields: process_struct_fields(fields),
+        pack: None,
     })
 }
 
@@ -289,6 +368,20 @@ fn transform_const_value(expr: &ast::Expr) -> Option<ConstValue> {
         ast::ExprKind::Struct(ref path, ref fields, None) => transform_const_struct(path, fields),
         ast::ExprKind::AddrOf(_, ref expr) => transform_const_value(expr),
         ast::ExprKind::Cast(ref expr, ref ty) => transform_const_cast(expr, ty),
+        ast::ExprKind::Binary(op, ref lhs, ref rhs) if is_arithmetic(op.node) => {
+            match const_int(expr) {
+                Some(value) => Some(ConstValue::Int(value)),
+                None => Some(ConstValue::Binary(
+                    Box::new(try_opt!(transform_const_value(lhs))),
+                    op.node.to_string(),
+                    Box::new(try_opt!(transform_const_value(rhs))),
+                )),
+            }
+        }
+        ast::ExprKind::Paren(ref expr) => transform_const_value(expr),
+        ast::ExprKind::Path(None, ref path) if path.segments.len() == 1 => Some(
+            ConstValue::Const(path.segments[0].identifier.name.as_str().to_string()),
+        ),
         _ => None,
     }
 }
@@ -386,6 +479,7 @@ fn transform_ptr_and_len_to_array(
     ptr_ty: &Type,
     len_name: &str,
     len_ty: &Type,
+    explicit: bool,
 ) -> Option<(String, Type)> {
     let elem_ty = if let Type::Pointer(ref ty) = *ptr_ty {
         &**ty
@@ -398,6 +492,13 @@ fn transform_ptr_and_len_to_array(
         return None;
     }
 
+    if explicit {
+        return Some((
+            ptr_name.to_string(),
+            Type::Array(Box::new(elem_ty.clone()), ArraySize::Dynamic),
+        ));
+    }
+
     // Matches "foo_ptr"/"foo_len" and "foo"/"foo_len"
     let ptr_index = if ptr_name.ends_with("_ptr") {
         ptr_name.len() - "_ptr".len()
@@ -501,8 +602,13 @@ fn process_struct_fields(mut input: Vec<StructField>) -> Vec<StructField> {
     loop {
         if let Some(field1) = iter.next() {
             if let Some((name, ty)) =
-                transform_ptr_and_len_to_array(&field0.name, &field0.ty, &field1.name, &field1.ty)
-            {
+                transform_ptr_and_len_to_array(
+                    &field0.name,
+                    &field0.ty,
+                    &field1.name,
+                    &field1.ty,
+                    false,
+                ) {
                 output.push(StructField {
                     docs: String::new(),
                     name,
@@ -6,7 +6,9 @@ mod tests;
 
 use self::emit::*;
 use self::intermediate::*;
+use bitflags::BitFlags;
 use common::{self, FilterMode, Lang, Outputs};
+use docs::{DocSections, SectionPolicy};
 use inflector::Inflector;
 use output::IndentedWriter;
 use std::collections::btree_map::Entry;
@@ -27,6 +29,8 @@ pub struct LangCSharp {
     consts_enabled: bool,
     types_enabled: bool,
     utils_enabled: bool,
+    resolver_enabled: bool,
+    mock_enabled: bool,
     context: Context,
     custom_consts: Vec<String>,
     consts: Vec<Snippet<Const>>,
@@ -34,18 +38,30 @@ pub struct LangCSharp {
     structs: Vec<Snippet<Struct>>,
     functions: Vec<Snippet<Function>>,
     aliases: HashMap<String, Type>,
+    opaque_free_fns: HashMap<String, String>,
+    detect_free_fns: bool,
 }
 
 pub struct Context {
     lib_name: String,
+    /// Names of the native library on the platforms given by a preprocessor symbol.
+    platform_lib_names: Vec<(String, String)>,
     interface_section: Section,
     functions_section: Section,
     consts_section: Section,
     types_section: Section,
     utils_section: Section,
+    resolver_section: Section,
+    mock_section: Section,
     preserve_comments: bool,
+    /// Rewriting of the Rust-specific doc sections and code blocks.
+    doc_sections: DocSections,
     opaque_types: HashSet<String>,
     native_types: HashSet<String>,
+    /// Opaque types wrapped in a `SafeHandle`, and the functions freeing them.
+    safe_handles: BTreeMap<String, String>,
+    /// Error codes thrown as exceptions by the wrapper functions.
+    error_codes: Option<ErrorCodes>,
 }
 
 impl Context {
@@ -53,6 +69,17 @@ impl Context {
         self.opaque_types.contains(name)
     }
 
+    pub fn has_safe_handle(&self, name: &str) -> bool {
+        self.safe_handles.contains_key(name)
+    }
+
+    pub fn is_error_code(&self, ty: &Type) -> bool {
+        match (ty, &self.error_codes) {
+            (&Type::User(ref name), &Some(ref codes)) => *name == codes.name,
+            _ => false,
+        }
+    }
+
     pub fn is_native_name(&self, name: &str) -> bool {
         self.native_types.contains(name)
     }
@@ -96,16 +123,28 @@ impl LangCSharp {
             consts_enabled: true,
             types_enabled: true,
             utils_enabled: true,
+            resolver_enabled: false,
+            mock_enabled: false,
             context: Context {
                 lib_name: "backend".to_string(),
+                platform_lib_names: vec![("__IOS__".to_string(), "__Internal".to_string())],
                 interface_section: Section::new("IBackend.cs", "Backend", "IBackend"),
                 functions_section: Section::new("Backend.cs", "Backend", "Backend"),
                 consts_section: Section::new("Constants.cs", "Backend", "Constants"),
                 types_section: Section::new("Types.cs", "Backend", ""),
                 utils_section: Section::new("Utils.cs", "Backend", "Utils"),
+                resolver_section: Section::new(
+                    "NativeLibraryResolver.cs",
+                    "Backend",
+                    "NativeLibraryResolver",
+                ),
+                mock_section: Section::new("Mock.cs", "Backend", "MockBackend"),
                 preserve_comments: false,
+                doc_sections: DocSections::default(),
                 opaque_types: Default::default(),
                 native_types: Default::default(),
+                safe_handles: Default::default(),
+                error_codes: None,
             },
             custom_consts: Vec::new(),
             consts: Vec::new(),
@@ -113,6 +152,8 @@ impl LangCSharp {
             structs: Vec::new(),
             functions: Vec::new(),
             aliases: Default::default(),
+            opaque_free_fns: Default::default(),
+            detect_free_fns: false,
         }
     }
 
@@ -121,6 +162,42 @@ impl LangCSharp {
         self.context.lib_name = name.into();
     }
 
+    /// Set the name of the native library on the platform defined by the given preprocessor
+    /// symbol (e.g. `__ANDROID__`), overriding the one set with `set_lib_name`.
+    ///
+    /// By default, the library is linked statically on iOS (`__IOS__` maps to `__Internal`).
+    pub fn set_platform_lib_name<S: Into<String>, N: Into<String>>(&mut self, symbol: S, name: N) {
+        let symbol = symbol.into();
+        let name = name.into();
+
+        let names = &mut self.context.platform_lib_names;
+        match names.iter().position(|&(ref existing, _)| *existing == symbol) {
+            Some(index) => names[index].1 = name,
+            None => names.push((symbol, name)),
+        }
+    }
+
+    /// Remove the platform specific names of the native library (including the iOS default).
+    pub fn reset_platform_lib_names(&mut self) {
+        self.context.platform_lib_names.clear();
+    }
+
+    /// Emit the doc comments of the items (disabled by default).
+    pub fn set_preserve_comments(&mut self, enabled: bool) {
+        self.context.preserve_comments = enabled;
+    }
+
+    /// Keep, drop or rename the doc sections with the given heading, e.g. dropping `"Panics"`.
+    /// Sections are kept by default.
+    pub fn set_doc_section<T: Into<String>>(&mut self, heading: T, policy: SectionPolicy) {
+        self.context.doc_sections.set(heading, policy);
+    }
+
+    /// Remove the Rust code blocks from the docs (disabled by default).
+    pub fn set_drop_rust_code(&mut self, enabled: bool) {
+        self.context.doc_sections.drop_rust_code(enabled);
+    }
+
     /// Set path, namespace and interface name of the interface section.
     pub fn set_interface_section<P, N, C>(&mut self, path: P, namespace: N, interface: C)
     where
@@ -185,11 +262,66 @@ impl LangCSharp {
         self.context.utils_section = Section::new(path, namespace, class)
     }
 
+    /// Enable/disable generation of the native library resolver, which loads the library from
+    /// the platform specific `runtimes/{rid}/native` directories of NuGet packages (.NET Core 3
+    /// and newer).
+    pub fn set_resolver_enabled(&mut self, enabled: bool) {
+        self.resolver_enabled = enabled;
+    }
+
+    /// Set path, namespace and class name of the native library resolver.
+    pub fn set_resolver_section<P, N, C>(&mut self, path: P, namespace: N, class: C)
+    where
+        P: Into<String>,
+        N: Into<String>,
+        C: Into<String>,
+    {
+        self.context.resolver_section = Section::new(path, namespace, class)
+    }
+
+    /// Enable/disable generation of a mock implementation of the interface, counting the calls,
+    /// recording the last arguments and returning configurable results, so the consumers of the
+    /// API can be unit tested without the native library.
+    pub fn set_mock_enabled(&mut self, enabled: bool) {
+        self.mock_enabled = enabled;
+    }
+
+    /// Set path, namespace and class name of the mock implementation.
+    pub fn set_mock_section<P, N, C>(&mut self, path: P, namespace: N, class: C)
+    where
+        P: Into<String>,
+        N: Into<String>,
+        C: Into<String>,
+    {
+        self.context.mock_section = Section::new(path, namespace, class)
+    }
+
     /// Add definition of opaque type (type represented by an opaque pointer).
     pub fn add_opaque_type<T: Into<String>>(&mut self, name: T) {
         let _ = self.context.opaque_types.insert(name.into());
     }
 
+    /// Add definition of opaque type freed by the given function.
+    ///
+    /// The pointers to the type returned by the native functions are wrapped in a `SafeHandle`
+    /// which calls the function when released. The ones passed to callbacks are borrowed, so
+    /// their handles don't free them.
+    pub fn add_opaque_type_with_free<T: Into<String>, F: Into<String>>(
+        &mut self,
+        name: T,
+        free_fn: F,
+    ) {
+        let name = name.into();
+        let _ = self.opaque_free_fns.insert(name.clone(), free_fn.into());
+        self.add_opaque_type(name);
+    }
+
+    /// Enable/disable wrapping the opaque types added with `add_opaque_type` in a `SafeHandle` too,
+    /// if there is a `{type}_free` function taking them. Disabled by default.
+    pub fn set_detect_free_fns(&mut self, enabled: bool) {
+        self.detect_free_fns = enabled;
+    }
+
     /// Add constant definition.
     pub fn add_const<T: Display>(&mut self, ty: &str, name: &str, value: T) {
         self.custom_consts.push(format!(
@@ -272,6 +404,31 @@ impl LangCSharp {
         }
     }
 
+    fn resolve_safe_handles(&mut self) {
+        for name in &self.context.opaque_types {
+            let free_fn = match self.opaque_free_fns.get(name) {
+                Some(free_fn) => free_fn.clone(),
+                None if self.detect_free_fns => {
+                    let free_fn = format!("{}_free", name.to_snake_case());
+                    let detected = self.functions.iter().any(|snippet| {
+                        snippet.name == free_fn && is_free_function(&snippet.item, name)
+                    });
+                    if !detected {
+                        continue;
+                    }
+                    free_fn
+                }
+                None => continue,
+            };
+
+            let _ = self.context.safe_handles.insert(name.clone(), free_fn);
+        }
+
+        // The handles are freed when released, so the free functions are not exposed.
+        let free_fns: HashSet<_> = self.context.safe_handles.values().cloned().collect();
+        self.functions.retain(|snippet| !free_fns.contains(&snippet.name));
+    }
+
     fn is_ignored(&self, ident: &str) -> bool {
         match self.filter_mode {
             FilterMode::Blacklist => self.filter.contains(ident),
@@ -282,6 +439,42 @@ impl LangCSharp {
     fn is_interface_function(&self, name: &str, item: &Function) -> bool {
         !self.wrapper_function_blacklist.contains(name) && num_callbacks(&item.inputs) <= 1
     }
+
+    /// Generate the mock implementation of the interface.
+    fn mock(&self, functions: &[Snippet<Function>]) -> String {
+        let mut writer = IndentedWriter::new(INDENT_WIDTH);
+
+        emit!(writer, "using System;\n");
+        emit!(writer, "using System.Collections.Generic;\n");
+        emit!(writer, "using System.Threading.Tasks;\n");
+        if self.context.mock_section.namespace != self.context.interface_section.namespace {
+            emit!(writer, "using {};\n", self.context.interface_section.namespace);
+        }
+        emit!(writer, "\n");
+
+        emit!(writer, "namespace {} {{\n", self.context.mock_section.namespace);
+        writer.indent();
+
+        emit!(
+            writer,
+            "public class {} : {} {{\n",
+            self.context.mock_section.class,
+            self.context.interface_section.class
+        );
+        writer.indent();
+
+        for snippet in functions {
+            emit_mock_function(&mut writer, &self.context, &snippet.name, &snippet.item);
+        }
+
+        writer.unindent();
+        emit!(writer, "}}\n");
+
+        writer.unindent();
+        emit!(writer, "}}\n");
+
+        writer.into_inner()
+    }
 }
 
 impl Default for LangCSharp {
@@ -291,6 +484,26 @@ impl Default for LangCSharp {
 }
 
 impl Lang for LangCSharp {
+    fn name(&self) -> &'static str {
+        "csharp"
+    }
+
+    fn crate_version(&mut self, _name: &str, version: &str) {
+        let (major, minor, patch) = common::version_parts(version);
+
+        self.add_const("string", "Version", format!("\"{}\"", version));
+        self.add_const("ulong", "VersionMajor", major);
+        self.add_const("ulong", "VersionMinor", minor);
+        self.add_const("ulong", "VersionPatch", patch);
+    }
+
+    fn add_handle(&mut self, name: &str, free_fn: Option<&str>) {
+        match free_fn {
+            Some(free_fn) => self.add_opaque_type_with_free(name, free_fn),
+            None => self.add_opaque_type(name),
+        }
+    }
+
     fn parse_ty(
         &mut self,
         item: &ast::Item,
@@ -304,7 +517,7 @@ impl Lang for LangCSharp {
 
         if let ast::ItemKind::Ty(ref ty, ref generics) = item.node {
             if generics.is_parameterized() {
-                println!(
+                warn!(
                     "parameterized type aliases not supported ({}). Skipping.",
                     name,
                 );
@@ -318,6 +531,7 @@ impl Lang for LangCSharp {
                     "bindgen can not handle the type `{}`",
                     pprust::ty_to_string(ty)
                 ),
+                help: None,
             })?;
 
             self.aliases.insert(name.to_string(), ty);
@@ -347,6 +561,7 @@ impl Lang for LangCSharp {
                     "bindgen can not handle constant {}",
                     pprust::item_to_string(item)
                 ),
+                help: None,
             })?;
             let name = name.to_string();
 
@@ -380,19 +595,78 @@ impl Lang for LangCSharp {
                 return Err(unsupported_generics_error(item, "enums"));
             }
 
-            let item = transform_enum(variants).ok_or_else(|| Error {
+            let error = || Error {
                 level: Level::Error,
                 span: Some(item.span),
                 message: format!(
                     "bindgen can not handle enum {}",
                     pprust::item_to_string(item)
                 ),
-            })?;
+                help: None,
+            };
+            let enum_item = transform_enum(variants).ok_or_else(&error)?;
             let name = name.to_string();
 
-            self.enums.push(Snippet { docs, name, item });
+            if item.attrs.iter().any(|attr| common::has_cheddar_flag(attr, "error_codes")) {
+                let values = common::enum_discriminants(variants).ok_or_else(&error)?;
+                let success = values
+                    .iter()
+                    .find(|&&(_, value)| value == 0)
+                    .map(|&(ref name, _)| name.clone())
+                    .ok_or_else(|| Error {
+                        level: Level::Error,
+                        span: Some(item.span),
+                        message: format!("error codes `{}` have no zero (success) variant", name),
+                        help: None,
+                    })?;
+                let errors = values
+                    .into_iter()
+                    .filter(|&(_, value)| value != 0)
+                    .map(|(name, _)| name)
+                    .collect();
+
+                self.context.error_codes = Some(ErrorCodes {
+                    name: name.clone(),
+                    success,
+                    errors,
+                });
+            }
+
+            self.enums.push(Snippet {
+                docs,
+                name,
+                item: enum_item,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn parse_bitflags(
+        &mut self,
+        item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        if self.is_ignored(&flags.name) {
+            return Ok(());
         }
 
+        let (_, docs) = common::parse_attr(&flags.attrs, |_| true, retrieve_docstring);
+        let enum_item = transform_bitflags(flags).ok_or_else(|| Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: format!("bindgen can not handle bitflags {}", flags.name),
+            help: None,
+        })?;
+
+        self.enums.push(Snippet {
+            docs,
+            name: flags.name.clone(),
+            item: enum_item,
+        });
+
         Ok(())
     }
 
@@ -425,17 +699,33 @@ impl Lang for LangCSharp {
                     level: Level::Error,
                     span: Some(item.span),
                     message: format!("bindgen can not handle unit or tuple structs ({})", name),
+                    help: None,
                 });
             }
 
-            let item = transform_struct(variants.fields()).ok_or_else(|| Error {
+            let layout = common::repr_layout(&item.attrs);
+            if let Some(align) = layout.align {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: format!(
+                        "bindgen can not express `#[repr(align({}))]` in C# ({})",
+                        align, name
+                    ),
+                    help: None,
+                });
+            }
+
+            let mut item = transform_struct(variants.fields()).ok_or_else(|| Error {
                 level: Level::Error,
                 span: Some(item.span),
                 message: format!(
                     "bindgen can not handle struct {}",
                     pprust::item_to_string(item)
                 ),
+                help: None,
             })?;
+            item.pack = layout.packed;
             let name = name.to_string();
             self.structs.push(Snippet { docs, name, item });
             self.resolve_native_types();
@@ -455,6 +745,9 @@ impl Lang for LangCSharp {
             return Ok(());
         }
 
+        let renamed = common::rename_user_data(item);
+        let item = renamed.as_ref().unwrap_or(item);
+
         let (no_mangle, docs) =
             common::parse_attr(&item.attrs, common::check_no_mangle, retrieve_docstring);
 
@@ -474,7 +767,7 @@ impl Lang for LangCSharp {
                 return Err(unsupported_generics_error(item, "extern functions"));
             }
 
-            let function = transform_function(fn_decl).ok_or_else(|| {
+            let function = transform_function(fn_decl, &item.attrs).ok_or_else(|| {
                 let string =
                     pprust::fun_to_string(fn_decl, unsafety, constness.node, item.ident, generics);
 
@@ -482,6 +775,7 @@ impl Lang for LangCSharp {
                     level: Level::Error,
                     span: Some(item.span),
                     message: format!("bindgen can not handle function {}", string),
+                    help: None,
                 }
             })?;
 
@@ -497,6 +791,7 @@ impl Lang for LangCSharp {
 
     fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
         self.resolve_aliases();
+        self.resolve_safe_handles();
 
         if !self.functions.is_empty() {
             // Functions
@@ -524,15 +819,7 @@ impl Lang for LangCSharp {
 
             // Define constant with the native library name, to be used in
             // the [DllImport] attributes.
-            emit!(writer, "#if __IOS__\n");
-            emit!(writer, "private const string DllName = \"__Internal\";\n");
-            emit!(writer, "#else\n");
-            emit!(
-                writer,
-                "private const string DllName = \"{}\";\n",
-                self.context.lib_name
-            );
-            emit!(writer, "#endif\n\n");
+            emit_dll_name(&mut writer, &self.context);
 
             for snippet in &self.functions {
                 emit_docs(&mut writer, &self.context, &snippet.docs);
@@ -559,6 +846,12 @@ impl Lang for LangCSharp {
             writer.unindent();
             emit!(writer, "}}\n");
 
+            // Safe handles of the opaque types.
+            for (name, free_fn) in &self.context.safe_handles {
+                emit!(writer, "\n");
+                emit_safe_handle(&mut writer, &self.context, name, free_fn);
+            }
+
             writer.unindent();
             emit!(writer, "}}\n");
 
@@ -569,12 +862,12 @@ impl Lang for LangCSharp {
 
             // Interface
             let functions: Vec<_> = mem::replace(&mut self.functions, Vec::new());
-            let mut functions = functions
+            let functions: Vec<_> = functions
                 .into_iter()
                 .filter(|snippet| self.is_interface_function(&snippet.name, &snippet.item))
-                .peekable();
+                .collect();
 
-            if functions.peek().is_some() {
+            if !functions.is_empty() {
                 let mut writer = IndentedWriter::new(INDENT_WIDTH);
 
                 emit!(writer, "using System;\n");
@@ -594,17 +887,15 @@ impl Lang for LangCSharp {
                 );
                 writer.indent();
 
-                for snippet in functions {
-                    if num_callbacks(&snippet.item.inputs) <= 1 {
-                        emit_wrapper_function_decl(
-                            &mut writer,
-                            &self.context,
-                            "",
-                            &snippet.name,
-                            &snippet.item,
-                        );
-                        emit!(writer, ";\n");
-                    }
+                for snippet in &functions {
+                    emit_wrapper_function_decl(
+                        &mut writer,
+                        &self.context,
+                        "",
+                        &snippet.name,
+                        &snippet.item,
+                    );
+                    emit!(writer, ";\n");
                 }
 
                 writer.unindent();
@@ -617,6 +908,13 @@ impl Lang for LangCSharp {
                     self.context.interface_section.path.clone(),
                     writer.into_inner(),
                 );
+
+                if self.mock_enabled {
+                    outputs.insert(
+                        self.context.mock_section.path.clone(),
+                        self.mock(&functions),
+                    );
+                }
             }
         }
 
@@ -647,10 +945,8 @@ impl Lang for LangCSharp {
                 emit_const(&mut writer, &self.context, &snippet.name, &snippet.item);
             }
 
-            if !self.custom_consts.is_empty() {
-                for decl in self.custom_consts.drain(..) {
-                    emit!(writer, "{}\n", decl);
-                }
+            for decl in &self.custom_consts {
+                emit!(writer, "{}\n", decl);
             }
 
             writer.unindent();
@@ -687,6 +983,11 @@ impl Lang for LangCSharp {
                 emit_enum(&mut writer, &self.context, &snippet.name, &snippet.item);
             }
 
+            // Exceptions thrown for the error codes
+            if let Some(ref codes) = self.context.error_codes {
+                emit_error_codes_exceptions(&mut writer, codes);
+            }
+
             // Structs
             for snippet in &self.structs {
                 emit_docs(&mut writer, &self.context, &snippet.docs);
@@ -713,9 +1014,29 @@ impl Lang for LangCSharp {
             outputs.insert(self.context.utils_section.path.clone(), writer.into_inner());
         }
 
-        // Other cleanup.
+        // Native library resolver
+        if self.resolver_enabled {
+            let mut writer = IndentedWriter::new(INDENT_WIDTH);
+            emit_resolver(&mut writer, &self.context);
+
+            outputs.insert(
+                self.context.resolver_section.path.clone(),
+                writer.into_inner(),
+            );
+        }
+
+        // Start over for the next compilation (e.g. for another target), keeping the constants
+        // added to the language.
+        self.consts.clear();
+        self.enums.clear();
+        self.structs.clear();
+        self.functions.clear();
+        self.aliases.clear();
         self.context.opaque_types.clear();
         self.context.native_types.clear();
+        self.context.safe_handles.clear();
+        self.context.error_codes = None;
+        self.opaque_free_fns.clear();
 
         Ok(())
     }
@@ -784,6 +1105,26 @@ fn collect_callbacks(functions: &[Snippet<Function>]) -> Vec<(&Function, bool)>
     stash.into_iter().map(|(_, entry)| entry).collect()
 }
 
+/// Whether the function takes a pointer to the opaque type and returns nothing.
+fn is_free_function(function: &Function, name: &str) -> bool {
+    if function.inputs.len() != 1 {
+        return false;
+    }
+
+    let takes_handle = match function.inputs[0].1 {
+        Type::Pointer(ref ty) => match **ty {
+            Type::User(ref pointee) => pointee == name,
+            _ => false,
+        },
+        _ => false,
+    };
+
+    match function.output {
+        Type::Unit => takes_handle,
+        _ => false,
+    }
+}
+
 fn callback_wrapper_name(callback: &Function) -> String {
     let mut writer = IndentedWriter::new(INDENT_WIDTH);
     emit_callback_wrapper_name(&mut writer, callback);
@@ -795,5 +1136,6 @@ fn unsupported_generics_error(item: &ast::Item, name: &str) -> Error {
         level: Level::Error,
         span: Some(item.span),
         message: format!("bindgen can not handle parameterized {}", name),
+        help: None,
     }
 }
@@ -95,6 +95,51 @@ fn structs() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn packed_structs() {
+    let outputs = compile!(None, {
+        #[repr(C, packed)]
+        pub struct Header {
+            version: u8,
+            length: u32,
+        }
+    });
+
+    let actual = fetch(&outputs, "Types.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Runtime.InteropServices;
+         using JetBrains.Annotations;
+
+         namespace Backend {
+           [PublicAPI]
+           [StructLayout(LayoutKind.Sequential, Pack = 1)]
+           public struct Header {
+             public byte Version;
+             public uint Length;
+           }
+
+         }
+         "
+    );
+
+    assert_multiline_eq!(actual, expected);
+
+    let errors = try_compile!(None, {
+        #[repr(C, align(16))]
+        pub struct Block {
+            lo: u64,
+            hi: u64,
+        }
+    }).unwrap_err();
+
+    assert_eq!(
+        errors[0].message,
+        "bindgen can not express `#[repr(align(16))]` in C# (Block)"
+    );
+}
+
 #[test]
 fn native_structs() {
     let outputs = compile!(None, {
@@ -257,7 +302,7 @@ fn native_structs() {
 
              public Task<Entry> Fun2Async() {
                var (ret, userData) = Utils.PrepareTask<Entry>();
-               Fun2Native(userData, OnFfiResultEntryCb);
+               Fun2Native(userData, DelegateOnFfiResultEntryCb);
                return ret;
              }
 
@@ -267,18 +312,19 @@ fn native_structs() {
 
              public Task<List<Entry>> Fun3Async() {
                var (ret, userData) = Utils.PrepareTask<List<Entry>>();
-               Fun3Native(userData, OnFfiResultEntryListCb);
+               Fun3Native(userData, DelegateOnFfiResultEntryListCb);
                return ret;
              }
 
              [DllImport(DllName, EntryPoint = \"fun3\")]
              private static extern void Fun3Native(IntPtr userData, FfiResultEntryListCb cb);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultEntryCb(IntPtr userData, \
                                                     IntPtr result, \
                                                     IntPtr entry);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultEntryCb))]
              #endif
              private static void OnFfiResultEntryCb(IntPtr userData, \
@@ -289,12 +335,15 @@ fn native_structs() {
                                   () => new Entry(Marshal.PtrToStructure<EntryNative>(entry)));
              }
 
+             private static readonly FfiResultEntryCb DelegateOnFfiResultEntryCb = OnFfiResultEntryCb;
+
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultEntryListCb(IntPtr userData, \
                                                         IntPtr result, \
                                                         IntPtr entriesPtr, \
                                                         UIntPtr entriesLen);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultEntryListCb))]
              #endif
              private static void OnFfiResultEntryListCb(IntPtr userData, \
@@ -309,6 +358,8 @@ fn native_structs() {
                    (int) entriesLen).Select(native => new Entry(native)).ToList());
              }
 
+             private static readonly FfiResultEntryListCb DelegateOnFfiResultEntryListCb = OnFfiResultEntryListCb;
+
            }
          }
         "
@@ -378,7 +429,7 @@ fn type_aliases() {
 
              public Task<ulong> FunAsync(ulong id) {
                var (ret, userData) = Utils.PrepareTask<ulong>();
-               FunNative(id, userData, OnFfiResultULongCb);
+               FunNative(id, userData, DelegateOnFfiResultULongCb);
                return ret;
              }
 
@@ -387,11 +438,12 @@ fn type_aliases() {
                                                   IntPtr userData, \
                                                   FfiResultULongCb cb);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultULongCb(IntPtr arg0, \
                                                     IntPtr arg1, \
                                                     ulong arg2);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultULongCb))]
              #endif
              private static void OnFfiResultULongCb(IntPtr arg0, \
@@ -402,6 +454,8 @@ fn type_aliases() {
                                   () => arg2);
              }
 
+             private static readonly FfiResultULongCb DelegateOnFfiResultULongCb = OnFfiResultULongCb;
+
            }
          }
          "
@@ -454,6 +508,41 @@ fn enums() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn bitflags() {
+    let outputs = compile!(None, {
+        bitflags! {
+            pub struct Access: u32 {
+                const READ = 0b01;
+                const WRITE = 0b10;
+                const ALL = Self::READ.bits | Self::WRITE.bits;
+            }
+        }
+    });
+
+    let actual = fetch(&outputs, "Types.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Runtime.InteropServices;
+         using JetBrains.Annotations;
+
+         namespace Backend {
+           [Flags]
+           [PublicAPI]
+           public enum Access : uint {
+             READ = 1,
+             WRITE = 2,
+             ALL = 3,
+           }
+
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
 #[test]
 fn functions_without_extern_and_no_mangle_are_ignored() {
     let outputs = compile!(None, {
@@ -536,7 +625,7 @@ fn functions_taking_one_callback() {
 
              public Task Fun1Async(int num, string name) {
                var (ret, userData) = Utils.PrepareTask();
-               Fun1Native(num, name, userData, OnFfiResultCb);
+               Fun1Native(num, name, userData, DelegateOnFfiResultCb);
                return ret;
              }
 
@@ -547,15 +636,18 @@ fn functions_taking_one_callback() {
                IntPtr userData, \
                FfiResultCb cb);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultCb(IntPtr userData, IntPtr result);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultCb))]
              #endif
              private static void OnFfiResultCb(IntPtr userData, IntPtr result) {
                Utils.CompleteTask(userData, Marshal.PtrToStructure<FfiResult>(result));
              }
 
+             private static readonly FfiResultCb DelegateOnFfiResultCb = OnFfiResultCb;
+
            }
          }
         "
@@ -564,6 +656,36 @@ fn functions_taking_one_callback() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn functions_taking_callback_with_custom_user_data() {
+    let outputs = compile!(None, {
+        #[no_mangle]
+        #[cheddar(user_data = "ctx")]
+        pub extern "C" fn fun1(
+            num: i32,
+            name: *const c_char,
+            ctx: *mut c_void,
+            cb: extern "C" fn(ctx: *mut c_void, result: *const FfiResult),
+        ) {
+        }
+    });
+    let expected_outputs = compile!(None, {
+        #[no_mangle]
+        pub extern "C" fn fun1(
+            num: i32,
+            name: *const c_char,
+            user_data: *mut c_void,
+            cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult),
+        ) {
+        }
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = fetch(&expected_outputs, "Backend.cs");
+
+    assert_multiline_eq!(actual, expected);
+}
+
 #[test]
 fn functions_taking_multiple_callbacks() {
     // Only the native declaration should be produced.
@@ -601,10 +723,12 @@ fn functions_taking_multiple_callbacks() {
                                                    NoneCb cb0, \
                                                    FfiResultIntCb cb1);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultIntCb(IntPtr userData, \
                                                    IntPtr result, \
                                                    int output);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void NoneCb(IntPtr userData);
 
            }
@@ -697,6 +821,91 @@ fn functions_taking_array() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn functions_taking_explicit_slice() {
+    let outputs = compile!(None, {
+        #[no_mangle]
+        #[cheddar(slice(ptr = "bytes", len = "count"))]
+        pub extern "C" fn fun0(bytes: *const u8, count: usize) {}
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Linq;
+         using System.Runtime.InteropServices;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           internal partial class Backend : IBackend {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #else
+             private const string DllName = \"backend\";
+             #endif
+
+             public void Fun0(List<byte> bytes) {
+               Fun0Native(bytes?.ToArray(), (UIntPtr) (bytes?.Count ?? 0));
+             }
+
+             [DllImport(DllName, EntryPoint = \"fun0\")]
+             private static extern void Fun0Native(\
+               [MarshalAs(UnmanagedType.LPArray, SizeParamIndex = 1)] byte[] bytes, \
+               UIntPtr bytesLen\
+             );
+
+           }
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
+#[test]
+fn utf8_strings() {
+    let outputs = compile!(None, {
+        #[no_mangle]
+        #[cheddar(utf8, free_with = "string_free")]
+        pub extern "C" fn greet(name: *const c_char) -> *mut c_char {}
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Linq;
+         using System.Runtime.InteropServices;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           internal partial class Backend : IBackend {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #else
+             private const string DllName = \"backend\";
+             #endif
+
+             public string Greet(string name) {
+               var retPtr = GreetNative(name);
+               var ret = Marshal.PtrToStringUTF8(retPtr);
+               StringFreeNative(retPtr);
+               return ret;
+             }
+
+             [DllImport(DllName, EntryPoint = \"greet\")]
+             private static extern IntPtr GreetNative(\
+               [MarshalAs(UnmanagedType.LPUTF8Str)] string name);
+
+           }
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
 #[test]
 fn functions_taking_callback_taking_const_size_array() {
     let outputs = compile!(None, {
@@ -741,7 +950,7 @@ fn functions_taking_callback_taking_const_size_array() {
 
              public Task<byte[]> Fun2Async() {
                var (ret, userData) = Utils.PrepareTask<byte[]>();
-               Fun2Native(userData, OnFfiResultByteArray32Cb);
+               Fun2Native(userData, DelegateOnFfiResultByteArray32Cb);
                return ret;
              }
 
@@ -751,7 +960,7 @@ fn functions_taking_callback_taking_const_size_array() {
 
              public Task<byte[]> Fun3Async() {
                var (ret, userData) = Utils.PrepareTask<byte[]>();
-               Fun3Native(userData, OnFfiResultByteArrayNonceLenCb);
+               Fun3Native(userData, DelegateOnFfiResultByteArrayNonceLenCb);
                return ret;
              }
 
@@ -759,11 +968,12 @@ fn functions_taking_callback_taking_const_size_array() {
              private static extern void Fun3Native(IntPtr userData, \
                                                    FfiResultByteArrayNonceLenCb cb);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultByteArray32Cb(IntPtr userData, \
                                                           IntPtr result, \
                                                           IntPtr key);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultByteArray32Cb))]
              #endif
              private static void OnFfiResultByteArray32Cb(IntPtr userData, \
@@ -774,11 +984,14 @@ fn functions_taking_callback_taking_const_size_array() {
                                   () => Utils.CopyToByteArray(key, 32));
              }
 
+             private static readonly FfiResultByteArray32Cb DelegateOnFfiResultByteArray32Cb = OnFfiResultByteArray32Cb;
+
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultByteArrayNonceLenCb(IntPtr userData, \
                                                                 IntPtr result, \
                                                                 IntPtr nonce);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultByteArrayNonceLenCb))]
              #endif
              private static void OnFfiResultByteArrayNonceLenCb(IntPtr userData, \
@@ -790,6 +1003,8 @@ fn functions_taking_callback_taking_const_size_array() {
                  () => Utils.CopyToByteArray(nonce, (int) Constants.NonceLen));
              }
 
+             private static readonly FfiResultByteArrayNonceLenCb DelegateOnFfiResultByteArrayNonceLenCb = OnFfiResultByteArrayNonceLenCb;
+
            }
          }
         "
@@ -846,7 +1061,7 @@ fn functions_taking_callback_taking_dynamic_array() {
 
              public Task<List<byte>> Fun0Async() {
                var (ret, userData) = Utils.PrepareTask<List<byte>>();
-               Fun0Native(userData, OnFfiResultByteListCb);
+               Fun0Native(userData, DelegateOnFfiResultByteListCb);
                return ret;
              }
 
@@ -856,7 +1071,7 @@ fn functions_taking_callback_taking_dynamic_array() {
 
              public Task<List<Record>> Fun1Async() {
                var (ret, userData) = Utils.PrepareTask<List<Record>>();
-               Fun1Native(userData, OnFfiResultRecordListCb);
+               Fun1Native(userData, DelegateOnFfiResultRecordListCb);
                return ret;
              }
 
@@ -864,12 +1079,13 @@ fn functions_taking_callback_taking_dynamic_array() {
              private static extern void Fun1Native(IntPtr userData, \
                                                    FfiResultRecordListCb cb);
 
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultByteListCb(IntPtr userData, \
                                                        IntPtr result, \
                                                        IntPtr dataPtr, \
                                                        UIntPtr dataLen);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultByteListCb))]
              #endif
              private static void OnFfiResultByteListCb(IntPtr userData, \
@@ -881,12 +1097,15 @@ fn functions_taking_callback_taking_dynamic_array() {
                                   () => Utils.CopyToByteList(dataPtr, (int) dataLen));
              }
 
+             private static readonly FfiResultByteListCb DelegateOnFfiResultByteListCb = OnFfiResultByteListCb;
+
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
              private delegate void FfiResultRecordListCb(IntPtr userData, \
                                                           IntPtr result, \
                                                           IntPtr recordsPtr, \
                                                           UIntPtr recordsLen);
 
-             #if __IOS__
+             #if __IOS__ || ENABLE_IL2CPP
              [MonoPInvokeCallback(typeof(FfiResultRecordListCb))]
              #endif
              private static void OnFfiResultRecordListCb(IntPtr userData, \
@@ -900,6 +1119,8 @@ fn functions_taking_callback_taking_dynamic_array() {
                                     (int) recordsLen));
              }
 
+             private static readonly FfiResultRecordListCb DelegateOnFfiResultRecordListCb = OnFfiResultRecordListCb;
+
            }
          }
         "
@@ -991,7 +1212,9 @@ fn constants() {
 
     let outputs = compile!(lang, {
         pub const NUMBER: i32 = 123;
+        pub const LIMIT: i32 = NUMBER * 2 + 4 * 16;
         pub const STRING: &'static str = "hello world";
+        pub const QUOTED: &'static str = "\"Ol\u{e1}\"\tC:\\";
         pub const ARRAY: [u8; 4] = [0, 1, 2, 3];
 
         pub const STRUCT_VALUE: Record = Record {
@@ -1016,7 +1239,9 @@ fn constants() {
            [PublicAPI]
            public static class Constants {
              public const int Number = 123;
+             public const int Limit = ((Constants.Number * 2) + 64);
              public const string String = \"hello world\";
+             public const string Quoted = \"\\\"Ol\\u00e1\\\"\\tC:\\\\\";
              public static readonly byte[] Array = new byte[] { 0, 1, 2, 3 };
              public static readonly Record StructValue = new Record { \
                  id = 0, secretCode = \"xyz\" };
@@ -1101,6 +1326,66 @@ fn arrays() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn platform_lib_names() {
+    let mut lang = LangCSharp::new();
+    lang.set_lib_name("mylib");
+    lang.set_platform_lib_name("__ANDROID__", "mylib_android");
+
+    let outputs = compile!(lang, {
+        #[no_mangle]
+        pub extern "C" fn fun() {}
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Linq;
+         using System.Runtime.InteropServices;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           internal partial class Backend : IBackend {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #elif __ANDROID__
+             private const string DllName = \"mylib_android\";
+             #else
+             private const string DllName = \"mylib\";
+             #endif
+
+             public void Fun() {
+               FunNative();
+             }
+
+             [DllImport(DllName, EntryPoint = \"fun\")]
+             private static extern void FunNative();
+
+           }
+         }
+        "
+    );
+    assert_multiline_eq!(actual, expected);
+}
+
+#[test]
+fn native_library_resolver() {
+    let mut lang = LangCSharp::new();
+    lang.set_lib_name("mylib");
+    lang.set_resolver_enabled(true);
+
+    let outputs = compile!(lang, {
+        #[no_mangle]
+        pub extern "C" fn fun() {}
+    });
+
+    let actual = fetch(&outputs, "NativeLibraryResolver.cs");
+    assert!(actual.contains("namespace Backend {"));
+    assert!(actual.contains("public static class NativeLibraryResolver {"));
+    assert!(actual.contains("private const string LibName = \"mylib\";"));
+}
+
 #[test]
 fn opaque_types() {
     let mut lang = LangCSharp::new();
@@ -1164,6 +1449,230 @@ fn opaque_types() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn opaque_types_with_safe_handles() {
+    let mut lang = LangCSharp::new();
+    lang.add_opaque_type("App");
+    lang.set_detect_free_fns(true);
+
+    let outputs = compile!(lang, {
+        #[no_mangle]
+        pub extern "C" fn app_new(
+            user_data: *mut c_void,
+            o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, app: *mut App),
+        ) {
+        }
+
+        #[no_mangle]
+        pub extern "C" fn app_id(app: *const App) -> u32 {}
+
+        #[no_mangle]
+        pub extern "C" fn app_free(app: *mut App) {}
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Linq;
+         using System.Runtime.InteropServices;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           internal partial class Backend : IBackend {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #else
+             private const string DllName = \"backend\";
+             #endif
+
+             public Task<AppHandle> AppNewAsync() {
+               var (ret, userData) = Utils.PrepareTask<AppHandle>();
+               AppNewNative(userData, DelegateOnFfiResultAppCb);
+               return ret;
+             }
+
+             [DllImport(DllName, EntryPoint = \"app_new\")]
+             private static extern void AppNewNative(IntPtr userData, FfiResultAppCb oCb);
+
+             public uint AppId(AppHandle app) {
+               var ret = AppIdNative(app);
+               return ret;
+             }
+
+             [DllImport(DllName, EntryPoint = \"app_id\")]
+             private static extern uint AppIdNative(AppHandle app);
+
+             [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+             private delegate void FfiResultAppCb(IntPtr userData, IntPtr result, IntPtr app);
+
+             #if __IOS__ || ENABLE_IL2CPP
+             [MonoPInvokeCallback(typeof(FfiResultAppCb))]
+             #endif
+             private static void OnFfiResultAppCb(IntPtr userData, IntPtr result, IntPtr app) {
+               Utils.CompleteTask(userData, \
+                                  Marshal.PtrToStructure<FfiResult>(result), \
+                                  () => new AppHandle(app, false));
+             }
+
+             private static readonly FfiResultAppCb DelegateOnFfiResultAppCb = OnFfiResultAppCb;
+
+           }
+
+           public sealed class AppHandle : SafeHandle {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #else
+             private const string DllName = \"backend\";
+             #endif
+
+             private AppHandle() : base(IntPtr.Zero, true) {
+             }
+
+             internal AppHandle(IntPtr handle, bool ownsHandle) : base(IntPtr.Zero, ownsHandle) {
+               SetHandle(handle);
+             }
+
+             public override bool IsInvalid => handle == IntPtr.Zero;
+
+             protected override bool ReleaseHandle() {
+               AppFreeNative(handle);
+               return true;
+             }
+
+             [DllImport(DllName, EntryPoint = \"app_free\")]
+             private static extern void AppFreeNative(IntPtr handle);
+           }
+         }
+        "
+    );
+    assert_multiline_eq!(actual, expected);
+}
+
+#[test]
+fn opaque_types_without_free_fn_detection() {
+    let mut lang = LangCSharp::new();
+    lang.add_opaque_type("App");
+
+    let outputs = compile!(lang, {
+        #[no_mangle]
+        pub extern "C" fn app_visit(
+            app: *const App,
+            user_data: *mut c_void,
+            o_cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, app: *const App),
+        ) {
+        }
+
+        #[no_mangle]
+        pub extern "C" fn app_free(app: *mut App) {}
+    });
+
+    let actual = fetch(&outputs, "Backend.cs");
+    assert!(!actual.contains("AppHandle"));
+    assert!(actual.contains("public void AppFree(IntPtr app) {\n"));
+    assert!(actual.contains("() => app);\n"));
+}
+
+#[test]
+fn error_codes() {
+    let outputs = compile!(None, {
+        #[cheddar(error_codes)]
+        #[repr(C)]
+        pub enum ErrorCode {
+            Ok,
+            NotFound,
+            AccessDenied = 5,
+        }
+
+        #[no_mangle]
+        pub extern "C" fn fun(id: u32) -> ErrorCode {}
+    });
+
+    let actual = fetch(&outputs, "Types.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Runtime.InteropServices;
+         using JetBrains.Annotations;
+
+         namespace Backend {
+           [PublicAPI]
+           public enum ErrorCode {
+             Ok,
+             NotFound,
+             AccessDenied = 5,
+           }
+
+           [PublicAPI]
+           public class ErrorCodeException : Exception {
+             public ErrorCode Code { get; }
+
+             public ErrorCodeException(ErrorCode code) : base(code.ToString()) {
+               Code = code;
+             }
+
+             public static ErrorCodeException FromCode(ErrorCode code) {
+               switch (code) {
+                 case ErrorCode.NotFound:
+                   return new NotFoundException();
+                 case ErrorCode.AccessDenied:
+                   return new AccessDeniedException();
+                 default:
+                   return new ErrorCodeException(code);
+               }
+             }
+           }
+
+           [PublicAPI]
+           public class NotFoundException : ErrorCodeException {
+             public NotFoundException() : base(ErrorCode.NotFound) {
+             }
+           }
+
+           [PublicAPI]
+           public class AccessDeniedException : ErrorCodeException {
+             public AccessDeniedException() : base(ErrorCode.AccessDenied) {
+             }
+           }
+
+         }
+        "
+    );
+    assert_multiline_eq!(actual, expected);
+
+    let actual = fetch(&outputs, "Backend.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Linq;
+         using System.Runtime.InteropServices;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           internal partial class Backend : IBackend {
+             #if __IOS__
+             private const string DllName = \"__Internal\";
+             #else
+             private const string DllName = \"backend\";
+             #endif
+
+             public void Fun(uint id) {
+               var ret = FunNative(id);
+               if (ret != ErrorCode.Ok) {
+                 throw ErrorCodeException.FromCode(ret);
+               }
+             }
+
+             [DllImport(DllName, EntryPoint = \"fun\")]
+             private static extern ErrorCode FunNative(uint id);
+
+           }
+         }
+        "
+    );
+    assert_multiline_eq!(actual, expected);
+}
+
 #[test]
 fn interface() {
     let outputs = compile!(None, {
@@ -1193,6 +1702,122 @@ fn interface() {
     assert_multiline_eq!(actual, expected);
 }
 
+#[test]
+fn mock() {
+    let mut lang = LangCSharp::new();
+    lang.set_mock_enabled(true);
+
+    let outputs = compile!(lang, {
+        #[no_mangle]
+        pub extern "C" fn count(id: u32, enabled: bool) -> u64 {}
+
+        #[no_mangle]
+        pub extern "C" fn fetch(
+            id: u32,
+            user_data: *mut c_void,
+            cb: extern "C" fn(user_data: *mut c_void, result: *const FfiResult, len: u64),
+        ) {
+        }
+
+        #[no_mangle]
+        pub extern "C" fn reset() {}
+    });
+
+    let actual = fetch(&outputs, "Mock.cs");
+    let expected = indoc!(
+        "using System;
+         using System.Collections.Generic;
+         using System.Threading.Tasks;
+
+         namespace Backend {
+           public class MockBackend : IBackend {
+             public int CountCalls;
+             public uint CountId;
+             public bool CountEnabled;
+             public ulong CountResult;
+
+             public ulong Count(uint id, bool enabled) {
+               ++CountCalls;
+               CountId = id;
+               CountEnabled = enabled;
+               return CountResult;
+             }
+
+             public int FetchCalls;
+             public uint FetchId;
+             public ulong FetchResult;
+
+             public Task<ulong> FetchAsync(uint id) {
+               ++FetchCalls;
+               FetchId = id;
+               return Task.FromResult(FetchResult);
+             }
+
+             public int ResetCalls;
+
+             public void Reset() {
+               ++ResetCalls;
+             }
+
+           }
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
+#[test]
+fn hidden_items_are_ignored() {
+    let outputs = compile!(None, {
+        #[doc(hidden)]
+        pub const HIDDEN: i32 = 1;
+        pub const VISIBLE: i32 = 2;
+    });
+
+    let actual = fetch(&outputs, "Constants.cs");
+    let expected = indoc!(
+        "using System;
+         using JetBrains.Annotations;
+
+         namespace Backend {
+           [PublicAPI]
+           public static class Constants {
+             public const int Visible = 2;
+           }
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
+#[test]
+fn crate_version_constants() {
+    let mut lang = LangCSharp::new();
+    lang.crate_version("safe_app", "0.6.0-beta.1");
+
+    let outputs = compile!(lang, {});
+    let actual = fetch(&outputs, "Constants.cs");
+    let expected = indoc!(
+        "using System;
+         using JetBrains.Annotations;
+
+         namespace Backend {
+           [PublicAPI]
+           public static class Constants {
+             public const string Version = \"0.6.0-beta.1\";
+             public const ulong VersionMajor = 0;
+             public const ulong VersionMinor = 6;
+             public const ulong VersionPatch = 0;
+           }
+         }
+        "
+    );
+
+    assert_multiline_eq!(actual, expected);
+}
+
 fn try_compile<T: Into<Option<LangCSharp>>>(
     lang: T,
     rust_src: String,
@@ -1207,7 +1832,13 @@ fn try_compile<T: Into<Option<LangCSharp>>>(
     let mut outputs = Outputs::default();
     let mut lang = lang.into().unwrap_or_else(LangCSharp::new);
 
-    parse::parse_mod(&mut lang, &ast.module, &[Default::default()], &mut outputs)?;
+    parse::parse_mod(
+        &mut lang,
+        &ast.module,
+        &[Default::default()],
+        &Default::default(),
+        &mut outputs,
+    )?;
     lang.finalise_output(&mut outputs)?;
 
     Ok(outputs)
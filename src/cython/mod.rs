@@ -0,0 +1,389 @@
+//! Generation of Cython declarations (`.pxd`) mirroring the C header.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, extern_fn, literal_const, repr_c_enum,
+    repr_c_struct, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use lang_c::rust_to_c;
+use lang_c::types::{CPtrType, CType, CTypeNamed};
+use std::collections::BTreeSet;
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "    ";
+
+/// Generates a `<lib_name>.pxd` file declaring the contents of the C header (as generated by
+/// `LangC`) in a `cdef extern from "<lib_name>.h"` block.
+pub struct LangCython {
+    lib_name: String,
+    /// Opaque handle types of the registry, declared as opaque structs.
+    handles: BTreeSet<String>,
+}
+
+impl LangCython {
+    pub fn new() -> Self {
+        LangCython {
+            lib_name: "backend".to_owned(),
+            handles: BTreeSet::new(),
+        }
+    }
+
+    /// Set the name of the native library, which is also the name of the top-level header.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}.pxd", self.lib_name)
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+}
+
+impl Default for LangCython {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangCython {
+    fn name(&self) -> &'static str {
+        "cython"
+    }
+
+    fn add_handle(&mut self, name: &str, _free_fn: Option<&str>) {
+        let _ = self.handles.insert(name.to_string());
+    }
+
+    /// Convert `pub const NAME: Type = value;` into a declaration of the `NAME` macro.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let constant = match literal_const(item)? {
+            Some(constant) => constant,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let decl = match constant.lit {
+            Some(&ast::LitKind::Str(..)) => format!("const char* {}", name),
+            _ => cython_decl(&rust_to_c(constant.ty, &name)?),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!("{}{}\n\n", INDENT, decl));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `ctypedef B A`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let decl = match alias_type(item)? {
+            Some(ty) => cython_decl(&rust_to_c(ty, &name)?),
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!("{}ctypedef {}\n\n", INDENT, decl));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into a `ctypedef enum`.
+    ///
+    /// Enums with data-carrying variants are declared as opaque structs.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+
+        match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, _)) => {
+                buffer.push_str(&format!("{}ctypedef enum {}:\n", INDENT, name));
+                for var in variants {
+                    buffer.push_str(&doc_comments(&var.node.attrs, &INDENT.repeat(2), "# "));
+                    buffer.push_str(&format!(
+                        "{}{}_{}\n",
+                        INDENT.repeat(2),
+                        name,
+                        var.node.name.name.as_str()
+                    ));
+                }
+                buffer.push('\n');
+            }
+            Some(ReprCEnum::Opaque) => buffer.push_str(&format!(
+                "{0}ctypedef struct {1}:\n{0}{0}pass\n\n",
+                INDENT, name
+            )),
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into a `ctypedef struct`.
+    ///
+    /// Tuple structs with a single field are declared as opaque structs.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let fields = match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => fields,
+            // Opaque handles are only used through pointers.
+            Some(ReprCStruct::Opaque) => Vec::new(),
+            None if self.handles.contains(&*name) => Vec::new(),
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!("{}ctypedef struct {}:\n", INDENT, name));
+
+        for &(ref field_name, field) in &fields {
+            buffer.push_str(&doc_comments(&field.attrs, &INDENT.repeat(2), "# "));
+            buffer.push_str(&format!(
+                "{}{}\n",
+                INDENT.repeat(2),
+                cython_decl(&rust_to_c(&field.ty, field_name)?)
+            ));
+        }
+        if fields.is_empty() {
+            buffer.push_str(&format!("{}pass\n", INDENT.repeat(2)));
+        }
+
+        buffer.push('\n');
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into a `ctypedef` of the underlying integer type and an
+    /// anonymous enum declaring the flags.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = doc_comments(&flags.attrs, INDENT, "# ");
+        buffer.push_str(&format!(
+            "{}ctypedef {}\n\n",
+            INDENT,
+            cython_decl(&rust_to_c(&flags.ty, &flags.name)?)
+        ));
+
+        if !flags.flags.is_empty() {
+            buffer.push_str(&format!("{}enum:\n", INDENT));
+            for flag in &flags.flags {
+                buffer.push_str(&doc_comments(&flag.attrs, &INDENT.repeat(2), "# "));
+                buffer.push_str(&format!("{}{}_{}\n", INDENT.repeat(2), flags.name, flag.name));
+            }
+            buffer.push('\n');
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into a function declaration.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let fn_decl = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl,
+            None => return Ok(()),
+        };
+
+        let mut args = Vec::new();
+        for arg in &fn_decl.inputs {
+            let arg_name = pprust::pat_to_string(&arg.pat);
+            args.push(cython_decl(&rust_to_c(&arg.ty, &arg_name)?));
+        }
+        let buf = format!("{}({})", item.ident.name.as_str(), args.join(", "));
+
+        let decl = match fn_decl.output {
+            ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: "panics across a C boundary are naughty!".into(),
+                    help: None,
+                });
+            }
+            ast::FunctionRetTy::Default(..) => format!("void {}", buf),
+            ast::FunctionRetTy::Ty(ref ty) => cython_decl(&rust_to_c(ty, &buf)?),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!("{}{}\n\n", INDENT, decl));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+        let body = if body.is_empty() {
+            format!("{}pass\n", INDENT)
+        } else {
+            body
+        };
+
+        let _ = outputs.insert(
+            self.output_name(),
+            format!(
+                "from libc.stdint cimport int8_t, int16_t, int32_t, int64_t, intptr_t\n\
+                 from libc.stdint cimport uint8_t, uint16_t, uint32_t, uint64_t, uintptr_t\n\n\
+                 cdef extern from \"stdbool.h\":\n\
+                 {0}ctypedef bint bool\n\n\
+                 cdef extern from \"{1}.h\":\n\
+                 {2}",
+                INDENT, self.lib_name, body
+            ),
+        );
+
+        Ok(())
+    }
+}
+
+/// Render a named C declaration using the Cython syntax.
+fn cython_decl(decl: &CTypeNamed) -> String {
+    match decl.1 {
+        CType::FnDecl { .. } => cython_ty(&decl.1),
+        ref ty => format!("{} {}", cython_ty(ty), decl.0),
+    }
+}
+
+/// Render a C type using the Cython syntax, which only supports `const` in front of the type.
+fn cython_ty(ty: &CType) -> String {
+    match *ty {
+        CType::Void => "void".to_owned(),
+        CType::Mapping(ref name) => name.clone(),
+        CType::Native(name) => name.to_owned(),
+        CType::Ptr(ref inner, CPtrType::Const) => match **inner {
+            CType::Ptr(..) => format!("{}*", cython_ty(inner)),
+            _ => format!("const {}*", cython_ty(inner)),
+        },
+        CType::Ptr(ref inner, CPtrType::Mutable) => format!("{}*", cython_ty(inner)),
+        // Cython has no `volatile` qualifier.
+        CType::Volatile(ref inner) => cython_ty(inner),
+        CType::FnDecl {
+            ref inner,
+            ref args,
+            ref return_type,
+        } => format!(
+            "{} (*{})({})",
+            cython_ty(return_type),
+            inner,
+            args.iter().map(cython_decl).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            pub type Id = u64;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok = 0,
+                Failed = 1,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                /// Vertical.
+                y: i32,
+            }
+
+            bitflags! {
+                pub struct Flags: u32 {
+                    const READ = 1;
+                    const WRITE = 2;
+                }
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_name(
+                point: *const Point,
+                user_data: *mut c_void,
+                cb: extern \"C\" fn(user_data: *mut c_void, name: *const c_char),
+            ) -> bool {}
+
+            #[no_mangle]
+            pub extern \"C\" fn reset() {}
+        ";
+
+        let mut lang = LangCython::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.pxd"],
+            "from libc.stdint cimport int8_t, int16_t, int32_t, int64_t, intptr_t\n\
+             from libc.stdint cimport uint8_t, uint16_t, uint32_t, uint64_t, uintptr_t\n\n\
+             cdef extern from \"stdbool.h\":\n    \
+             ctypedef bint bool\n\n\
+             cdef extern from \"safe_app.h\":\n    \
+             # Maximum length.\n    \
+             uint32_t MAX_LEN\n\n    \
+             ctypedef uint64_t Id\n\n    \
+             ctypedef enum Status:\n        \
+             # Success.\n        \
+             Status_Ok\n        \
+             Status_Failed\n\n    \
+             ctypedef struct Point:\n        \
+             int32_t x\n        \
+             # Vertical.\n        \
+             int32_t y\n\n    \
+             ctypedef uint32_t Flags\n\n    \
+             enum:\n        \
+             Flags_READ\n        \
+             Flags_WRITE\n\n    \
+             bool point_name(const Point* point, void* user_data, \
+             void (*cb)(void* user_data, const char* name))\n\n    \
+             void reset()\n\n"
+        );
+    }
+}
@@ -0,0 +1,517 @@
+//! Generation of D modules declaring the `extern(C)` API.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, escape_keyword, extern_fn, literal_const,
+    repr_c_enum, repr_c_struct, repr_layout, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use lang_c::option_inner;
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "    ";
+
+/// D keywords, which get an underscore appended when used as identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "alias", "align", "asm", "assert", "auto", "body", "bool", "break", "byte",
+    "case", "cast", "catch", "char", "class", "const", "continue", "dchar", "debug", "default",
+    "delegate", "delete", "deprecated", "do", "double", "else", "enum", "export", "extern",
+    "false", "final", "finally", "float", "for", "foreach", "foreach_reverse", "function",
+    "goto", "if", "immutable", "import", "in", "inout", "int", "interface", "invariant", "is",
+    "lazy", "long", "macro", "mixin", "module", "new", "nothrow", "null", "out", "override",
+    "package", "pragma", "private", "protected", "public", "pure", "real", "ref", "return",
+    "scope", "shared", "short", "static", "struct", "super", "switch", "synchronized",
+    "template", "this", "throw", "true", "try", "typeid", "typeof", "ubyte", "uint", "ulong",
+    "union", "unittest", "ushort", "version", "void", "wchar", "while", "with",
+];
+
+/// Generates a `<lib_name>.d` module with `extern(C)` declarations of the functions, mirrors of
+/// the structs and enums, and the doc comments as DDoc.
+pub struct LangD {
+    lib_name: String,
+    module: Option<String>,
+}
+
+impl LangD {
+    pub fn new() -> Self {
+        LangD {
+            lib_name: "backend".to_owned(),
+            module: None,
+        }
+    }
+
+    /// Set the name of the native library, which is also the name of the generated file.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Set the fully qualified name of the generated module (e.g. `safe.app`). Defaults to the
+    /// name of the native library.
+    pub fn set_module<T: Into<String>>(&mut self, module: T) {
+        self.module = Some(module.into());
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}.d", self.lib_name)
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+}
+
+impl Default for LangD {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangD {
+    fn name(&self) -> &'static str {
+        "d"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into the manifest constant
+    /// `enum Type NAME = value;`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let constant = match literal_const(item)? {
+            Some(constant) => constant,
+            None => return Ok(()),
+        };
+
+        let name = ident(&item.ident.name.as_str());
+        let decl = match constant.lit {
+            Some(&ast::LitKind::Str(..)) => format!("enum {} = {};", name, constant.value),
+            _ => format!(
+                "enum {} {} = {};",
+                rust_to_d(constant.ty)?,
+                name,
+                constant.value
+            ),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!("{}\n\n", decl));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `alias A = B;`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let ty = match alias_type(item)? {
+            Some(ty) => rust_to_d(ty)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!(
+            "alias {} = {};\n\n",
+            ident(&item.ident.name.as_str()),
+            ty
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into an `enum Name : int`.
+    ///
+    /// Enums with data-carrying variants are declared as opaque structs.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = ident(&item.ident.name.as_str());
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+
+        match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => {
+                buffer.push_str(&format!("enum {} : int {{\n", name));
+                for (var, &(ref var_name, value)) in variants.iter().zip(&values) {
+                    buffer.push_str(&doc_comments(&var.node.attrs, INDENT, "/// "));
+                    buffer.push_str(&format!("{}{} = {},\n", INDENT, ident(var_name), value));
+                }
+                buffer.push_str("}\n\n");
+            }
+            Some(ReprCEnum::Opaque) => buffer.push_str(&format!("struct {};\n\n", name)),
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into a D struct.
+    ///
+    /// Tuple structs with a single field are declared as opaque structs. `#[repr(packed)]` and
+    /// `#[repr(align(N))]` become `align` attributes of the fields and of the struct.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = ident(&item.ident.name.as_str());
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+
+        match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => {
+                let layout = repr_layout(&item.attrs);
+                if let Some(align) = layout.align {
+                    buffer.push_str(&format!("align({}) ", align));
+                }
+
+                buffer.push_str(&format!("struct {} {{\n", name));
+                if let Some(packed) = layout.packed {
+                    buffer.push_str(&format!("{}align({}):\n", INDENT, packed));
+                }
+
+                for &(ref field_name, field) in &fields {
+                    buffer.push_str(&doc_comments(&field.attrs, INDENT, "/// "));
+                    buffer.push_str(&format!(
+                        "{}{} {};\n",
+                        INDENT,
+                        rust_to_d(&field.ty)?,
+                        ident(field_name)
+                    ));
+                }
+                buffer.push_str("}\n\n");
+            }
+            Some(ReprCStruct::Opaque) => buffer.push_str(&format!("struct {};\n\n", name)),
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into an alias of the underlying integer type and a
+    /// manifest constant for every flag (e.g. `Flags_READ`).
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = doc_comments(&flags.attrs, "", "/// ");
+        buffer.push_str(&format!("alias {} = {};\n\n", flags.name, rust_to_d(&flags.ty)?));
+
+        for flag in &flags.flags {
+            buffer.push_str(&doc_comments(&flag.attrs, "", "/// "));
+            buffer.push_str(&format!(
+                "enum {0} {0}_{1} = {2:#x};\n",
+                flags.name, flag.name, flag.value
+            ));
+        }
+
+        buffer.push('\n');
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into a function declaration.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (args, output) = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl_to_d(fn_decl)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!(
+            "{} {}({});\n\n",
+            output,
+            ident(&item.ident.name.as_str()),
+            args
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+        let module = self.module.clone().unwrap_or_else(|| self.lib_name.clone());
+
+        let _ = outputs.insert(
+            self.output_name(),
+            format!(
+                "module {};\n\n\
+                 import core.stdc.config : c_long, c_ulong;\n\n\
+                 extern (C):\n\
+                 nothrow:\n\
+                 @nogc:\n\n\
+                 {}",
+                module, body
+            ),
+        );
+
+        Ok(())
+    }
+}
+
+/// Convert the parameters and the return type of a function into D (e.g. `int a` and `void`).
+fn fn_decl_to_d(fn_decl: &ast::FnDecl) -> Result<(String, String), Error> {
+    let mut args = Vec::new();
+    for arg in &fn_decl.inputs {
+        args.push(format!(
+            "{} {}",
+            rust_to_d(&arg.ty)?,
+            ident(&pprust::pat_to_string(&arg.pat))
+        ));
+    }
+
+    let output = match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(ty.span),
+                message: "panics across a C boundary are naughty!".into(),
+                help: None,
+            });
+        }
+        ast::FunctionRetTy::Default(..) => "void".to_owned(),
+        ast::FunctionRetTy::Ty(ref ty) => rust_to_d(ty)?,
+    };
+
+    Ok((args.join(", "), output))
+}
+
+/// Convert a Rust type into a D type.
+fn rust_to_d(ty: &ast::Ty) -> Result<String, Error> {
+    // Nullable pointers map to plain D pointers.
+    if let Some(inner) = option_inner(ty) {
+        return rust_to_d(inner);
+    }
+
+    match ty.node {
+        // Function pointers declared in the `extern (C)` scope use the C calling convention.
+        ast::TyKind::BareFn(ref bare_fn) => {
+            let (args, output) = fn_decl_to_d(&bare_fn.decl)?;
+            Ok(format!("{} function({})", output, args))
+        }
+        ast::TyKind::Array(ref inner, ref len) => Ok(format!(
+            "{}[{}]",
+            rust_to_d(inner)?,
+            pprust::expr_to_string(len)
+        )),
+        ast::TyKind::Ptr(ref ptr) => {
+            let inner = rust_to_d(&ptr.ty)?;
+            match ptr.mutbl {
+                ast::Mutability::Immutable => Ok(format!("const({})*", inner)),
+                ast::Mutability::Mutable => Ok(format!("{}*", inner)),
+            }
+        }
+        ast::TyKind::Path(None, ref path) => path_to_d(path),
+        _ => {
+            let new_type = pprust::ty_to_string(ty);
+            if new_type == "()" {
+                Ok("void".to_owned())
+            } else {
+                Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: format!("bindgen can not handle the type `{}`", new_type),
+                    help: None,
+                })
+            }
+        }
+    }
+}
+
+/// Convert a Rust path type into a D type, supporting the types of `libc` and `std::os::raw`.
+fn path_to_d(path: &ast::Path) -> Result<String, Error> {
+    let (ty, module) = match path.segments.split_last() {
+        Some(segments) => segments,
+        None => {
+            return Err(Error {
+                level: Level::Bug,
+                span: Some(path.span),
+                message: "invalid type".into(),
+                help: None,
+            })
+        }
+    };
+
+    let module: Vec<_> = module
+        .iter()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .collect();
+    match &*module.join("::") {
+        "" | "libc" | "std::os::raw" => {}
+        _ => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(path.span),
+                message: "can not handle types in other modules (except `libc` and `std::os::raw`)"
+                    .into(),
+                help: None,
+            })
+        }
+    }
+
+    let ty = match &*ty.identifier.name.as_str() {
+        "c_void" => "void",
+        "i8" | "c_schar" => "byte",
+        "i16" | "c_short" => "short",
+        "i32" | "c_int" => "int",
+        "i64" | "c_longlong" => "long",
+        "isize" => "ptrdiff_t",
+        "u8" | "c_uchar" => "ubyte",
+        "u16" | "c_ushort" => "ushort",
+        "u32" | "c_uint" => "uint",
+        "u64" | "c_ulonglong" => "ulong",
+        "usize" => "size_t",
+        "f32" | "c_float" => "float",
+        "f64" | "c_double" => "double",
+        "bool" => "bool",
+        "c_char" => "char",
+        "c_long" => "c_long",
+        "c_ulong" => "c_ulong",
+        // All other types are user-defined.
+        ty => return Ok(ident(ty)),
+    };
+
+    Ok(ty.to_owned())
+}
+
+/// Append an underscore to the identifier if it is a D keyword.
+fn ident(name: &str) -> String {
+    escape_keyword(name, KEYWORDS, "{}_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            pub const NAME: &'static str = \"safe\";
+
+            pub type Id = u64;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = 2,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                /// Vertical.
+                y: i32,
+                tags: [u8; 4],
+            }
+
+            bitflags! {
+                pub struct Flags: u32 {
+                    const READ = 1;
+                    const WRITE = 2;
+                }
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_name(
+                point: *const Point,
+                user_data: *mut c_void,
+                cb: extern \"C\" fn(user_data: *mut c_void, name: *const c_char),
+                version: usize,
+            ) -> bool {}
+        ";
+
+        let mut lang = LangD::new();
+        lang.set_lib_name("safe_app");
+        lang.set_module("safe.app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.d"],
+            "module safe.app;\n\n\
+             import core.stdc.config : c_long, c_ulong;\n\n\
+             extern (C):\n\
+             nothrow:\n\
+             @nogc:\n\n\
+             /// Maximum length.\n\
+             enum uint MAX_LEN = 16;\n\n\
+             enum NAME = \"safe\";\n\n\
+             alias Id = ulong;\n\n\
+             enum Status : int {\n    \
+             /// Success.\n    \
+             Ok = 0,\n    \
+             Failed = 2,\n\
+             }\n\n\
+             struct Point {\n    \
+             int x;\n    \
+             /// Vertical.\n    \
+             int y;\n    \
+             ubyte[4] tags;\n\
+             }\n\n\
+             alias Flags = uint;\n\n\
+             enum Flags Flags_READ = 0x1;\n\
+             enum Flags Flags_WRITE = 0x2;\n\n\
+             bool point_name(const(Point)* point, void* user_data, \
+             void function(void* user_data, const(char)* name) cb, size_t version_);\n\n"
+        );
+    }
+
+    #[test]
+    fn struct_layout() {
+        let source = "
+            #[repr(C, packed)]
+            pub struct Packed {
+                tag: u8,
+                value: u32,
+            }
+
+            #[repr(C, align(16))]
+            pub struct Aligned {
+                tag: u8,
+            }
+        ";
+
+        let mut lang = LangD::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert!(outputs["safe_app.d"].ends_with(
+            "struct Packed {\n    \
+             align(1):\n    \
+             ubyte tag;\n    \
+             uint value;\n\
+             }\n\n\
+             align(16) struct Aligned {\n    \
+             ubyte tag;\n\
+             }\n\n"
+        ));
+    }
+}
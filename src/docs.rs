@@ -0,0 +1,544 @@
+//! Documentation generated from `#[cheddar(..)]` attributes, reference parameters and
+//! `Send`/`Sync` implementations.
+//!
+//! The notes are added to the doc comments of the items, so every backend renders them.
+
+use common::retrieve_slices;
+use config::Config;
+use lang_c::option_inner;
+use references::{is_reference, returns_reference};
+use std::collections::HashMap;
+use std::mem;
+use syntax::ast;
+use syntax::attr;
+use syntax::codemap::DUMMY_SP;
+use syntax::print::pprust;
+use syntax::symbol::Symbol;
+
+/// Returns the attributes of the item extended with the generated notes, or `None` if there is
+/// nothing to add.
+pub fn augment(item: &ast::Item, config: &Config) -> Option<Vec<ast::Attribute>> {
+    let mut notes = ownership_notes(&item.attrs);
+    notes.extend(
+        retrieve_slices(&item.attrs)
+            .iter()
+            .map(|&(ref ptr, ref len)| {
+                format!("`{}` points to an array of `{}` elements.", ptr, len)
+            }),
+    );
+    notes.extend(reference_notes(item));
+    if let Some(ref types) = config.thread_safety {
+        notes.extend(thread_safety_notes(item, &types.borrow()));
+    }
+    if notes.is_empty() {
+        return None;
+    }
+
+    let mut attrs = item.attrs.clone();
+
+    // Separate the notes from the existing docs.
+    if attrs
+        .iter()
+        .any(|attr| attr.name() == "doc" && attr.value_str().is_some())
+    {
+        attrs.push(doc_attr(""));
+    }
+
+    attrs.extend(notes.iter().map(|note| doc_attr(note)));
+    Some(attrs)
+}
+
+/// Treatment of a section of the docs (e.g. `# Safety`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum SectionPolicy {
+    /// Emit the section as is (default).
+    Keep,
+    /// Remove the heading and the content of the section.
+    Drop,
+    /// Replace the heading with the given text (e.g. `@warning` for Doxygen).
+    Rename(String),
+}
+
+/// Settings of the rewriting of the Rust-specific parts of the docs for a target language.
+#[derive(Clone, Debug, Default)]
+pub struct DocSections {
+    /// Policies of the sections, indexed by heading (without the `#`s).
+    sections: HashMap<String, SectionPolicy>,
+    /// Whether the Rust code blocks are removed.
+    drop_rust_code: bool,
+}
+
+impl DocSections {
+    /// Set the treatment of the sections with the given heading (e.g. `"Safety"`).
+    pub fn set<T: Into<String>>(&mut self, heading: T, policy: SectionPolicy) {
+        let _ = self.sections.insert(heading.into(), policy);
+    }
+
+    /// Remove the Rust code blocks (untagged or tagged `rust`, `no_run`, etc.).
+    pub fn drop_rust_code(&mut self, enabled: bool) {
+        self.drop_rust_code = enabled;
+    }
+
+    /// Rewrite the sections and the code blocks of `docs`, made of `///` comment lines.
+    pub fn apply(&self, docs: &str) -> String {
+        if self.sections.is_empty() && !self.drop_rust_code {
+            return docs.to_string();
+        }
+
+        let mut output = String::with_capacity(docs.len());
+        // Level of the heading of the section being dropped, if any.
+        let mut dropped_level = None;
+        // Whether the current code block is kept, if inside one.
+        let mut code = None;
+        // Whether the empty line following a dropped code block is dropped too.
+        let mut skip_blank = false;
+
+        for line in docs.lines() {
+            if mem::replace(&mut skip_blank, false) && is_blank(line) {
+                continue;
+            }
+
+            let text = line.trim_left();
+            let text = text.trim_left_matches("///").trim_left_matches("//!").trim();
+
+            if text.starts_with("```") {
+                let keep = match code {
+                    Some(keep) => {
+                        code = None;
+                        skip_blank = !keep;
+                        keep
+                    }
+                    None => {
+                        let keep = !self.drop_rust_code || !is_rust_code(&text[3..]);
+                        code = Some(keep);
+                        keep
+                    }
+                };
+                if keep && dropped_level.is_none() {
+                    output.push_str(&format!("{}\n", line));
+                }
+                continue;
+            }
+
+            let level = text.chars().take_while(|&c| c == '#').count();
+            if code.is_none() && level > 0 && text[level..].starts_with(' ') {
+                if dropped_level.map_or(false, |dropped| level > dropped) {
+                    continue;
+                }
+                dropped_level = None;
+
+                match self.sections.get(text[level..].trim()) {
+                    Some(&SectionPolicy::Drop) => {
+                        dropped_level = Some(level);
+                        continue;
+                    }
+                    Some(&SectionPolicy::Rename(ref heading)) => {
+                        output.push_str(&format!("{}\n", line.replace(text, heading)));
+                        continue;
+                    }
+                    Some(&SectionPolicy::Keep) | None => {}
+                }
+            }
+
+            if dropped_level.is_none() && code != Some(false) {
+                output.push_str(&format!("{}\n", line));
+            }
+        }
+
+        // Don't leave the empty line separating the dropped last section.
+        if dropped_level.is_some() {
+            let start = output[..output.len().saturating_sub(1)]
+                .rfind('\n')
+                .map_or(0, |index| index + 1);
+            if is_blank(&output[start..]) {
+                output.truncate(start);
+            }
+        }
+
+        output
+    }
+}
+
+/// Whether a doc comment line is empty.
+fn is_blank(line: &str) -> bool {
+    line.trim()
+        .trim_left_matches("///")
+        .trim_left_matches("//!")
+        .trim()
+        .is_empty()
+}
+
+/// Whether the info string of a code block (e.g. `rust,no_run`) denotes Rust code, as rustdoc
+/// assumes for untagged blocks.
+fn is_rust_code(info: &str) -> bool {
+    info.split(',').map(str::trim).all(|tag| {
+        [
+            "",
+            "rust",
+            "ignore",
+            "no_run",
+            "should_panic",
+            "compile_fail",
+        ].contains(&tag) || tag.starts_with("edition")
+    })
+}
+
+/// Kind of the item targeted by an intra-doc link, guessed from the Rust naming conventions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkKind {
+    /// `snake_case` name.
+    Function,
+    /// `SCREAMING_SNAKE_CASE` name.
+    Constant,
+    /// `CamelCase` name.
+    Type,
+}
+
+/// Replace the intra-doc links of `docs` (e.g. ``[`App`]`` or `[ffi::app_new()]`) with the
+/// references returned by `render` for the name and the kind of the linked item. Code blocks,
+/// code spans and regular Markdown links are left alone.
+pub fn replace_links<F>(docs: &str, mut render: F) -> String
+where
+    F: FnMut(&str, LinkKind) -> String,
+{
+    let mut output = String::with_capacity(docs.len());
+    let mut in_code = false;
+
+    for (index, line) in docs.split('\n').enumerate() {
+        if index > 0 {
+            output.push('\n');
+        }
+        if line.contains("```") {
+            in_code = !in_code;
+        }
+        if in_code || line.contains("```") {
+            output.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| c == '[' || c == '`') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if rest.starts_with('`') {
+                let end = rest[1..].find('`').map_or(rest.len(), |end| end + 2);
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+
+            // `[text](url)` and `[text][reference]` are regular links.
+            let regular = output.ends_with(']');
+            let link = rest.find(']').and_then(|end| {
+                let next = rest[end + 1..].chars().next();
+                if regular || next == Some('(') || next == Some('[') {
+                    return None;
+                }
+                link_target(&rest[1..end]).map(|(name, kind)| (end, render(name, kind)))
+            });
+
+            match link {
+                Some((end, reference)) => {
+                    output.push_str(&reference);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    output.push('[');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        output.push_str(rest);
+    }
+
+    output
+}
+
+/// Returns the name and the kind of the item targeted by the text of an intra-doc link, or `None`
+/// if the text isn't a path.
+fn link_target(text: &str) -> Option<(&str, LinkKind)> {
+    let text = text.trim_matches('`');
+    // Disambiguators (`struct@App`, `fn@app_new`) and the parentheses of functions.
+    let text = match text.find('@') {
+        Some(index) => &text[index + 1..],
+        None => text,
+    };
+    let text = text.trim_right_matches("()");
+
+    let valid = text.split("::").all(|segment| {
+        !segment.is_empty() && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    });
+    let name = text.rsplit("::").next()?;
+    if !valid || name.starts_with(|c: char| c.is_digit(10)) {
+        return None;
+    }
+
+    let kind = if name.starts_with(|c: char| c.is_lowercase()) {
+        LinkKind::Function
+    } else if name.chars().all(|c| !c.is_lowercase()) {
+        LinkKind::Constant
+    } else {
+        LinkKind::Type
+    };
+
+    Some((name, kind))
+}
+
+/// Create a `/// text` doc comment.
+fn doc_attr(text: &str) -> ast::Attribute {
+    let text = if text.is_empty() {
+        "///".to_string()
+    } else {
+        format!("/// {}", text)
+    };
+
+    attr::mk_sugared_doc_attr(attr::mk_attr_id(), Symbol::intern(&text), DUMMY_SP)
+}
+
+/// Describe the memory ownership rules given by `#[cheddar(owns)]`, `#[cheddar(borrows)]` and
+/// `#[cheddar(free_with = "thing_free")]` (applying to the returned pointer) or
+/// `#[cheddar(owns = "param")]` and `#[cheddar(borrows = "param")]` (applying to a parameter).
+fn ownership_notes(attrs: &[ast::Attribute]) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    for attr in attrs {
+        let items = match attr.value.node {
+            ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items,
+            _ => continue,
+        };
+
+        for item in items {
+            let item = match item.node {
+                ast::NestedMetaItemKind::MetaItem(ref item) => item,
+                _ => continue,
+            };
+            let value = item.value_str().map(|value| value.as_str().to_string());
+
+            let note = match (&*item.name.as_str(), value) {
+                ("owns", None) => {
+                    "Ownership of the returned pointer is transferred to the caller.".to_string()
+                }
+                ("owns", Some(param)) => {
+                    format!("Ownership of `{}` is transferred to the callee.", param)
+                }
+                ("borrows", None) => "The returned pointer is borrowed and must not be freed \
+                                      by the caller."
+                    .to_string(),
+                ("borrows", Some(param)) => {
+                    format!("`{}` is only borrowed for the duration of the call.", param)
+                }
+                ("free_with", Some(function)) => {
+                    format!("The returned pointer must be freed with `{}`.", function)
+                }
+                _ => continue,
+            };
+
+            notes.push(note);
+        }
+    }
+
+    notes
+}
+
+/// Describe the requirements of the reference parameters of a function and the guarantees of a
+/// returned reference, which are declared as pointers.
+fn reference_notes(item: &ast::Item) -> Vec<String> {
+    match item.node {
+        ast::ItemKind::Fn(ref fn_decl, ..) => {
+            let mut notes: Vec<_> = fn_decl
+                .inputs
+                .iter()
+                .filter(|arg| is_reference(&arg.ty))
+                .map(|arg| {
+                    format!(
+                        "`{}` must be non-null and valid for the duration of the call.",
+                        pprust::pat_to_string(&arg.pat)
+                    )
+                })
+                .collect();
+            if returns_reference(fn_decl) {
+                notes.push("The returned pointer is non-null.".to_string());
+            }
+            notes
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether the values of a handle type can be sent to or shared with other threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ThreadSafety {
+    pub send: bool,
+    pub sync: bool,
+}
+
+/// Record the thread safety of the types of a module, declared by implementing `Send` and
+/// `Sync` (e.g. `unsafe impl Sync for App {}`, or `impl !Sync for App {}`) or with the
+/// `#[cheddar(send)]`, `#[cheddar(sync)]` and `#[cheddar(thread_unsafe)]` attributes of the type.
+pub fn collect_thread_safety(module: &ast::Mod, types: &mut HashMap<String, ThreadSafety>) {
+    for item in &module.items {
+        match item.node {
+            ast::ItemKind::Impl(_, polarity, _, Some(ref trait_ref), ref self_ty, _) => {
+                let name = match self_ty.node {
+                    ast::TyKind::Path(None, ref path) => path_name(path),
+                    _ => continue,
+                };
+                let positive = polarity == ast::ImplPolarity::Positive;
+                let safety = types.entry(name).or_insert_with(ThreadSafety::default);
+
+                match &*path_name(&trait_ref.path) {
+                    "Send" => safety.send = positive,
+                    "Sync" => safety.sync = positive,
+                    _ => {}
+                }
+            }
+            ast::ItemKind::Struct(..) | ast::ItemKind::Enum(..) | ast::ItemKind::Ty(..) => {
+                for attr in &item.attrs {
+                    let items = match attr.value.node {
+                        ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items,
+                        _ => continue,
+                    };
+
+                    for nested in items {
+                        let name = match nested.node {
+                            ast::NestedMetaItemKind::MetaItem(ref nested) => nested.name.as_str(),
+                            _ => continue,
+                        };
+                        if !["send", "sync", "thread_unsafe"].contains(&&*name) {
+                            continue;
+                        }
+
+                        let safety = types
+                            .entry(item.ident.name.as_str().to_string())
+                            .or_insert_with(ThreadSafety::default);
+                        match &*name {
+                            "send" => safety.send = true,
+                            "sync" => safety.sync = true,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Describe whether the handles passed to a function can be used across threads.
+fn thread_safety_notes(item: &ast::Item, types: &HashMap<String, ThreadSafety>) -> Vec<String> {
+    let fn_decl = match item.node {
+        ast::ItemKind::Fn(ref fn_decl, ..) => fn_decl,
+        _ => return Vec::new(),
+    };
+
+    fn_decl
+        .inputs
+        .iter()
+        .filter_map(|arg| {
+            let ty = option_inner(&arg.ty).unwrap_or(&arg.ty);
+            let name = match ty.node {
+                ast::TyKind::Ptr(ref ptr) | ast::TyKind::Rptr(_, ref ptr) => match ptr.ty.node {
+                    ast::TyKind::Path(None, ref path) => path_name(path),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            let safety = types.get(&name)?;
+            let param = pprust::pat_to_string(&arg.pat);
+
+            Some(match (safety.send, safety.sync) {
+                (_, true) => format!(
+                    "This `{}` handle (`{}`) may be shared across threads.",
+                    name, param
+                ),
+                (true, false) => format!(
+                    "This `{}` handle (`{}`) may be moved to another thread, but may not be \
+                     shared across threads.",
+                    name, param
+                ),
+                (false, false) => format!(
+                    "This `{}` handle (`{}`) may not be shared across threads.",
+                    name, param
+                ),
+            })
+        })
+        .collect()
+}
+
+/// The last segment of a path (e.g. `Sync` for `std::marker::Sync`).
+fn path_name(path: &ast::Path) -> String {
+    path.segments
+        .last()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let docs = "/// Frees the app.\n\
+                    ///\n\
+                    /// ```\n\
+                    /// app_free(app);\n\
+                    /// ```\n\
+                    ///\n\
+                    /// ```c\n\
+                    /// app_free(app);\n\
+                    /// ```\n\
+                    ///\n\
+                    /// # Safety\n\
+                    ///\n\
+                    /// `app` must not be used afterwards.\n\
+                    ///\n\
+                    /// # Panics\n\
+                    ///\n\
+                    /// ## Details\n\
+                    ///\n\
+                    /// Never.\n";
+
+        let mut sections = DocSections::default();
+        sections.set("Safety", SectionPolicy::Rename("@warning".to_string()));
+        sections.set("Panics", SectionPolicy::Drop);
+        sections.drop_rust_code(true);
+
+        assert_eq!(
+            sections.apply(docs),
+            "/// Frees the app.\n\
+             ///\n\
+             /// ```c\n\
+             /// app_free(app);\n\
+             /// ```\n\
+             ///\n\
+             /// @warning\n\
+             ///\n\
+             /// `app` must not be used afterwards.\n"
+        );
+    }
+
+    #[test]
+    fn links() {
+        let docs = "/// See [`App`], [ffi::app_new()] and [MAX_LEN].\n\
+                    /// Not links: [the docs](https://docs.rs), [text][App], `[App]`, [1].\n\
+                    /// ```\n\
+                    /// let app = [App];\n\
+                    /// ```";
+        let render = |name: &str, kind| match kind {
+            LinkKind::Function => format!("{}()", name),
+            LinkKind::Constant => format!("#{}", name),
+            LinkKind::Type => format!("<{}>", name),
+        };
+
+        assert_eq!(
+            replace_links(docs, render),
+            "/// See <App>, app_new() and #MAX_LEN.\n\
+             /// Not links: [the docs](https://docs.rs), [text][App], `[App]`, [1].\n\
+             /// ```\n\
+             /// let app = [App];\n\
+             /// ```"
+        );
+    }
+}
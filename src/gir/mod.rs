@@ -0,0 +1,565 @@
+//! Generation of GObject-introspection repositories (`.gir`) describing the C API.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, extern_fn, literal_const, repr_c_enum,
+    repr_c_struct, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use inflector::Inflector;
+use lang_c::{option_inner, rust_to_c};
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "  ";
+
+/// Generates a `<Namespace>-<version>.gir` file describing the functions, records, enumerations
+/// and constants of the C API (as generated by `LangC`), so that bindings can be produced with
+/// the introspection toolchain (e.g. `g-ir-compiler`).
+pub struct LangGir {
+    lib_name: String,
+    namespace: Option<String>,
+    version: String,
+}
+
+impl LangGir {
+    pub fn new() -> Self {
+        LangGir {
+            lib_name: "backend".to_owned(),
+            namespace: None,
+            version: "1.0".to_owned(),
+        }
+    }
+
+    /// Set the name of the native library (and of its C header).
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Set the name and the version of the introspection namespace. The namespace defaults to the
+    /// name of the native library in PascalCase, and the version to `1.0`.
+    pub fn set_namespace<N: Into<String>, V: Into<String>>(&mut self, namespace: N, version: V) {
+        self.namespace = Some(namespace.into());
+        self.version = version.into();
+    }
+
+    fn namespace(&self) -> String {
+        self.namespace
+            .clone()
+            .unwrap_or_else(|| self.lib_name.to_pascal_case())
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}-{}.gir", self.namespace(), self.version)
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+}
+
+impl Default for LangGir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangGir {
+    fn name(&self) -> &'static str {
+        "gir"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into a `<constant>`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (ty, value) = match literal_const(item)? {
+            Some(constant) => (constant.ty, constant.value),
+            None => return Ok(()),
+        };
+
+        // Strings are rendered with quotes, which are not part of the value.
+        let value = if value.starts_with('"') {
+            value[1..value.len() - 1].to_string()
+        } else {
+            value.trim_left_matches('(').trim_right_matches(')').to_string()
+        };
+
+        let name = item.ident.name.as_str();
+        let indent = INDENT.repeat(2);
+        let mut buffer = format!(
+            "{}<constant name=\"{}\" value=\"{}\" c:type=\"{}\">\n",
+            indent,
+            name,
+            escape(&value),
+            name
+        );
+        buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+        buffer.push_str(&gir_type(ty, &INDENT.repeat(3))?);
+        buffer.push_str(&format!("{}</constant>\n", indent));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into an `<alias>`, or a `<callback>` for function pointers.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let indent = INDENT.repeat(2);
+
+        let ty = match alias_type(item)? {
+            Some(ty) => ty,
+            None => return Ok(()),
+        };
+
+        let buffer = match option_inner(ty).unwrap_or(ty).node {
+            ast::TyKind::BareFn(ref bare_fn) => {
+                let mut buffer = format!(
+                    "{}<callback name=\"{}\" c:type=\"{}\">\n",
+                    indent, name, name
+                );
+                buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+                buffer.push_str(&callable(&bare_fn.decl, 3)?);
+                buffer.push_str(&format!("{}</callback>\n", indent));
+                buffer
+            }
+            _ => {
+                let mut buffer = format!(
+                    "{}<alias name=\"{}\" c:type=\"{}\">\n",
+                    indent, name, name
+                );
+                buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+                buffer.push_str(&gir_type(ty, &INDENT.repeat(3))?);
+                buffer.push_str(&format!("{}</alias>\n", indent));
+                buffer
+            }
+        };
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into an `<enumeration>`.
+    ///
+    /// Enums with data-carrying variants are skipped, so they can only be used through pointers.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (variants, values) = match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => (variants, values),
+            Some(ReprCEnum::Opaque) | None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let indent = INDENT.repeat(2);
+        let mut buffer = format!(
+            "{}<enumeration name=\"{}\" c:type=\"{}\">\n",
+            indent, name, name
+        );
+        buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+        for (var, &(ref var_name, value)) in variants.iter().zip(&values) {
+            buffer.push_str(&member(
+                &var.node.attrs,
+                &var_name.to_snake_case(),
+                &value.to_string(),
+                &format!("{}_{}", name, var_name),
+            ));
+        }
+        buffer.push_str(&format!("{}</enumeration>\n", indent));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into a `<record>`.
+    ///
+    /// Tuple structs with a single field become opaque records.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (fields, disguised) = match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => (fields, ""),
+            Some(ReprCStruct::Opaque) => (Vec::new(), " disguised=\"1\""),
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let indent = INDENT.repeat(2);
+        let mut buffer = format!(
+            "{}<record name=\"{}\" c:type=\"{}\"{}>\n",
+            indent, name, name, disguised
+        );
+        buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+
+        for &(ref field_name, field) in &fields {
+            buffer.push_str(&format!(
+                "{}<field name=\"{}\" writable=\"1\">\n",
+                INDENT.repeat(3),
+                field_name
+            ));
+            buffer.push_str(&docs(&field.attrs, &INDENT.repeat(4)));
+            buffer.push_str(&gir_type(&field.ty, &INDENT.repeat(4))?);
+            buffer.push_str(&format!("{}</field>\n", INDENT.repeat(3)));
+        }
+
+        buffer.push_str(&format!("{}</record>\n", indent));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into a `<bitfield>`.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let indent = INDENT.repeat(2);
+        let mut buffer = format!(
+            "{}<bitfield name=\"{}\" c:type=\"{}\">\n",
+            indent, flags.name, flags.name
+        );
+        buffer.push_str(&docs(&flags.attrs, &INDENT.repeat(3)));
+        for flag in &flags.flags {
+            buffer.push_str(&member(
+                &flag.attrs,
+                &flag.name.to_snake_case(),
+                &flag.value.to_string(),
+                &format!("{}_{}", flags.name, flag.name),
+            ));
+        }
+        buffer.push_str(&format!("{}</bitfield>\n", indent));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into a `<function>`.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let fn_decl = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let indent = INDENT.repeat(2);
+        let mut buffer = format!(
+            "{}<function name=\"{}\" c:identifier=\"{}\">\n",
+            indent, name, name
+        );
+        buffer.push_str(&docs(&item.attrs, &INDENT.repeat(3)));
+        buffer.push_str(&callable(fn_decl, 3)?);
+        buffer.push_str(&format!("{}</function>\n", indent));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+
+        let _ = outputs.insert(
+            self.output_name(),
+            format!(
+                "<?xml version=\"1.0\"?>\n\
+                 <repository version=\"1.2\"\n\
+                 {0}{0}{0}xmlns=\"http://www.gtk.org/introspection/core/1.0\"\n\
+                 {0}{0}{0}xmlns:c=\"http://www.gtk.org/introspection/c/1.0\">\n\
+                 {0}<c:include name=\"{1}.h\"/>\n\
+                 {0}<namespace name=\"{2}\"\n\
+                 {0}{0}{0}{0}{0}version=\"{3}\"\n\
+                 {0}{0}{0}{0}{0}shared-library=\"lib{1}.so\"\n\
+                 {0}{0}{0}{0}{0}c:identifier-prefixes=\"\"\n\
+                 {0}{0}{0}{0}{0}c:symbol-prefixes=\"\">\n\
+                 {4}\
+                 {0}</namespace>\n\
+                 </repository>\n",
+                INDENT,
+                self.lib_name,
+                self.namespace(),
+                self.version,
+                body
+            ),
+        );
+
+        Ok(())
+    }
+}
+
+/// Describe the parameters and the return value of a function (or callback).
+fn callable(fn_decl: &ast::FnDecl, depth: usize) -> Result<String, Error> {
+    let indent = INDENT.repeat(depth);
+
+    let return_value = match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(ty.span),
+                message: "panics across a C boundary are naughty!".into(),
+                help: None,
+            });
+        }
+        ast::FunctionRetTy::Default(..) => format!(
+            "{}<type name=\"none\" c:type=\"void\"/>\n",
+            INDENT.repeat(depth + 1)
+        ),
+        ast::FunctionRetTy::Ty(ref ty) => gir_type(ty, &INDENT.repeat(depth + 1))?,
+    };
+
+    let mut buffer = format!(
+        "{0}<return-value transfer-ownership=\"none\">\n{1}{0}</return-value>\n",
+        indent, return_value
+    );
+
+    if !fn_decl.inputs.is_empty() {
+        buffer.push_str(&format!("{}<parameters>\n", indent));
+        for arg in &fn_decl.inputs {
+            buffer.push_str(&format!(
+                "{}<parameter name=\"{}\" transfer-ownership=\"none\">\n",
+                INDENT.repeat(depth + 1),
+                pprust::pat_to_string(&arg.pat)
+            ));
+            buffer.push_str(&gir_type(&arg.ty, &INDENT.repeat(depth + 2))?);
+            buffer.push_str(&format!("{}</parameter>\n", INDENT.repeat(depth + 1)));
+        }
+        buffer.push_str(&format!("{}</parameters>\n", indent));
+    }
+
+    Ok(buffer)
+}
+
+/// Describe a Rust type as a `<type>` (or an `<array>` for fixed-length arrays).
+fn gir_type(ty: &ast::Ty, indent: &str) -> Result<String, Error> {
+    let ty = option_inner(ty).unwrap_or(ty);
+
+    match ty.node {
+        ast::TyKind::Array(ref inner, ref len) => Ok(format!(
+            "{0}<array zero-terminated=\"0\" fixed-size=\"{1}\">\n{2}{0}</array>\n",
+            indent,
+            pprust::expr_to_string(len),
+            gir_type(inner, &format!("{}{}", indent, INDENT))?
+        )),
+        // Function pointers are declared as callbacks when named by a type alias.
+        ast::TyKind::BareFn(..) => Ok(format!(
+            "{}<type name=\"gpointer\" c:type=\"gpointer\"/>\n",
+            indent
+        )),
+        _ => {
+            let c_ty = rust_to_c(ty, "")?.1;
+            Ok(format!(
+                "{}<type name=\"{}\" c:type=\"{}\"/>\n",
+                indent,
+                gir_type_name(ty),
+                escape(&c_ty.to_string())
+            ))
+        }
+    }
+}
+
+/// The introspection name of a type (e.g. `gint32` for `i32` or `utf8` for `*const c_char`).
+fn gir_type_name(ty: &ast::Ty) -> String {
+    match ty.node {
+        ast::TyKind::Ptr(ref ptr) => match ptr.ty.node {
+            ast::TyKind::Path(None, ref path) => {
+                let name = path_name(path);
+                match &*name {
+                    "c_char" if ptr.mutbl == ast::Mutability::Immutable => "utf8".to_owned(),
+                    _ if primitive_name(&name).is_some() => "gpointer".to_owned(),
+                    _ => name,
+                }
+            }
+            _ => "gpointer".to_owned(),
+        },
+        ast::TyKind::Path(None, ref path) => {
+            let name = path_name(path);
+            primitive_name(&name).map_or(name, |name| name.to_owned())
+        }
+        _ => "none".to_owned(),
+    }
+}
+
+/// The introspection name of a primitive type.
+fn primitive_name(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "c_void" => "none",
+        "i8" | "c_schar" => "gint8",
+        "i16" => "gint16",
+        "i32" => "gint32",
+        "i64" => "gint64",
+        "isize" => "gssize",
+        "u8" | "c_uchar" => "guint8",
+        "u16" => "guint16",
+        "u32" => "guint32",
+        "u64" => "guint64",
+        "usize" => "gsize",
+        "f32" | "c_float" => "gfloat",
+        "f64" | "c_double" => "gdouble",
+        "bool" => "gboolean",
+        "c_char" => "gchar",
+        "c_short" => "gshort",
+        "c_ushort" => "gushort",
+        "c_int" => "gint",
+        "c_uint" => "guint",
+        "c_long" => "glong",
+        "c_ulong" => "gulong",
+        "c_longlong" => "gint64",
+        "c_ulonglong" => "guint64",
+        _ => return None,
+    })
+}
+
+/// The last segment of a path (e.g. `c_int` for `libc::c_int`).
+fn path_name(path: &ast::Path) -> String {
+    path.segments
+        .last()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .unwrap_or_default()
+}
+
+/// A `<member>` of an enumeration or a bitfield.
+fn member(attrs: &[ast::Attribute], name: &str, value: &str, identifier: &str) -> String {
+    let indent = INDENT.repeat(3);
+    let docs = docs(attrs, &INDENT.repeat(4));
+
+    if docs.is_empty() {
+        format!(
+            "{}<member name=\"{}\" value=\"{}\" c:identifier=\"{}\"/>\n",
+            indent, name, value, identifier
+        )
+    } else {
+        format!(
+            "{0}<member name=\"{1}\" value=\"{2}\" c:identifier=\"{3}\">\n{4}{0}</member>\n",
+            indent, name, value, identifier, docs
+        )
+    }
+}
+
+/// Collect the doc comments into a `<doc>` node.
+fn docs(attrs: &[ast::Attribute], indent: &str) -> String {
+    let text = doc_comments(attrs, "", "");
+
+    if text.is_empty() {
+        text
+    } else {
+        format!(
+            "{}<doc xml:space=\"preserve\">{}</doc>\n",
+            indent,
+            escape(text.trim_right_matches('\n'))
+        )
+    }
+}
+
+/// Escape the XML special characters of `text`.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                NotFound = 2,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                tags: [u8; 4],
+            }
+
+            /// Compute the name of a `Point` & more.
+            #[no_mangle]
+            pub extern \"C\" fn point_name(point: *const Point, name: *const c_char) -> bool {}
+        ";
+
+        let mut lang = LangGir::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["SafeApp-1.0.gir"],
+            "<?xml version=\"1.0\"?>\n\
+             <repository version=\"1.2\"\n      \
+             xmlns=\"http://www.gtk.org/introspection/core/1.0\"\n      \
+             xmlns:c=\"http://www.gtk.org/introspection/c/1.0\">\n  \
+             <c:include name=\"safe_app.h\"/>\n  \
+             <namespace name=\"SafeApp\"\n          \
+             version=\"1.0\"\n          \
+             shared-library=\"libsafe_app.so\"\n          \
+             c:identifier-prefixes=\"\"\n          \
+             c:symbol-prefixes=\"\">\n    \
+             <constant name=\"MAX_LEN\" value=\"16\" c:type=\"MAX_LEN\">\n      \
+             <doc xml:space=\"preserve\">Maximum length.</doc>\n      \
+             <type name=\"guint32\" c:type=\"uint32_t\"/>\n    \
+             </constant>\n    \
+             <enumeration name=\"Status\" c:type=\"Status\">\n      \
+             <member name=\"ok\" value=\"0\" c:identifier=\"Status_Ok\">\n        \
+             <doc xml:space=\"preserve\">Success.</doc>\n      \
+             </member>\n      \
+             <member name=\"not_found\" value=\"2\" c:identifier=\"Status_NotFound\"/>\n    \
+             </enumeration>\n    \
+             <record name=\"Point\" c:type=\"Point\">\n      \
+             <field name=\"x\" writable=\"1\">\n        \
+             <type name=\"gint32\" c:type=\"int32_t\"/>\n      \
+             </field>\n      \
+             <field name=\"tags\" writable=\"1\">\n        \
+             <array zero-terminated=\"0\" fixed-size=\"4\">\n          \
+             <type name=\"guint8\" c:type=\"uint8_t\"/>\n        \
+             </array>\n      \
+             </field>\n    \
+             </record>\n    \
+             <function name=\"point_name\" c:identifier=\"point_name\">\n      \
+             <doc xml:space=\"preserve\">Compute the name of a `Point` &amp; more.</doc>\n      \
+             <return-value transfer-ownership=\"none\">\n        \
+             <type name=\"gboolean\" c:type=\"bool\"/>\n      \
+             </return-value>\n      \
+             <parameters>\n        \
+             <parameter name=\"point\" transfer-ownership=\"none\">\n          \
+             <type name=\"Point\" c:type=\"Point const*\"/>\n        \
+             </parameter>\n        \
+             <parameter name=\"name\" transfer-ownership=\"none\">\n          \
+             <type name=\"utf8\" c:type=\"char const*\"/>\n        \
+             </parameter>\n      \
+             </parameters>\n    \
+             </function>\n  \
+             </namespace>\n\
+             </repository>\n"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Registry of the opaque handle types, shared by all the target languages so the same types are
+//! declared as handles everywhere (e.g. `typedef struct App App;` in C and a `SafeHandle` in C#).
+
+use common::{
+    check_no_mangle, check_repr_c, has_cheddar_flag, is_extern, parse_attr, retrieve_free_with,
+};
+use inflector::Inflector;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use syntax::ast;
+use syntax::codemap::Span;
+use Error;
+use Level;
+
+/// Opaque handle types registered with `Bindgen::opaque_handle` or marked `#[cheddar(handle)]`,
+/// and the functions freeing them.
+#[derive(Default)]
+pub struct HandleRegistry {
+    /// Handles registered through the configuration, with their free function if given.
+    pub registered: BTreeMap<String, Option<String>>,
+    /// All the handles and their free function (if any), resolved from the crate before parsing.
+    pub handles: RefCell<BTreeMap<String, Option<String>>>,
+}
+
+/// Declarations of a type found in the crate.
+struct Declaration {
+    span: Span,
+    repr_c: bool,
+}
+
+/// Handle attributes, type declarations and exported functions of the crate.
+#[derive(Default)]
+pub struct HandleCollector {
+    /// Types marked `#[cheddar(handle)]`, with their free function and location.
+    marked: Vec<(String, Option<String>, Span)>,
+    /// Structs, enums and aliases declared in the crate.
+    declared: HashMap<String, Declaration>,
+    /// Exported functions.
+    functions: BTreeSet<String>,
+    /// Types pointed to by the parameters and return types of the exported functions.
+    pointees: BTreeSet<String>,
+}
+
+impl HandleCollector {
+    /// Record the handles, the type declarations and the exported functions of a module.
+    pub fn collect(&mut self, module: &ast::Mod) {
+        for item in &module.items {
+            let name = item.ident.name.as_str().to_string();
+            match item.node {
+                ast::ItemKind::Struct(..) | ast::ItemKind::Enum(..) | ast::ItemKind::Ty(..) => {
+                    let handle = item.attrs.iter().any(|attr| has_cheddar_flag(attr, "handle"));
+                    if handle {
+                        let (_, free_fn) = parse_attr(&item.attrs, |_| false, retrieve_free_with);
+                        let free_fn = if free_fn.is_empty() {
+                            None
+                        } else {
+                            Some(free_fn)
+                        };
+                        self.marked.push((name.clone(), free_fn, item.span));
+                    }
+
+                    let (repr_c, _) = parse_attr(&item.attrs, check_repr_c, |_| None);
+                    let _ = self.declared.insert(
+                        name,
+                        Declaration {
+                            span: item.span,
+                            repr_c: repr_c || is_alias(item),
+                        },
+                    );
+                }
+                ast::ItemKind::Fn(ref fn_decl, _, _, abi, _, _) => {
+                    if is_extern(abi) && parse_attr(&item.attrs, check_no_mangle, |_| None).0 {
+                        let _ = self.functions.insert(name);
+                        for arg in &fn_decl.inputs {
+                            self.pointee(&arg.ty);
+                        }
+                        if let ast::FunctionRetTy::Ty(ref ty) = fn_decl.output {
+                            self.pointee(ty);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Record the type pointed to by a pointer type (through any `Option`).
+    fn pointee(&mut self, ty: &ast::Ty) {
+        match ty.node {
+            ast::TyKind::Ptr(ref ptr) => match ptr.ty.node {
+                ast::TyKind::Path(None, ref path) => if let Some(segment) = path.segments.last() {
+                    let _ = self.pointees.insert(segment.identifier.name.as_str().to_string());
+                },
+                _ => self.pointee(&ptr.ty),
+            },
+            ast::TyKind::Path(None, ref path) => if let Some(segment) = path.segments.last() {
+                if segment.identifier.name != "Option" {
+                    return;
+                }
+                if let Some(ref parameters) = segment.parameters {
+                    if let ast::PathParameters::AngleBracketed(ref data) = **parameters {
+                        for ty in &data.types {
+                            self.pointee(ty);
+                        }
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Merge the handles marked in the crate with the registered ones, and check them against
+    /// the declarations of the crate.
+    ///
+    /// Handles registered or marked with different free functions, marked in several modules, or
+    /// also declared as `#[repr(C)]` types (or aliases) are errors. Registered handles not
+    /// declared in the crate, free functions which are not exported, and unregistered types
+    /// passed by pointer to the exported functions are reported as warnings. Handles without a
+    /// free function use the exported `<type>_free` function (in snake case), if any.
+    pub fn resolve(
+        &self,
+        registered: &BTreeMap<String, Option<String>>,
+    ) -> Result<BTreeMap<String, Option<String>>, Vec<Error>> {
+        let mut handles = registered.clone();
+        let mut errors = Vec::new();
+        let mut marked = BTreeSet::new();
+
+        for &(ref name, ref free_fn, span) in &self.marked {
+            if !marked.insert(name) {
+                errors.push(Error {
+                    level: Level::Error,
+                    span: Some(span),
+                    message: format!("opaque handle `{}` is declared more than once", name),
+                    help: Some("rename one of the types".into()),
+                });
+                continue;
+            }
+
+            // The free function given to the configuration takes precedence.
+            if let Some(&Some(ref registered)) = handles.get(name) {
+                match *free_fn {
+                    Some(ref free_fn) if free_fn != registered => errors.push(Error {
+                        level: Level::Error,
+                        span: Some(span),
+                        message: format!(
+                            "opaque handle `{}` is freed by `{}` but registered with `{}`",
+                            name, free_fn, registered
+                        ),
+                        help: None,
+                    }),
+                    _ => {}
+                }
+                continue;
+            }
+            let _ = handles.insert(name.clone(), free_fn.clone());
+        }
+
+        for (name, free_fn) in &mut handles {
+            match self.declared.get(name) {
+                Some(declaration) if declaration.repr_c => errors.push(Error {
+                    level: Level::Error,
+                    span: Some(declaration.span),
+                    message: format!(
+                        "`{}` is both an opaque handle and a type declared to the target \
+                         languages",
+                        name
+                    ),
+                    help: Some(
+                        "remove `#[repr(C)]` to keep the type opaque, or unregister the handle"
+                            .into(),
+                    ),
+                }),
+                Some(_) => {}
+                None => warn!("opaque handle `{}` is not declared in the crate", name),
+            }
+
+            if free_fn.is_none() {
+                let detected = format!("{}_free", name.to_snake_case());
+                if self.functions.contains(&detected) {
+                    *free_fn = Some(detected);
+                }
+            }
+            if let Some(ref free_fn) = *free_fn {
+                if !self.functions.contains(free_fn) {
+                    warn!(
+                        "function `{}` freeing the opaque handle `{}` is not exported",
+                        free_fn, name
+                    );
+                }
+            }
+        }
+
+        for name in &self.pointees {
+            let opaque = self.declared
+                .get(name)
+                .map_or(false, |declaration| !declaration.repr_c);
+            if opaque && !handles.contains_key(name) {
+                warn!(
+                    "`{}` is passed by pointer to the exported functions but is not registered \
+                     as an opaque handle",
+                    name
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(handles)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Whether the item is a type alias, which is declared to the target languages.
+fn is_alias(item: &ast::Item) -> bool {
+    match item.node {
+        ast::ItemKind::Ty(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(source: &str) -> HandleCollector {
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut collector = HandleCollector::default();
+        collector.collect(&krate.module);
+        collector
+    }
+
+    #[test]
+    fn resolve() {
+        let collector = collect(
+            "
+            #[cheddar(handle, free_with = \"auth_release\")]
+            pub struct Authenticator;
+            pub struct App;
+            pub struct Session;
+
+            #[no_mangle]
+            pub extern \"C\" fn app_free(app: *mut App) {}
+            #[no_mangle]
+            pub extern \"C\" fn auth_release(auth: *mut Authenticator) {}
+            #[no_mangle]
+            pub extern \"C\" fn session_get(app: *const App) -> *mut Session {}
+            ",
+        );
+
+        let mut registered = BTreeMap::new();
+        let _ = registered.insert("App".to_string(), None);
+        let handles = unwrap!(collector.resolve(&registered));
+
+        let mut expected = BTreeMap::new();
+        let _ = expected.insert("App".to_string(), Some("app_free".to_string()));
+        let _ = expected.insert("Authenticator".to_string(), Some("auth_release".to_string()));
+        assert_eq!(handles, expected);
+    }
+
+    #[test]
+    fn collisions() {
+        let collector = collect(
+            "
+            #[cheddar(handle, free_with = \"app_release\")]
+            pub struct App;
+            #[repr(C)]
+            pub struct Session { id: u32 }
+            ",
+        );
+
+        let mut registered = BTreeMap::new();
+        let _ = registered.insert("App".to_string(), Some("app_free".to_string()));
+        let _ = registered.insert("Session".to_string(), None);
+        let errors = match collector.resolve(&registered) {
+            Ok(handles) => panic!("unexpected success: {:?}", handles),
+            Err(errors) => errors,
+        };
+
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "opaque handle `App` is freed by `app_release` but registered with `app_free`",
+                "`Session` is both an opaque handle and a type declared to the target languages",
+            ]
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! Generation of Haskell FFI bindings.
+//!
+//! The output is an `hsc2hs` source, so the sizes and offsets of the structs used by the
+//! `Storable` instances are read from the C header. This also keeps the layout of
+//! `#[repr(packed)]` and `#[repr(align(N))]` structs, which the header declares.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, escape_keyword, extern_fn, literal_const,
+    repr_c_enum, repr_c_struct, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use inflector::Inflector;
+use lang_c::option_inner;
+use std::path::MAIN_SEPARATOR;
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+/// Haskell keywords, which get a prime appended when used as identifiers.
+const KEYWORDS: &[&str] = &[
+    "case", "class", "data", "default", "deriving", "do", "else", "foreign", "if", "import",
+    "in", "infix", "infixl", "infixr", "instance", "let", "module", "newtype", "of", "then",
+    "type", "where",
+];
+
+/// Generates a Haskell module with `foreign import ccall` declarations of the functions and
+/// `Storable` instances of the `#[repr(C)]` structs.
+pub struct LangHaskell {
+    lib_name: String,
+    module: Option<String>,
+}
+
+impl LangHaskell {
+    pub fn new() -> Self {
+        LangHaskell {
+            lib_name: "backend".to_owned(),
+            module: None,
+        }
+    }
+
+    /// Set the name of the native library, which is also the name of the included header.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Set the name of the generated module (e.g. `Safe.App`). Defaults to the name of the native
+    /// library in PascalCase.
+    pub fn set_module<T: Into<String>>(&mut self, module: T) {
+        self.module = Some(module.into());
+    }
+
+    fn module(&self) -> String {
+        self.module
+            .clone()
+            .unwrap_or_else(|| self.lib_name.to_pascal_case())
+    }
+
+    /// Path of the generated source, e.g. `Safe/App.hsc`.
+    fn output_name(&self) -> String {
+        format!(
+            "{}.hsc",
+            self.module().replace(".", &MAIN_SEPARATOR.to_string())
+        )
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+}
+
+impl Default for LangHaskell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangHaskell {
+    fn name(&self) -> &'static str {
+        "haskell"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into a top-level binding (`name :: Type`).
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let constant = match literal_const(item)? {
+            Some(constant) => constant,
+            None => return Ok(()),
+        };
+
+        let name = value_name(&item.ident.name.as_str());
+        let (ty, value) = match constant.lit {
+            Some(&ast::LitKind::Str(..)) => ("String".to_owned(), constant.value),
+            Some(&ast::LitKind::Bool(value)) => {
+                ("Bool".to_owned(), if value { "True" } else { "False" }.to_owned())
+            }
+            _ => (rust_to_haskell(constant.ty)?, constant.value),
+        };
+
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!("{0} :: {1}\n{0} = {2}\n\n", name, ty, value));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `type A = B`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let ty = match alias_type(item)? {
+            Some(ty) => rust_to_haskell(ty)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!(
+            "type {} = {}\n\n",
+            item.ident.name.as_str(),
+            ty
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into a `newtype` over `CInt`, with a binding for every
+    /// variant (e.g. `statusOk`).
+    ///
+    /// Enums with data-carrying variants are declared as opaque types.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let mut buffer = docs(&item.attrs, "");
+
+        match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => {
+                buffer.push_str(&format!(
+                    "newtype {0} = {0} CInt deriving (Eq, Show, Storable)\n\n",
+                    name
+                ));
+                for (var, &(ref var_name, value)) in variants.iter().zip(&values) {
+                    let binding = value_name(&format!("{}_{}", name, var_name));
+                    let value = if value < 0 {
+                        format!("({})", value)
+                    } else {
+                        value.to_string()
+                    };
+
+                    buffer.push_str(&docs(&var.node.attrs, ""));
+                    buffer.push_str(&format!(
+                        "{0} :: {1}\n{0} = {1} {2}\n\n",
+                        binding, name, value
+                    ));
+                }
+            }
+            Some(ReprCEnum::Opaque) => buffer.push_str(&format!("data {}\n\n", name)),
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into a record with a `Storable` instance.
+    ///
+    /// Tuple structs with a single field are declared as opaque types.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let mut buffer = docs(&item.attrs, "");
+
+        match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => buffer.push_str(&record(&name, &fields)?),
+            Some(ReprCStruct::Opaque) => buffer.push_str(&format!("data {}\n\n", name)),
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into an alias of the underlying integer type and a binding
+    /// for every flag (e.g. `flagsRead`).
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = docs(&flags.attrs, "");
+        buffer.push_str(&format!(
+            "type {} = {}\n\n",
+            flags.name,
+            rust_to_haskell(&flags.ty)?
+        ));
+
+        for flag in &flags.flags {
+            let binding = value_name(&format!("{}_{}", flags.name, flag.name));
+            buffer.push_str(&docs(&flag.attrs, ""));
+            buffer.push_str(&format!(
+                "{0} :: {1}\n{0} = {2:#x}\n\n",
+                binding, flags.name, flag.value
+            ));
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into a `foreign import ccall` declaration.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let signature = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl_to_haskell(fn_decl)?,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!(
+            "foreign import ccall \"{}\" {} :: {}\n\n",
+            name,
+            value_name(&name),
+            signature
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+
+        let _ = outputs.insert(
+            self.output_name(),
+            format!(
+                "{{-# LANGUAGE ForeignFunctionInterface #-}}\n\
+                 {{-# LANGUAGE GeneralizedNewtypeDeriving #-}}\n\n\
+                 module {} where\n\n\
+                 import Data.Int\n\
+                 import Data.Word\n\
+                 import Foreign.C.String\n\
+                 import Foreign.C.Types\n\
+                 import Foreign.Ptr\n\
+                 import Foreign.Storable\n\n\
+                 #include \"{}.h\"\n\n\
+                 {}",
+                self.module(),
+                self.lib_name,
+                body
+            ),
+        );
+
+        Ok(())
+    }
+}
+
+/// Generate a record mirroring a struct, with a `Storable` instance using the offsets computed by
+/// `hsc2hs`.
+fn record(name: &str, fields: &[(String, &ast::StructField)]) -> Result<String, Error> {
+    let mut decl = format!("data {0} = {0}\n", name);
+    let mut peeks = Vec::new();
+    let mut pokes = Vec::new();
+
+    for (index, &(ref field_name, field)) in fields.iter().enumerate() {
+        if let ast::TyKind::Array(..) = field.ty.node {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(field.span),
+                message: "bindgen can not handle array fields in Haskell records".into(),
+                help: None,
+            });
+        }
+
+        // Fields are prefixed with the name of the record to avoid clashes.
+        let accessor = format!("{}_{}", name, field_name).to_camel_case();
+
+        decl.push_str(&docs(&field.attrs, "  "));
+        decl.push_str(&format!(
+            "  {} {} :: {}\n",
+            if index == 0 { "{" } else { "," },
+            accessor,
+            rust_to_haskell(&field.ty)?
+        ));
+        peeks.push(format!("#{{peek {}, {}}} ptr", name, field_name));
+        pokes.push(format!(
+            "    #{{poke {}, {}}} ptr ({} value)\n",
+            name, field_name, accessor
+        ));
+    }
+
+    if fields.is_empty() {
+        decl.push_str("  {\n");
+    }
+    decl.push_str("  }\n\n");
+
+    decl.push_str(&format!(
+        "instance Storable {0} where\n  \
+         sizeOf _ = #{{size {0}}}\n  \
+         alignment _ = #{{alignment {0}}}\n  \
+         peek ptr = {0}\n",
+        name
+    ));
+    for (index, peek) in peeks.iter().enumerate() {
+        decl.push_str(&format!(
+            "    {} {}\n",
+            if index == 0 { "<$>" } else { "<*>" },
+            peek
+        ));
+    }
+    decl.push_str("  poke ptr value = do\n");
+    for poke in &pokes {
+        decl.push_str(poke);
+    }
+    if pokes.is_empty() {
+        decl.push_str("    return ()\n");
+    }
+    decl.push('\n');
+
+    Ok(decl)
+}
+
+/// Convert the parameters and the return type of a function into a Haskell function type (e.g.
+/// `CInt -> IO ()`).
+fn fn_decl_to_haskell(fn_decl: &ast::FnDecl) -> Result<String, Error> {
+    let mut types = Vec::new();
+    for arg in &fn_decl.inputs {
+        types.push(rust_to_haskell(&arg.ty)?);
+    }
+
+    let output = match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(ty.span),
+                message: "panics across a C boundary are naughty!".into(),
+                help: None,
+            });
+        }
+        ast::FunctionRetTy::Default(..) => "()".to_owned(),
+        ast::FunctionRetTy::Ty(ref ty) => rust_to_haskell(ty)?,
+    };
+    types.push(format!("IO {}", paren(&output)));
+
+    Ok(types.join(" -> "))
+}
+
+/// Convert a Rust type into a Haskell type.
+fn rust_to_haskell(ty: &ast::Ty) -> Result<String, Error> {
+    // Nullable pointers map to plain pointers (compared against `nullPtr`/`nullFunPtr`).
+    if let Some(inner) = option_inner(ty) {
+        return rust_to_haskell(inner);
+    }
+
+    match ty.node {
+        ast::TyKind::BareFn(ref bare_fn) => Ok(format!(
+            "FunPtr ({})",
+            fn_decl_to_haskell(&bare_fn.decl)?
+        )),
+        ast::TyKind::Ptr(ref ptr) => {
+            let inner = rust_to_haskell(&ptr.ty)?;
+            if inner == "CChar" {
+                Ok("CString".to_owned())
+            } else {
+                Ok(format!("Ptr {}", paren(&inner)))
+            }
+        }
+        ast::TyKind::Path(None, ref path) => path_to_haskell(path),
+        _ => {
+            let new_type = pprust::ty_to_string(ty);
+            if new_type == "()" {
+                Ok("()".to_owned())
+            } else {
+                Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: format!("bindgen can not handle the type `{}`", new_type),
+                    help: None,
+                })
+            }
+        }
+    }
+}
+
+/// Convert a Rust path type into a Haskell type, supporting the types of `libc` and
+/// `std::os::raw`.
+fn path_to_haskell(path: &ast::Path) -> Result<String, Error> {
+    let (ty, module) = match path.segments.split_last() {
+        Some(segments) => segments,
+        None => {
+            return Err(Error {
+                level: Level::Bug,
+                span: Some(path.span),
+                message: "invalid type".into(),
+                help: None,
+            })
+        }
+    };
+
+    let module: Vec<_> = module
+        .iter()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .collect();
+    match &*module.join("::") {
+        "" | "libc" | "std::os::raw" => {}
+        _ => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(path.span),
+                message: "can not handle types in other modules (except `libc` and `std::os::raw`)"
+                    .into(),
+                help: None,
+            })
+        }
+    }
+
+    let ty = match &*ty.identifier.name.as_str() {
+        "c_void" => "()",
+        "i8" => "Int8",
+        "i16" => "Int16",
+        "i32" => "Int32",
+        "i64" => "Int64",
+        "isize" => "CIntPtr",
+        "u8" => "Word8",
+        "u16" => "Word16",
+        "u32" => "Word32",
+        "u64" => "Word64",
+        "usize" => "CUIntPtr",
+        "f32" => "Float",
+        "f64" => "Double",
+        "bool" => "CBool",
+        "c_char" => "CChar",
+        "c_schar" => "CSChar",
+        "c_uchar" => "CUChar",
+        "c_short" => "CShort",
+        "c_ushort" => "CUShort",
+        "c_int" => "CInt",
+        "c_uint" => "CUInt",
+        "c_long" => "CLong",
+        "c_ulong" => "CULong",
+        "c_longlong" => "CLLong",
+        "c_ulonglong" => "CULLong",
+        "c_float" => "CFloat",
+        "c_double" => "CDouble",
+        // All other types are user-defined.
+        ty => return Ok(ty.to_owned()),
+    };
+
+    Ok(ty.to_owned())
+}
+
+/// Wrap a type applied to arguments (e.g. `Ptr CInt`) in parentheses.
+fn paren(ty: &str) -> String {
+    if ty.contains(' ') && !ty.starts_with('(') {
+        format!("({})", ty)
+    } else {
+        ty.to_owned()
+    }
+}
+
+/// Name of a value-level binding (e.g. `MAX_LEN` -> `maxLen`), with a prime appended to keywords.
+fn value_name(name: &str) -> String {
+    escape_keyword(&name.to_camel_case(), KEYWORDS, "{}'")
+}
+
+/// Collect the doc comments as a Haddock comment.
+fn docs(attrs: &[ast::Attribute], indent: &str) -> String {
+    let docs = doc_comments(attrs, indent, "-- ");
+
+    // Only the first line is marked with a `|`.
+    docs.replacen(&format!("{}--", indent), &format!("{}-- |", indent), 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            pub type Id = u64;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = -1,
+            }
+
+            /// A point.
+            ///
+            /// In two dimensions.
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                /// Vertical.
+                y: i32,
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_name(
+                point: *const Point,
+                user_data: *mut c_void,
+                cb: extern \"C\" fn(user_data: *mut c_void, name: *const c_char),
+            ) -> *mut Point {}
+        ";
+
+        let mut lang = LangHaskell::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["SafeApp.hsc"],
+            "{-# LANGUAGE ForeignFunctionInterface #-}\n\
+             {-# LANGUAGE GeneralizedNewtypeDeriving #-}\n\n\
+             module SafeApp where\n\n\
+             import Data.Int\n\
+             import Data.Word\n\
+             import Foreign.C.String\n\
+             import Foreign.C.Types\n\
+             import Foreign.Ptr\n\
+             import Foreign.Storable\n\n\
+             #include \"safe_app.h\"\n\n\
+             -- | Maximum length.\n\
+             maxLen :: Word32\n\
+             maxLen = 16\n\n\
+             type Id = Word64\n\n\
+             newtype Status = Status CInt deriving (Eq, Show, Storable)\n\n\
+             -- | Success.\n\
+             statusOk :: Status\n\
+             statusOk = Status 0\n\n\
+             statusFailed :: Status\n\
+             statusFailed = Status (-1)\n\n\
+             -- | A point.\n\
+             --\n\
+             -- In two dimensions.\n\
+             data Point = Point\n  \
+             { pointX :: Int32\n  \
+             -- | Vertical.\n  \
+             , pointY :: Int32\n  \
+             }\n\n\
+             instance Storable Point where\n  \
+             sizeOf _ = #{size Point}\n  \
+             alignment _ = #{alignment Point}\n  \
+             peek ptr = Point\n    \
+             <$> #{peek Point, x} ptr\n    \
+             <*> #{peek Point, y} ptr\n  \
+             poke ptr value = do\n    \
+             #{poke Point, x} ptr (pointX value)\n    \
+             #{poke Point, y} ptr (pointY value)\n\n\
+             foreign import ccall \"point_name\" pointName :: Ptr Point -> Ptr () -> \
+             FunPtr (Ptr () -> CString -> IO ()) -> IO (Ptr Point)\n\n"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Monomorphization of the generic `#[repr(C)]` structs.
+//!
+//! A generic struct marked `#[cheddar(instantiate(u8, Key))]` is declared once per listed type,
+//! with the type parameter replaced and a mangled name (`FfiVec_u8` and `FfiVec_Key`). The uses
+//! of these instantiations (`FfiVec<u8>`) are renamed the same way.
+
+use common::std_type_alternative;
+use syntax::ast;
+use syntax::fold::{self, Folder};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use Error;
+use Level;
+
+/// Returns the concrete structs requested by the `#[cheddar(instantiate(..))]` attribute of a
+/// generic struct, or `None` if the item has no such attribute.
+pub fn instantiate(item: &ast::Item) -> Option<Result<Vec<ast::Item>, Error>> {
+    let types = item.attrs.iter().filter_map(instantiate_attr).next()?;
+
+    let (variants, generics) = match item.node {
+        ast::ItemKind::Struct(ref variants, ref generics) => (variants, generics),
+        _ => {
+            return Some(Err(Error {
+                level: Level::Error,
+                span: Some(item.span),
+                message: "`#[cheddar(instantiate(..))]` only applies to structs".into(),
+                help: None,
+            }))
+        }
+    };
+    if generics.ty_params.len() != 1 || !generics.lifetimes.is_empty() {
+        return Some(Err(Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: "`#[cheddar(instantiate(..))]` requires a struct with a single type parameter"
+                .into(),
+            help: None,
+        }));
+    }
+
+    let attrs: Vec<_> = item.attrs
+        .iter()
+        .filter(|attr| instantiate_attr(attr).is_none())
+        .cloned()
+        .collect();
+
+    Some(Ok(types
+        .iter()
+        .map(|ty| {
+            let mut substitute = Substitute {
+                param: generics.ty_params[0].ident,
+                ty: P(ast::Ty {
+                    id: ast::DUMMY_NODE_ID,
+                    node: ast::TyKind::Path(
+                        None,
+                        ast::Path::from_ident(item.span, ast::Ident::from_str(ty)),
+                    ),
+                    span: item.span,
+                }),
+            };
+
+            ast::Item {
+                ident: ast::Ident::from_str(&format!("{}_{}", item.ident.name.as_str(), ty)),
+                attrs: attrs.clone(),
+                node: ast::ItemKind::Struct(
+                    substitute.fold_variant_data(variants.clone()),
+                    Default::default(),
+                ),
+                ..item.clone()
+            }
+        })
+        .collect()))
+}
+
+/// Replace the instantiations of generic types (e.g. `FfiVec<u8>`) in the signature of a
+/// function, the fields of a struct or an aliased type with the concrete types (`FfiVec_u8`).
+pub fn mangle(item: ast::Item) -> ast::Item {
+    let mut folder = Mangle;
+    let node = match item.node {
+        ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block) => {
+            ast::ItemKind::Fn(
+                folder.fold_fn_decl(fn_decl),
+                unsafety,
+                constness,
+                abi,
+                generics,
+                block,
+            )
+        }
+        ast::ItemKind::Struct(variants, generics) => {
+            ast::ItemKind::Struct(folder.fold_variant_data(variants), generics)
+        }
+        ast::ItemKind::Ty(ty, generics) => ast::ItemKind::Ty(folder.fold_ty(ty), generics),
+        node => node,
+    };
+
+    ast::Item { node, ..item }
+}
+
+/// Returns the name of the concrete type of an instantiation of a generic type (e.g. `FfiVec_u8`
+/// for `FfiVec<u8>`), or `None` if the type isn't generic. `Option`s and the standard library
+/// types are left alone.
+pub fn mangled_name(ty: &ast::Ty) -> Option<String> {
+    let path = match ty.node {
+        ast::TyKind::Path(None, ref path) if path.segments.len() == 1 => path,
+        _ => return None,
+    };
+
+    let segment = &path.segments[0];
+    let name = segment.identifier.name.as_str();
+    if name == "Option" || std_type_alternative(&name).is_some() {
+        return None;
+    }
+
+    match segment.parameters {
+        Some(ref parameters) => match **parameters {
+            ast::PathParameters::AngleBracketed(ref data) if !data.types.is_empty() => {
+                let args: Vec<_> = data.types
+                    .iter()
+                    .map(|ty| {
+                        pprust::ty_to_string(ty)
+                            .chars()
+                            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                            .collect::<String>()
+                    })
+                    .collect();
+                Some(format!("{}_{}", name, args.join("_")))
+            }
+            _ => None,
+        },
+        None => None,
+    }
+}
+
+/// Returns the types listed by a `#[cheddar(instantiate(..))]` attribute.
+fn instantiate_attr(attr: &ast::Attribute) -> Option<Vec<String>> {
+    let items = match attr.value.node {
+        ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items,
+        _ => return None,
+    };
+
+    for item in items {
+        let types = match item.node {
+            ast::NestedMetaItemKind::MetaItem(ref item) if item.name == "instantiate" => {
+                match item.node {
+                    ast::MetaItemKind::List(ref types) => types,
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        return Some(
+            types
+                .iter()
+                .filter_map(|ty| match ty.node {
+                    ast::NestedMetaItemKind::MetaItem(ref ty) => Some(ty.name.as_str().to_string()),
+                    _ => None,
+                })
+                .collect(),
+        );
+    }
+
+    None
+}
+
+/// Replaces a type parameter with a concrete type.
+struct Substitute {
+    param: ast::Ident,
+    ty: P<ast::Ty>,
+}
+
+impl Folder for Substitute {
+    fn fold_ty(&mut self, ty: P<ast::Ty>) -> P<ast::Ty> {
+        match ty.node {
+            ast::TyKind::Path(None, ref path)
+                if path.segments.len() == 1 && path.segments[0].parameters.is_none()
+                    && path.segments[0].identifier.name == self.param.name =>
+            {
+                return self.ty.clone()
+            }
+            _ => {}
+        }
+
+        fold::noop_fold_ty(ty, self)
+    }
+}
+
+/// Renames the instantiations of generic types.
+struct Mangle;
+
+impl Folder for Mangle {
+    fn fold_ty(&mut self, ty: P<ast::Ty>) -> P<ast::Ty> {
+        let ty = fold::noop_fold_ty(ty, self);
+
+        match mangled_name(&ty) {
+            Some(name) => P(ast::Ty {
+                id: ty.id,
+                node: ast::TyKind::Path(
+                    None,
+                    ast::Path::from_ident(ty.span, ast::Ident::from_str(&name)),
+                ),
+                span: ty.span,
+            }),
+            None => ty,
+        }
+    }
+}
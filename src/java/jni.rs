@@ -1,8 +1,11 @@
 //! Functions to generate JNI bindings
 
-use super::types::{callback_name, rust_ty_to_java};
+use super::types::{callback_name, rust_ty_to_java, struct_to_java_classname};
 use super::{Context, Outputs};
-use common::{append_output, is_array_arg, is_user_data_arg};
+use common::{
+    append_output, has_cheddar_flag, is_array_arg, is_user_data_arg, parse_attr,
+    retrieve_free_with, retrieve_slices,
+};
 use inflector::Inflector;
 use jni::signature::{self, JavaType, Primitive, TypeSignature};
 use quote;
@@ -10,13 +13,40 @@ use struct_field::StructField;
 use syntax::ast;
 use syntax::print::pprust;
 
+/// Runtime support of the JNI callbacks.
+///
+/// Java listeners are kept alive as global references for as long as the native code holds
+/// them, and the callbacks attach the thread they are called from (which might not be known to
+/// the JVM) before calling into Java.
+pub const CALLBACK_SUPPORT: &str = r#"
+/// Reference to the JVM, set when the library is loaded.
+pub static mut JVM: Option<JavaVM> = None;
+
+/// Turn the Java listeners into global references and box them up into a callback context.
+macro_rules! gen_ctx {
+    ($env:ident, $cb:ident) => {{
+        let cb = jni_unwrap!($env.new_global_ref($cb));
+        Box::into_raw(Box::new(cb)) as *mut c_void
+    }};
+    ($env:ident, $($cb:ident),+) => {{
+        let cbs = [$(Some(jni_unwrap!($env.new_global_ref($cb)))),+];
+        Box::into_raw(Box::new(cbs)) as *mut c_void
+    }};
+}
+
+/// Take the listener back from a single-callback context, releasing the context.
+unsafe fn convert_cb_from_java(_env: &JNIEnv, ctx: *mut c_void) -> GlobalRef {
+    *Box::from_raw(ctx as *mut GlobalRef)
+}
+"#;
+
 fn to_jni_arg(arg: &ast::Arg, ty_name: &str) -> quote::Tokens {
     let pat = quote::Ident::new(pprust::pat_to_string(&*arg.pat));
     let ty_name = quote::Ident::new(ty_name);
     quote! { #pat: #ty_name }
 }
 
-fn transform_jni_arg(arg: &ast::Arg) -> quote::Tokens {
+fn transform_jni_arg(arg: &ast::Arg, context: &Context) -> quote::Tokens {
     match arg.ty.node {
         // Callback
         ast::TyKind::BareFn(ref _bare_fn) => to_jni_arg(arg, "JObject"),
@@ -45,6 +75,7 @@ fn transform_jni_arg(arg: &ast::Arg) -> quote::Tokens {
             // Detect strings, which are *const c_char or *mut c_char
             match pprust::ty_to_string(&ptr.ty).as_str() {
                 "c_char" => to_jni_arg(arg, "JString"),
+                handle if context.handles.contains_key(handle) => to_jni_arg(arg, "JObject"),
                 "App" | "Authenticator" => to_jni_arg(arg, "jlong"), // Opaque ptr,
                 _ => to_jni_arg(arg, "JObject"),
             }
@@ -62,6 +93,15 @@ fn fully_qualified(ty: &str, context: &Context) -> String {
     }
 }
 
+// Produces the fully qualified class name of a handle (which lives next to the NativeBindings)
+fn handle_class(ty: &str, context: &Context) -> String {
+    format!(
+        "{}/{}",
+        context.namespace.replace(".", "/"),
+        struct_to_java_classname(ty)
+    )
+}
+
 // Checks whether the type string is defined in the type map and if it is
 // then returns the correct Object signature. Otherwise, uses the default `Ljava/lang/Object;`.
 fn lookup_object_type(ty: &str, context: &Context) -> JavaType {
@@ -89,9 +129,12 @@ fn rust_ty_to_signature(ty: &ast::Ty, context: &Context) -> Option<JavaType> {
 
         // Standard pointers.
         ast::TyKind::Ptr(ref ptr) => {
+            let ty = pprust::ty_to_string(&ptr.ty);
             // Detect strings, which are *const c_char or *mut c_char
-            if pprust::ty_to_string(&ptr.ty) == "c_char" {
+            if ty == "c_char" {
                 Some(JavaType::Object(From::from("java/lang/String")))
+            } else if context.handles.contains_key(&ty) {
+                Some(JavaType::Object(handle_class(&ty, context)))
             } else {
                 rust_ty_to_signature(&ptr.ty, context)
             }
@@ -179,6 +222,21 @@ fn transform_callbacks_arg(
     JniArgResult { stmt, call_args }
 }
 
+fn transform_handle_arg(arg_name: &str, ty: &str) -> JniArgResult {
+    // statements
+    let arg_name = quote::Ident::new(arg_name);
+    let ty = quote::Ident::new(ty);
+    let stmt = quote! {
+        let #arg_name = jni_unwrap!(jni_unwrap!(env.get_field(#arg_name, "ptr", "J")).j())
+            as *mut #ty;
+    };
+
+    // call arg value(s)
+    let call_args = vec![quote! { #arg_name }];
+
+    JniArgResult { stmt, call_args }
+}
+
 fn transform_opaque_ptr(arg_name: &str, ty: &str) -> JniArgResult {
     // statements
     let arg_name = quote::Ident::new(arg_name);
@@ -194,18 +252,34 @@ fn transform_opaque_ptr(arg_name: &str, ty: &str) -> JniArgResult {
 }
 
 /// Generates JNI function binding based on a native function
+///
+/// The strings returned by functions marked `#[cheddar(utf8)]` are converted into Java strings,
+/// then freed with the `#[cheddar(free_with = "..")]` function (if any).
 pub fn generate_jni_function(
     args: Vec<ast::Arg>,
+    output: &ast::FunctionRetTy,
     native_name: &str,
     func_name: &str,
+    attrs: &[ast::Attribute],
     context: &mut Context,
     outputs: &mut Outputs,
 ) -> String {
-    let func_name = quote::Ident::new(format!(
-        "Java_{}_NativeBindings_{}",
-        context.namespace.replace("_", "_1").replace(".", "_"),
-        func_name
-    ));
+    let slices = retrieve_slices(attrs);
+    let (utf8, free_with) =
+        parse_attr(attrs, |attr| has_cheddar_flag(attr, "utf8"), retrieve_free_with);
+    let returns_code = match *output {
+        ast::FunctionRetTy::Ty(ref ty) => super::error_codes_name(ty, context).is_some(),
+        ast::FunctionRetTy::Default(..) => false,
+    };
+    let returns_string = utf8 && match *output {
+        ast::FunctionRetTy::Ty(ref ty) => match ty.node {
+            ast::TyKind::Ptr(ref ptr) => pprust::ty_to_string(&ptr.ty) == "c_char",
+            _ => false,
+        },
+        ast::FunctionRetTy::Default(..) => false,
+    };
+
+    let func_name = quote::Ident::new(native_method_symbol(func_name, context));
     let native_name_str = native_name;
     let native_name = quote::Ident::new(native_name);
 
@@ -223,7 +297,7 @@ pub fn generate_jni_function(
     while let Some(arg) = args_iter.next() {
         let arg_name = pprust::pat_to_string(&*arg.pat);
 
-        let res = if is_array_arg(&arg, args_iter.peek()) {
+        let res = if is_array_arg(&arg, args_iter.peek(), &slices) {
             args_iter.next();
             Some(transform_array_arg(&arg_name))
         } else {
@@ -237,6 +311,10 @@ pub fn generate_jni_function(
                 // Pointers
                 ast::TyKind::Ptr(ref ptr) => {
                     match pprust::ty_to_string(&ptr.ty).as_str() {
+                        // Handle object wrapping the pointer
+                        handle if context.handles.contains_key(handle) => {
+                            Some(transform_handle_arg(&arg_name, handle))
+                        }
                         // Opaque pointer that should be passed as a long value
                         opaque @ "App" | opaque @ "Authenticator" => {
                             Some(transform_opaque_ptr(&arg_name, opaque))
@@ -265,7 +343,7 @@ pub fn generate_jni_function(
             stmts.push(jni_arg_res.stmt);
         }
 
-        jni_fn_inputs.push(transform_jni_arg(&arg));
+        jni_fn_inputs.push(transform_jni_arg(&arg, context));
     }
 
     if !callbacks.is_empty() {
@@ -288,7 +366,7 @@ pub fn generate_jni_function(
 
         for (idx, &(ref cb, _)) in callbacks.iter().enumerate() {
             let full_cb_name = format!("call_{}_{}", native_name_str, idx);
-            eprintln!("Generating JNI CB {}", full_cb_name);
+            debug!("Generating JNI CB {}", full_cb_name);
 
             if !context.generated_jni_cbs.contains(&full_cb_name) {
                 let mut jni = generate_multi_jni_callback(cb, &full_cb_name, idx, count, context);
@@ -300,21 +378,176 @@ pub fn generate_jni_function(
         }
     }
 
-    let tokens = quote! {
-        #[no_mangle]
-        pub unsafe extern "system" fn #func_name(
-            env: JNIEnv,
-            _class: JClass,
-            #(#jni_fn_inputs),*
-        ) {
-            #(#stmts)*
-            #native_name(#(#call_args),*);
+    let tokens = if returns_string {
+        let free = if free_with.is_empty() {
+            quote!{}
+        } else {
+            let free_with = quote::Ident::new(free_with);
+            quote! { #free_with(ret as *mut _); }
+        };
+
+        quote! {
+            #[no_mangle]
+            pub unsafe extern "system" fn #func_name(
+                env: JNIEnv,
+                _class: JClass,
+                #(#jni_fn_inputs),*
+            ) -> ::jni::sys::jstring {
+                #(#stmts)*
+                let ret = #native_name(#(#call_args),*);
+                let string = env.new_string(::std::ffi::CStr::from_ptr(ret).to_string_lossy());
+                #free
+                match string {
+                    Ok(string) => string.into_inner(),
+                    Err(_) => ::std::ptr::null_mut(),
+                }
+            }
+        }
+    } else if returns_code {
+        quote! {
+            #[no_mangle]
+            pub unsafe extern "system" fn #func_name(
+                env: JNIEnv,
+                _class: JClass,
+                #(#jni_fn_inputs),*
+            ) -> jint {
+                #(#stmts)*
+                #native_name(#(#call_args),*) as jint
+            }
+        }
+    } else {
+        quote! {
+            #[no_mangle]
+            pub unsafe extern "system" fn #func_name(
+                env: JNIEnv,
+                _class: JClass,
+                #(#jni_fn_inputs),*
+            ) {
+                #(#stmts)*
+                #native_name(#(#call_args),*);
+            }
         }
     };
 
     tokens.to_string()
 }
 
+/// A native method of the NativeBindings class
+pub struct NativeMethod {
+    /// Name of the Java method
+    pub name: String,
+    /// Types of the arguments
+    pub args: Vec<JavaType>,
+    /// Return type
+    pub ret: JavaType,
+}
+
+/// Name of the JNI function implementing a native method of the NativeBindings class
+fn native_method_symbol(java_name: &str, context: &Context) -> String {
+    format!(
+        "Java_{}_NativeBindings_{}",
+        context.namespace.replace("_", "_1").replace(".", "_"),
+        java_name
+    )
+}
+
+// Produces the descriptor of a Java type (e.g. `[Ljava/lang/String;`)
+fn type_descriptor(ty: &JavaType, context: &Context) -> String {
+    match *ty {
+        JavaType::Object(ref obj) if obj.contains('/') => format!("L{};", obj),
+        JavaType::Object(ref obj) => format!("L{};", fully_qualified(obj, context)),
+        JavaType::Array(ref ty) => format!("[{}", type_descriptor(ty, context)),
+        ref ty => format!("{}", ty),
+    }
+}
+
+// Produces the C type of a Java type as defined by `jni.h`
+fn c_type(ty: &JavaType) -> String {
+    match *ty {
+        JavaType::Primitive(ref primitive) => match *primitive {
+            Primitive::Boolean => "jboolean",
+            Primitive::Byte => "jbyte",
+            Primitive::Char => "jchar",
+            Primitive::Double => "jdouble",
+            Primitive::Float => "jfloat",
+            Primitive::Int => "jint",
+            Primitive::Long => "jlong",
+            Primitive::Short => "jshort",
+            Primitive::Void => "void",
+        }.to_string(),
+        JavaType::Object(ref obj) if obj == "String" || obj == "java/lang/String" => {
+            "jstring".to_string()
+        }
+        JavaType::Array(ref ty) => match **ty {
+            JavaType::Primitive(..) => format!("{}Array", c_type(ty)),
+            _ => "jobjectArray".to_string(),
+        },
+        _ => "jobject".to_string(),
+    }
+}
+
+/// Generates a C source file defining `JNI_OnLoad`, which registers the native methods with
+/// the NativeBindings class
+pub fn generate_registration(methods: &[NativeMethod], context: &Context) -> String {
+    let mut decls = String::new();
+    let mut entries = String::new();
+
+    for method in methods {
+        let symbol = native_method_symbol(&method.name, context);
+
+        let mut args = vec!["JNIEnv *".to_string(), "jclass".to_string()];
+        args.extend(method.args.iter().map(c_type));
+
+        decls.push_str(&format!(
+            "JNIEXPORT {} JNICALL {}({});\n",
+            c_type(&method.ret),
+            symbol,
+            args.join(", ")
+        ));
+
+        let args: String = method
+            .args
+            .iter()
+            .map(|arg| type_descriptor(arg, context))
+            .collect();
+
+        entries.push_str(&format!(
+            "\t{{ \"{}\", \"({}){}\", (void *) {} }},\n",
+            method.name,
+            args,
+            type_descriptor(&method.ret, context),
+            symbol
+        ));
+    }
+
+    format!(
+        "#include <jni.h>\n\n\
+         {decls}\n\
+         static const JNINativeMethod methods[] = {{\n\
+         {entries}\
+         }};\n\n\
+         JNIEXPORT jint JNICALL JNI_OnLoad(JavaVM *vm, void *reserved) {{\n\
+         \tJNIEnv *env;\n\
+         \tjclass cls;\n\n\
+         \tif ((*vm)->GetEnv(vm, (void **) &env, JNI_VERSION_1_6) != JNI_OK) {{\n\
+         \t\treturn JNI_ERR;\n\
+         \t}}\n\n\
+         \tcls = (*env)->FindClass(env, \"{class}\");\n\
+         \tif (cls == NULL) {{\n\
+         \t\treturn JNI_ERR;\n\
+         \t}}\n\n\
+         \tif ((*env)->RegisterNatives(env, cls, methods, \
+         sizeof(methods) / sizeof(methods[0])) != 0) {{\n\
+         \t\treturn JNI_ERR;\n\
+         \t}}\n\n\
+         \treturn JNI_VERSION_1_6;\n\
+         }}\n",
+        decls = decls,
+        entries = entries,
+        class = format!("{}/NativeBindings", context.namespace.replace(".", "/")),
+    )
+}
+
 /// Transform `ast::Arg` into an (identifier, type) tuple
 fn transform_arg(arg: &ast::Arg) -> (quote::Ident, quote::Ident) {
     (
@@ -352,7 +585,7 @@ fn generate_callback(cb: &ast::BareFnTy, context: &Context) -> JniCallback {
         jni_cb_inputs.push(quote! { #arg_name: #arg_ty });
         args.push(quote! { #arg_name.into() });
 
-        if is_array_arg(arg, args_iter.peek().cloned()) {
+        if is_array_arg(arg, args_iter.peek().cloned(), &[]) {
             // Handle array arguments
             let val_java_type = rust_ty_to_signature(&arg.ty, context).unwrap();
             arg_java_ty.push(JavaType::Array(Box::new(val_java_type)));
@@ -374,6 +607,17 @@ fn generate_callback(cb: &ast::BareFnTy, context: &Context) -> JniCallback {
                 // Pointers
                 ast::TyKind::Ptr(ref ptr) => {
                     match pprust::ty_to_string(&ptr.ty).as_str() {
+                        // Handles wrapped in objects
+                        handle if context.handles.contains_key(handle) => {
+                            let class = handle_class(handle, context);
+                            quote! {
+                                let #arg_name = jni_unwrap!(env.new_object(
+                                    #class,
+                                    "(J)V",
+                                    &[::jni::objects::JValue::Long(#arg_name as jlong)],
+                                ));
+                            }
+                        }
                         // Opaque ptrs passed as long values
                         "App" | "Authenticator" => {
                             quote! {
@@ -4,14 +4,18 @@ mod jni;
 mod types;
 
 use common::{
-    self, append_output, check_no_mangle, is_array_arg, is_user_data_arg, parse_attr,
-    retrieve_docstring, Outputs,
+    self, append_output, check_no_mangle, has_cheddar_flag, is_array_arg, is_result_arg,
+    is_user_data_arg, parse_attr, retrieve_docstring, retrieve_slices, Outputs,
 };
+use docs::{self, DocSections, LinkKind, SectionPolicy};
 use inflector::Inflector;
-use java::types::{callback_name, java_type_to_str, rust_to_java, struct_to_java_classname};
-use jni::signature::JavaType;
+use java::types::{
+    callback_name, java_type_to_str, rust_to_java, rust_ty_to_java, struct_to_java_classname,
+};
+use jni::signature::{JavaType, Primitive};
 use rustfmt;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::MAIN_SEPARATOR;
 use struct_field::{transform_struct_fields, StructField};
 use syntax::abi::Abi;
 use syntax::print::pprust;
@@ -34,6 +38,26 @@ pub struct Context {
     type_map: HashMap<&'static str, JavaType>,
     /// Keeps track of which JNI callback functions has been generated already
     generated_jni_cbs: BTreeSet<String>,
+    /// Version of the crate, exposed as constants of the NativeBindings class
+    version: Option<String>,
+    /// Whether to generate the runtime support used by the JNI callbacks
+    callback_support: bool,
+    /// Whether to generate `JNI_OnLoad` registering the native methods
+    registration: bool,
+    /// Native methods of the NativeBindings class, registered by `JNI_OnLoad`
+    native_methods: Vec<jni::NativeMethod>,
+    /// Opaque types wrapped in `AutoCloseable` classes, and the functions freeing them
+    handles: BTreeMap<String, String>,
+    /// Enum holding the error codes, which are thrown as exceptions
+    error_codes: Option<String>,
+    /// Declarations of the constants of the Constants class
+    consts: Vec<String>,
+    /// Whether to put the Java sources in directories matching their packages
+    package_dirs: bool,
+    /// Whether to load the native library with the generated NativeLoader
+    native_loader: bool,
+    /// Rewriting of the Rust-specific doc sections and code blocks
+    doc_sections: DocSections,
 }
 
 impl Default for Context {
@@ -44,6 +68,16 @@ impl Default for Context {
             namespace_model: "net.maidsafe.dummy".to_string(),
             type_map: Default::default(),
             generated_jni_cbs: Default::default(),
+            version: None,
+            callback_support: false,
+            registration: false,
+            native_methods: Vec::new(),
+            handles: Default::default(),
+            error_codes: None,
+            consts: Vec::new(),
+            package_dirs: false,
+            native_loader: false,
+            doc_sections: DocSections::default(),
         }
     }
 }
@@ -57,6 +91,16 @@ impl LangJava {
                 namespace: "net.maidsafe.bindings".to_owned(),
                 namespace_model: "net.maidsafe.model".to_owned(),
                 generated_jni_cbs: BTreeSet::new(),
+                version: None,
+                callback_support: false,
+                registration: false,
+                native_methods: Vec::new(),
+                handles: BTreeMap::new(),
+                error_codes: None,
+                consts: Vec::new(),
+                package_dirs: false,
+                native_loader: false,
+                doc_sections: DocSections::default(),
             },
         }
     }
@@ -76,6 +120,52 @@ impl LangJava {
         self.context.namespace_model = namespace.into();
     }
 
+    /// Generate the runtime support used by the JNI callbacks: the `JVM` reference used to
+    /// attach foreign threads, the `gen_ctx!` macro turning the Java listeners into global
+    /// references and `convert_cb_from_java` releasing them once the callback is called.
+    ///
+    /// Disabled by default, as it clashes with the hand-written definitions.
+    pub fn set_callback_support(&mut self, enabled: bool) {
+        self.context.callback_support = enabled;
+    }
+
+    /// Put the Java sources in directories matching their packages (e.g. `com/example/Foo.java`),
+    /// as expected by the Java build tools.
+    pub fn set_package_dirs(&mut self, enabled: bool) {
+        self.context.package_dirs = enabled;
+    }
+
+    /// Generate a NativeLoader class, loading the native library from the JAR resources
+    /// (`/native/{os}-{arch}/`) when the NativeBindings class is initialised.
+    pub fn set_native_loader(&mut self, enabled: bool) {
+        self.context.native_loader = enabled;
+    }
+
+    /// Add an opaque type freed by the given function.
+    ///
+    /// Pointers to the type are wrapped in an `AutoCloseable` class whose `close()` calls the
+    /// function, and the class replaces them in the signatures of the native methods.
+    pub fn add_handle_type<T: Into<String>, F: Into<String>>(&mut self, name: T, free_fn: F) {
+        let _ = self.context.handles.insert(name.into(), free_fn.into());
+    }
+
+    /// Keep, drop or rename the doc sections with the given heading, e.g. `"Throws"` renamed to
+    /// `"@throws"`. Sections are kept by default.
+    pub fn set_doc_section<T: Into<String>>(&mut self, heading: T, policy: SectionPolicy) {
+        self.context.doc_sections.set(heading, policy);
+    }
+
+    /// Remove the Rust code blocks from the Javadoc (disabled by default).
+    pub fn set_drop_rust_code(&mut self, enabled: bool) {
+        self.context.doc_sections.drop_rust_code(enabled);
+    }
+
+    /// Generate `jni_onload.c`, defining a `JNI_OnLoad` which registers all the native methods
+    /// of the NativeBindings class with `RegisterNatives`.
+    pub fn set_native_registration(&mut self, enabled: bool) {
+        self.context.registration = enabled;
+    }
+
     /// Applies rustfmt to JNI code to improve debuggability
     fn format_jni_output(&self, input: &mut String) {
         let mut output: Vec<u8> = Vec::with_capacity(input.len() * 2);
@@ -94,6 +184,20 @@ impl LangJava {
 
     /// Adds package info to the NativeBindings Java module and indents lines
     fn format_native_functions(&self, funcs: &mut String) {
+        if let Some(ref version) = self.context.version {
+            funcs.insert_str(0, &version_constants(version));
+        }
+
+        if self.context.native_loader {
+            funcs.insert_str(
+                0,
+                &format!(
+                    "static {{\n\tNativeLoader.load(\"{}\");\n}}\n\n",
+                    self.context.lib_name
+                ),
+            );
+        }
+
         // Indent lines
         let lines = funcs.lines().fold(String::new(), |mut output, line| {
             output.push_str(&format!("\t{}\n", line));
@@ -101,6 +205,7 @@ impl LangJava {
         });
         *funcs = format!(
             "package {namespace};\n\n
+                         import java.util.concurrent.CompletableFuture;\n\n
                          public class NativeBindings {{\n
                          {lines}\n
                          }}",
@@ -110,6 +215,21 @@ impl LangJava {
     }
 }
 impl common::Lang for LangJava {
+    fn name(&self) -> &'static str {
+        "java"
+    }
+
+    fn crate_version(&mut self, _name: &str, version: &str) {
+        self.context.version = Some(version.to_string());
+    }
+
+    /// Handles are wrapped in `AutoCloseable` classes if they have a free function.
+    fn add_handle(&mut self, name: &str, free_fn: Option<&str>) {
+        if let Some(free_fn) = free_fn {
+            self.add_handle_type(name, free_fn);
+        }
+    }
+
     /// Convert a Rust function declaration into Java.
     fn parse_fn(
         &mut self,
@@ -117,6 +237,9 @@ impl common::Lang for LangJava {
         _module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
+        let renamed = common::rename_user_data(item);
+        let item = renamed.as_ref().unwrap_or(item);
+
         let (no_mangle, docs) = parse_attr(&item.attrs, check_no_mangle, |attr| {
             retrieve_docstring(attr, "")
         });
@@ -138,6 +261,7 @@ impl common::Lang for LangJava {
                     level: Level::Error,
                     span: Some(item.span),
                     message: "cheddar can not handle parameterized extern functions".into(),
+                    help: None,
                 });
             }
 
@@ -145,6 +269,7 @@ impl common::Lang for LangJava {
                 &*fn_decl,
                 &docs,
                 &format!("{}", name),
+                &item.attrs,
                 outputs,
                 &mut self.context,
             )?;
@@ -155,10 +280,128 @@ impl common::Lang for LangJava {
                 level: Level::Bug,
                 span: Some(item.span),
                 message: "`parse_fn` called on wrong `Item_`".into(),
+                help: None,
             })
         }
     }
 
+    /// Convert a Rust constant into a constant of the Constants class.
+    ///
+    /// Only literal constants are supported, others are skipped.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let docs = parse_attr(&item.attrs, |_| true, |attr| retrieve_docstring(attr, "")).1;
+
+        if let ast::ItemKind::Const(ref ty, ref expr) = item.node {
+            if let Some((ty, value)) = const_to_java(ty, expr) {
+                let mut buffer = String::new();
+
+                if !docs.is_empty() {
+                    buffer.push_str("/**\n");
+                    buffer.push_str(&javadoc(&docs, &self.context));
+                    buffer.push_str(" */\n");
+                }
+
+                buffer.push_str(&format!(
+                    "public static final {} {} = {};\n",
+                    ty,
+                    item.ident.name.as_str().to_screaming_snake_case(),
+                    value
+                ));
+
+                self.context.consts.push(buffer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the enum holding the error codes (`#[cheddar(error_codes)]`) into an exception
+    /// hierarchy. Other enums are not supported.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        if !item.attrs.iter().any(|attr| common::has_cheddar_flag(attr, "error_codes")) {
+            return Ok(());
+        }
+
+        if let ast::ItemKind::Enum(ast::EnumDef { ref variants }, _) = item.node {
+            let name = struct_to_java_classname(&*item.ident.name.as_str());
+            let codes = common::enum_discriminants(variants).ok_or_else(|| Error {
+                level: Level::Error,
+                span: Some(item.span),
+                message: "error codes must be given by integer literals".into(),
+                help: None,
+            })?;
+
+            let base = format!("{}Exception", name);
+            let mut cases = String::new();
+
+            for &(ref variant, code) in codes.iter().filter(|&&(_, code)| code != 0) {
+                let class = format!("{}Exception", variant);
+                cases.push_str(&format!(
+                    "\t\t\tcase {}:\n\t\t\t\treturn new {}();\n",
+                    code, class
+                ));
+
+                let _ = outputs.insert(
+                    format!("{}.java", class),
+                    format!(
+                        "package {namespace};\n\n\
+                         public class {class} extends {base} {{\n\
+                         \tpublic {class}() {{\n\
+                         \t\tsuper({code}, \"{variant}\");\n\
+                         \t}}\n\
+                         }}\n",
+                        namespace = self.context.namespace,
+                        class = class,
+                        base = base,
+                        code = code,
+                        variant = variant,
+                    ),
+                );
+            }
+
+            let _ = outputs.insert(
+                format!("{}.java", base),
+                format!(
+                    "package {namespace};\n\n\
+                     public class {base} extends Exception {{\n\
+                     \tprivate final int code;\n\n\
+                     \tpublic {base}(int code, String message) {{\n\
+                     \t\tsuper(message);\n\
+                     \t\tthis.code = code;\n\
+                     \t}}\n\n\
+                     \tpublic int getCode() {{\n\
+                     \t\treturn code;\n\
+                     \t}}\n\n\
+                     \tpublic static {base} fromCode(int code) {{\n\
+                     \t\tswitch (code) {{\n\
+                     {cases}\
+                     \t\t\tdefault:\n\
+                     \t\t\t\treturn new {base}(code, \"error code \" + code);\n\
+                     \t\t}}\n\
+                     \t}}\n\
+                     }}\n",
+                    namespace = self.context.namespace,
+                    base = base,
+                    cases = cases,
+                ),
+            );
+
+            self.context.error_codes = Some(item.ident.name.as_str().to_string());
+        }
+
+        Ok(())
+    }
+
     /// Convert a Rust struct into a Java class.
     fn parse_struct(
         &mut self,
@@ -188,6 +431,7 @@ impl common::Lang for LangJava {
                     level: Level::Error,
                     span: Some(item.span),
                     message: "cheddar can not handle parameterized `#[repr(C)]` structs".into(),
+                    help: None,
                 });
             }
 
@@ -222,6 +466,7 @@ impl common::Lang for LangJava {
                     message: "cheddar can not handle unit or tuple `#[repr(C)]` \
                               structs with >1 members"
                         .into(),
+                    help: None,
                 });
             }
         } else {
@@ -229,6 +474,7 @@ impl common::Lang for LangJava {
                 level: Level::Bug,
                 span: Some(item.span),
                 message: "`parse_struct` called on wrong `Item_`".into(),
+                help: None,
             });
         }
 
@@ -242,6 +488,9 @@ impl common::Lang for LangJava {
     fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
         match outputs.get_mut("jni.rs") {
             Some(input) => {
+                if self.context.callback_support && !self.context.generated_jni_cbs.is_empty() {
+                    input.insert_str(0, jni::CALLBACK_SUPPORT);
+                }
                 self.format_jni_output(input);
             }
             None => {
@@ -249,24 +498,211 @@ impl common::Lang for LangJava {
                     level: Level::Error,
                     span: None,
                     message: "no jni bindings generated?".to_owned(),
+                    help: None,
                 })
             }
         }
 
+        if !self.context.consts.is_empty() {
+            let consts: String = self.context.consts.drain(..).collect();
+            let _ = outputs.insert(
+                "Constants.java".to_owned(),
+                constants_class(&consts, &self.context),
+            );
+        }
+
+        for (name, free_fn) in &self.context.handles {
+            let class = struct_to_java_classname(name);
+            let source = handle_class(&class, free_fn, &self.context);
+            let _ = outputs.insert(format!("{}.java", class), source);
+        }
+
+        if self.context.native_loader {
+            let source = include_str!("../../resources/java/NativeLoader.java.template")
+                .replace("@Namespace", &self.context.namespace);
+            let _ = outputs.insert("NativeLoader.java".to_owned(), source);
+        }
+
+        if self.context.registration {
+            let source = jni::generate_registration(&self.context.native_methods, &self.context);
+            let _ = outputs.insert("jni_onload.c".to_owned(), source);
+        }
+
         match outputs.get_mut("NativeBindings.java") {
             Some(input) => {
                 self.format_native_functions(input);
-                Ok(())
             }
-            None => Err(Error {
-                level: Level::Error,
-                span: None,
-                message: "no native bindings generated?".to_owned(),
-            }),
+            None => {
+                return Err(Error {
+                    level: Level::Error,
+                    span: None,
+                    message: "no native bindings generated?".to_owned(),
+                    help: None,
+                })
+            }
         }
+
+        if self.context.package_dirs {
+            move_to_package_dirs(outputs);
+        }
+
+        // Start over for the next compilation (e.g. for another target).
+        self.context.generated_jni_cbs.clear();
+        self.context.native_methods.clear();
+        self.context.error_codes = None;
+
+        Ok(())
     }
 }
 
+/// Move the Java sources into the directories matching their packages (e.g. `Foo.java` of the
+/// `com.example` package goes to `com/example/Foo.java`).
+fn move_to_package_dirs(outputs: &mut Outputs) {
+    let moves: Vec<_> = outputs
+        .iter()
+        .filter(|&(path, _)| path.ends_with(".java"))
+        .filter_map(|(path, contents)| {
+            let dir = package_dir(contents)?;
+            Some((path.clone(), format!("{}{}{}", dir, MAIN_SEPARATOR, path)))
+        })
+        .collect();
+
+    for (from, to) in moves {
+        if let Some(contents) = outputs.remove(&from) {
+            let _ = outputs.insert(to, contents);
+        }
+    }
+}
+
+/// Directory matching the package declared by a Java source (e.g. `com/example`).
+pub fn package_dir(contents: &str) -> Option<String> {
+    let package = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("package "))?;
+    let package = package["package ".len()..].trim_right_matches(';').trim();
+
+    Some(package.replace(".", &MAIN_SEPARATOR.to_string()))
+}
+
+/// Generates the Constants class holding the given declarations.
+fn constants_class(consts: &str, context: &Context) -> String {
+    let consts = consts.lines().fold(String::new(), |mut output, line| {
+        output.push_str(&format!("\t{}\n", line));
+        output
+    });
+
+    format!(
+        "package {namespace};\n\n\
+         public final class Constants {{\n\
+         \tprivate Constants() {{\n\
+         \t}}\n\n\
+         {consts}\
+         }}\n",
+        namespace = context.namespace,
+        consts = consts,
+    )
+}
+
+/// Render a Java string literal, escaping the quotes, backslashes, and the control (as octal
+/// escapes, since the `\u` escapes of line terminators would end the literal) and non-ASCII
+/// characters (as UTF-16 escapes).
+fn string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for unit in value.encode_utf16() {
+        match unit {
+            0x22 => literal.push_str("\\\""),
+            0x5c => literal.push_str("\\\\"),
+            0x0a => literal.push_str("\\n"),
+            0x0d => literal.push_str("\\r"),
+            0x09 => literal.push_str("\\t"),
+            0x20...0x7e => literal.push(unit as u8 as char),
+            0x00...0x7f => literal.push_str(&format!("\\{:03o}", unit)),
+            _ => literal.push_str(&format!("\\u{:04x}", unit)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Converts a literal constant into its Java type and value.
+fn const_to_java(ty: &ast::Ty, expr: &ast::Expr) -> Option<(String, String)> {
+    let (negative, lit) = match expr.node {
+        ast::ExprKind::Lit(ref lit) => (false, lit),
+        ast::ExprKind::Unary(ast::UnOp::Neg, ref expr) => match expr.node {
+            ast::ExprKind::Lit(ref lit) => (true, lit),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let sign = if negative { "-" } else { "" };
+
+    match lit.node {
+        ast::LitKind::Str(ref value, _) => {
+            Some(("String".to_string(), string_literal(&value.as_str())))
+        }
+        ast::LitKind::Bool(value) => Some(("boolean".to_string(), value.to_string())),
+        ast::LitKind::Char(value) => Some(("char".to_string(), format!("{:?}", value))),
+        ast::LitKind::Int(value, _) => match rust_ty_to_java(&pprust::ty_to_string(ty))? {
+            JavaType::Primitive(Primitive::Long) => {
+                Some(("long".to_string(), format!("{}{}L", sign, value)))
+            }
+            ty @ JavaType::Primitive(..) => {
+                Some((java_type_to_str(&ty).ok()?, format!("{}{}", sign, value)))
+            }
+            _ => None,
+        },
+        ast::LitKind::Float(ref value, _) | ast::LitKind::FloatUnsuffixed(ref value) => {
+            match rust_ty_to_java(&pprust::ty_to_string(ty))? {
+                JavaType::Primitive(Primitive::Float) => {
+                    Some(("float".to_string(), format!("{}{}f", sign, value)))
+                }
+                _ => Some(("double".to_string(), format!("{}{}", sign, value))),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Generates a class owning a handle, which is freed when the object is closed.
+fn handle_class(class: &str, free_fn: &str, context: &Context) -> String {
+    format!(
+        "package {namespace};\n\n\
+         public class {class} implements AutoCloseable {{\n\
+         \tprivate long ptr;\n\n\
+         \tpublic {class}(long ptr) {{\n\
+         \t\tthis.ptr = ptr;\n\
+         \t}}\n\n\
+         \tpublic long getPtr() {{\n\
+         \t\treturn ptr;\n\
+         \t}}\n\n\
+         \t@Override\n\
+         \tpublic void close() {{\n\
+         \t\tif (ptr != 0) {{\n\
+         \t\t\tNativeBindings.{free_fn}(this);\n\
+         \t\t\tptr = 0;\n\
+         \t\t}}\n\
+         \t}}\n\
+         }}\n",
+        namespace = context.namespace,
+        class = class,
+        free_fn = free_fn.to_camel_case(),
+    )
+}
+
+/// Generates the version constants of the NativeBindings class.
+fn version_constants(version: &str) -> String {
+    let (major, minor, patch) = common::version_parts(version);
+
+    format!(
+        "public static final String VERSION = \"{}\";\n\
+         public static final int VERSION_MAJOR = {};\n\
+         public static final int VERSION_MINOR = {};\n\
+         public static final int VERSION_PATCH = {};\n\n",
+        version, major, minor, patch
+    )
+}
+
 /// Contains all information necessary to construct a Java class
 /// field, transformed from `StructField`.
 struct JavaClassField {
@@ -389,15 +825,37 @@ fn generate_parametrised_constructor(
     ))
 }
 
+/// Convert `///` doc comments into the lines of a Javadoc comment, with the intra-doc links
+/// rendered as `{@link}` tags.
+fn javadoc(docs: &str, context: &Context) -> String {
+    let bindings = format!("{}.NativeBindings", context.namespace);
+
+    let docs = context.doc_sections.apply(docs);
+    docs::replace_links(&docs, |name, kind| match kind {
+        LinkKind::Function => format!("{{@link {}#{}}}", bindings, name.to_camel_case()),
+        LinkKind::Constant => format!("{{@link {}#{}}}", bindings, name),
+        LinkKind::Type => format!(
+            "{{@link {}.{}}}",
+            context.namespace_model,
+            struct_to_java_classname(name)
+        ),
+    }).replace("///", " *")
+}
+
 /// Transform a Rust FFI function into a Java native function
 pub fn transform_native_fn(
     fn_decl: &ast::FnDecl,
     docs: &str,
     name: &str,
+    attrs: &[ast::Attribute],
     outputs: &mut Outputs,
     context: &mut Context,
 ) -> Result<(), Error> {
+    let slices = retrieve_slices(attrs);
     let mut args_str = Vec::new();
+    let mut java_args = Vec::new();
+    let mut arg_names = Vec::new();
+    let mut callbacks = Vec::new();
 
     let mut fn_args = fn_decl
         .inputs
@@ -411,23 +869,30 @@ pub fn transform_native_fn(
         // Generate function arguments
         let mut java_type = rust_to_java(&arg.ty, context)?;
 
-        if is_array_arg(arg, fn_args.peek().cloned()) {
+        if is_array_arg(arg, fn_args.peek().cloned(), &slices) {
             // Skip the length args - e.g. for a case of `ptr: *const u8, ptr_len: usize`
             // we're going to skip the `len` part.
             java_type = JavaType::Array(Box::new(java_type));
             fn_args.next();
         }
 
-        let java_type = java_type_to_str(&java_type)?;
-        args_str.push(format!("{} {}", java_type, arg_name.to_camel_case()));
+        args_str.push(format!(
+            "{} {}",
+            java_type_to_str(&java_type)?,
+            arg_name.to_camel_case()
+        ));
+        arg_names.push(arg_name.to_camel_case());
+        java_args.push(java_type);
 
         // Generate a callback class - if it wasn't generated already
         if let ast::TyKind::BareFn(ref bare_fn) = arg.ty.node {
+            callbacks.push((arg_names.len() - 1, bare_fn, arg.ty.span));
+
             let cb_class = callback_name(&*bare_fn.decl.inputs, context)?;
             let cb_file = format!("{}.java", cb_class);
 
             if outputs.get(&cb_file).is_none() {
-                eprintln!("Generating CB {}", cb_class);
+                debug!("Generating CB {}", cb_class);
 
                 let cb_output = transform_callback(&*arg.ty, &cb_class, context)?;
                 let _ = outputs.insert(cb_file, cb_output);
@@ -452,6 +917,7 @@ pub fn transform_native_fn(
                 level: Level::Error,
                 span: Some(ty.span),
                 message: "panics across a C boundary are naughty!".into(),
+                help: None,
             });
         }
         ast::FunctionRetTy::Default(..) => String::from("public static native void"),
@@ -459,19 +925,90 @@ pub fn transform_native_fn(
     };
 
     let java_name = name.to_camel_case();
-    let func_decl = format!(
-        "{} {}({})",
-        return_type,
-        &java_name,
-        args_str.as_slice().join(", ")
-    );
+    let error_codes = match *output_type {
+        ast::FunctionRetTy::Ty(ref ty) => error_codes_name(ty, context),
+        ast::FunctionRetTy::Default(..) => None,
+    };
+    // Functions returning error codes are called by a wrapper throwing them as exceptions.
+    let native_name = if error_codes.is_some() {
+        format!("{}Native", java_name)
+    } else {
+        java_name.clone()
+    };
+
+    if context.registration {
+        let ret = match *output_type {
+            _ if error_codes.is_some() => JavaType::Primitive(Primitive::Int),
+            ast::FunctionRetTy::Ty(ref ty) => rust_to_java(&*ty, context)?,
+            ast::FunctionRetTy::Default(..) => JavaType::Primitive(Primitive::Void),
+        };
+        context.native_methods.push(jni::NativeMethod {
+            name: native_name.clone(),
+            args: java_args,
+            ret,
+        });
+    }
 
     let mut buffer = String::new();
     buffer.push_str("/**\n");
-    buffer.push_str(&docs.replace("///", " *"));
+    buffer.push_str(&javadoc(docs, context));
     buffer.push_str(" */\n");
-    buffer.push_str(&func_decl);
-    buffer.push_str(";\n\n");
+
+    if let Some(ref error_codes) = error_codes {
+        buffer.push_str(&format!(
+            "public static void {name}({args}) throws {exception} {{\n\
+             \tint code = {native_name}({arg_names});\n\
+             \tif (code != 0) {{\n\
+             \t\tthrow {exception}.fromCode(code);\n\
+             \t}}\n\
+             }}\n\n\
+             private static native int {native_name}({args});\n\n",
+            name = java_name,
+            native_name = native_name,
+            args = args_str.join(", "),
+            arg_names = arg_names.join(", "),
+            exception = format!("{}Exception", struct_to_java_classname(error_codes)),
+        ));
+    } else {
+        buffer.push_str(&format!(
+            "{} {}({})",
+            return_type,
+            &java_name,
+            args_str.as_slice().join(", ")
+        ));
+        buffer.push_str(";\n\n");
+    }
+
+    if attrs.iter().any(|attr| has_cheddar_flag(attr, "async")) {
+        if callbacks.len() != 1 {
+            return Err(Error {
+                level: Level::Error,
+                span: None,
+                message: format!("async function `{}` must take exactly one callback", name),
+                help: None,
+            });
+        }
+
+        let (index, bare_fn, span) = callbacks[0];
+        let cb_params = future_callback_params(bare_fn, span, context)?;
+
+        let mut params = args_str.clone();
+        let _ = params.remove(index);
+        let mut call_args = arg_names.clone();
+        call_args[index] = future_callback(&cb_params);
+
+        buffer.push_str(&format!(
+            "public static CompletableFuture<{ty}> {name}Async({params}) {{\n\
+             \tCompletableFuture<{ty}> future = new CompletableFuture<>();\n\
+             \t{name}({call_args});\n\
+             \treturn future;\n\
+             }}\n\n",
+            ty = future_type(&cb_params)?,
+            name = java_name,
+            params = params.join(", "),
+            call_args = call_args.join(", "),
+        ));
+    }
 
     append_output(buffer, "NativeBindings.java", outputs);
 
@@ -491,8 +1028,10 @@ pub fn transform_native_fn(
     // Generate the JNI part of the interface
     jni.push_str(&jni::generate_jni_function(
         fn_decl.inputs.clone(),
+        &fn_decl.output,
         name,
-        &java_name,
+        &native_name,
+        attrs,
         context,
         outputs,
     ));
@@ -502,6 +1041,106 @@ pub fn transform_native_fn(
     Ok(())
 }
 
+/// Returns the Java names and types of the params of a callback completing a future, which must
+/// be the result followed by at most one value.
+fn future_callback_params(
+    callback: &ast::BareFnTy,
+    span: codemap::Span,
+    context: &Context,
+) -> Result<Vec<(String, JavaType)>, Error> {
+    let mut params = Vec::new();
+    let mut args = callback
+        .decl
+        .inputs
+        .iter()
+        .filter(|arg| !is_user_data_arg(arg))
+        .peekable();
+
+    match args.next() {
+        Some(arg) if is_result_arg(arg) => {
+            params.push(("result".to_string(), rust_to_java(&arg.ty, context)?));
+        }
+        _ => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(span),
+                message: "async callbacks must take the result first".into(),
+                help: None,
+            })
+        }
+    }
+
+    while let Some(arg) = args.next() {
+        let mut java_type = rust_to_java(&arg.ty, context)?;
+
+        if is_array_arg(arg, args.peek().cloned(), &[]) {
+            java_type = JavaType::Array(Box::new(java_type));
+            args.next();
+        }
+
+        let arg_name = pprust::pat_to_string(&*arg.pat).to_camel_case();
+        params.push((arg_name, java_type));
+    }
+
+    if params.len() > 2 {
+        return Err(Error {
+            level: Level::Error,
+            span: Some(span),
+            message: "async callbacks can complete with at most one value".into(),
+            help: None,
+        });
+    }
+
+    Ok(params)
+}
+
+/// Returns the type the future of an async function completes with.
+fn future_type(params: &[(String, JavaType)]) -> Result<String, Error> {
+    match params.get(1) {
+        Some(&(_, JavaType::Primitive(ref primitive))) => Ok(match *primitive {
+            Primitive::Boolean => "Boolean",
+            Primitive::Byte => "Byte",
+            Primitive::Char => "Character",
+            Primitive::Double => "Double",
+            Primitive::Float => "Float",
+            Primitive::Int => "Integer",
+            Primitive::Long => "Long",
+            Primitive::Short => "Short",
+            Primitive::Void => "Void",
+        }.to_string()),
+        Some(&(_, ref ty)) => java_type_to_str(ty),
+        None => Ok("Void".to_string()),
+    }
+}
+
+/// Returns the lambda completing the future of an async function.
+fn future_callback(params: &[(String, JavaType)]) -> String {
+    let names: Vec<_> = params.iter().map(|&(ref name, _)| name.as_str()).collect();
+
+    format!(
+        "({params}) -> {{\n\
+         \t\tif (result.getErrorCode() != 0) {{\n\
+         \t\t\tfuture.completeExceptionally(new Exception(result.getDescription()));\n\
+         \t\t}} else {{\n\
+         \t\t\tfuture.complete({value});\n\
+         \t\t}}\n\
+         \t}}",
+        params = names.join(", "),
+        value = names.get(1).cloned().unwrap_or("null"),
+    )
+}
+
+/// Returns the name of the error codes enum if the type is that enum.
+fn error_codes_name(ty: &ast::Ty, context: &Context) -> Option<String> {
+    match (&ty.node, &context.error_codes) {
+        (&ast::TyKind::Path(None, ref path), &Some(ref name)) => match path.segments.last() {
+            Some(segment) if *segment.identifier.name.as_str() == **name => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Turn a Rust callback function type into a Java interface.
 pub fn transform_callback<S: AsRef<str>>(
     ty: &ast::Ty,
@@ -522,6 +1161,7 @@ pub fn transform_callback<S: AsRef<str>>(
             level: Level::Error,
             span: Some(ty.span),
             message: "Invalid callback type".into(),
+            help: None,
         }),
     }
 }
@@ -540,6 +1180,7 @@ fn callback_to_java(
                 level: Level::Error,
                 span: Some(fn_span),
                 message: "callbacks that don't have C ABI are not supported".into(),
+                help: Some("declare the callback as `extern \"C\" fn(..)`".into()),
             })
         }
     }
@@ -549,6 +1190,7 @@ fn callback_to_java(
             level: Level::Error,
             span: Some(fn_span),
             message: "can not handle lifetimes".into(),
+            help: None,
         });
     }
 
@@ -565,7 +1207,7 @@ fn callback_to_java(
         let arg_name = pprust::pat_to_string(&*arg.pat);
         let mut java_type = rust_to_java(&*arg.ty, context)?;
 
-        if is_array_arg(arg, args_iter.peek().cloned()) {
+        if is_array_arg(arg, args_iter.peek().cloned(), &[]) {
             // Detect array ptrs: skip the length args and add array to the type sig
             java_type = JavaType::Array(Box::new(java_type));
             args_iter.next();
@@ -607,6 +1249,7 @@ mod tests {
             namespace: "net.maidsafe.bindings".to_owned(),
             namespace_model: "net.maidsafe.model".to_owned(),
             generated_jni_cbs: BTreeSet::new(),
+            ..Default::default()
         };
 
         let inputs = get_inputs("fn dummy() {}");
@@ -639,4 +1282,149 @@ mod tests {
             unwrap!(callback_name(&inputs, &context))
         );
     }
+
+    #[test]
+    fn native_registration() {
+        let context = Context {
+            namespace: "net.maidsafe.bindings".to_owned(),
+            namespace_model: "net.maidsafe.model".to_owned(),
+            ..Default::default()
+        };
+
+        let methods = vec![
+            jni::NativeMethod {
+                name: "appFree".to_owned(),
+                args: vec![JavaType::Primitive(Primitive::Long)],
+                ret: JavaType::Primitive(Primitive::Void),
+            },
+            jni::NativeMethod {
+                name: "getName".to_owned(),
+                args: vec![
+                    JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
+                    JavaType::Object("CallbackResultString".to_owned()),
+                ],
+                ret: JavaType::Object("String".to_owned()),
+            },
+        ];
+
+        let source = jni::generate_registration(&methods, &context);
+
+        assert!(source.contains(
+            "JNIEXPORT void JNICALL \
+             Java_net_maidsafe_bindings_NativeBindings_appFree(JNIEnv *, jclass, jlong);\n"
+        ));
+        assert!(source.contains(
+            "JNIEXPORT jstring JNICALL \
+             Java_net_maidsafe_bindings_NativeBindings_getName(\
+             JNIEnv *, jclass, jbyteArray, jobject);\n"
+        ));
+        assert!(source.contains(
+            "\t{ \"appFree\", \"(J)V\", \
+             (void *) Java_net_maidsafe_bindings_NativeBindings_appFree },\n"
+        ));
+        assert!(source.contains(
+            "\t{ \"getName\", \
+             \"([BLnet/maidsafe/model/CallbackResultString;)Ljava/lang/String;\", \
+             (void *) Java_net_maidsafe_bindings_NativeBindings_getName },\n"
+        ));
+        assert!(source.contains("FindClass(env, \"net/maidsafe/bindings/NativeBindings\")"));
+    }
+
+    #[test]
+    fn handle_classes() {
+        let context = Context {
+            namespace: "net.maidsafe.bindings".to_owned(),
+            ..Default::default()
+        };
+
+        let source = handle_class("App", "app_free", &context);
+
+        assert!(source.starts_with(
+            "package net.maidsafe.bindings;\n\n\
+             public class App implements AutoCloseable {\n"
+        ));
+        assert!(source.contains("\tpublic App(long ptr) {\n"));
+        assert!(source.contains("\t\t\tNativeBindings.appFree(this);\n"));
+    }
+
+    #[test]
+    fn async_wrappers() {
+        let parse_sess = ParseSess::new();
+        let item = unwrap!(unwrap!(parse::parse_item_from_source_str(
+            "dummy.rs".to_owned(),
+            "fn dummy(o_cb: extern \"C\" fn(user_data: *mut c_void, \
+             result: *const FfiResult, app_id: u64)) {}"
+                .to_owned(),
+            &parse_sess,
+        )));
+
+        let bare_fn = match item.node {
+            ItemKind::Fn(ref fn_decl, _, _, _, _, _) => match fn_decl.inputs[0].ty.node {
+                ast::TyKind::BareFn(ref bare_fn) => bare_fn.clone(),
+                _ => panic!("wrong arg type"),
+            },
+            _ => panic!("wrong item type"),
+        };
+
+        let context = Context::default();
+        let params = unwrap!(future_callback_params(&bare_fn, item.span, &context));
+
+        assert_eq!(unwrap!(future_type(&params)), "Long");
+        assert_eq!(
+            future_callback(&params),
+            "(result, appId) -> {\n\
+             \t\tif (result.getErrorCode() != 0) {\n\
+             \t\t\tfuture.completeExceptionally(new Exception(result.getDescription()));\n\
+             \t\t} else {\n\
+             \t\t\tfuture.complete(appId);\n\
+             \t\t}\n\
+             \t}"
+        );
+    }
+
+    #[test]
+    fn constants() {
+        fn to_java(source: &str) -> Option<(String, String)> {
+            let parse_sess = ParseSess::new();
+            let item = unwrap!(unwrap!(parse::parse_item_from_source_str(
+                "dummy.rs".to_owned(),
+                source.to_owned(),
+                &parse_sess,
+            )));
+
+            match item.node {
+                ItemKind::Const(ref ty, ref expr) => const_to_java(ty, expr),
+                _ => panic!("wrong item type"),
+            }
+        }
+
+        let convert = |source| to_java(source).map(|(ty, value)| format!("{} {}", ty, value));
+
+        assert_eq!(unwrap!(convert("const A: u64 = 10;")), "long 10L");
+        assert_eq!(unwrap!(convert("const A: i32 = -1;")), "int -1");
+        assert_eq!(unwrap!(convert("const A: f32 = 1.5;")), "float 1.5f");
+        assert_eq!(unwrap!(convert("const A: bool = true;")), "boolean true");
+        assert_eq!(
+            unwrap!(convert("const A: &'static str = \"a \\\"b\\\"\";")),
+            "String \"a \\\"b\\\"\""
+        );
+        assert_eq!(convert("const A: u32 = B + 1;"), None);
+    }
+
+    #[test]
+    fn package_dirs() {
+        let mut outputs = Outputs::new();
+        let _ = outputs.insert(
+            "Foo.java".to_owned(),
+            "package com.example.model;\n\npublic class Foo {}\n".to_owned(),
+        );
+        let _ = outputs.insert("jni.rs".to_owned(), String::new());
+
+        move_to_package_dirs(&mut outputs);
+
+        let path = format!("com{0}example{0}model{0}Foo.java", MAIN_SEPARATOR);
+        assert!(outputs.contains_key(&path));
+        assert!(outputs.contains_key("jni.rs"));
+        assert!(!outputs.contains_key("Foo.java"));
+    }
 }
@@ -35,6 +35,7 @@ pub fn java_type_to_str(ty: &JavaType) -> Result<String, Error> {
             level: Level::Error,
             span: None,
             message: "Java methods are not supported".into(),
+            help: None,
         }),
     }
 }
@@ -69,7 +70,7 @@ pub fn callback_name(inputs: &[ast::Arg], context: &Context) -> Result<String, E
         let arg_type = &rust_ty_to_java_class_name(&*arg.ty, context)?;
         let mut arg_type = struct_to_java_classname(arg_type);
 
-        if is_array_arg(arg, inputs.peek().cloned()) {
+        if is_array_arg(arg, inputs.peek().cloned(), &[]) {
             inputs.next();
             arg_type.push_str("ArrayLen");
         }
@@ -98,6 +99,7 @@ fn callback_arg_to_java(
                 level: Level::Error,
                 span: Some(fn_span),
                 message: "callbacks that don't have C ABI are not supported".into(),
+                help: Some("declare the callback as `extern \"C\" fn(..)`".into()),
             })
         }
     }
@@ -107,6 +109,7 @@ fn callback_arg_to_java(
             level: Level::Error,
             span: Some(fn_span),
             message: "can not handle lifetimes".into(),
+            help: None,
         });
     }
 
@@ -158,6 +161,7 @@ fn anon_rust_to_java(
             message: "C function pointers must have a name or function declaration \
                       associated with them"
                 .into(),
+            help: None,
         }),
 
         // Standard pointers.
@@ -167,6 +171,10 @@ fn anon_rust_to_java(
             if ty_str == "c_char" {
                 return Ok(JavaType::Object("String".into()));
             }
+            // Handles are wrapped in their own classes
+            if context.handles.contains_key(&ty_str) {
+                return Ok(JavaType::Object(struct_to_java_classname(ty_str)));
+            }
             anon_rust_to_java(&ptr.ty, context, use_type_map)
         }
 
@@ -183,6 +191,7 @@ fn anon_rust_to_java(
                     level: Level::Error,
                     span: Some(ty.span),
                     message: format!("unknown type `{}`", new_type),
+                    help: None,
                 })
             }
         }
@@ -203,6 +212,7 @@ fn path_to_java(
             level: Level::Bug,
             span: Some(path.span),
             message: "invalid type".into(),
+            help: None,
         });
     }
 
@@ -226,6 +236,7 @@ fn path_to_java(
                 level: Level::Error,
                 span: Some(path.span),
                 message: "can't convert type".into(),
+                help: None,
             }),
         }
     } else {
@@ -0,0 +1,417 @@
+//! Generation of JavaScript glue for crates compiled to `wasm32-unknown-unknown`.
+
+use bitflags::BitFlags;
+use common::{
+    append_output, doc_comments, extern_fn, literal_const, repr_c_enum, Lang, Outputs, ReprCEnum,
+};
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "  ";
+
+/// Generates a `<lib_name>.js` ES module which instantiates the WebAssembly module and wraps the
+/// exported functions.
+///
+/// Strings (`*const c_char`) and byte slices (`*const u8` followed by a `usize` named
+/// `<name>_len`) are copied into the linear memory with the allocation functions exported by the
+/// crate, which have the signatures `fn(size: usize) -> *mut u8` and
+/// `fn(ptr: *mut u8, size: usize)`. Returned strings are decoded into JavaScript strings.
+pub struct LangJs {
+    lib_name: String,
+    alloc: String,
+    dealloc: String,
+}
+
+impl LangJs {
+    pub fn new() -> Self {
+        LangJs {
+            lib_name: "backend".to_owned(),
+            alloc: "alloc".to_owned(),
+            dealloc: "dealloc".to_owned(),
+        }
+    }
+
+    /// Set the name of the WebAssembly module.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Set the names of the functions exported by the crate to allocate and free memory in the
+    /// linear memory. Defaults to `alloc` and `dealloc`.
+    pub fn set_allocator<A: Into<String>, D: Into<String>>(&mut self, alloc: A, dealloc: D) {
+        self.alloc = alloc.into();
+        self.dealloc = dealloc.into();
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}.js", self.lib_name)
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+
+    /// Generate the body of the wrapper of a function, marshaling its parameters and result.
+    fn fn_body(&self, name: &str, fn_decl: &ast::FnDecl) -> Result<(Vec<String>, String), Error> {
+        let mut params = Vec::new();
+        let mut setup = String::new();
+        let mut cleanup = String::new();
+        let mut args = Vec::new();
+
+        let mut inputs = fn_decl.inputs.iter().peekable();
+        while let Some(arg) = inputs.next() {
+            let arg_name = pprust::pat_to_string(&arg.pat);
+
+            if is_ptr_to(&arg.ty, "u8") {
+                let len_name = format!("{}_len", arg_name);
+                let is_slice = match inputs.peek() {
+                    Some(len) => {
+                        pprust::pat_to_string(&len.pat) == len_name
+                            && pprust::ty_to_string(&len.ty) == "usize"
+                    }
+                    None => false,
+                };
+
+                if is_slice {
+                    let _ = inputs.next();
+                    setup.push_str(&format!(
+                        "{0}const {1}_ptr = passBytes({1});\n",
+                        INDENT, arg_name
+                    ));
+                    cleanup.push_str(&format!(
+                        "{0}{0}wasm.{1}({2}_ptr, {2}.length);\n",
+                        INDENT, self.dealloc, arg_name
+                    ));
+                    args.push(format!("{0}_ptr, {0}.length", arg_name));
+                    params.push(arg_name);
+                    continue;
+                }
+            }
+
+            if is_ptr_to(&arg.ty, "c_char") {
+                setup.push_str(&format!(
+                    "{0}const [{1}_ptr, {1}_size] = passString({1});\n",
+                    INDENT, arg_name
+                ));
+                cleanup.push_str(&format!(
+                    "{0}{0}wasm.{1}({2}_ptr, {2}_size);\n",
+                    INDENT, self.dealloc, arg_name
+                ));
+                args.push(format!("{}_ptr", arg_name));
+            } else {
+                args.push(arg_name.clone());
+            }
+            params.push(arg_name);
+        }
+
+        let call = format!("wasm.{}({})", name, args.join(", "));
+        let result = match fn_decl.output {
+            ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: "panics across a C boundary are naughty!".into(),
+                    help: None,
+                });
+            }
+            ast::FunctionRetTy::Default(..) => format!("{};", call),
+            ast::FunctionRetTy::Ty(ref ty) if is_ptr_to(ty, "c_char") => {
+                format!("return readString({});", call)
+            }
+            ast::FunctionRetTy::Ty(ref ty) if pprust::ty_to_string(ty) == "bool" => {
+                format!("return {} !== 0;", call)
+            }
+            ast::FunctionRetTy::Ty(_) => format!("return {};", call),
+        };
+
+        let body = if cleanup.is_empty() {
+            format!("{}{}\n", INDENT, result)
+        } else {
+            format!(
+                "{1}{0}try {{\n{0}{0}{2}\n{0}}} finally {{\n{3}{0}}}\n",
+                INDENT, setup, result, cleanup
+            )
+        };
+
+        Ok((params, body))
+    }
+}
+
+impl Default for LangJs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangJs {
+    fn name(&self) -> &'static str {
+        "js"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into `export const NAME = value;`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let value = match literal_const(item)? {
+            Some(constant) => constant.value,
+            None => return Ok(()),
+        };
+
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!(
+            "export const {} = {};\n\n",
+            item.ident.name.as_str(),
+            value
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Type aliases don't exist in JavaScript, so they are skipped.
+    fn parse_ty(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into a frozen object mapping the names of the variants to
+    /// their values.
+    ///
+    /// Enums with data-carrying variants are skipped.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (variants, values) = match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => (variants, values),
+            Some(ReprCEnum::Opaque) | None => return Ok(()),
+        };
+
+        let variants: Vec<_> = variants
+            .iter()
+            .zip(&values)
+            .map(|(var, &(ref var_name, value))| {
+                format!(
+                    "{}{}{}: {},\n",
+                    docs(&var.node.attrs, INDENT),
+                    INDENT,
+                    var_name,
+                    value
+                )
+            })
+            .collect();
+
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!(
+            "export const {} = Object.freeze({{\n{}}});\n\n",
+            item.ident.name.as_str(),
+            variants.concat()
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Structs live in the linear memory and are only handled through pointers, so they are
+    /// skipped.
+    fn parse_struct(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into a frozen object mapping the names of the flags to
+    /// their values.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = docs(&flags.attrs, "");
+        buffer.push_str(&format!("export const {} = Object.freeze({{\n", flags.name));
+        for flag in &flags.flags {
+            buffer.push_str(&docs(&flag.attrs, INDENT));
+            buffer.push_str(&format!("{}{}: {:#x},\n", INDENT, flag.name, flag.value));
+        }
+        buffer.push_str("});\n\n");
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into an exported wrapper calling the function
+    /// exported by the WebAssembly module.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let (params, body) = match extern_fn(item)? {
+            Some(fn_decl) => self.fn_body(&name, fn_decl)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = docs(&item.attrs, "");
+        buffer.push_str(&format!(
+            "export function {}({}) {{\n{}}}\n\n",
+            name,
+            params.join(", "),
+            body
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+        let glue = include_str!("../../resources/js/glue.js.template")
+            .replace("@LibName", &self.lib_name)
+            .replace("@Alloc", &self.alloc);
+
+        let _ = outputs.insert(self.output_name(), format!("{}{}", glue, body));
+
+        Ok(())
+    }
+}
+
+/// Whether `ty` is a pointer to the type named `name` (e.g. `*const c_char`).
+fn is_ptr_to(ty: &ast::Ty, name: &str) -> bool {
+    match ty.node {
+        ast::TyKind::Ptr(ref ptr) => match ptr.ty.node {
+            ast::TyKind::Path(None, ref path) => path.segments
+                .last()
+                .map_or(false, |segment| segment.identifier.name == name),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Collect the doc comments into a JSDoc comment.
+fn docs(attrs: &[ast::Attribute], indent: &str) -> String {
+    let lines = doc_comments(attrs, indent, " * ");
+
+    if lines.is_empty() {
+        lines
+    } else {
+        format!("{0}/**\n{1}{0} */\n", indent, lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = 2,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+            }
+
+            bitflags! {
+                pub struct Flags: u32 {
+                    const READ = 1;
+                }
+            }
+
+            /// Store a value.
+            #[no_mangle]
+            pub extern \"C\" fn put(
+                key: *const c_char,
+                value: *const u8,
+                value_len: usize,
+                flags: u32,
+            ) -> bool {}
+
+            #[no_mangle]
+            pub extern \"C\" fn version() -> *const c_char {}
+
+            #[no_mangle]
+            pub extern \"C\" fn reset() {}
+        ";
+
+        let mut lang = LangJs::new();
+        lang.set_lib_name("store");
+        lang.set_allocator("store_alloc", "store_free");
+        let outputs = compile_source(&mut lang, source);
+
+        let module = &outputs["store.js"];
+        assert!(module.starts_with("let wasm;\n"));
+        assert!(module.contains("fetch(\"store.wasm\")"));
+        assert!(module.contains("const ptr = wasm.store_alloc(bytes.length);\n"));
+        assert!(module.ends_with(
+            "/**\n * Maximum length.\n */\n\
+             export const MAX_LEN = 16;\n\n\
+             export const Status = Object.freeze({\n  \
+             /**\n   * Success.\n   */\n  \
+             Ok: 0,\n  \
+             Failed: 2,\n\
+             });\n\n\
+             export const Flags = Object.freeze({\n  \
+             READ: 0x1,\n\
+             });\n\n\
+             /**\n * Store a value.\n */\n\
+             export function put(key, value, flags) {\n  \
+             const [key_ptr, key_size] = passString(key);\n  \
+             const value_ptr = passBytes(value);\n  \
+             try {\n    \
+             return wasm.put(key_ptr, value_ptr, value.length, flags) !== 0;\n  \
+             } finally {\n    \
+             wasm.store_free(key_ptr, key_size);\n    \
+             wasm.store_free(value_ptr, value.length);\n  \
+             }\n\
+             }\n\n\
+             export function version() {\n  \
+             return readString(wasm.version());\n\
+             }\n\n\
+             export function reset() {\n  \
+             wasm.reset();\n\
+             }\n\n"
+        ));
+    }
+
+    #[test]
+    fn string_constants() {
+        let source = "pub const GREETING: &'static str = \"h\\u{e9}llo\\t\\u{1}\";";
+
+        let mut lang = LangJs::new();
+        lang.set_lib_name("store");
+        let outputs = compile_source(&mut lang, source);
+
+        assert!(
+            outputs["store.js"].ends_with("export const GREETING = \"h\u{e9}llo\\t\\u{1}\";\n\n")
+        );
+    }
+}
@@ -2,12 +2,18 @@
 
 #[cfg(test)]
 mod tests;
-mod types;
+pub mod types;
 
 use self::types::{CPtrType, CType, CTypeNamed};
+use bitflags::BitFlags;
 use common::{
-    append_output, check_no_mangle, check_repr_c, parse_attr, retrieve_docstring, Lang, Outputs,
+    alias_type, append_output, check_derive_default, check_no_mangle, check_repr_c,
+    has_cheddar_flag, is_integer, parse_attr, repr_layout, retrieve_bits, retrieve_doc_comment,
+    retrieve_docstring, retrieve_header, uintptr_max, version_parts, Lang, Outputs,
 };
+use config::override_types;
+use docs::{self, DocSections, SectionPolicy};
+use methods;
 use petgraph::{algo, Graph};
 use std::collections::btree_map::Entry;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
@@ -23,6 +29,171 @@ pub struct LangC {
     decls: BTreeMap<String, String>,
     deps: BTreeMap<String, Vec<String>>,
     custom_code: String,
+    /// Name and version of the crate, emitted as version macros.
+    version: Option<(String, String)>,
+    /// Header requested by the item being parsed with `#[cheddar(header = "file.h")]`.
+    item_header: Option<String>,
+    /// Style of the nullability annotations of pointers.
+    nullability: Nullability,
+    /// Whether to generate `Enum_to_string` functions.
+    enum_to_string: bool,
+    /// Implementations of the `Enum_to_string` functions, emitted into `<lib_name>.c`.
+    enum_to_string_impls: String,
+    /// Whether to generate `STRUCT_INIT(..)` macros.
+    struct_init_macros: bool,
+    /// Installation prefix of the library, for the pkg-config and CMake package files.
+    install_prefix: Option<String>,
+    /// Whether to generate a SWIG interface file.
+    swig_interface: bool,
+    /// Typemaps of the byte buffers, emitted into the SWIG interface file.
+    swig_typemaps: BTreeSet<String>,
+    /// Manual typemaps emitted into the SWIG interface file.
+    swig_custom_code: String,
+    /// Whether to generate a LuaJIT module declaring the API with `ffi.cdef`.
+    luajit_module: bool,
+    /// Whether to extract the C code blocks of the doc comments into example files.
+    doc_examples: bool,
+    /// Name of the documented item and code of the extracted examples.
+    examples: Vec<(String, String)>,
+    /// Whether to generate a mock implementation of the functions.
+    mock: bool,
+    /// Declarations of the states of the mocks, emitted into `<lib_name>_mock.h`.
+    mock_decls: String,
+    /// Mock implementations of the functions, emitted into `<lib_name>_mock.c`.
+    mock_impls: String,
+    /// Statements resetting the states of the mocks.
+    mock_resets: String,
+    /// Structs declared inside their parent with `#[cheddar(inline)]`, indexed by name.
+    inline_structs: BTreeMap<String, InlineStruct>,
+    /// Whether to head the groups of functions operating on a type with a comment.
+    method_sections: bool,
+    /// Type the previous function operates on.
+    method_owner: Option<String>,
+    /// Width (in columns) the docs of the struct fields and enum variants are wrapped to, if any.
+    doc_width: Option<usize>,
+    /// Rewriting of the Rust-specific doc sections and code blocks.
+    doc_sections: DocSections,
+    /// Opaque handle types of the registry, declared as incomplete structs.
+    handles: BTreeSet<String>,
+    /// Representation of `bool`.
+    bool_repr: BoolRepr,
+}
+
+/// Fields of a `#[cheddar(inline)]` struct, declared as an anonymous struct inside its parent.
+struct InlineStruct {
+    body: String,
+    parent: Option<String>,
+}
+
+/// Style of the annotations describing whether pointers can be null.
+///
+/// Pointers wrapped in an `Option` are nullable, all other pointers are non-null.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nullability {
+    /// No annotations.
+    None,
+    /// Clang's `_Nullable`/`_Nonnull` qualifiers.
+    Clang,
+    /// Microsoft's SAL annotations (`_In_opt_`, `_Ret_notnull_`...).
+    Sal,
+}
+
+/// Representation of Rust's `bool`, for compilers without `<stdbool.h>` (e.g. pre-C99).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoolRepr {
+    /// `bool` of `<stdbool.h>`.
+    Stdbool,
+    /// `uint8_t`, with `TRUE` and `FALSE` macros.
+    UInt8,
+    /// `int`, with `TRUE` and `FALSE` macros.
+    Int,
+}
+
+impl BoolRepr {
+    /// C type replacing `bool`, unless `<stdbool.h>` is used.
+    fn c_type(self) -> Option<&'static str> {
+        match self {
+            BoolRepr::Stdbool => None,
+            BoolRepr::UInt8 => Some("uint8_t"),
+            BoolRepr::Int => Some("int"),
+        }
+    }
+
+    /// Include or macros declaring the boolean values, put at the top of every header.
+    fn prelude(self) -> &'static str {
+        match self {
+            BoolRepr::Stdbool => "#include <stdbool.h>\n",
+            BoolRepr::UInt8 | BoolRepr::Int => {
+                "#ifndef TRUE\n#define TRUE 1\n#endif\n#ifndef FALSE\n#define FALSE 0\n#endif\n"
+            }
+        }
+    }
+}
+
+/// Qualifiers of a pointer requested with `#[cheddar(volatile)]` and `#[cheddar(restrict)]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Qualifiers {
+    /// Whether the pointee is `volatile`.
+    volatile: bool,
+    /// Whether the pointer is `restrict`.
+    restrict: bool,
+}
+
+impl Qualifiers {
+    /// Read the qualifiers of a field (`#[cheddar(volatile)]`), or of the parameter `param` of a
+    /// function (`#[cheddar(volatile = "param")]`).
+    fn from_attrs(attrs: &[ast::Attribute], param: Option<&str>) -> Self {
+        let mut qualifiers = Qualifiers::default();
+
+        for attr in attrs {
+            let items = match attr.value.node {
+                ast::MetaItemKind::List(ref items) if attr.name() == "cheddar" => items,
+                _ => continue,
+            };
+
+            for item in items {
+                let item = match item.node {
+                    ast::NestedMetaItemKind::MetaItem(ref item) => item,
+                    _ => continue,
+                };
+                let value = item.value_str().map(|value| value.as_str().to_string());
+                if value.as_ref().map(|value| &**value) != param {
+                    continue;
+                }
+
+                match &*item.name.as_str() {
+                    "volatile" => qualifiers.volatile = true,
+                    "restrict" => qualifiers.restrict = true,
+                    _ => {}
+                }
+            }
+        }
+
+        qualifiers
+    }
+
+    /// Qualify the pointee of a pointer as `volatile`.
+    fn apply(self, c_ty: CTypeNamed) -> CTypeNamed {
+        if !self.volatile {
+            return c_ty;
+        }
+
+        match c_ty {
+            CTypeNamed(name, CType::Ptr(pointee, ptr_type)) => CTypeNamed(
+                name,
+                CType::Ptr(Box::new(CType::Volatile(pointee)), ptr_type),
+            ),
+            c_ty => c_ty,
+        }
+    }
+}
+
+/// Where a pointer is declared, which determines its SAL annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Position {
+    Param,
+    Return,
+    Field,
 }
 
 /// Compile the header declarations then add the needed `#include`s.
@@ -30,7 +201,7 @@ pub struct LangC {
 /// Currently includes:
 ///
 /// - `stdint.h`
-/// - `stdbool.h`
+/// - `stdbool.h` (or the `TRUE` and `FALSE` macros, see `set_bool_repr`)
 impl LangC {
     pub fn new() -> Self {
         Self {
@@ -38,6 +209,30 @@ impl LangC {
             decls: BTreeMap::new(),
             deps: BTreeMap::new(),
             custom_code: Default::default(),
+            version: None,
+            item_header: None,
+            nullability: Nullability::None,
+            enum_to_string: false,
+            enum_to_string_impls: String::new(),
+            struct_init_macros: false,
+            install_prefix: None,
+            swig_interface: false,
+            swig_typemaps: BTreeSet::new(),
+            swig_custom_code: String::new(),
+            luajit_module: false,
+            doc_examples: false,
+            examples: Vec::new(),
+            mock: false,
+            mock_decls: String::new(),
+            mock_impls: String::new(),
+            mock_resets: String::new(),
+            inline_structs: BTreeMap::new(),
+            method_sections: false,
+            method_owner: None,
+            doc_width: None,
+            doc_sections: DocSections::default(),
+            handles: BTreeSet::new(),
+            bool_repr: BoolRepr::Stdbool,
         }
     }
 
@@ -46,17 +241,250 @@ impl LangC {
         self.lib_name = name.into();
     }
 
+    /// Set the style of the nullability annotations of pointers (none by default).
+    ///
+    /// A pointer is nullable when wrapped in an `Option`, unless the function or field is marked
+    /// `#[cheddar(nonnull)]`. All other pointers are non-null.
+    pub fn set_nullability(&mut self, nullability: Nullability) {
+        self.nullability = nullability;
+    }
+
+    /// Set the representation of `bool` in the struct fields, function signatures and constants
+    /// (`<stdbool.h>` by default). Headers then define `TRUE` and `FALSE` instead of including
+    /// `<stdbool.h>`.
+    pub fn set_bool_repr(&mut self, repr: BoolRepr) {
+        self.bool_repr = repr;
+    }
+
+    /// Generate a `const char* Enum_to_string(Enum value)` function returning the name of the
+    /// variant for every C-like enum (disabled by default).
+    ///
+    /// The functions are declared next to the enums and implemented in `<lib_name>.c`, which has
+    /// to be compiled with the rest of the C code. They return `NULL` for unknown values.
+    pub fn set_enum_to_string(&mut self, enabled: bool) {
+        self.enum_to_string = enabled;
+    }
+
+    /// Generate a `MYSTRUCT_INIT(field_a, field_b)` macro building a struct with designated
+    /// initializers for every `#[repr(C)]` struct (disabled by default).
+    ///
+    /// Independently of this setting, structs marked `#[cheddar(default)]` which derive `Default`
+    /// get a `static inline MyStruct MyStruct_default(void)` function.
+    pub fn set_struct_init_macros(&mut self, enabled: bool) {
+        self.struct_init_macros = enabled;
+    }
+
+    /// Generate the `<lib_name>.pc` pkg-config file and the `<lib_name>Config.cmake` and
+    /// `<lib_name>ConfigVersion.cmake` package files for a library installed under `prefix`
+    /// (the headers in `<prefix>/include` and the library in `<prefix>/lib`).
+    ///
+    /// Native consumers can then find the library with `pkg-config` or CMake's `find_package`.
+    pub fn set_install_prefix<T: Into<String>>(&mut self, prefix: T) {
+        self.install_prefix = Some(prefix.into());
+    }
+
+    /// Generate a `<lib_name>.i` SWIG interface file including the headers (disabled by default).
+    ///
+    /// Byte buffers passed as a `*const u8` followed by its `usize` length named `<buf>_len` are
+    /// mapped to the target language's strings/byte arrays. More typemaps can be added with
+    /// `add_swig_typemaps`.
+    pub fn set_swig_interface(&mut self, enabled: bool) {
+        self.swig_interface = enabled;
+    }
+
+    /// Adds manual typemaps into the SWIG interface file.
+    pub fn add_swig_typemaps(&mut self, code: &str) {
+        self.swig_custom_code.push_str(code);
+    }
+
+    /// Generate a `<lib_name>.lua` module declaring the API in an `ffi.cdef` block and returning
+    /// the loaded library (disabled by default). The path of the library can be overridden with
+    /// the `<LIB_NAME>_LIB` environment variable.
+    ///
+    /// LuaJIT can't handle the preprocessor, so integer macros are declared as `static const int`
+    /// and the other macros, the inline functions and the nullability annotations are stripped.
+    pub fn set_luajit_module(&mut self, enabled: bool) {
+        self.luajit_module = enabled;
+    }
+
+    /// Extract the code blocks marked ```` ```c ```` from the doc comments into
+    /// `examples/NNN_<item>.c` files including the generated header (disabled by default), so
+    /// the documented examples can be compiled like doctests.
+    ///
+    /// Like doctests, examples without a `main` function are wrapped into one, after the
+    /// preprocessor directives they contain.
+    pub fn set_doc_examples(&mut self, enabled: bool) {
+        self.doc_examples = enabled;
+    }
+
+    /// Generate a mock implementation of the functions into `<lib_name>_mock.c` (disabled by
+    /// default), so the consumers of the API can be unit tested without linking the library.
+    ///
+    /// Every call to `fn` is counted and its arguments recorded into the `fn_mock` state declared
+    /// in `<lib_name>_mock.h`, and the mock returns `fn_mock.result` (zero unless set by the
+    /// test). `<lib_name>_mock_reset()` resets the states of all the mocks.
+    pub fn set_mock(&mut self, enabled: bool) {
+        self.mock = enabled;
+    }
+
+    /// Head the groups of functions operating on a type (e.g. `app_len` for `App`) with a
+    /// `/* App methods */` comment (disabled by default), usually combined with
+    /// `Bindgen::group_methods`.
+    pub fn set_method_sections(&mut self, enabled: bool) {
+        self.method_sections = enabled;
+    }
+
+    /// Wrap the doc comments of the struct fields and enum variants longer than `width` columns
+    /// (counting tabs as 4 columns), keeping their indentation. Not wrapped by default.
+    pub fn set_doc_width(&mut self, width: usize) {
+        self.doc_width = Some(width);
+    }
+
+    /// Keep, drop or rename the doc sections with the given heading, e.g. `"Safety"` renamed to
+    /// `"@warning"` for Doxygen. Sections are kept by default.
+    pub fn set_doc_section<T: Into<String>>(&mut self, heading: T, policy: SectionPolicy) {
+        self.doc_sections.set(heading, policy);
+    }
+
+    /// Remove the Rust code blocks from the docs (disabled by default).
+    pub fn set_drop_rust_code(&mut self, enabled: bool) {
+        self.doc_sections.drop_rust_code(enabled);
+    }
+
     /// Adds manual C code into the top-level header - can be useful for typedefs,
     /// like e.g. opaque pointers.
     pub fn add_custom_code(&mut self, code: &str) {
         self.custom_code.push_str(code);
     }
 
+    /// Replace `bool` in the types of an item with its representation, unless `<stdbool.h>` is
+    /// used.
+    fn map_bools(&self, item: &ast::Item) -> Option<ast::Item> {
+        let c_type = self.bool_repr.c_type()?;
+        let mut types = HashMap::new();
+        let _ = types.insert("bool".to_string(), c_type.to_string());
+        Some(override_types(item.clone(), &types))
+    }
+
+    /// Name of the header the current item goes into.
+    fn header_name(&self, module: &[String]) -> Result<String, Error> {
+        match self.item_header {
+            Some(ref header) => Ok(header.clone()),
+            None => header_name(module, &self.lib_name),
+        }
+    }
+
+    /// Remember the header requested by the item's attributes (if any).
+    fn set_item_header(&mut self, item: &ast::Item) {
+        let (_, header) = parse_attr(&item.attrs, |_| false, retrieve_header);
+        self.item_header = if header.is_empty() {
+            None
+        } else {
+            Some(header)
+        };
+    }
+
+    /// Remember the C code blocks of the item's doc comments, if examples are enabled.
+    fn collect_examples(&mut self, item: &ast::Item) {
+        if !self.doc_examples {
+            return;
+        }
+
+        let (_, docs) = parse_attr(&item.attrs, |_| false, |attr| {
+            retrieve_doc_comment(attr, "", "")
+        });
+
+        let mut code: Option<String> = None;
+        for line in docs.lines() {
+            if line.trim() == "```" && code.is_some() {
+                let code = unwrap!(code.take());
+                self.examples.push((item.ident.name.as_str().to_string(), code));
+            } else if line.trim() == "```c" && code.is_none() {
+                code = Some(String::new());
+            } else if let Some(ref mut code) = code {
+                code.push_str(line);
+                code.push('\n');
+            }
+        }
+    }
+
+    /// Convert a Rust type into a C declaration of `name`, annotated with the nullability and the
+    /// qualifiers of the pointer (if it is one).
+    fn annotated_c_ty(
+        &self,
+        ty: &ast::Ty,
+        name: &str,
+        nonnull: bool,
+        qualifiers: Qualifiers,
+        position: Position,
+    ) -> Result<(String, CTypeNamed), Error> {
+        let restricted;
+        let name = if qualifiers == Qualifiers::default() {
+            name
+        } else {
+            match option_inner(ty).unwrap_or(ty).node {
+                ast::TyKind::Ptr(..) => {}
+                _ => {
+                    return Err(Error {
+                        level: Level::Error,
+                        span: Some(ty.span),
+                        message: "`volatile` and `restrict` only apply to pointers".into(),
+                        help: None,
+                    })
+                }
+            }
+
+            if qualifiers.restrict {
+                restricted = format!("restrict {}", name);
+                &restricted
+            } else {
+                name
+            }
+        };
+
+        let nullable = match pointer_nullability(ty) {
+            Some(nullable) => nullable && !nonnull,
+            None => {
+                let c_ty = rust_to_c(ty, name)?;
+                return Ok((format!("{}", c_ty), c_ty));
+            }
+        };
+
+        match self.nullability {
+            Nullability::None => {
+                let c_ty = qualifiers.apply(rust_to_c(ty, name)?);
+                Ok((format!("{}", c_ty), c_ty))
+            }
+            Nullability::Clang => {
+                let qualifier = if nullable { "_Nullable" } else { "_Nonnull" };
+                let c_ty = qualifiers.apply(rust_to_c(ty, &format!("{} {}", qualifier, name))?);
+                Ok((format!("{}", c_ty), c_ty))
+            }
+            Nullability::Sal => {
+                let mutable = match option_inner(ty).unwrap_or(ty).node {
+                    ast::TyKind::Ptr(ref ptr) => ptr.mutbl == ast::Mutability::Mutable,
+                    _ => false,
+                };
+                let annotation = match (position, mutable, nullable) {
+                    (Position::Param, false, false) => "_In_ ",
+                    (Position::Param, false, true) => "_In_opt_ ",
+                    (Position::Param, true, false) => "_Inout_ ",
+                    (Position::Param, true, true) => "_Inout_opt_ ",
+                    (Position::Return, _, false) => "_Ret_notnull_ ",
+                    (Position::Return, _, true) => "_Ret_maybenull_ ",
+                    (Position::Field, _, _) => "",
+                };
+                let c_ty = qualifiers.apply(rust_to_c(ty, name)?);
+                Ok((format!("{}{}", annotation, c_ty), c_ty))
+            }
+        }
+    }
+
     fn add_dependencies(&mut self, module: &[String], cty: &CType) -> Result<(), Error> {
         let deps = cty.dependencies();
 
         if !deps.is_empty() {
-            let header = header_name(module, &self.lib_name)?;
+            let header = self.header_name(module)?;
 
             match self.deps.entry(header) {
                 Entry::Occupied(o) => o.into_mut().extend(deps.into_iter()),
@@ -69,38 +497,73 @@ impl LangC {
         Ok(())
     }
 
+    /// Docs of a struct field or enum variant, indented inside the braces.
+    fn member_docs(&self, attrs: &[ast::Attribute]) -> String {
+        let (_, docs) = parse_attr(attrs, |_| true, |attr| retrieve_docs(attr, "\t"));
+        let docs = self.doc_sections.apply(&docs);
+        match self.doc_width {
+            Some(width) => wrap_docs(&docs, width),
+            None => docs,
+        }
+    }
+
     fn append_to_header(
         &mut self,
         buffer: String,
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
-        let header = header_name(module, &self.lib_name)?;
+        let header = self.header_name(module)?;
         append_output(buffer, &header, outputs);
         Ok(())
     }
 
     /// Transform a Rust FFI function into a C function decl
+    ///
+    /// `nonnull` marks the returned pointer as non-null, even if it is wrapped in an `Option`.
     pub fn transform_native_fn(
         &mut self,
         fn_decl: &ast::FnDecl,
         docs: &str,
         name: &str,
+        attrs: &[ast::Attribute],
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
         // Handle the case when the return type is a function pointer (which requires that the
         // entire declaration is wrapped by the function pointer type) by first creating the name
         // and parameters, then passing that whole thing to `rust_to_c`.
-        let fn_args = fn_decl.inputs.clone();
+        let fn_args = &fn_decl.inputs;
         let mut args = Vec::new();
 
         // Arguments
-        for arg in &fn_args {
+        for arg in fn_args {
             let arg_name = pprust::pat_to_string(&*arg.pat);
-            let c_ty = rust_to_c(&arg.ty, &arg_name)?;
+            let qualifiers = Qualifiers::from_attrs(attrs, Some(&arg_name));
+            let (decl, c_ty) =
+                self.annotated_c_ty(&arg.ty, &arg_name, false, qualifiers, Position::Param)?;
             self.add_dependencies(module, &c_ty.1)?;
-            args.push(c_ty);
+            args.push(decl);
+        }
+
+        // Byte buffers followed by their length.
+        if self.swig_interface {
+            for pair in fn_args.windows(2) {
+                let (buf, len) = (&pair[0], &pair[1]);
+                let buf_name = pprust::pat_to_string(&*buf.pat);
+                let len_name = pprust::pat_to_string(&*len.pat);
+
+                if pprust::ty_to_string(&buf.ty) == "*const u8"
+                    && pprust::ty_to_string(&len.ty) == "usize"
+                    && len_name == format!("{}_len", buf_name)
+                {
+                    let _ = self.swig_typemaps.insert(format!(
+                        "%apply (char *STRING, size_t LENGTH) {{ ({}, {}) }};\n",
+                        rust_to_c(&buf.ty, &buf_name)?,
+                        rust_to_c(&len.ty, &len_name)?
+                    ));
+                }
+            }
         }
 
         let buf = format!(
@@ -109,10 +572,7 @@ impl LangC {
             if args.is_empty() {
                 String::from("void")
             } else {
-                args.into_iter()
-                    .map(|cty| format!("{}", cty))
-                    .collect::<Vec<_>>()
-                    .join(", ")
+                args.join(", ")
             }
         );
 
@@ -124,22 +584,101 @@ impl LangC {
                     level: Level::Error,
                     span: Some(ty.span),
                     message: "panics across a C boundary are naughty!".into(),
+                    help: None,
                 });
             }
             ast::FunctionRetTy::Default(..) => format!("void {}", buf),
             ast::FunctionRetTy::Ty(ref ty) => {
-                let c_ty = rust_to_c(&*ty, &buf)?;
+                let nonnull = attrs.iter().any(|attr| has_cheddar_flag(attr, "nonnull"));
+                let (decl, c_ty) = self.annotated_c_ty(
+                    &*ty,
+                    &buf,
+                    nonnull,
+                    Qualifiers::default(),
+                    Position::Return,
+                )?;
                 self.add_dependencies(module, &c_ty.1)?;
-                format!("{}", c_ty)
+                decl
             }
         };
 
-        let mut output = String::new();
+        let mut output = String::with_capacity(docs.len() + full_declaration.len() + 3);
         output.push_str(docs);
         output.push_str(&full_declaration);
         output.push_str(";\n\n");
 
-        append_output(output, &header_name(module, &self.lib_name)?, outputs);
+        append_output(output, &self.header_name(module)?, outputs);
+
+        Ok(())
+    }
+}
+
+impl LangC {
+    /// Lower a `#[repr(C)]` enum with data-carrying variants to its C layout: an enum of tags,
+    /// a struct with the fields of every variant and a struct holding the tag followed by a
+    /// union of those variant structs.
+    fn transform_tagged_enum(
+        &mut self,
+        name: &str,
+        docs: &str,
+        definition: &ast::EnumDef,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = String::new();
+
+        buffer.push_str(&format!("typedef enum {}_Tag {{\n", name));
+        for var in &definition.variants {
+            let docs = self.member_docs(&var.node.attrs);
+            buffer.push_str(&docs);
+            buffer.push_str(&format!("\t{}_{},\n", name, var.node.name.name.as_str()));
+        }
+        buffer.push_str(&format!("}} {}_Tag;\n\n", name));
+
+        let mut members = String::new();
+        for var in &definition.variants {
+            let fields = var.node.data.fields();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let var_name = var.node.name.name.as_str();
+            let body_name = format!("{}_{}_Body", name, var_name);
+
+            buffer.push_str(&format!("typedef struct {} {{\n", body_name));
+            for (index, field) in fields.iter().enumerate() {
+                let docs = self.member_docs(&field.attrs);
+                buffer.push_str(&docs);
+
+                // Tuple variants have their fields named by position.
+                let field_name = match field.ident {
+                    Some(ident) => ident.name.as_str().to_string(),
+                    None => format!("_{}", index),
+                };
+
+                let ty = rust_to_c(&*field.ty, &field_name)?;
+                self.add_dependencies(module, &ty.1)?;
+                buffer.push_str(&format!("\t{};\n", ty));
+            }
+            buffer.push_str(&format!("}} {};\n\n", body_name));
+
+            members.push_str(&format!("\t\t{} {};\n", body_name, var_name));
+        }
+
+        buffer.push_str(docs);
+        buffer.push_str(&format!(
+            "typedef struct {0} {{\n\
+             \t{0}_Tag tag;\n\
+             \tunion {{\n\
+             {1}\
+             \t}} payload;\n\
+             }} {0};\n\n",
+            name, members
+        ));
+        self.append_to_header(buffer, module, outputs)?;
+
+        let header = self.header_name(module)?;
+        self.decls.insert(name.to_string(), header);
 
         Ok(())
     }
@@ -152,44 +691,95 @@ impl Default for LangC {
 }
 
 impl Lang for LangC {
-    /// Convert `pub type A = B;` into `typedef B A;`.
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn crate_version(&mut self, name: &str, version: &str) {
+        self.version = Some((name.to_string(), version.to_string()));
+    }
+
+    fn add_handle(&mut self, name: &str, _free_fn: Option<&str>) {
+        let _ = self.handles.insert(name.to_string());
+    }
+
+    /// Convert `pub const NAME: Type = value;` into `#define NAME value`.
     ///
-    /// Aborts if A is generic.
-    fn parse_ty(
+    /// Only literal values are supported, other constants are skipped.
+    fn parse_const(
         &mut self,
         item: &ast::Item,
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
-        let (_, docs) = parse_attr(&item.attrs, |_| true, |attr| retrieve_docstring(attr, ""));
-
-        let mut buffer = String::new();
-        buffer.push_str(&docs);
-
-        let name = item.ident.name.as_str();
-        let new_type = match item.node {
-            ast::ItemKind::Ty(ref ty, ref generics) => {
-                // Can not yet convert generics.
-                if generics.is_parameterized() {
-                    return Ok(());
-                }
-
-                rust_to_c(&*ty, &name)?
-            }
+        self.set_item_header(item);
+        self.collect_examples(item);
+
+        let value = match item.node {
+            ast::ItemKind::Const(_, ref expr) => match c_const_value(expr) {
+                Some(value) => value,
+                None => return Ok(()),
+            },
             _ => {
                 return Err(Error {
                     level: Level::Bug,
                     span: Some(item.span),
-                    message: "`parse_ty` called on wrong `Item_`".into(),
+                    message: "`parse_const` called on wrong `Item_`".into(),
+                    help: None,
                 });
             }
         };
 
+        // Without `<stdbool.h>`, the boolean values are the `TRUE` and `FALSE` macros.
+        let value = match self.bool_repr {
+            BoolRepr::Stdbool => value,
+            BoolRepr::UInt8 | BoolRepr::Int => match &*value {
+                "true" => "TRUE".to_string(),
+                "false" => "FALSE".to_string(),
+                _ => value.clone(),
+            },
+        };
+
+        let (_, docs) = parse_attr(&item.attrs, |_| true, |attr| retrieve_docs(attr, ""));
+        let docs = self.doc_sections.apply(&docs);
+
+        let mut buffer = String::new();
+        buffer.push_str(&docs);
+        buffer.push_str(&format!("#define {} {}\n\n", item.ident.name.as_str(), value));
+        self.append_to_header(buffer, module, outputs)?;
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `typedef B A;`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        self.set_item_header(item);
+        self.collect_examples(item);
+        let mapped = self.map_bools(item);
+        let item = mapped.as_ref().unwrap_or(item);
+
+        let (_, docs) = parse_attr(&item.attrs, |_| true, |attr| retrieve_docs(attr, ""));
+        let docs = self.doc_sections.apply(&docs);
+
+        let mut buffer = String::new();
+        buffer.push_str(&docs);
+
+        let name = item.ident.name.as_str();
+        let new_type = match alias_type(item)? {
+            Some(ty) => rust_to_c(ty, &name)?,
+            None => return Ok(()),
+        };
+
         buffer.push_str(&format!("typedef {};\n\n", new_type));
         self.append_to_header(buffer, module, outputs)?;
 
-        self.decls
-            .insert(name.to_string(), header_name(module, &self.lib_name)?);
+        let header = self.header_name(module)?;
+        self.decls.insert(name.to_string(), header);
 
         Ok(())
     }
@@ -200,15 +790,26 @@ impl Lang for LangC {
     /// will abort.
     ///
     /// Bindgen will error if the enum if generic or if it contains non-unit variants.
+    ///
+    /// Enums marked `#[cheddar(sentinel)]` end with an `Enum_SENTINEL` enumerator, one past the
+    /// last variant (the number of variants if they are not given values), and enums marked
+    /// `#[cheddar(force_int)]` with an `Enum_FORCE_INT = 0x7FFFFFFF` enumerator so the size of
+    /// the enum doesn't depend on the values of the variants.
     fn parse_enum(
         &mut self,
         item: &ast::Item,
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
+        self.set_item_header(item);
+        self.collect_examples(item);
+        let mapped = self.map_bools(item);
+        let item = mapped.as_ref().unwrap_or(item);
+
         let (repr_c, docs) = parse_attr(&item.attrs, check_repr_c, |attr| {
-            retrieve_docstring(attr, "")
+            retrieve_docs(attr, "")
         });
+        let docs = self.doc_sections.apply(&docs);
         // If it's not #[repr(C)] then it can't be called from C.
         if !repr_c {
             return Ok(());
@@ -219,43 +820,60 @@ impl Lang for LangC {
 
         let name = item.ident.name.as_str();
         buffer.push_str(&format!("typedef enum {} {{\n", name));
+        let mut cases = String::new();
         if let ast::ItemKind::Enum(ref definition, ref generics) = item.node {
             if generics.is_parameterized() {
                 return Err(Error {
                     level: Level::Error,
                     span: Some(item.span),
                     message: "bindgen can not handle parameterized `#[repr(C)]` enums".into(),
+                    help: None,
                 });
             }
 
-            for var in &definition.variants {
-                if !var.node.data.is_unit() {
-                    return Err(Error {
-                        level: Level::Error,
-                        span: Some(var.span),
-                        message: "bindgen can not handle `#[repr(C)]` enums with non-unit variants"
-                            .into(),
-                    });
-                }
+            if definition.variants.iter().any(|var| !var.node.data.is_unit()) {
+                return self.transform_tagged_enum(&name, &docs, definition, module, outputs);
+            }
 
-                let (_, docs) = parse_attr(
-                    &var.node.attrs,
-                    |_| true,
-                    |attr| retrieve_docstring(attr, "\t"),
-                );
+            for var in &definition.variants {
+                let docs = self.member_docs(&var.node.attrs);
                 buffer.push_str(&docs);
 
                 buffer.push_str(&format!("\t{}_{},\n", name, pprust::variant_to_string(var)));
+
+                let variant = var.node.name.name.as_str();
+                cases.push_str(&format!(
+                    "\tcase {}_{}:\n\t\treturn \"{}\";\n",
+                    name, variant, variant
+                ));
+            }
+
+            if item.attrs.iter().any(|attr| has_cheddar_flag(attr, "sentinel")) {
+                buffer.push_str(&format!("\t{}_SENTINEL,\n", name));
+            }
+            if item.attrs.iter().any(|attr| has_cheddar_flag(attr, "force_int")) {
+                buffer.push_str(&format!("\t{}_FORCE_INT = 0x7FFFFFFF,\n", name));
             }
         } else {
             return Err(Error {
                 level: Level::Bug,
                 span: Some(item.span),
                 message: "`parse_enum` called on wrong `Item_`".into(),
+                help: None,
             });
         }
 
         buffer.push_str(&format!("}} {};\n\n", name));
+
+        if self.enum_to_string {
+            let signature = format!("const char* {}_to_string({} value)", name, name);
+            buffer.push_str(&format!("{};\n\n", signature));
+            self.enum_to_string_impls.push_str(&format!(
+                "{} {{\n\tswitch (value) {{\n{}\tdefault:\n\t\treturn NULL;\n\t}}\n}}\n\n",
+                signature, cases
+            ));
+        }
+
         self.append_to_header(buffer, module, outputs)?;
 
         Ok(())
@@ -273,19 +891,40 @@ impl Lang for LangC {
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
+        self.set_item_header(item);
+        self.collect_examples(item);
+        let mapped = self.map_bools(item);
+        let item = mapped.as_ref().unwrap_or(item);
+
         let (repr_c, docs) = parse_attr(&item.attrs, check_repr_c, |attr| {
-            retrieve_docstring(attr, "")
+            retrieve_docs(attr, "")
         });
-        // If it's not #[repr(C)] then it can't be called from C.
+        let docs = self.doc_sections.apply(&docs);
+        // If it's not #[repr(C)] then it can't be called from C, except through pointers to an
+        // opaque handle.
         if !repr_c {
+            let name = item.ident.name.as_str();
+            if self.handles.contains(&*name) {
+                let mut buffer = docs;
+                buffer.push_str(&format!("typedef struct {0} {0};\n\n", name));
+                self.append_to_header(buffer, module, outputs)?;
+
+                let header = self.header_name(module)?;
+                self.decls.insert(name.to_string(), header);
+            }
             return Ok(());
         }
 
         let mut buffer = String::new();
+        let layout = repr_layout(&item.attrs);
+        if let Some(packed) = layout.packed {
+            buffer.push_str(&format!("#pragma pack(push, {})\n", packed));
+        }
         buffer.push_str(&docs);
 
         let name = item.ident.name.as_str();
         buffer.push_str(&format!("typedef struct {}", name));
+        let mut fields = Vec::new();
 
         if let ast::ItemKind::Struct(ref variants, ref generics) = item.node {
             if generics.is_parameterized() {
@@ -293,18 +932,17 @@ impl Lang for LangC {
                     level: Level::Error,
                     span: Some(item.span),
                     message: "bindgen can not handle parameterized `#[repr(C)]` structs".into(),
+                    help: None,
                 });
             }
 
             if variants.is_struct() {
                 buffer.push_str(" {\n");
+                let body_start = buffer.len();
 
-                for field in variants.fields() {
-                    let (_, docs) = parse_attr(
-                        &field.attrs,
-                        |_| true,
-                        |attr| retrieve_docstring(attr, "\t"),
-                    );
+                let count = variants.fields().len();
+                for (index, field) in variants.fields().iter().enumerate() {
+                    let docs = self.member_docs(&field.attrs);
                     buffer.push_str(&docs);
 
                     let name = match field.ident {
@@ -312,9 +950,117 @@ impl Lang for LangC {
                         None => unreachable!("a tuple struct snuck through"),
                     };
 
-                    let ty = rust_to_c(&*field.ty, &name)?;
+                    let flexible = field
+                        .attrs
+                        .iter()
+                        .any(|attr| has_cheddar_flag(attr, "flexible"));
+                    if flexible {
+                        let elem = match field.ty.node {
+                            ast::TyKind::Array(ref elem, ref len)
+                                if index + 1 == count && pprust::expr_to_string(len) == "0" =>
+                            {
+                                elem
+                            }
+                            _ => {
+                                return Err(Error {
+                                    level: Level::Error,
+                                    span: Some(field.span),
+                                    message: "`#[cheddar(flexible)]` only applies to a trailing \
+                                              zero-length array field (e.g. `data: [u8; 0]`)"
+                                        .into(),
+                                    help: None,
+                                })
+                            }
+                        };
+
+                        let c_ty = anon_rust_to_c(elem)?;
+                        self.add_dependencies(module, &c_ty)?;
+                        buffer.push_str(&format!("\t{} {}[];\n", c_ty, name));
+                        continue;
+                    }
+
+                    {
+                        let inline = match field.ty.node {
+                            ast::TyKind::Path(None, ref path) if path.segments.len() == 1 => self
+                                .inline_structs
+                                .get_mut(&*path.segments[0].identifier.name.as_str()),
+                            _ => None,
+                        };
+                        if let Some(inline) = inline {
+                            if let Some(ref parent) = inline.parent {
+                                return Err(Error {
+                                    level: Level::Error,
+                                    span: Some(field.span),
+                                    message: format!(
+                                        "`#[cheddar(inline)]` structs can only be used by a \
+                                         single struct (already used by `{}`)",
+                                        parent
+                                    ),
+                                    help: None,
+                                });
+                            }
+                            inline.parent = Some(item.ident.name.as_str().to_string());
+
+                            buffer.push_str("\tstruct {\n");
+                            for line in inline.body.lines() {
+                                buffer.push_str(&format!("\t{}\n", line));
+                            }
+                            buffer.push_str(&format!("\t}} {};\n", name));
+                            continue;
+                        }
+                    }
+
+                    fields.push(name.to_string());
+
+                    let nonnull = field.attrs.iter().any(|attr| has_cheddar_flag(attr, "nonnull"));
+                    let qualifiers = Qualifiers::from_attrs(&field.attrs, None);
+                    let (decl, ty) = self.annotated_c_ty(
+                        &*field.ty,
+                        &name,
+                        nonnull,
+                        qualifiers,
+                        Position::Field,
+                    )?;
                     self.add_dependencies(module, &ty.1)?;
-                    buffer.push_str(&format!("\t{};\n", ty));
+
+                    // Over-aligning the first field over-aligns the whole struct.
+                    let decl = match layout.align {
+                        Some(align) if index == 0 => format!("_Alignas({}) {}", align, decl),
+                        _ => decl,
+                    };
+
+                    let (_, bits) = parse_attr(&field.attrs, |_| false, retrieve_bits);
+                    if bits.is_empty() {
+                        buffer.push_str(&format!("\t{};\n", decl));
+                    } else {
+                        if !is_integer(&field.ty) {
+                            return Err(Error {
+                                level: Level::Error,
+                                span: Some(field.span),
+                                message: "bitfields are only supported on integer fields".into(),
+                                help: None,
+                            });
+                        }
+
+                        warn!(
+                            "`{}::{}` is emitted as a {}-bit bitfield, the Rust side \
+                             has to pack it manually",
+                            item.ident.name.as_str(),
+                            name,
+                            bits
+                        );
+                        buffer.push_str(&format!("\t{} : {};\n", decl, bits));
+                    }
+                }
+
+                let inline = item.attrs.iter().any(|attr| has_cheddar_flag(attr, "inline"));
+                if inline {
+                    let inline = InlineStruct {
+                        body: buffer[body_start..].to_string(),
+                        parent: None,
+                    };
+                    let _ = self.inline_structs.insert(name.to_string(), inline);
+                    return Ok(());
                 }
 
                 buffer.push_str("}");
@@ -326,6 +1072,7 @@ impl Lang for LangC {
                     span: Some(item.span),
                     message: "can not handle unit or tuple `#[repr(C)]` structs with >1 members"
                         .into(),
+                    help: None,
                 });
             }
         } else {
@@ -333,14 +1080,120 @@ impl Lang for LangC {
                 level: Level::Bug,
                 span: Some(item.span),
                 message: "`parse_struct` called on wrong `Item_`".into(),
+                help: None,
             });
         }
 
-        buffer.push_str(&format!(" {};\n\n", name));
+        buffer.push_str(&format!(" {};\n", name));
+        if layout.packed.is_some() {
+            buffer.push_str("#pragma pack(pop)\n");
+        }
+        buffer.push_str("\n");
+
+        if self.struct_init_macros && !fields.is_empty() {
+            // The parameters are suffixed so they don't replace the field designators.
+            let params: Vec<_> = fields.iter().map(|field| format!("{}_", field)).collect();
+            let inits: Vec<_> = fields
+                .iter()
+                .map(|field| format!(".{} = ({}_)", field, field))
+                .collect();
+            buffer.push_str(&format!(
+                "#define {}_INIT({}) (({}){{ {} }})\n\n",
+                name.to_uppercase(),
+                params.join(", "),
+                name,
+                inits.join(", ")
+            ));
+        }
+
+        let default = item.attrs.iter().any(|attr| has_cheddar_flag(attr, "default"));
+        if default {
+            // Only the derived implementation is known to zero-initialise the struct, as long as
+            // the defaults of all the fields are zeroes too.
+            let (derived, _) = parse_attr(&item.attrs, check_derive_default, |_| None);
+            if !derived || fields.is_empty() {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: "`#[cheddar(default)]` requires a struct with named fields \
+                              deriving `Default`"
+                        .into(),
+                    help: None,
+                });
+            }
+            if let ast::ItemKind::Struct(ref variants, _) = item.node {
+                let nonzero = variants.fields().iter().find(|field| !is_zero_default(&field.ty));
+                if let Some(field) = nonzero {
+                    return Err(Error {
+                        level: Level::Error,
+                        span: Some(field.span),
+                        message: format!(
+                            "`#[cheddar(default)]` requires fields whose default is zero, \
+                             but `{}` isn't known to be",
+                            pprust::ty_to_string(&field.ty)
+                        ),
+                        help: Some(
+                            "only use numbers, `bool`, `char`, optional pointers and arrays of \
+                             them"
+                                .into(),
+                        ),
+                    });
+                }
+            }
+
+            buffer.push_str(&format!(
+                "static inline {0} {0}_default(void) {{\n\t{0} value = {{ 0 }};\n\treturn \
+                 value;\n}}\n\n",
+                name
+            ));
+        }
+
+        self.append_to_header(buffer, module, outputs)?;
+
+        let header = self.header_name(module)?;
+        self.decls.insert(name.to_string(), header);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into a typedef of the underlying integer type and a
+    /// `#define` for every flag (e.g. `Flags_READ`).
+    fn parse_bitflags(
+        &mut self,
+        item: &ast::Item,
+        flags: &BitFlags,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        self.set_item_header(item);
+        self.collect_examples(item);
+
+        let (_, docs) = parse_attr(&flags.attrs, |_| true, |attr| retrieve_docs(attr, ""));
+        let docs = self.doc_sections.apply(&docs);
+
+        let mut buffer = String::new();
+        buffer.push_str(&docs);
+        buffer.push_str(&format!(
+            "typedef {};\n\n",
+            rust_to_c(&flags.ty, &flags.name)?
+        ));
+
+        for flag in &flags.flags {
+            let (_, docs) =
+                parse_attr(&flag.attrs, |_| true, |attr| retrieve_docs(attr, ""));
+            let docs = self.doc_sections.apply(&docs);
+            buffer.push_str(&docs);
+            buffer.push_str(&format!(
+                "#define {}_{} {:#x}\n",
+                flags.name, flag.name, flag.value
+            ));
+        }
+
+        buffer.push('\n');
         self.append_to_header(buffer, module, outputs)?;
 
-        self.decls
-            .insert(name.to_string(), header_name(module, &self.lib_name)?);
+        let header = self.header_name(module)?;
+        self.decls.insert(flags.name.clone(), header);
 
         Ok(())
     }
@@ -357,9 +1210,15 @@ impl Lang for LangC {
         module: &[String],
         outputs: &mut Outputs,
     ) -> Result<(), Error> {
+        self.set_item_header(item);
+        self.collect_examples(item);
+        let mapped = self.map_bools(item);
+        let item = mapped.as_ref().unwrap_or(item);
+
         let (no_mangle, docs) = parse_attr(&item.attrs, check_no_mangle, |attr| {
-            retrieve_docstring(attr, "")
+            retrieve_docs(attr, "")
         });
+        let docs = self.doc_sections.apply(&docs);
         // If it's not #[no_mangle] then it can't be called from C.
         if !no_mangle {
             return Ok(());
@@ -379,10 +1238,37 @@ impl Lang for LangC {
                     level: Level::Error,
                     span: Some(item.span),
                     message: "bindgen can not handle parameterized extern functions".into(),
+                    help: None,
                 });
             }
 
-            self.transform_native_fn(&*fn_decl, &docs, &format!("{}", name), module, outputs)?;
+            if self.method_sections {
+                let owner = methods::owner(&name, self.decls.keys());
+                if owner.is_some() && owner != self.method_owner {
+                    let section = format!("/* {} methods */\n\n", unwrap!(owner.as_ref()));
+                    self.append_to_header(section, module, outputs)?;
+                }
+                self.method_owner = owner;
+            }
+
+            self.transform_native_fn(
+                &*fn_decl,
+                &docs,
+                &format!("{}", name),
+                &item.attrs,
+                module,
+                outputs,
+            )?;
+
+            if self.mock {
+                let (decl, imp) = mock_fn(&name, fn_decl)?;
+                self.mock_decls.push_str(&decl);
+                self.mock_impls.push_str(&imp);
+                self.mock_resets.push_str(&format!(
+                    "\tmemset(&{0}_mock, 0, sizeof {0}_mock);\n",
+                    name
+                ));
+            }
 
             Ok(())
         } else {
@@ -390,11 +1276,29 @@ impl Lang for LangC {
                 level: Level::Bug,
                 span: Some(item.span),
                 message: "`parse_fn` called on wrong `Item_`".into(),
+                help: None,
             })
         }
     }
 
     fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let unused = self.inline_structs
+            .iter()
+            .find(|&(_, inline)| inline.parent.is_none())
+            .map(|(name, _)| name.clone());
+        self.inline_structs.clear();
+        if let Some(name) = unused {
+            return Err(Error {
+                level: Level::Error,
+                span: None,
+                message: format!(
+                    "`{}` is `#[cheddar(inline)]` but no `#[repr(C)]` struct of its module uses it",
+                    name
+                ),
+                help: None,
+            });
+        }
+
         let mut depgraph = Graph::<String, String>::new();
         let nodes_map: HashMap<String, _> = outputs
             .keys()
@@ -404,11 +1308,16 @@ impl Lang for LangC {
             nodes_map.iter().map(|(k, v)| (*v, k.clone())).collect();
         let mut edges = BTreeSet::new();
 
+        // The declarations without the include guards, for the LuaJIT module.
+        let declarations = if self.luajit_module {
+            outputs.clone()
+        } else {
+            Outputs::new()
+        };
+
         // Wrap modules with common includes
         for (header_name, value) in outputs.iter_mut() {
-            let code = format!("#include <stdint.h>\n#include <stdbool.h>\n\n{}", value);
-
-            *value = wrap_guard(&wrap_extern(&code), header_name);
+            *value = wrap_header(value, header_name, self.bool_repr);
 
             // Building a graph of dependencies
             if let Some(module_deps) = self.deps.get(header_name) {
@@ -431,12 +1340,17 @@ impl Lang for LangC {
 
         // Generate a top-level header and add custom user code
         let mut top_level_header = String::new();
+        if let Some((ref name, ref version)) = self.version {
+            top_level_header.push_str(&version_macros(name, version));
+        }
         if !self.custom_code.is_empty() {
             top_level_header.push_str(&format!("{}\n", self.custom_code));
         }
+        let mut headers = Vec::new();
         for node_id in sorted_deps {
             let header_name = &node_ids_map[&node_id];
             top_level_header.push_str(&format!("#include \"{}\"\n", header_name));
+            headers.push(header_name.clone());
         }
 
         outputs.insert(
@@ -444,12 +1358,128 @@ impl Lang for LangC {
             wrap_guard(&top_level_header, &format!("{}_root", self.lib_name)),
         );
 
+        if self.luajit_module {
+            let mut cdef = luajit_cdef(&self.custom_code);
+            for header in &headers {
+                if let Some(declarations) = declarations.get(header) {
+                    cdef.push_str(&luajit_cdef(declarations));
+                }
+            }
+
+            outputs.insert(
+                format!("{}.lua", self.lib_name),
+                format!(
+                    "local ffi = require(\"ffi\")\n\n\
+                     ffi.cdef[[\n{}]]\n\n\
+                     return ffi.load(os.getenv(\"{}_LIB\") or \"{}\")\n",
+                    cdef,
+                    sanitise_id(&self.lib_name).to_uppercase(),
+                    self.lib_name
+                ),
+            );
+        }
+
+        if self.swig_interface {
+            outputs.insert(
+                format!("{}.i", self.lib_name),
+                swig_interface(
+                    &self.lib_name,
+                    &headers,
+                    &self.swig_typemaps,
+                    &self.swig_custom_code,
+                ),
+            );
+        }
+
+        if let Some(ref prefix) = self.install_prefix {
+            let (krate, version) = match self.version {
+                Some((ref name, ref version)) => (name.as_str(), version.as_str()),
+                None => (self.lib_name.as_str(), "0.0.0"),
+            };
+            let fill = |template: &str| {
+                template
+                    .replace("@Prefix", prefix.trim_right_matches('/'))
+                    .replace("@LibName", &self.lib_name)
+                    .replace("@Crate", krate)
+                    .replace("@Version", version)
+            };
+
+            outputs.insert(
+                format!("{}.pc", self.lib_name),
+                fill(include_str!("../../resources/c/package.pc.template")),
+            );
+            outputs.insert(
+                format!("{}Config.cmake", self.lib_name),
+                fill(include_str!("../../resources/c/Config.cmake.template")),
+            );
+            outputs.insert(
+                format!("{}ConfigVersion.cmake", self.lib_name),
+                fill(include_str!("../../resources/c/ConfigVersion.cmake.template")),
+            );
+        }
+
+        for (index, &(ref name, ref code)) in self.examples.iter().enumerate() {
+            outputs.insert(
+                format!("examples{}{:03}_{}.c", path::MAIN_SEPARATOR, index + 1, name),
+                example(&self.lib_name, code),
+            );
+        }
+
+        if self.mock {
+            let mock_header = format!("{}_mock.h", self.lib_name);
+            let reset = format!("void {}_mock_reset(void)", sanitise_id(&self.lib_name));
+
+            outputs.insert(
+                mock_header.clone(),
+                wrap_guard(
+                    &wrap_extern(&format!(
+                        "#include \"{}.h\"\n\n{}{};",
+                        self.lib_name, self.mock_decls, reset
+                    )),
+                    &mock_header,
+                ),
+            );
+            outputs.insert(
+                format!("{}_mock.c", self.lib_name),
+                format!(
+                    "#include <string.h>\n#include \"{}\"\n\n{}{} {{\n{}}}\n",
+                    mock_header, self.mock_impls, reset, self.mock_resets
+                ),
+            );
+        }
+
+        if !self.enum_to_string_impls.is_empty() {
+            outputs.insert(
+                format!("{}.c", self.lib_name),
+                format!(
+                    "#include <stddef.h>\n#include \"{}.h\"\n\n{}",
+                    self.lib_name, self.enum_to_string_impls
+                ),
+            );
+        }
+
+        // Start over for the next compilation (e.g. for another target).
+        self.decls.clear();
+        self.deps.clear();
+        self.method_owner = None;
+        self.enum_to_string_impls.clear();
+        self.swig_typemaps.clear();
+        self.examples.clear();
+        self.mock_decls.clear();
+        self.mock_impls.clear();
+        self.mock_resets.clear();
+
         Ok(())
     }
 }
 
 /// Turn a Rust type with an associated name or type into a C type.
 pub fn rust_to_c(ty: &ast::Ty, assoc: &str) -> Result<CTypeNamed, Error> {
+    // Nullable pointers map to plain C pointers.
+    if let Some(inner) = option_inner(ty) {
+        return rust_to_c(inner, assoc);
+    }
+
     match ty.node {
         // Function pointers make life an absolute pain here.
         ast::TyKind::BareFn(ref bare_fn) => Ok(CTypeNamed(
@@ -463,6 +1493,10 @@ pub fn rust_to_c(ty: &ast::Ty, assoc: &str) -> Result<CTypeNamed, Error> {
 
 /// Turn a Rust type into a C type.
 fn anon_rust_to_c(ty: &ast::Ty) -> Result<CType, Error> {
+    if let Some(inner) = option_inner(ty) {
+        return anon_rust_to_c(inner);
+    }
+
     match ty.node {
         // Function pointers should not be in this function.
         ast::TyKind::BareFn(..) => Err(Error {
@@ -471,6 +1505,7 @@ fn anon_rust_to_c(ty: &ast::Ty) -> Result<CType, Error> {
             message:
                 "C function ptrs must have a name or function declaration associated with them"
                     .into(),
+            help: None,
         }),
         // Fixed-length arrays, converted into pointers.
         ast::TyKind::Array(ref ty, _) => {
@@ -491,12 +1526,129 @@ fn anon_rust_to_c(ty: &ast::Ty) -> Result<CType, Error> {
                     level: Level::Error,
                     span: Some(ty.span),
                     message: format!("bindgen can not handle the type `{}`", new_type),
+                    help: None,
                 })
             }
         }
     }
 }
 
+/// Render the C prototype of a function (e.g. `int32_t app_len(App const* app)`), without the
+/// nullability annotations.
+pub fn c_prototype(name: &str, fn_decl: &ast::FnDecl) -> Result<String, Error> {
+    let mut args = Vec::new();
+    for arg in &fn_decl.inputs {
+        let arg_name = pprust::pat_to_string(&*arg.pat);
+        args.push(format!("{}", rust_to_c(&arg.ty, &arg_name)?));
+    }
+
+    let declaration = format!(
+        "{}({})",
+        name,
+        if args.is_empty() {
+            String::from("void")
+        } else {
+            args.join(", ")
+        }
+    );
+
+    match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => Err(Error {
+            level: Level::Error,
+            span: Some(ty.span),
+            message: "panics across a C boundary are naughty!".into(),
+            help: None,
+        }),
+        ast::FunctionRetTy::Default(..) => Ok(format!("void {}", declaration)),
+        ast::FunctionRetTy::Ty(ref ty) => Ok(format!("{}", rust_to_c(ty, &declaration)?)),
+    }
+}
+
+/// Generate the state recording the calls of a function (with its last arguments and the result
+/// to return), and the mock implementation of the function updating it.
+fn mock_fn(name: &str, fn_decl: &ast::FnDecl) -> Result<(String, String), Error> {
+    let prototype = c_prototype(name, fn_decl)?;
+    let mut fields = String::from("\tunsigned int calls;\n");
+    let mut body = format!("\t++{}_mock.calls;\n", name);
+
+    for arg in &fn_decl.inputs {
+        let arg_name = pprust::pat_to_string(&*arg.pat);
+        fields.push_str(&format!("\t{};\n", rust_to_c(&arg.ty, &arg_name)?));
+        body.push_str(&format!("\t{0}_mock.{1} = {1};\n", name, arg_name));
+    }
+
+    if let ast::FunctionRetTy::Ty(ref ty) = fn_decl.output {
+        if pprust::ty_to_string(ty) != "()" {
+            fields.push_str(&format!("\t{};\n", rust_to_c(ty, "result")?));
+            body.push_str(&format!("\treturn {}_mock.result;\n", name));
+        }
+    }
+
+    Ok((
+        format!(
+            "struct {0}_mock {{\n{1}}};\nextern struct {0}_mock {0}_mock;\n\n",
+            name, fields
+        ),
+        format!(
+            "struct {0}_mock {0}_mock;\n\n{1} {{\n{2}}}\n\n",
+            name, prototype, body
+        ),
+    ))
+}
+
+/// Whether the default value of the type is known to be all zeroes: numbers, `bool`, `char`,
+/// optional pointers (`None`) and arrays of them.
+fn is_zero_default(ty: &ast::Ty) -> bool {
+    match ty.node {
+        ast::TyKind::Array(ref elem, _) => is_zero_default(elem),
+        ast::TyKind::Path(None, _) if option_inner(ty).is_some() => true,
+        ast::TyKind::Path(None, _) => match &*pprust::ty_to_string(ty) {
+            "f32" | "f64" | "c_float" | "c_double" | "libc::c_float" | "libc::c_double"
+            | "bool" | "char" => true,
+            _ => is_integer(ty),
+        },
+        _ => false,
+    }
+}
+
+/// Returns the pointer `T` of an `Option<T>` type (where `T` is a pointer or a function pointer).
+pub fn option_inner(ty: &ast::Ty) -> Option<&ast::Ty> {
+    let path = match ty.node {
+        ast::TyKind::Path(None, ref path) if path.segments.len() == 1 => path,
+        _ => return None,
+    };
+
+    let segment = &path.segments[0];
+    if segment.identifier.name != "Option" {
+        return None;
+    }
+
+    match segment.parameters {
+        Some(ref parameters) => match **parameters {
+            ast::PathParameters::AngleBracketed(ref data) if data.types.len() == 1 => {
+                match data.types[0].node {
+                    ast::TyKind::Ptr(..) | ast::TyKind::BareFn(..) => Some(&*data.types[0]),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        None => None,
+    }
+}
+
+/// Returns whether a pointer type is nullable, or `None` if the type is not a pointer.
+fn pointer_nullability(ty: &ast::Ty) -> Option<bool> {
+    if option_inner(ty).is_some() {
+        return Some(true);
+    }
+
+    match ty.node {
+        ast::TyKind::Ptr(..) | ast::TyKind::BareFn(..) => Some(false),
+        _ => None,
+    }
+}
+
 /// Turn a Rust pointer (*mut or *const) into the correct C form.
 fn ptr_to_c(ty: &ast::MutTy) -> Result<CType, Error> {
     let new_type = anon_rust_to_c(&ty.ty)?;
@@ -531,6 +1683,7 @@ fn fn_ptr_to_c(fn_ty: &ast::BareFnTy, fn_span: codemap::Span, inner: &str) -> Re
             level: Level::Error,
             span: Some(fn_span),
             message: "bindgen can not handle lifetimes".into(),
+            help: None,
         });
     }
 
@@ -557,6 +1710,7 @@ fn fn_ptr_to_c(fn_ty: &ast::BareFnTy, fn_span: codemap::Span, inner: &str) -> Re
                 level: Level::Error,
                 span: Some(ty.span),
                 message: "panics across a C boundary are naughty!".into(),
+                help: None,
             });
         }
         ast::FunctionRetTy::Default(..) => CType::Void,
@@ -580,6 +1734,7 @@ fn path_to_c(path: &ast::Path) -> Result<CType, Error> {
             level: Level::Bug,
             span: Some(path.span),
             message: "invalid type".into(),
+            help: None,
         });
     }
 
@@ -590,71 +1745,80 @@ fn path_to_c(path: &ast::Path) -> Result<CType, Error> {
             .split_last()
             .expect("already checked that there were at least two elements");
         let ty: &str = &ty.identifier.name.as_str();
-        let mut segments = Vec::with_capacity(module.len());
-        for segment in module {
-            segments.push(String::from(&*segment.identifier.name.as_str()));
-        }
-        let module = segments.join("::");
-        match &*module {
-            "libc" => Ok(libc_ty_to_c(ty)),
-            "std::os::raw" => Ok(osraw_ty_to_c(ty)),
-            _ => Err(Error {
+        let is_module = |names: &[&str]| {
+            module.len() == names.len()
+                && module
+                    .iter()
+                    .zip(names)
+                    .all(|(segment, name)| segment.identifier.name == *name)
+        };
+
+        if is_module(&["libc"]) || is_module(&["std", "os", "raw"]) {
+            Ok(alias_ty_to_c(ty))
+        } else {
+            Err(Error {
                 level: Level::Error,
                 span: Some(path.span),
                 message: "can not handle types in other modules (except `libc` and `std::os::raw`)"
                     .into(),
-            }),
+                help: None,
+            })
         }
     } else {
         Ok(rust_ty_to_c(&path.segments[0].identifier.name.as_str()))
     }
 }
 
-/// Convert a Rust type from `libc` into a C type.
-///
-/// Most map straight over but some have to be converted.
-fn libc_ty_to_c(ty: &str) -> CType {
-    match ty {
-        "c_void" => CType::Void,
-        "c_float" => CType::Native("float"),
-        "c_double" => CType::Native("double"),
-        "c_char" => CType::Native("char"),
-        "c_schar" => CType::Native("signed char"),
-        "c_uchar" => CType::Native("unsigned char"),
-        "c_short" => CType::Native("short"),
-        "c_ushort" => CType::Native("unsigned short"),
-        "c_int" => CType::Native("int"),
-        "c_uint" => CType::Native("unsigned int"),
-        "c_long" => CType::Native("long"),
-        "c_ulong" => CType::Native("unsigned long"),
-        "c_longlong" => CType::Native("long long"),
-        "c_ulonglong" => CType::Native("unsigned long long"),
-        // All other types should map over to C.
-        ty => CType::Mapping(ty.to_string()),
-    }
+/// C types of the Rust primitives and of the aliases of `libc` and `std::os::raw`, sorted by Rust
+/// name so they can be looked up with a binary search. New mappings only need a row here.
+const PRIMITIVES: &[(&str, &str)] = &[
+    ("bool", "bool"),
+    ("c_char", "char"),
+    ("c_double", "double"),
+    ("c_float", "float"),
+    ("c_int", "int"),
+    ("c_long", "long"),
+    ("c_longlong", "long long"),
+    ("c_schar", "signed char"),
+    ("c_short", "short"),
+    ("c_uchar", "unsigned char"),
+    ("c_uint", "unsigned int"),
+    ("c_ulong", "unsigned long"),
+    ("c_ulonglong", "unsigned long long"),
+    ("c_ushort", "unsigned short"),
+    // Unlike C's `char`, Rust's `char` is 32 bits wide.
+    ("char", "uint32_t /* Unicode scalar value */"),
+    ("f32", "float"),
+    ("f64", "double"),
+    ("i16", "int16_t"),
+    ("i32", "int32_t"),
+    ("i64", "int64_t"),
+    ("i8", "int8_t"),
+    ("isize", "intptr_t"),
+    ("u16", "uint16_t"),
+    ("u32", "uint32_t"),
+    ("u64", "uint64_t"),
+    ("u8", "uint8_t"),
+    ("usize", "uintptr_t"),
+];
+
+/// Returns the C type of a Rust primitive or of a `libc`/`std::os::raw` alias (e.g. `c_int`).
+fn primitive_to_c(ty: &str) -> Option<&'static str> {
+    PRIMITIVES
+        .binary_search_by(|&(name, _)| name.cmp(ty))
+        .ok()
+        .map(|index| PRIMITIVES[index].1)
 }
 
-/// Convert a Rust type from `std::os::raw` into a C type.
+/// Convert a Rust type from `libc` or `std::os::raw` into a C type.
 ///
-/// These mostly mirror the libc crate.
-fn osraw_ty_to_c(ty: &str) -> CType {
-    match ty {
-        "c_void" => CType::Void,
-        "c_char" => CType::Native("char"),
-        "c_double" => CType::Native("double"),
-        "c_float" => CType::Native("float"),
-        "c_int" => CType::Native("int"),
-        "c_long" => CType::Native("long"),
-        "c_longlong" => CType::Native("long long"),
-        "c_schar" => CType::Native("signed char"),
-        "c_short" => CType::Native("short"),
-        "c_uchar" => CType::Native("unsigned char"),
-        "c_uint" => CType::Native("unsigned int"),
-        "c_ulong" => CType::Native("unsigned long"),
-        "c_ulonglong" => CType::Native("unsigned long long"),
-        "c_ushort" => CType::Native("unsigned short"),
+/// Most map straight over but the `c_*` aliases have to be converted.
+fn alias_ty_to_c(ty: &str) -> CType {
+    match primitive_to_c(ty) {
+        _ if ty == "c_void" => CType::Void,
+        Some(c_ty) if ty.starts_with("c_") => CType::Native(c_ty),
         // All other types should map over to C.
-        ty => CType::Mapping(ty.to_string()),
+        _ => CType::Mapping(ty.to_string()),
     }
 }
 
@@ -663,25 +1827,385 @@ fn osraw_ty_to_c(ty: &str) -> CType {
 /// This includes user-defined types. We currently trust the user not to use types which we don't
 /// know the structure of (like String).
 fn rust_ty_to_c(ty: &str) -> CType {
-    match ty {
-        "()" => CType::Void,
-        "f32" => CType::Native("float"),
-        "f64" => CType::Native("double"),
-        "i8" => CType::Native("int8_t"),
-        "i16" => CType::Native("int16_t"),
-        "i32" => CType::Native("int32_t"),
-        "i64" => CType::Native("int64_t"),
-        "isize" => CType::Native("intptr_t"),
-        "u8" => CType::Native("uint8_t"),
-        "u16" => CType::Native("uint16_t"),
-        "u32" => CType::Native("uint32_t"),
-        "u64" => CType::Native("uint64_t"),
-        "usize" => CType::Native("uintptr_t"),
-        "bool" => CType::Native("bool"),
-        ty => libc_ty_to_c(ty),
+    if ty == "()" || ty == "c_void" {
+        return CType::Void;
+    }
+
+    match primitive_to_c(ty) {
+        Some(c_ty) => CType::Native(c_ty),
+        None => CType::Mapping(ty.to_string()),
+    }
+}
+
+/// Turn the code of a documented example into a C source file including the header.
+fn example(lib_name: &str, code: &str) -> String {
+    let mut source = format!("#include \"{}.h\"\n", lib_name);
+
+    if code.contains("main(") {
+        source.push('\n');
+        source.push_str(code);
+    } else {
+        let (directives, body): (Vec<_>, Vec<_>) =
+            code.lines().partition(|line| line.starts_with('#'));
+        for directive in directives {
+            source.push_str(&format!("{}\n", directive));
+        }
+
+        source.push_str("\nint main(void) {\n");
+        for line in body.join("\n").trim_matches('\n').lines() {
+            if line.is_empty() {
+                source.push('\n');
+            } else {
+                source.push_str(&format!("\t{}\n", line));
+            }
+        }
+        source.push_str("\treturn 0;\n}\n");
+    }
+
+    source
+}
+
+/// Generate the version macros of the crate (e.g. `MYCRATE_VERSION_MAJOR`).
+fn version_macros(name: &str, version: &str) -> String {
+    let prefix = sanitise_id(&name.replace("-", "_")).to_uppercase();
+    let (major, minor, patch) = version_parts(version);
+
+    format!(
+        "#define {0}_VERSION_MAJOR {1}\n\
+         #define {0}_VERSION_MINOR {2}\n\
+         #define {0}_VERSION_PATCH {3}\n\
+         #define {0}_VERSION \"{4}\"\n\n",
+        prefix, major, minor, patch, version
+    )
+}
+
+/// Generate a SWIG interface file wrapping the given headers (in dependency order).
+fn swig_interface(
+    lib_name: &str,
+    headers: &[String],
+    typemaps: &BTreeSet<String>,
+    custom_code: &str,
+) -> String {
+    let mut output = format!(
+        "%module {0}\n\n\
+         %{{\n\
+         #include \"{0}.h\"\n\
+         %}}\n\n\
+         %include <stdint.i>\n\
+         %include <typemaps.i>\n\n",
+        lib_name
+    );
+
+    if !typemaps.is_empty() {
+        for typemap in typemaps {
+            output.push_str(typemap);
+        }
+        output.push('\n');
+    }
+
+    if !custom_code.is_empty() {
+        output.push_str(&format!("{}\n", custom_code));
+    }
+
+    for header in headers {
+        output.push_str(&format!("%include \"{}\"\n", header));
+    }
+
+    output
+}
+
+/// Strip the C declarations of everything LuaJIT's `ffi.cdef` can't handle: preprocessor
+/// directives (integer macros are turned into `static const int`, `#pragma pack` is kept, and of
+/// the sections guarded by `UINTPTR_MAX` only the one for the pointer width of the host is kept),
+/// inline function definitions and nullability annotations.
+fn luajit_cdef(code: &str) -> String {
+    const ANNOTATIONS: &[&str] = &[
+        "_Nullable ",
+        "_Nonnull ",
+        "_In_opt_ ",
+        "_In_ ",
+        "_Inout_opt_ ",
+        "_Inout_ ",
+        "_Ret_maybenull_ ",
+        "_Ret_notnull_ ",
+    ];
+
+    let host_guard = format!(
+        "#if UINTPTR_MAX == {}",
+        uintptr_max(if cfg!(target_pointer_width = "64") { 64 } else { 32 })
+    );
+
+    let mut output = String::new();
+    let mut lines = code.lines();
+    // Whether the content of each enclosing `#if` is kept.
+    let mut conditions = Vec::new();
+
+    while let Some(line) = lines.next() {
+        if line.starts_with("#if") {
+            conditions.push(!line.starts_with("#if UINTPTR_MAX ") || line == host_guard);
+            continue;
+        }
+        if line.starts_with("#endif") {
+            let _ = conditions.pop();
+            continue;
+        }
+        if conditions.contains(&false) {
+            continue;
+        }
+
+        if line.starts_with("static inline ") {
+            // Skip the body of the function.
+            while let Some(line) = lines.next() {
+                if line == "}" {
+                    break;
+                }
+            }
+            let _ = lines.next();
+            continue;
+        }
+
+        if line.starts_with("#pragma pack") {
+            output.push_str(&format!("{}\n", line));
+            continue;
+        }
+        if line.starts_with('#') {
+            let mut parts = line.split_whitespace();
+            if let (Some("#define"), Some(name), Some(value), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            {
+                if let Some(value) = int_macro_value(value) {
+                    output.push_str(&format!("static const int {} = {};\n", name, value));
+                }
+            }
+            continue;
+        }
+
+        let line = ANNOTATIONS
+            .iter()
+            .fold(line.to_string(), |line, annotation| line.replace(annotation, ""));
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Parse the value of an integer macro (e.g. `10`, `(-1)` or `0x4`) fitting into an `int`.
+fn int_macro_value(value: &str) -> Option<i32> {
+    let value = value.trim_left_matches('(').trim_right_matches(')');
+    let (negative, value) = if value.starts_with('-') {
+        (true, &value[1..])
+    } else {
+        (false, value)
+    };
+
+    let value = if value.starts_with("0x") {
+        i64::from_str_radix(&value[2..], 16).ok()?
+    } else {
+        value.parse::<i64>().ok()?
+    };
+    let value = if negative { -value } else { value };
+
+    if value >= i64::from(i32::min_value()) && value <= i64::from(i32::max_value()) {
+        Some(value as i32)
+    } else {
+        None
+    }
+}
+
+/// Convert a literal constant expression into its representation, for the languages sharing
+/// the literal syntax of Rust.
+///
+/// Integer arithmetic on literals is evaluated, while expressions referencing other constants
+/// are not supported, as the operators and the names of the constants differ between languages.
+pub fn const_value(expr: &ast::Expr) -> Option<String> {
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Int(value, _) => Some(value.to_string()),
+            ast::LitKind::Float(ref value, _) | ast::LitKind::FloatUnsuffixed(ref value) => {
+                Some(value.as_str().to_string())
+            }
+            ast::LitKind::Bool(value) => Some(value.to_string()),
+            ast::LitKind::Byte(value) => Some(value.to_string()),
+            ast::LitKind::Str(ref value, _) => Some(format!("{:?}", &*value.as_str())),
+            _ => None,
+        },
+        ast::ExprKind::Unary(ast::UnOp::Neg, ref expr) => {
+            const_value(expr).map(|value| format!("(-{})", value))
+        }
+        ast::ExprKind::Binary(..) | ast::ExprKind::Paren(..) => const_int(expr).map(|value| {
+            if value < 0 {
+                format!("(-{})", -value)
+            } else {
+                value.to_string()
+            }
+        }),
+        _ => None,
+    }
+}
+
+/// Convert a constant expression into its C representation.
+///
+/// Integer arithmetic on literals is evaluated (`16 * 1024` becomes `16384`), while expressions
+/// referencing other constants are re-emitted (`(MAX_LEN * 2)`) so they stay in sync.
+fn c_const_value(expr: &ast::Expr) -> Option<String> {
+    if let ast::ExprKind::Binary(..) = expr.node {
+        if let Some(value) = const_int(expr) {
+            return Some(if value < 0 {
+                format!("(-{})", -value)
+            } else {
+                value.to_string()
+            });
+        }
+    }
+
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Int(value, _) => Some(value.to_string()),
+            ast::LitKind::Float(ref value, _) | ast::LitKind::FloatUnsuffixed(ref value) => {
+                Some(value.as_str().to_string())
+            }
+            ast::LitKind::Bool(value) => Some(value.to_string()),
+            ast::LitKind::Byte(value) => Some(value.to_string()),
+            ast::LitKind::Str(ref value, _) => Some(string_literal(&value.as_str())),
+            _ => None,
+        },
+        ast::ExprKind::Unary(ast::UnOp::Neg, ref expr) => {
+            c_const_value(expr).map(|value| format!("(-{})", value))
+        }
+        ast::ExprKind::Binary(op, ref lhs, ref rhs) if is_arithmetic(op.node) => Some(format!(
+            "({} {} {})",
+            c_const_value(lhs)?,
+            op.node.to_string(),
+            c_const_value(rhs)?
+        )),
+        ast::ExprKind::Paren(ref expr) => c_const_value(expr),
+        // Another constant.
+        ast::ExprKind::Path(None, ref path) if path.segments.len() == 1 => {
+            Some(path.segments[0].identifier.name.as_str().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate an integer constant expression made of literals, or `None` if it references other
+/// constants, overflows or isn't an integer.
+pub fn const_int(expr: &ast::Expr) -> Option<i64> {
+    match expr.node {
+        ast::ExprKind::Lit(ref lit) => match lit.node {
+            ast::LitKind::Int(value, _) if value as i64 >= 0 => Some(value as i64),
+            _ => None,
+        },
+        ast::ExprKind::Unary(ast::UnOp::Neg, ref expr) => const_int(expr)?.checked_neg(),
+        ast::ExprKind::Paren(ref expr) => const_int(expr),
+        ast::ExprKind::Binary(op, ref lhs, ref rhs) => {
+            let (lhs, rhs) = (const_int(lhs)?, const_int(rhs)?);
+            match op.node {
+                ast::BinOpKind::Add => lhs.checked_add(rhs),
+                ast::BinOpKind::Sub => lhs.checked_sub(rhs),
+                ast::BinOpKind::Mul => lhs.checked_mul(rhs),
+                ast::BinOpKind::Div => lhs.checked_div(rhs),
+                ast::BinOpKind::Rem => lhs.checked_rem(rhs),
+                ast::BinOpKind::BitAnd => Some(lhs & rhs),
+                ast::BinOpKind::BitOr => Some(lhs | rhs),
+                ast::BinOpKind::BitXor => Some(lhs ^ rhs),
+                ast::BinOpKind::Shl if rhs >= 0 && rhs < 64 => lhs.checked_shl(rhs as u32),
+                ast::BinOpKind::Shr if rhs >= 0 && rhs < 64 => lhs.checked_shr(rhs as u32),
+                _ => None,
+            }
+        }
+        _ => None,
     }
 }
 
+/// Whether the operator is an arithmetic or bitwise one, which C, C# and most other languages
+/// share with Rust.
+pub fn is_arithmetic(op: ast::BinOpKind) -> bool {
+    match op {
+        ast::BinOpKind::Add
+        | ast::BinOpKind::Sub
+        | ast::BinOpKind::Mul
+        | ast::BinOpKind::Div
+        | ast::BinOpKind::Rem
+        | ast::BinOpKind::BitAnd
+        | ast::BinOpKind::BitOr
+        | ast::BinOpKind::BitXor
+        | ast::BinOpKind::Shl
+        | ast::BinOpKind::Shr => true,
+        _ => false,
+    }
+}
+
+/// Render a C string literal, escaping the quotes and backslashes, and the control and non-ASCII
+/// characters (as octal escapes of their UTF-8 bytes, which unlike hexadecimal escapes can't
+/// swallow the next characters).
+fn string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\r' => literal.push_str("\\r"),
+            b'\t' => literal.push_str("\\t"),
+            b' '...b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// If the attribute is a docstring, return it indented with its intra-doc links replaced by the
+/// names of the linked items.
+fn retrieve_docs(attr: &ast::Attribute, prepend: &str) -> Option<String> {
+    retrieve_docstring(attr, prepend).map(|text| docs::replace_links(&text, |name, _| name.into()))
+}
+
+/// Re-wrap the doc comment lines (`/// text`) longer than `width` columns, repeating their
+/// indentation on the continuation lines. Code blocks are left alone.
+fn wrap_docs(docs: &str, width: usize) -> String {
+    let columns = |text: &str| -> usize {
+        text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+    };
+
+    let mut output = String::new();
+    let mut in_code = false;
+
+    for line in docs.lines() {
+        let text = line.trim_left();
+        if !text.starts_with("///") && !text.starts_with("//!") {
+            output.push_str(&format!("{}\n", line));
+            continue;
+        }
+
+        let content = text[3..].trim_left();
+        if content.starts_with("```") {
+            in_code = !in_code;
+        }
+        if in_code || content.starts_with("```") || columns(line) <= width {
+            output.push_str(&format!("{}\n", line));
+            continue;
+        }
+
+        let prefix = &line[..line.len() - content.len()];
+        let mut current = prefix.to_string();
+        for word in content.split_whitespace() {
+            if current.len() > prefix.len() && columns(&current) + 1 + columns(word) > width {
+                output.push_str(&format!("{}\n", current.trim_right()));
+                current = prefix.to_string();
+            }
+            if current.len() > prefix.len() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        output.push_str(&format!("{}\n", current));
+    }
+
+    output
+}
+
 /// Wrap a block of code with an extern declaration.
 fn wrap_extern(code: &str) -> String {
     format!(
@@ -700,6 +2224,30 @@ extern "C" {{
     )
 }
 
+/// Wrap the declarations of a module with the common includes, an `extern "C"` block and an
+/// include-guard (as `wrap_guard` and `wrap_extern` do), copying them only once.
+fn wrap_header(code: &str, id: &str, bool_repr: BoolRepr) -> String {
+    let id = sanitise_id(id);
+    let parts = [
+        "\n#ifndef bindgen_",
+        id.as_str(),
+        "\n#define bindgen_",
+        id.as_str(),
+        "\n\n\n#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n",
+        "#include <stdint.h>\n",
+        bool_repr.prelude(),
+        "\n",
+        code,
+        "\n\n#ifdef __cplusplus\n}\n#endif\n\n\n#endif\n",
+    ];
+
+    let mut header = String::with_capacity(parts.iter().map(|part| part.len()).sum());
+    for part in &parts {
+        header.push_str(part);
+    }
+    header
+}
+
 /// Wrap a block of code with an include-guard.
 fn wrap_guard(code: &str, id: &str) -> String {
     format!(
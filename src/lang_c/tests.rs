@@ -1,5 +1,10 @@
 use super::types::CType;
+use super::LangC;
+use common::{Lang, Outputs};
+use config::Config;
+use parse;
 use syntax::ast;
+use Error;
 
 #[test]
 fn sanitise_id() {
@@ -42,6 +47,7 @@ fn pure_rust_types() {
         ("u32", CType::Native("uint32_t")),
         ("u64", CType::Native("uint64_t")),
         ("usize", CType::Native("uintptr_t")),
+        ("char", CType::Native("uint32_t /* Unicode scalar value */")),
     ];
 
     let name = "gabriel";
@@ -254,3 +260,1379 @@ fn paths() {
         "can't use a multi-segment path which isn't `libc`"
     );
 }
+
+#[test]
+fn version_macros() {
+    assert_eq!(
+        super::version_macros("safe-app", "0.5.1-alpha.2"),
+        "#define SAFE_APP_VERSION_MAJOR 0\n\
+         #define SAFE_APP_VERSION_MINOR 5\n\
+         #define SAFE_APP_VERSION_PATCH 1\n\
+         #define SAFE_APP_VERSION \"0.5.1-alpha.2\"\n\n"
+    );
+}
+
+#[test]
+fn item_hooks() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn foo() {}
+    ";
+    let mut config = Config::default();
+    config.hooks.pre.push(Box::new(|item: &ast::Item, _: &str| {
+        Some(format!("/* begin {} */\n", item.ident.name.as_str()))
+    }));
+    config
+        .hooks
+        .post
+        .push(Box::new(|_: &ast::Item, path: &str| Some(format!("/* end {} */\n", path))));
+
+    let outputs = compile_with(&mut LangC::new(), source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        format!("/* begin foo */\nvoid foo(void);\n\n/* end {} */\n", header)
+    );
+}
+
+#[test]
+fn custom_header() {
+    let source = "
+        #[no_mangle]
+        #[cheddar(header = \"auth.h\")]
+        pub extern \"C\" fn login() {}
+
+        #[no_mangle]
+        pub extern \"C\" fn logout() {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(outputs["auth.h"], "void login(void);\n\n");
+    assert_eq!(outputs[&header], "void logout(void);\n\n");
+}
+
+#[test]
+fn impl_functions() {
+    let source = "
+        impl Session {
+            #[no_mangle]
+            pub extern \"C\" fn close(session: *mut Session) {}
+            #[export_name = \"Session_flush\"]
+            pub extern \"C\" fn flush(session: *mut Session) {}
+
+            pub fn not_exported(&self) {}
+        }
+    ";
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+
+    let outputs = compile(&mut LangC::new(), source);
+    assert_eq!(outputs[&header], "void close(Session* session);\n\n");
+
+    let mut config = Config::default();
+    config.prefix_impl_fns = true;
+
+    // Only the methods exported under the prefixed name are declared with it.
+    let outputs = compile_with(&mut LangC::new(), source, &config);
+    assert_eq!(
+        outputs[&header],
+        "void close(Session* session);\n\n\
+         void Session_flush(Session* session);\n\n"
+    );
+}
+
+#[test]
+fn impl_self() {
+    let source = "
+        impl Session {
+            #[no_mangle]
+            pub extern \"C\" fn session_new() -> *mut Self {}
+            #[no_mangle]
+            pub extern \"C\" fn session_merge(session: *mut Self, other: *const Self) {}
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "Session* session_new(void);\n\n\
+         void session_merge(Session* session, Session const* other);\n\n"
+    );
+}
+
+#[test]
+fn impl_receivers() {
+    let source = "
+        impl Session {
+            #[no_mangle]
+            pub extern \"C\" fn close(&mut self) {}
+        }
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "`Session::close` takes `self`, which can not be passed from C"
+    );
+}
+
+#[test]
+fn method_sections() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_len(app: *const App) -> usize {}
+        #[repr(C)]
+        pub struct App { len: usize }
+        #[no_mangle]
+        pub extern \"C\" fn init() {}
+        #[no_mangle]
+        pub extern \"C\" fn app_clear(app: *mut App) {}
+    ";
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+
+    let mut lang = LangC::new();
+    lang.set_method_sections(true);
+    let config = Config {
+        group_methods: true,
+        ..Default::default()
+    };
+    let outputs = compile_with(&mut lang, source, &config);
+
+    assert_eq!(
+        outputs[&header],
+        "/* App methods */\n\n\
+         uintptr_t app_len(App const* app);\n\n\
+         void app_clear(App* app);\n\n\
+         void init(void);\n\n"
+    );
+}
+
+#[test]
+fn constants() {
+    let source = "
+        /// Maximum size.
+        pub const MAX_SIZE: usize = 1024;
+        pub const OFFSET: i32 = -5;
+        pub const NAME: &'static str = \"safe\";
+        pub const DEFAULT: Flags = Flags { bits: 0 };
+
+        impl Flags {
+            pub const READ: u32 = 1;
+            pub const WRITE: u32 = 2;
+        }
+    ";
+    let mut config = Config::default();
+    config.export_assoc_consts = true;
+
+    let outputs = compile_with(&mut LangC::new(), source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Maximum size.\n\
+         #define MAX_SIZE 1024\n\n\
+         #define OFFSET (-5)\n\n\
+         #define NAME \"safe\"\n\n\
+         #define Flags_READ 1\n\n\
+         #define Flags_WRITE 2\n\n"
+    );
+}
+
+#[test]
+fn constant_expressions() {
+    let source = "
+        pub const MAX_LEN: u32 = 16 * 1024;
+        pub const MIN_OFFSET: i32 = -(1 << 4);
+        pub const MAX_SIZE: u32 = MAX_LEN * 2 + 1;
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "#define MAX_LEN 16384\n\n\
+         #define MIN_OFFSET (-16)\n\n\
+         #define MAX_SIZE ((MAX_LEN * 2) + 1)\n\n"
+    );
+}
+
+#[test]
+fn string_constants() {
+    let source = r#"
+        pub const APP_ID: &str = "net.maidsafe.app";
+        pub const GREETING: &str = "\"Ol\u{e1}\"\tC:\\\n";
+    "#;
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        r#"#define APP_ID "net.maidsafe.app"
+
+#define GREETING "\"Ol\303\241\"\tC:\\\n"
+
+"#
+    );
+}
+
+#[test]
+fn bitflags() {
+    let source = "
+        bitflags! {
+            /// Access rights.
+            pub struct Access: u32 {
+                const READ = 0b01;
+                const WRITE = 0b10;
+            }
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Access rights.\n\
+         typedef uint32_t Access;\n\n\
+         #define Access_READ 0x1\n\
+         #define Access_WRITE 0x2\n\n"
+    );
+}
+
+#[test]
+fn tagged_unions() {
+    let source = "
+        #[repr(C)]
+        pub enum Shape {
+            Empty,
+            Circle { radius: f32 },
+            Rect(f32, f32),
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef enum Shape_Tag {\n\
+         \tShape_Empty,\n\
+         \tShape_Circle,\n\
+         \tShape_Rect,\n\
+         } Shape_Tag;\n\n\
+         typedef struct Shape_Circle_Body {\n\
+         \tfloat radius;\n\
+         } Shape_Circle_Body;\n\n\
+         typedef struct Shape_Rect_Body {\n\
+         \tfloat _0;\n\
+         \tfloat _1;\n\
+         } Shape_Rect_Body;\n\n\
+         typedef struct Shape {\n\
+         \tShape_Tag tag;\n\
+         \tunion {\n\
+         \t\tShape_Circle_Body Circle;\n\
+         \t\tShape_Rect_Body Rect;\n\
+         \t} payload;\n\
+         } Shape;\n\n"
+    );
+}
+
+#[test]
+fn bitfields() {
+    let source = "
+        #[repr(C)]
+        pub struct Header {
+            #[cheddar(bits = 3)]
+            version: u32,
+            #[cheddar(bits = 29)]
+            length: u32,
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Header {\n\
+         \tuint32_t version : 3;\n\
+         \tuint32_t length : 29;\n\
+         } Header;\n\n"
+    );
+}
+
+#[test]
+fn member_docs() {
+    let source = "
+        #[repr(C)]
+        pub enum Status {
+            /** Everything went fine,
+             * nothing to report. */
+            Ok,
+        }
+
+        #[repr(C)]
+        pub struct Buffer {
+            /// Length of the buffer in bytes, which is always smaller than the capacity.
+            ///
+            /// ```
+            /// assert!(buffer.len <= buffer.capacity);
+            /// ```
+            len: usize,
+        }
+    ";
+    let mut lang = LangC::new();
+    lang.set_doc_width(40);
+    let outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef enum Status {\n\
+         \t/** Everything went fine,\n\
+         \t             * nothing to report. */\n\
+         \tStatus_Ok,\n\
+         } Status;\n\n\
+         typedef struct Buffer {\n\
+         \t/// Length of the buffer in bytes,\n\
+         \t/// which is always smaller than the\n\
+         \t/// capacity.\n\
+         \t///\n\
+         \t/// ```\n\
+         \t/// assert!(buffer.len <= buffer.capacity);\n\
+         \t/// ```\n\
+         \tuintptr_t len;\n\
+         } Buffer;\n\n"
+    );
+}
+
+#[test]
+fn struct_layout() {
+    let source = "
+        #[repr(C, packed)]
+        pub struct Header {
+            version: u8,
+            length: u32,
+        }
+
+        #[repr(C, align(16))]
+        pub struct Block {
+            lo: u64,
+            hi: u64,
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "#pragma pack(push, 1)\n\
+         typedef struct Header {\n\
+         \tuint8_t version;\n\
+         \tuint32_t length;\n\
+         } Header;\n\
+         #pragma pack(pop)\n\n\
+         typedef struct Block {\n\
+         \t_Alignas(16) uint64_t lo;\n\
+         \tuint64_t hi;\n\
+         } Block;\n\n"
+    );
+}
+
+#[test]
+fn flexible_array_members() {
+    let source = "
+        #[repr(C)]
+        pub struct Packet {
+            len: u32,
+            #[cheddar(flexible)]
+            data: [u8; 0],
+        }
+
+        #[repr(C)]
+        pub struct Broken {
+            #[cheddar(flexible)]
+            data: [u8; 0],
+            len: u32,
+        }
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "`#[cheddar(flexible)]` only applies to a trailing zero-length array field \
+         (e.g. `data: [u8; 0]`)"
+    );
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Packet {\n\
+         \tuint32_t len;\n\
+         \tuint8_t data[];\n\
+         } Packet;\n\n"
+    );
+}
+
+#[test]
+fn inline_structs() {
+    let source = "
+        #[repr(C)]
+        pub struct Device {
+            id: u32,
+            regs: Registers,
+        }
+
+        #[cheddar(inline)]
+        #[repr(C)]
+        pub struct Registers {
+            /// Control register.
+            ctrl: u32,
+            status: u32,
+        }
+    ";
+    let mut lang = LangC::new();
+    let mut outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Device {\n\
+         \tuint32_t id;\n\
+         \tstruct {\n\
+         \t\t/// Control register.\n\
+         \t\tuint32_t ctrl;\n\
+         \t\tuint32_t status;\n\
+         \t} regs;\n\
+         } Device;\n\n"
+    );
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    // An inline struct must be used by a struct.
+    let source = "
+        #[cheddar(inline)]
+        #[repr(C)]
+        pub struct Registers {
+            ctrl: u32,
+        }
+    ";
+    let mut lang = LangC::new();
+    let mut outputs = compile(&mut lang, source);
+    assert_eq!(
+        lang.finalise_output(&mut outputs).unwrap_err().message,
+        "`Registers` is `#[cheddar(inline)]` but no `#[repr(C)]` struct of its module uses it"
+    );
+}
+
+#[test]
+fn nullability() {
+    use super::Nullability;
+
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn find(
+            data: *const u8,
+            out: Option<*mut u8>,
+            cb: Option<extern \"C\" fn()>,
+        ) -> *mut Item {}
+    ";
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+
+    let declare = |nullability| {
+        let mut lang = LangC::new();
+        lang.set_nullability(nullability);
+
+        let mut outputs = compile(&mut lang, source);
+        unwrap!(outputs.remove(&header))
+    };
+
+    assert_eq!(
+        declare(Nullability::None),
+        "Item* find(uint8_t const* data, uint8_t* out, void (*cb)(void));\n\n"
+    );
+    assert_eq!(
+        declare(Nullability::Clang),
+        "Item* _Nonnull find(uint8_t const* _Nonnull data, uint8_t* _Nullable out, \
+         void (*_Nullable cb)(void));\n\n"
+    );
+    assert_eq!(
+        declare(Nullability::Sal),
+        "_Ret_notnull_ Item* find(_In_ uint8_t const* data, _Inout_opt_ uint8_t* out, \
+         _In_opt_ void (*cb)(void));\n\n"
+    );
+}
+
+#[test]
+fn qualifiers() {
+    let source = "
+        #[repr(C)]
+        pub struct Device {
+            #[cheddar(volatile)]
+            regs: *mut u32,
+        }
+
+        #[no_mangle]
+        #[cheddar(restrict = \"dst\", restrict = \"src\", volatile = \"src\")]
+        pub extern \"C\" fn copy(dst: *mut u8, src: *const u8, len: usize) {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Device {\n\
+         \tuint32_t volatile* regs;\n\
+         } Device;\n\n\
+         void copy(uint8_t* restrict dst, uint8_t volatile const* restrict src, uintptr_t len);\n\n"
+    );
+}
+
+#[test]
+fn ownership_docs() {
+    let source = "
+        /// Creates a session.
+        #[no_mangle]
+        #[cheddar(owns, free_with = \"session_free\", borrows = \"config\")]
+        pub extern \"C\" fn session_new(config: *const Config) -> *mut Session {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Creates a session.\n\
+         ///\n\
+         /// Ownership of the returned pointer is transferred to the caller.\n\
+         /// The returned pointer must be freed with `session_free`.\n\
+         /// `config` is only borrowed for the duration of the call.\n\
+         Session* session_new(Config const* config);\n\n"
+    );
+}
+
+#[test]
+fn intra_doc_links() {
+    let source = "
+        /// Frees a [`Session`] created by [ffi::session_new()].
+        #[no_mangle]
+        pub extern \"C\" fn session_free(session: *mut Session) {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Frees a Session created by session_new.\n\
+         void session_free(Session* session);\n\n"
+    );
+}
+
+#[test]
+fn thread_safety_docs() {
+    use docs;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    let source = "
+        pub struct App;
+        unsafe impl Send for App {}
+        unsafe impl Sync for App {}
+
+        #[cheddar(send)]
+        pub struct Session;
+
+        #[cheddar(thread_unsafe)]
+        pub struct Cursor;
+
+        /// Runs a query.
+        #[no_mangle]
+        pub extern \"C\" fn app_query(
+            app: *const App,
+            session: Option<*mut Session>,
+            cursor: *mut Cursor,
+            len: usize,
+        ) {}
+    ";
+    let mut types = HashMap::new();
+    docs::collect_thread_safety(&parse_crate(source).module, &mut types);
+    let mut config = Config::default();
+    config.thread_safety = Some(RefCell::new(types));
+
+    let outputs = compile_with(&mut LangC::new(), source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Runs a query.\n\
+         ///\n\
+         /// This `App` handle (`app`) may be shared across threads.\n\
+         /// This `Session` handle (`session`) may be moved to another thread, but may not be \
+         shared across threads.\n\
+         /// This `Cursor` handle (`cursor`) may not be shared across threads.\n\
+         void app_query(App const* app, Session* session, Cursor* cursor, uintptr_t len);\n\n"
+    );
+}
+
+#[test]
+fn enum_to_string() {
+    let source = "
+        #[repr(C)]
+        pub enum Status {
+            Ok = 0,
+            Failed = 1,
+        }
+    ";
+    let mut lang = LangC::new();
+    lang.set_enum_to_string(true);
+    let mut outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef enum Status {\n\
+         \tStatus_Ok = 0,\n\
+         \tStatus_Failed = 1,\n\
+         } Status;\n\n\
+         const char* Status_to_string(Status value);\n\n"
+    );
+
+    unwrap!(lang.finalise_output(&mut outputs));
+    assert_eq!(
+        outputs["backend.c"],
+        "#include <stddef.h>\n\
+         #include \"backend.h\"\n\n\
+         const char* Status_to_string(Status value) {\n\
+         \tswitch (value) {\n\
+         \tcase Status_Ok:\n\
+         \t\treturn \"Ok\";\n\
+         \tcase Status_Failed:\n\
+         \t\treturn \"Failed\";\n\
+         \tdefault:\n\
+         \t\treturn NULL;\n\
+         \t}\n\
+         }\n\n"
+    );
+}
+
+#[test]
+fn struct_initializers() {
+    let source = "
+        #[repr(C)]
+        #[derive(Default)]
+        #[cheddar(default)]
+        pub struct Point {
+            x: i32,
+            y: i32,
+        }
+    ";
+    let mut lang = LangC::new();
+    lang.set_struct_init_macros(true);
+    let outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Point {\n\
+         \tint32_t x;\n\
+         \tint32_t y;\n\
+         } Point;\n\n\
+         #define POINT_INIT(x_, y_) ((Point){ .x = (x_), .y = (y_) })\n\n\
+         static inline Point Point_default(void) {\n\
+         \tPoint value = { 0 };\n\
+         \treturn value;\n\
+         }\n\n"
+    );
+}
+
+#[test]
+fn struct_defaults() {
+    let source = "
+        #[repr(C)]
+        #[derive(Default)]
+        #[cheddar(default)]
+        pub struct Request {
+            retries: u32,
+            timeout: Duration,
+        }
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "`#[cheddar(default)]` requires fields whose default is zero, but `Duration` isn't known \
+         to be"
+    );
+}
+
+#[test]
+fn package_files() {
+    let mut lang = LangC::new();
+    lang.set_lib_name("safe_app");
+    lang.crate_version("safe-app", "0.5.1");
+    lang.set_install_prefix("/usr/local/");
+
+    let mut outputs = Outputs::default();
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    assert_eq!(
+        outputs["safe_app.pc"],
+        "prefix=/usr/local\n\
+         libdir=${prefix}/lib\n\
+         includedir=${prefix}/include\n\n\
+         Name: safe_app\n\
+         Description: C bindings to the safe-app crate\n\
+         Version: 0.5.1\n\
+         Libs: -L${libdir} -lsafe_app\n\
+         Libs.private: -lpthread -ldl -lm\n\
+         Cflags: -I${includedir}\n"
+    );
+
+    let config = &outputs["safe_appConfig.cmake"];
+    assert!(config.contains("set(safe_app_VERSION \"0.5.1\")\n"));
+    assert!(config.contains("add_library(safe_app::safe_app UNKNOWN IMPORTED)\n"));
+    assert!(config.contains("INTERFACE_INCLUDE_DIRECTORIES \"/usr/local/include\")"));
+
+    let version = &outputs["safe_appConfigVersion.cmake"];
+    assert!(version.starts_with("set(PACKAGE_VERSION \"0.5.1\")\n"));
+}
+
+#[test]
+fn swig_interface() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn put(key: *const u8, key_len: usize, value: *const u8, size: usize) {}
+    ";
+    let mut lang = LangC::new();
+    lang.set_swig_interface(true);
+    lang.add_swig_typemaps("%apply int *OUTPUT { int32_t* count };\n");
+    let mut outputs = compile(&mut lang, source);
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    assert_eq!(
+        outputs["backend.i"],
+        format!(
+            "%module backend\n\n\
+             %{{\n\
+             #include \"backend.h\"\n\
+             %}}\n\n\
+             %include <stdint.i>\n\
+             %include <typemaps.i>\n\n\
+             %apply (char *STRING, size_t LENGTH) {{ (uint8_t const* key, uintptr_t key_len) }};\n\n\
+             %apply int *OUTPUT {{ int32_t* count }};\n\n\
+             %include \"backend{}backend.h\"\n",
+            ::std::path::MAIN_SEPARATOR
+        )
+    );
+}
+
+#[test]
+fn luajit_module() {
+    let source = "
+        pub const LIMIT: i32 = 16;
+        pub const HUGE: u64 = 0x1_0000_0000;
+
+        #[no_mangle]
+        pub extern \"C\" fn limit(count: i32) -> i32 {}
+    ";
+    let mut lang = LangC::new();
+    lang.set_luajit_module(true);
+    let mut outputs = compile(&mut lang, source);
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    let module = &outputs["backend.lua"];
+    assert!(module.starts_with("local ffi = require(\"ffi\")\n\nffi.cdef[[\n"));
+    assert!(module.contains("static const int LIMIT = 16;\n"));
+    assert!(!module.contains("HUGE"));
+    assert!(module.contains("int32_t limit(int32_t count);\n"));
+    assert!(!module.contains("#include"));
+    assert!(module.ends_with("]]\n\nreturn ffi.load(os.getenv(\"BACKEND_LIB\") or \"backend\")\n"));
+}
+
+#[test]
+fn doc_examples() {
+    let source = "
+        /// Length of an app.
+        ///
+        /// ```c
+        /// #include <assert.h>
+        ///
+        /// assert(app_len(NULL) == 0);
+        /// ```
+        ///
+        /// ```c
+        /// int main(void) { return app_len(NULL); }
+        /// ```
+        #[no_mangle]
+        pub extern \"C\" fn app_len(app: *const App) -> usize {}
+    ";
+    let mut lang = LangC::new();
+    lang.set_doc_examples(true);
+    let mut outputs = compile(&mut lang, source);
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    assert_eq!(
+        outputs[&format!("examples{}001_app_len.c", ::std::path::MAIN_SEPARATOR)],
+        "#include \"backend.h\"\n\
+         #include <assert.h>\n\n\
+         int main(void) {\n\
+         \tassert(app_len(NULL) == 0);\n\
+         \treturn 0;\n\
+         }\n"
+    );
+    assert_eq!(
+        outputs[&format!("examples{}002_app_len.c", ::std::path::MAIN_SEPARATOR)],
+        "#include \"backend.h\"\n\n\
+         int main(void) { return app_len(NULL); }\n"
+    );
+}
+
+#[test]
+fn mock() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_len(app: *const App) -> usize {}
+
+        #[no_mangle]
+        pub extern \"C\" fn app_clear(app: *mut App) {}
+    ";
+    let mut lang = LangC::new();
+    lang.set_mock(true);
+    let mut outputs = compile(&mut lang, source);
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    assert!(outputs["backend_mock.h"].contains(
+        "#include \"backend.h\"\n\n\
+         struct app_len_mock {\n\
+         \tunsigned int calls;\n\
+         \tApp const* app;\n\
+         \tuintptr_t result;\n\
+         };\n\
+         extern struct app_len_mock app_len_mock;\n\n\
+         struct app_clear_mock {\n\
+         \tunsigned int calls;\n\
+         \tApp* app;\n\
+         };\n\
+         extern struct app_clear_mock app_clear_mock;\n\n\
+         void backend_mock_reset(void);"
+    ));
+    assert_eq!(
+        outputs["backend_mock.c"],
+        "#include <string.h>\n\
+         #include \"backend_mock.h\"\n\n\
+         struct app_len_mock app_len_mock;\n\n\
+         uintptr_t app_len(App const* app) {\n\
+         \t++app_len_mock.calls;\n\
+         \tapp_len_mock.app = app;\n\
+         \treturn app_len_mock.result;\n\
+         }\n\n\
+         struct app_clear_mock app_clear_mock;\n\n\
+         void app_clear(App* app) {\n\
+         \t++app_clear_mock.calls;\n\
+         \tapp_clear_mock.app = app;\n\
+         }\n\n\
+         void backend_mock_reset(void) {\n\
+         \tmemset(&app_len_mock, 0, sizeof app_len_mock);\n\
+         \tmemset(&app_clear_mock, 0, sizeof app_clear_mock);\n\
+         }\n"
+    );
+}
+
+#[test]
+fn instantiated_structs() {
+    let source = "
+        #[repr(C)]
+        #[cheddar(instantiate(u8, Key))]
+        pub struct FfiVec<T> {
+            ptr: *mut T,
+            len: usize,
+        }
+
+        #[no_mangle]
+        pub extern \"C\" fn keys_free(keys: FfiVec<Key>) {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct FfiVec_u8 {\n\
+         \tuint8_t* ptr;\n\
+         \tuintptr_t len;\n\
+         } FfiVec_u8;\n\n\
+         typedef struct FfiVec_Key {\n\
+         \tKey* ptr;\n\
+         \tuintptr_t len;\n\
+         } FfiVec_Key;\n\n\
+         void keys_free(FfiVec_Key keys);\n\n"
+    );
+}
+
+#[test]
+fn lifetime_generics() {
+    let source = "
+        #[repr(C)]
+        pub struct View<'a> {
+            ptr: *const u8,
+            len: usize,
+        }
+
+        #[no_mangle]
+        pub extern \"C\" fn view_len<'a>(view: *const View<'a>) -> usize {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct View {\n\
+         \tuint8_t const* ptr;\n\
+         \tuintptr_t len;\n\
+         } View;\n\n\
+         uintptr_t view_len(View const* view);\n\n"
+    );
+}
+
+#[test]
+fn reference_params() {
+    let source = "
+        /// Length of an app.
+        #[no_mangle]
+        pub extern \"C\" fn app_len(app: &App, out: &mut usize) {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Length of an app.\n\
+         ///\n\
+         /// `app` must be non-null and valid for the duration of the call.\n\
+         /// `out` must be non-null and valid for the duration of the call.\n\
+         void app_len(App const* app, uintptr_t* out);\n\n"
+    );
+}
+
+#[test]
+fn reference_returns() {
+    let source = "
+        /// Settings of an app.
+        #[no_mangle]
+        pub extern \"C\" fn app_settings(app: &mut App) -> &mut Settings {}
+
+        #[no_mangle]
+        pub extern \"C\" fn app_name(app: *const App) -> &c_char {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Settings of an app.\n\
+         ///\n\
+         /// `app` must be non-null and valid for the duration of the call.\n\
+         /// The returned pointer is non-null.\n\
+         Settings* app_settings(App* app);\n\n\
+         /// The returned pointer is non-null.\n\
+         char const* app_name(App const* app);\n\n"
+    );
+}
+
+#[test]
+fn unsized_pointers() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_name(app: *const App, name: &str) {}
+
+        #[repr(C)]
+        pub struct Buffer {
+            data: Option<*const [u8]>,
+        }
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].message.starts_with(
+        "`&str` is a fat pointer to an unsized type, which can not be represented in C; pass a \
+         `*const c_char`"
+    ));
+    assert!(errors[1].message.starts_with(
+        "`*const [u8]` is a fat pointer to an unsized type, which can not be represented in C; \
+         pass a pointer to the first element"
+    ));
+}
+
+#[test]
+fn std_types() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_keys(app: *const App, keys: Option<Vec<Key>>) {}
+
+        #[repr(C)]
+        pub struct Account {
+            name: String,
+            keys: *mut HashMap<String, Key>,
+        }
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0].message,
+        "`Vec<Key>` is a Rust standard library type, which can not be represented in C; pass a \
+         pointer to the first element and a `usize` length instead"
+    );
+    assert_eq!(
+        errors[1].message,
+        "`String` is a Rust standard library type, which can not be represented in C; pass a \
+         `*const c_char` to a nul-terminated string instead (e.g. from `CString::into_raw`)"
+    );
+}
+
+#[test]
+fn int128() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_id(app: *const App) -> u128 {}
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "`u128` is a 128-bit integer, which has no standard C equivalent (nor a stable ABI); \
+         split it into two 64-bit integers or pass it as a `[u8; 16]` array instead"
+    );
+}
+
+#[test]
+fn strict_char() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_key(app: *const App) -> char {}
+    ";
+    let mut lang = LangC::new();
+    let config = Config {
+        strict_char: true,
+        ..Default::default()
+    };
+    let errors = try_compile_with(&mut lang, source, &config).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].message,
+        "`char` is a 32-bit Unicode scalar value, unlike C's `char`; use `u32` (or `c_char` for \
+         a byte) instead"
+    );
+}
+
+#[test]
+fn non_exported_items() {
+    let source = "
+        pub fn helper(s: &str) -> String {}
+        pub struct Cfg { name: String }
+        pub type Res<T> = Result<T, String>;
+
+        #[no_mangle]
+        pub extern \"C\" fn app_reset(app: *mut App) {}
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(outputs[&header], "void app_reset(App* app);\n\n");
+}
+
+#[test]
+fn primitives() {
+    // The table is looked up with a binary search.
+    assert!(
+        super::PRIMITIVES
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0)
+    );
+
+    assert_eq!(super::primitive_to_c("u8"), Some("uint8_t"));
+    assert_eq!(super::primitive_to_c("c_ulonglong"), Some("unsigned long long"));
+    assert_eq!(super::primitive_to_c("App"), None);
+}
+
+#[test]
+fn suggestions() {
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_get<T>(app: *const App) -> T {}
+    ";
+    let errors = try_compile(&mut LangC::new(), source).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].to_string(),
+        "error: bindgen can not handle parameterized extern functions\n\
+         help: export a non-generic `extern \"C\"` wrapper for each type"
+    );
+}
+
+#[test]
+fn skip_unsupported() {
+    use parse::Unsupported;
+
+    let source = "
+        #[no_mangle]
+        pub extern \"C\" fn app_get<T>(app: *const App) -> T {}
+        #[no_mangle]
+        pub extern \"C\" fn app_len(app: *const App) -> usize {}
+    ";
+    let mut lang = LangC::new();
+    let mut config = Config::default();
+    let _ = config.skip_unsupported.insert(Unsupported::ParameterizedFn);
+    let outputs = compile_with(&mut lang, source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(outputs[&header], "uintptr_t app_len(App const* app);\n\n");
+}
+
+#[test]
+fn type_overrides() {
+    let source = "
+        #[repr(C)]
+        pub struct Id {
+            name: XorName,
+            len: usize,
+        }
+    ";
+    let mut lang = LangC::new();
+    let mut config = Config::default();
+    config
+        .type_overrides
+        .insert("c", "XorName", "unsigned char[32]");
+    config.type_overrides.insert("csharp", "XorName", "byte[]");
+    let outputs = compile_with(&mut lang, source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef struct Id {\n\
+         \tunsigned char name[32];\n\
+         \tuintptr_t len;\n\
+         } Id;\n\n"
+    );
+}
+
+#[test]
+fn opaque_handles() {
+    let source = "
+        /// Registered app.
+        pub struct App {
+            inner: u32,
+        }
+
+        pub struct Internal;
+
+        #[no_mangle]
+        pub extern \"C\" fn app_free(app: *mut App) {}
+    ";
+    let mut lang = LangC::new();
+    lang.add_handle("App", Some("app_free"));
+    let outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "/// Registered app.\n\
+         typedef struct App App;\n\n\
+         void app_free(App* app);\n\n"
+    );
+}
+
+#[test]
+fn bool_repr() {
+    use super::BoolRepr;
+
+    let source = "
+        pub const ENABLED: bool = true;
+
+        #[repr(C)]
+        pub struct Options {
+            verbose: bool,
+            on_done: extern \"C\" fn(success: bool),
+        }
+
+        #[no_mangle]
+        pub extern \"C\" fn options_valid(options: *const Options, strict: bool) -> bool {}
+    ";
+    let mut lang = LangC::new();
+    lang.set_bool_repr(BoolRepr::UInt8);
+    let outputs = compile(&mut lang, source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "#define ENABLED TRUE\n\n\
+         typedef struct Options {\n\
+         \tuint8_t verbose;\n\
+         \tvoid (*on_done)(uint8_t success);\n\
+         } Options;\n\n\
+         uint8_t options_valid(Options const* options, uint8_t strict);\n\n"
+    );
+
+    let wrapped = super::wrap_header("", "backend.h", BoolRepr::Int);
+    assert!(wrapped.contains("#define TRUE 1\n"));
+    assert!(!wrapped.contains("stdbool.h"));
+}
+
+#[test]
+fn pointer_widths() {
+    use common::{pointer_width, uintptr_max};
+
+    let source = "
+        #[cfg(target_pointer_width = \"32\")]
+        pub const PAGE_COUNT: usize = 1024;
+        #[cfg(target_pointer_width = \"64\")]
+        pub const PAGE_COUNT: usize = 4096;
+    ";
+    let widths: Vec<_> = parse_crate(source)
+        .module
+        .items
+        .iter()
+        .map(|item| pointer_width(&item.attrs))
+        .collect();
+    assert_eq!(widths, vec![Some(32), Some(64)]);
+    assert_eq!(uintptr_max(32), "0xFFFFFFFF");
+
+    let mut config = Config::default();
+    config.pointer_widths = vec![64];
+    config.hooks.pre.push(Box::new(|item: &ast::Item, _: &str| {
+        pointer_width(&item.attrs)
+            .map(|width| format!("#if UINTPTR_MAX == {}\n", uintptr_max(width)))
+    }));
+    config
+        .hooks
+        .post
+        .push(Box::new(|_: &ast::Item, _: &str| Some("#endif\n".to_string())));
+    let outputs = compile_with(&mut LangC::new(), source, &config);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "#if UINTPTR_MAX == 0xFFFFFFFFFFFFFFFF\n\
+         #define PAGE_COUNT 4096\n\n\
+         #endif\n"
+    );
+}
+
+#[test]
+fn enum_sentinels() {
+    let source = "
+        #[repr(C)]
+        #[cheddar(sentinel)]
+        pub enum Colour {
+            Red,
+            Green,
+        }
+
+        #[repr(C)]
+        #[cheddar(force_int)]
+        pub enum Status {
+            Ok = 0,
+            Failed = 1,
+        }
+    ";
+    let outputs = compile(&mut LangC::new(), source);
+
+    let header = format!("backend{}backend.h", ::std::path::MAIN_SEPARATOR);
+    assert_eq!(
+        outputs[&header],
+        "typedef enum Colour {\n\
+         \tColour_Red,\n\
+         \tColour_Green,\n\
+         \tColour_SENTINEL,\n\
+         } Colour;\n\n\
+         typedef enum Status {\n\
+         \tStatus_Ok = 0,\n\
+         \tStatus_Failed = 1,\n\
+         \tStatus_FORCE_INT = 0x7FFFFFFF,\n\
+         } Status;\n\n"
+    );
+}
+
+#[test]
+fn luajit_directives() {
+    use common::{pointer_width, uintptr_max};
+
+    let source = "
+        #[cfg(target_pointer_width = \"32\")]
+        pub const PAGE_COUNT: i32 = 1024;
+        #[cfg(target_pointer_width = \"64\")]
+        pub const PAGE_COUNT: i32 = 4096;
+
+        #[repr(C, packed)]
+        pub struct Header {
+            tag: u8,
+            len: u32,
+        }
+    ";
+    let mut lang = LangC::new();
+    lang.set_luajit_module(true);
+    let mut config = Config::default();
+    config.pointer_widths = vec![32, 64];
+    config.hooks.pre.push(Box::new(|item: &ast::Item, _: &str| {
+        pointer_width(&item.attrs)
+            .map(|width| format!("#if UINTPTR_MAX == {}\n", uintptr_max(width)))
+    }));
+    config.hooks.post.push(Box::new(|item: &ast::Item, _: &str| {
+        pointer_width(&item.attrs).map(|width| format!("#endif /* {}-bit */\n", width))
+    }));
+    let mut outputs = compile_with(&mut lang, source, &config);
+    unwrap!(lang.finalise_output(&mut outputs));
+
+    let module = &outputs["backend.lua"];
+    let page_count = if cfg!(target_pointer_width = "64") {
+        4096
+    } else {
+        1024
+    };
+    assert_eq!(module.matches("PAGE_COUNT").count(), 1);
+    assert!(module.contains(&format!("static const int PAGE_COUNT = {};\n", page_count)));
+    assert!(module.contains("#pragma pack(push, 1)\n"));
+    assert!(module.contains("#pragma pack(pop)\n"));
+    assert!(!module.contains("UINTPTR_MAX"));
+}
+
+/// Parse a crate from its source.
+fn parse_crate(source: &str) -> ast::Crate {
+    let session = ::syntax::parse::ParseSess::new();
+    unwrap!(::syntax::parse::parse_crate_from_source_str(
+        "lib.rs".to_string(),
+        source.to_string(),
+        &session,
+    ))
+}
+
+/// Parse the items of `source` into the (not finalised) outputs of `lang`, as the `ffi` module.
+fn try_compile_with(
+    lang: &mut LangC,
+    source: &str,
+    config: &Config,
+) -> Result<Outputs, Vec<Error>> {
+    let mut outputs = Outputs::default();
+    parse::parse_mod(
+        lang,
+        &parse_crate(source).module,
+        &["ffi".to_string()],
+        config,
+        &mut outputs,
+    )?;
+    Ok(outputs)
+}
+
+fn try_compile(lang: &mut LangC, source: &str) -> Result<Outputs, Vec<Error>> {
+    try_compile_with(lang, source, &Default::default())
+}
+
+fn compile_with(lang: &mut LangC, source: &str, config: &Config) -> Outputs {
+    unwrap!(try_compile_with(lang, source, config))
+}
+
+fn compile(lang: &mut LangC, source: &str) -> Outputs {
+    compile_with(lang, source, &Default::default())
+}
@@ -12,6 +12,13 @@ impl Display for CTypeNamed {
             // special case
             CType::FnDecl { .. } => write!(f, "{}", self.1),
 
+            // Array types (e.g. an overridden type rendered as `unsigned char[32]`) are declared
+            // with the dimensions after the name.
+            CType::Mapping(ref ty) if ty.ends_with(']') && ty.contains('[') => {
+                let (elem, dims) = ty.split_at(ty.find('[').unwrap_or(0));
+                write!(f, "{} {}{}", elem.trim_right(), self.0, dims)
+            }
+
             // For all other cases we add a type prefix
             _ => write!(f, "{} {}", self.1, self.0),
         }
@@ -39,6 +46,8 @@ pub enum CType {
     Mapping(String),
     Native(&'static str),
     Ptr(Box<CType>, CPtrType),
+    /// `volatile` qualified type (e.g. the pointee of a memory-mapped register).
+    Volatile(Box<CType>),
     FnDecl {
         inner: String,
         args: Vec<CTypeNamed>,
@@ -63,7 +72,7 @@ impl CType {
                         .flat_map(|&CTypeNamed(_, ref cty)| cty.dependencies()),
                 )
                 .collect(),
-            CType::Ptr(ref cty, _) => cty.dependencies(),
+            CType::Ptr(ref cty, _) | CType::Volatile(ref cty) => cty.dependencies(),
             CType::Mapping(ref mapping) => vec![mapping.clone()],
             _ => Default::default(),
         }
@@ -77,6 +86,7 @@ impl Display for CType {
             CType::Mapping(ref s) => write!(f, "{}", s),
             CType::Native(s) => write!(f, "{}", s),
             CType::Ptr(ref cty, ref ptrty) => write!(f, "{}{}*", cty, ptrty),
+            CType::Volatile(ref cty) => write!(f, "{} volatile", cty),
             CType::FnDecl {
                 ref inner,
                 ref args,
@@ -40,8 +40,12 @@ extern crate toml;
 #[macro_use]
 extern crate quote;
 extern crate jni;
+#[macro_use]
+extern crate log;
 extern crate petgraph;
 extern crate rustfmt;
+#[cfg(feature = "templates")]
+extern crate tera;
 
 #[cfg(test)]
 extern crate colored;
@@ -53,26 +57,82 @@ extern crate indoc;
 #[macro_use]
 extern crate unwrap;
 
+pub use abi::{AbiChange, AbiReport, LangAbi};
+pub use cbindgen::CbindgenConfig;
 pub use common::FilterMode;
-use common::{Lang, Outputs};
+use common::{pointer_width, uintptr_max, Lang, Outputs};
+use config::Config;
 pub use csharp::LangCSharp;
+pub use cython::LangCython;
+pub use dlang::LangD;
+pub use docs::SectionPolicy;
 pub use errors::Level;
+pub use gir::LangGir;
+use handles::HandleCollector;
+pub use haskell::LangHaskell;
 pub use java::LangJava;
-pub use lang_c::LangC;
+pub use js::LangJs;
+pub use lang_c::{BoolRepr, LangC, Nullability};
+use lifecycle::Lifecycle;
+pub use man::LangMan;
+pub use output::LineEnding;
+pub use markdown::LangMarkdown;
+pub use order::{Order, OrderedKind};
+pub use parse::{ItemHook, Unsupported};
+pub use profile::{BackendProfile, Profile};
+pub use reachability::ExportReport;
+use reachability::{Pruning, TypeGraph};
+pub use ruby::LangRuby;
+#[cfg(feature = "templates")]
+pub use template::{ItemKind, LangTemplate};
+pub use zig::LangZig;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::env;
 use std::fmt::Display;
 use std::fs;
-use std::io::Error as IoError;
+use std::io::{self, Error as IoError};
 use std::io::{Read, Write};
 use std::path::{self, Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use target::Target;
 
+mod abi;
+mod bitflags;
+mod cbindgen;
 mod common;
+mod config;
 mod csharp;
+mod cython;
+mod dlang;
+mod docs;
+mod gir;
+mod handles;
+mod haskell;
+mod instantiate;
 mod java;
+mod js;
 mod lang_c;
+mod lifecycle;
+mod lifetimes;
+mod man;
+mod markdown;
+mod methods;
+mod order;
 mod output;
 mod parse;
+mod profile;
+mod reachability;
+mod references;
+mod ruby;
+mod shim;
 mod struct_field;
+mod target;
+#[cfg(feature = "templates")]
+mod template;
+mod zig;
 
 /// Describes an error encountered by the compiler.
 ///
@@ -82,6 +142,8 @@ pub struct Error {
     pub level: Level,
     span: Option<syntax::codemap::Span>,
     pub message: String,
+    /// Suggestion on how to fix the error (e.g. "add `#[repr(C)]`"), if any.
+    pub help: Option<String>,
 }
 
 impl Error {
@@ -90,13 +152,18 @@ impl Error {
             level: Level::Error,
             span: None,
             message: message.to_string(),
+            help: None,
         }
     }
 }
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(formatter, "{}: {}", self.level, self.message)
+        write!(formatter, "{}: {}", self.level, self.message)?;
+        if let Some(ref help) = self.help {
+            write!(formatter, "\nhelp: {}", help)?;
+        }
+        Ok(())
     }
 }
 
@@ -119,6 +186,7 @@ impl From<IoError> for Error {
             level: Level::Fatal,
             span: None,
             message: format!("I/O Error: {}", e),
+            help: None,
         }
     }
 }
@@ -135,6 +203,22 @@ impl Error {
     fn print(&self, sess: &syntax::parse::ParseSess) {
         // TODO: there must be some way to reduce the amount of code here.
         // Throw away the results (with { ...; }) since they are handled elsewhere.
+        if let Some(ref help) = self.help {
+            let handler = &sess.span_diagnostic;
+            let diagnostic = match (self.level, self.span) {
+                (Level::Error, Some(span)) => Some(handler.struct_span_err(span, &self.message)),
+                (Level::Error, None) => Some(handler.struct_err(&self.message)),
+                (Level::Warning, Some(span)) => Some(handler.struct_span_warn(span, &self.message)),
+                (Level::Warning, None) => Some(handler.struct_warn(&self.message)),
+                _ => None,
+            };
+            if let Some(mut diagnostic) = diagnostic {
+                diagnostic.help(help);
+                diagnostic.emit();
+                return;
+            }
+        }
+
         if let Some(span) = self.span {
             match self.level {
                 Level::Bug => {
@@ -186,6 +270,12 @@ impl Error {
     }
 }
 
+/// Hook run on every generated file before it is written.
+///
+/// Receives the name of the target language (see `Lang::name`), the path of the file and its
+/// contents, which can be modified in place.
+pub type PostProcess = Box<Fn(&str, &str, &mut String) -> Result<(), Error>>;
+
 /// Stores configuration for the bindgen.
 ///
 /// # Examples
@@ -217,6 +307,31 @@ impl Error {
 pub struct Bindgen {
     /// The root source file of the crate.
     input: PathBuf,
+    /// Name and version of the crate, as read from the cargo manifest.
+    package: Package,
+    /// Whether to prepend a "generated by" banner to every output file.
+    banner: bool,
+    /// Whether the banner includes the generation time, which makes the outputs differ on every
+    /// run.
+    timestamp: bool,
+    /// Hooks applied to the generated files, in registration order.
+    post_processors: Vec<PostProcess>,
+    /// Item filters and hooks applied while parsing.
+    config: Config,
+    /// Whether to arrange the outputs into the layout of an Android library module.
+    android_layout: bool,
+    /// Line endings of the output files.
+    line_ending: LineEnding,
+    /// Whether to start the output files with a UTF-8 byte order mark.
+    bom: bool,
+    /// Whether to skip writing the output files, only reporting the errors.
+    dry_run: bool,
+    /// Whether to write the outputs to stdout instead of files.
+    stdout: bool,
+    /// Targets to generate the bindings for, each into its own directory, if any.
+    targets: Vec<Target>,
+    /// Timings and statistics of the generation, if profiling is enabled.
+    profile: Option<RefCell<Profile>>,
     /// The current parser session.
     ///
     /// Used for printing errors.
@@ -229,11 +344,29 @@ impl Bindgen {
     /// This can only fail if there are issues reading the cargo manifest. If there is no cargo
     /// manifest available then the source file defaults to `src/lib.rs`.
     pub fn new() -> Result<Self, Error> {
-        let source_path = source_file_from_cargo()?;
-        let input = PathBuf::from(source_path);
+        let manifest = read_cargo_manifest()?;
+        let input = PathBuf::from(source_file_from_cargo(manifest.as_ref()));
+        let package = package_from_cargo(manifest.as_ref());
+        let mut config = Config::default();
+        if let Some(ref manifest) = manifest {
+            config.filter.extend_from_manifest(manifest);
+            config.type_overrides.extend_from_manifest(manifest);
+        }
 
         Ok(Bindgen {
             input: input,
+            package: package,
+            banner: true,
+            timestamp: false,
+            post_processors: Vec::new(),
+            config: config,
+            android_layout: false,
+            line_ending: LineEnding::Lf,
+            bom: false,
+            dry_run: false,
+            stdout: false,
+            targets: Vec::new(),
+            profile: None,
             session: syntax::parse::ParseSess::new(),
         })
     }
@@ -249,6 +382,320 @@ impl Bindgen {
         self
     }
 
+    /// Enable/disable the "generated by" banner at the top of every output file (enabled by
+    /// default).
+    pub fn banner(&mut self, enabled: bool) -> &mut Self {
+        self.banner = enabled;
+        self
+    }
+
+    /// Enable/disable the generation time in the banner (disabled by default).
+    ///
+    /// The generated files are then no longer byte-for-byte reproducible, and the files of the
+    /// unchanged modules are rewritten by `write_outputs` too.
+    pub fn timestamp(&mut self, enabled: bool) -> &mut Self {
+        self.timestamp = enabled;
+        self
+    }
+
+    /// Arrange the outputs into the `src/main` layout of an Android library module (disabled by
+    /// default): Java sources under `java`, the C header and JNI registration under `cpp` and
+    /// the Rust JNI glue under `rust`, with notes on where to copy the native library for each
+    /// ABI under `jniLibs`.
+    ///
+    /// Write the outputs into the root directory of the module.
+    pub fn android_layout(&mut self, enabled: bool) -> &mut Self {
+        self.android_layout = enabled;
+        self
+    }
+
+    /// Collect the time spent parsing, resolving and emitting for each language, and the number
+    /// of items of each kind (disabled by default).
+    ///
+    /// The report is printed to stderr by `run_build` and the like, and can be retrieved with
+    /// `profile_report`.
+    pub fn profile(&mut self, enabled: bool) -> &mut Self {
+        self.profile = if enabled {
+            Some(Default::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Returns the timings and statistics collected so far, if profiling is enabled.
+    pub fn profile_report(&self) -> Option<Profile> {
+        self.profile.as_ref().map(|profile| profile.borrow().clone())
+    }
+
+    /// Set the line endings of all the output files (`LineEnding::Lf` by default).
+    pub fn line_endings(&mut self, line_ending: LineEnding) -> &mut Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Start all the output files with a UTF-8 byte order mark (disabled by default), for the
+    /// tools which need it to detect the encoding.
+    pub fn byte_order_mark(&mut self, enabled: bool) -> &mut Self {
+        self.bom = enabled;
+        self
+    }
+
+    /// Run the parsing and all the checks but write no files (disabled by default), e.g. to
+    /// validate the FFI surface from a pre-commit hook. `run_build` and the like still panic on
+    /// errors.
+    pub fn dry_run(&mut self, enabled: bool) -> &mut Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Write the outputs to stdout instead of files (disabled by default), e.g. to pipe them into
+    /// a formatter or `diff`. See `write_outputs_to`.
+    pub fn stdout(&mut self, enabled: bool) -> &mut Self {
+        self.stdout = enabled;
+        self
+    }
+
+    /// Register a hook to post-process every generated file (e.g. to add extra pragmas or apply
+    /// custom formatting).
+    ///
+    /// Hooks run after the output is finalised, in the order they were registered. Returning an
+    /// error from a hook aborts the compilation.
+    pub fn post_process<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&str, &str, &mut String) -> Result<(), Error> + 'static,
+    {
+        self.post_processors.push(Box::new(hook));
+        self
+    }
+
+    /// Only generate bindings for the items whose name matches the glob `pattern` (e.g.
+    /// `"safe_*"`).
+    ///
+    /// Can be called multiple times. Patterns can also be listed in the `include` array of the
+    /// `[package.metadata.safe_bindgen]` section of the cargo manifest.
+    pub fn include<T: Into<String>>(&mut self, pattern: T) -> &mut Self {
+        self.config.filter.include(pattern);
+        self
+    }
+
+    /// Skip the items whose name matches the glob `pattern` (e.g. `"test_*"`), even if they are
+    /// included.
+    ///
+    /// Can be called multiple times. Patterns can also be listed in the `exclude` array of the
+    /// `[package.metadata.safe_bindgen]` section of the cargo manifest.
+    pub fn exclude<T: Into<String>>(&mut self, pattern: T) -> &mut Self {
+        self.config.filter.exclude(pattern);
+        self
+    }
+
+    /// Generate bindings for items marked `#[doc(hidden)]` too (disabled by default).
+    pub fn include_hidden(&mut self, enabled: bool) -> &mut Self {
+        self.config.include_hidden = enabled;
+        self
+    }
+
+    /// Prefix the names of functions exported from `impl` blocks with the type name (disabled by
+    /// default), so `impl Session { .. fn close() }` is declared as `Session_close`.
+    ///
+    /// Only the methods exported under the prefixed name, using
+    /// `#[export_name = "Session_close"]` instead of `#[no_mangle]`, are prefixed: the others
+    /// keep their own name, which is their exported symbol.
+    pub fn prefix_impl_fns(&mut self, enabled: bool) -> &mut Self {
+        self.config.prefix_impl_fns = enabled;
+        self
+    }
+
+    /// Export the associated constants of `impl` blocks (disabled by default), prefixed with the
+    /// type name, so `impl Flags { pub const READ: u32 = 1; }` becomes `Flags_READ`.
+    pub fn export_assoc_consts(&mut self, enabled: bool) -> &mut Self {
+        self.config.export_assoc_consts = enabled;
+        self
+    }
+
+    /// Set the order in which the items of a kind are emitted (source order by default).
+    ///
+    /// Sorting alphabetically keeps the output stable across releases when the source is
+    /// rearranged; the sorted items take the places of the items of the same kind. Grouping the
+    /// lifecycle functions or the methods applies afterwards.
+    pub fn emission_order(&mut self, kind: OrderedKind, order: Order) -> &mut Self {
+        let _ = self.config.order.insert(kind, order);
+        self
+    }
+
+    /// Group constructor/destructor pairs recognised by their suffixes (e.g. `"_new"` and
+    /// `"_free"`), emitting each pair together after the declaration of the type returned by the
+    /// constructor.
+    ///
+    /// A warning is printed for every constructor without a matching destructor.
+    pub fn group_lifecycle_fns<T: Into<String>>(
+        &mut self,
+        constructor_suffix: T,
+        destructor_suffix: T,
+    ) -> &mut Self {
+        self.config.lifecycle = Some(Lifecycle {
+            constructor: constructor_suffix.into(),
+            destructor: destructor_suffix.into(),
+        });
+        self
+    }
+
+    /// Group the functions operating on a type (disabled by default), recognised by their names
+    /// (`app_len` for `App`) or declared in an `impl App` block, emitting them after the
+    /// declaration of the type.
+    ///
+    /// Combined with `LangC::set_method_sections`, the groups are headed by a comment in C.
+    pub fn group_methods(&mut self, enabled: bool) -> &mut Self {
+        self.config.group_methods = enabled;
+        self
+    }
+
+    /// Reject Rust's `char` in the exported signatures and types (disabled by default), instead of
+    /// declaring it as a 32-bit integer holding a Unicode scalar value, as it is easily mistaken
+    /// for C's 8-bit `char`.
+    pub fn strict_char(&mut self, enabled: bool) -> &mut Self {
+        self.config.strict_char = enabled;
+        self
+    }
+
+    /// Add notes about the thread safety of handles to the docs of the functions taking them
+    /// (disabled by default).
+    ///
+    /// A handle type is `Send` or `Sync` if the crate implements these traits for it (e.g.
+    /// `unsafe impl Sync for App {}`) or if it is marked `#[cheddar(send)]` or `#[cheddar(sync)]`.
+    /// Types marked `#[cheddar(thread_unsafe)]` or with negative implementations are neither.
+    pub fn thread_safety_notes(&mut self, enabled: bool) -> &mut Self {
+        self.config.thread_safety = if enabled {
+            Some(Default::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Declare `name` as an opaque handle type in every target language (e.g.
+    /// `typedef struct App App;` in C, a `SafeHandle` in C# and an `AutoCloseable` class in Java),
+    /// freed by `free_fn` (by default the exported `<name>_free` function in snake case, if any).
+    ///
+    /// Can be called multiple times. Types can also be marked `#[cheddar(handle)]` (with an
+    /// optional `#[cheddar(free_with = "app_release")]`). Conflicting free functions and handles
+    /// which are also `#[repr(C)]` types fail the build, handles which are not declared and
+    /// unregistered types passed by pointer are reported as warnings.
+    pub fn opaque_handle<T: Into<String>>(&mut self, name: T, free_fn: Option<&str>) -> &mut Self {
+        let _ = self.config
+            .handles
+            .get_or_insert_with(Default::default)
+            .registered
+            .insert(name.into(), free_fn.map(String::from));
+        self
+    }
+
+    /// Enable the registry of opaque handle types (see `opaque_handle`) for the types marked
+    /// `#[cheddar(handle)]` alone.
+    pub fn opaque_handles(&mut self) -> &mut Self {
+        let _ = self.config.handles.get_or_insert_with(Default::default);
+        self
+    }
+
+    /// Skip the items of `construct` with a warning when the target language can't handle them
+    /// (e.g. generic structs), instead of failing the build.
+    ///
+    /// Can be called multiple times.
+    pub fn skip_unsupported(&mut self, construct: Unsupported) -> &mut Self {
+        let _ = self.config.skip_unsupported.insert(construct);
+        self
+    }
+
+    /// Render the Rust type `rust_type` (e.g. `"XorName"`) as `rendered` (e.g.
+    /// `"unsigned char[32]"`) in the outputs of the language `lang` (e.g. `"c"`), instead of
+    /// translating it.
+    ///
+    /// Can be called multiple times. Overrides can also be listed in the
+    /// `[package.metadata.safe_bindgen.types.<lang>]` tables of the cargo manifest.
+    pub fn override_type<L, R, T>(&mut self, lang: L, rust_type: R, rendered: T) -> &mut Self
+    where
+        L: Into<String>,
+        R: Into<String>,
+        T: Into<String>,
+    {
+        self.config.type_overrides.insert(lang, rust_type, rendered);
+        self
+    }
+
+    /// Only emit the types reachable from the exported functions (through their signatures, the
+    /// fields of the structs or the aliased types), plus the types listed in `keep`, so the
+    /// `#[repr(C)]` types only used inside the crate are not declared.
+    pub fn prune_unreachable_types(&mut self, keep: &[&str]) -> &mut Self {
+        self.config.pruning = Some(Pruning {
+            keep: keep.iter().map(|name| name.to_string()).collect(),
+            reachable: Default::default(),
+        });
+        self
+    }
+
+    /// Register a hook invoked before each item is emitted.
+    ///
+    /// The hook receives the item and the path of the file it is emitted into, and returns the
+    /// text to insert before the item (e.g. an export marker). Only affects backends which emit
+    /// items as they are parsed (C and Java).
+    pub fn pre_item<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&syntax::ast::Item, &str) -> Option<String> + 'static,
+    {
+        self.config.hooks.pre.push(Box::new(hook));
+        self
+    }
+
+    /// Register a hook invoked after each item is emitted.
+    ///
+    /// See `pre_item` for details.
+    pub fn post_item<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&syntax::ast::Item, &str) -> Option<String> + 'static,
+    {
+        self.config.hooks.post.push(Box::new(hook));
+        self
+    }
+
+    /// Generate the bindings for targets of several pointer widths (e.g. `&[32, 64]` for 32-bit
+    /// Android and 64-bit desktops) at once.
+    ///
+    /// The items marked `#[cfg(target_pointer_width = "..")]` for one of the widths are emitted
+    /// into sections of the C headers guarded by `#if UINTPTR_MAX == ..`, so the declarations
+    /// matching the layout of the compiled library are used. Items for other widths are skipped.
+    pub fn pointer_widths(&mut self, widths: &[u32]) -> &mut Self {
+        let guarded = !self.config.pointer_widths.is_empty();
+        self.config.pointer_widths = widths.to_vec();
+        if guarded {
+            return self;
+        }
+
+        self.pre_item(|item, path| {
+            if !path.ends_with(".h") {
+                return None;
+            }
+            pointer_width(&item.attrs)
+                .map(|width| format!("#if UINTPTR_MAX == {}\n", uintptr_max(width)))
+        }).post_item(|item, path| {
+            if !path.ends_with(".h") {
+                return None;
+            }
+            pointer_width(&item.attrs).map(|width| format!("#endif /* {}-bit */\n\n", width))
+        })
+    }
+
+    /// Generate the bindings for each of the target triples (e.g. `aarch64-linux-android` and
+    /// `x86_64-unknown-linux-gnu`) in a single invocation, only keeping the items whose
+    /// `#[cfg(..)]` attributes match the target (`target_os`, `target_arch`,
+    /// `target_pointer_width`...).
+    ///
+    /// `run_build` then writes the outputs of each target into a subdirectory named after the
+    /// triple. See `compile_targets`.
+    pub fn targets(&mut self, triples: &[&str]) -> &mut Self {
+        self.targets = triples.iter().map(|triple| Target::parse(triple)).collect();
+        self
+    }
+
     /// Compile just the code into header declarations.
     ///
     /// This does not add any include-guards, includes, or extern declarations. It is mainly
@@ -260,15 +707,376 @@ impl Bindgen {
         outputs: &mut Outputs,
         finalise: bool,
     ) -> Result<(), Vec<Error>> {
+        self.compile_modules(lang, None, outputs, finalise)
+    }
+
+    /// Like `compile`, but generates the bindings of a crate which is already parsed, for tools
+    /// embedding the compiler or custom drivers.
+    ///
+    /// The FFI modules re-exported by the root module (`pub use ffi::*;`) are looked up in the
+    /// crate instead of being read from the source files. The crate is still named after the
+    /// root source file (see `source_file`).
+    pub fn compile_crate<L: Lang>(
+        &self,
+        lang: &mut L,
+        krate: &syntax::ast::Crate,
+        outputs: &mut Outputs,
+        finalise: bool,
+    ) -> Result<(), Vec<Error>> {
+        self.compile_modules(lang, Some(krate), outputs, finalise)
+    }
+
+    /// Compile the modules of the crate, parsing the source files unless `krate` is given.
+    fn compile_modules<L: Lang>(
+        &self,
+        lang: &mut L,
+        krate: Option<&syntax::ast::Crate>,
+        outputs: &mut Outputs,
+        finalise: bool,
+    ) -> Result<(), Vec<Error>> {
+        lang.crate_version(&self.package.name, &self.package.version);
+        self.config.strict.set(env_flag(STRICT_VAR));
+        self.collect_crate_info(krate)?;
+        self.register_handles(lang);
+
+        self.visit_modules(krate, |module, module_path| {
+            let start = Instant::now();
+            let result = parse::parse_mod(lang, module, module_path, &self.config, outputs);
+            self.record(|profile| {
+                profile.count_items(module);
+                profile.backend(lang.name()).translating += start.elapsed();
+            });
+            result
+        })?;
+
+        if finalise {
+            self.finalise(lang, outputs)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compile the code for each of the targets (see `targets`), returning the finalised
+    /// outputs of each target triple.
+    pub fn compile_targets<L: Lang>(
+        &self,
+        lang: &mut L,
+    ) -> Result<Vec<(String, Outputs)>, Vec<Error>> {
+        let mut outputs = Vec::new();
+        for target in &self.targets {
+            let mut target_outputs = Outputs::new();
+            self.with_target(target, || self.compile(lang, &mut target_outputs, true))?;
+            outputs.push((target.triple.clone(), target_outputs));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Run `f` with the items filtered for `target`.
+    fn with_target<T, F: FnOnce() -> T>(&self, target: &Target, f: F) -> T {
+        *self.config.target.borrow_mut() = Some(target.clone());
+        let result = f();
+        *self.config.target.borrow_mut() = None;
+        result
+    }
+
+    /// Returns the module without the items compiled for other targets than the current one, or
+    /// outside of the configured targets if there is no current one.
+    fn target_module<'a>(&self, module: &'a syntax::ast::Mod) -> Cow<'a, syntax::ast::Mod> {
+        let current = self.config.target.borrow();
+        let targets: Vec<&Target> = match *current {
+            Some(ref target) => vec![target],
+            None if self.targets.is_empty() => return Cow::Borrowed(module),
+            None => self.targets.iter().collect(),
+        };
+
+        Cow::Owned(syntax::ast::Mod {
+            inner: module.inner,
+            items: module
+                .items
+                .iter()
+                .filter(|item| targets.iter().any(|target| target.matches(&item.attrs)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Run the parsing and all the checks for a target language, returning the errors without
+    /// producing any output.
+    pub fn validate<L: Lang>(&self, lang: &mut L) -> Result<(), Vec<Error>> {
+        self.compile(lang, &mut Outputs::new(), true)
+    }
+
+    /// Compile the code for several target languages at once, returning the finalised outputs
+    /// of each language (in the same order).
+    ///
+    /// Every module is parsed a single time and handed to all the languages in turn, instead of
+    /// parsing the whole crate again for each of them. The targets are ignored, see
+    /// `compile_all_targets`.
+    pub fn compile_all(&self, langs: &mut [&mut Lang]) -> Result<Vec<Outputs>, Vec<Error>> {
+        let mut outputs = self.parse_all(langs)?;
+        for (lang, outputs) in langs.iter_mut().zip(outputs.iter_mut()) {
+            self.finalise(&mut **lang, outputs)?;
+        }
+
+        Ok(outputs)
+    }
+
+    /// Like `compile_all`, but for each of the targets (see `targets`), returning the finalised
+    /// outputs of the languages for each target triple.
+    pub fn compile_all_targets(
+        &self,
+        langs: &mut [&mut Lang],
+    ) -> Result<Vec<(String, Vec<Outputs>)>, Vec<Error>> {
+        let mut outputs = Vec::new();
+        for target in &self.targets {
+            let target_outputs = self.with_target(target, || self.compile_all(langs))?;
+            outputs.push((target.triple.clone(), target_outputs));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Parse the crate once for several languages, returning their outputs before finalisation.
+    fn parse_all(&self, langs: &mut [&mut Lang]) -> Result<Vec<Outputs>, Vec<Error>> {
+        for lang in langs.iter_mut() {
+            lang.crate_version(&self.package.name, &self.package.version);
+        }
+        self.config.strict.set(env_flag(STRICT_VAR));
+        self.collect_crate_info(None)?;
+        for lang in langs.iter_mut() {
+            self.register_handles(&mut **lang);
+        }
+
+        let mut outputs: Vec<Outputs> = langs.iter().map(|_| Outputs::new()).collect();
+        self.parse_modules(|module, module_path| {
+            self.record(|profile| profile.count_items(module));
+
+            let mut errors = Vec::new();
+            for (lang, outputs) in langs.iter_mut().zip(outputs.iter_mut()) {
+                let start = Instant::now();
+                if let Err(errs) =
+                    parse::parse_mod(&mut **lang, module, module_path, &self.config, outputs)
+                {
+                    errors.extend(errs);
+                }
+                self.record(|profile| profile.backend(lang.name()).translating += start.elapsed());
+            }
+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
+            }
+        })?;
+
+        Ok(outputs)
+    }
+
+    /// Collect the information about the whole crate needed before parsing (thread safety of
+    /// the types, opaque handles and the reachable types), if enabled.
+    fn collect_crate_info(&self, krate: Option<&syntax::ast::Crate>) -> Result<(), Vec<Error>> {
+        if let Some(ref types) = self.config.thread_safety {
+            types.borrow_mut().clear();
+            self.visit_modules(krate, |module, _| {
+                let start = Instant::now();
+                let module = self.target_module(module);
+                docs::collect_thread_safety(&module, &mut types.borrow_mut());
+                self.record(|profile| profile.resolving += start.elapsed());
+                Ok(())
+            })?;
+        }
+
+        if let Some(ref handles) = self.config.handles {
+            let mut collector = HandleCollector::default();
+            self.visit_modules(krate, |module, _| {
+                let start = Instant::now();
+                collector.collect(&self.target_module(module));
+                self.record(|profile| profile.resolving += start.elapsed());
+                Ok(())
+            })?;
+            *handles.handles.borrow_mut() = collector.resolve(&handles.registered)?;
+        }
+
+        if let Some(ref pruning) = self.config.pruning {
+            let mut graph = TypeGraph::default();
+            self.visit_modules(krate, |module, _| {
+                let start = Instant::now();
+                graph.collect(&self.target_module(module), &self.config);
+                self.record(|profile| profile.resolving += start.elapsed());
+                Ok(())
+            })?;
+            *pruning.reachable.borrow_mut() = graph.reachable(&pruning.keep);
+        }
+
+        Ok(())
+    }
+
+    /// Hand the opaque handle types of the registry to a language, if enabled.
+    fn register_handles<L: Lang + ?Sized>(&self, lang: &mut L) {
+        if let Some(ref handles) = self.config.handles {
+            for (name, free_fn) in handles.handles.borrow().iter() {
+                lang.add_handle(name, free_fn.as_ref().map(|free_fn| free_fn.as_str()));
+            }
+        }
+    }
+
+    /// Finalise the outputs of a language, then arrange them and apply the banner and the
+    /// post-processing hooks.
+    fn finalise<L: Lang + ?Sized>(
+        &self,
+        lang: &mut L,
+        outputs: &mut Outputs,
+    ) -> Result<(), Vec<Error>> {
+        let start = Instant::now();
+        lang.finalise_output(outputs)?;
+        self.complete_outputs(lang.name(), outputs)?;
+        self.record_emitting(lang.name(), start.elapsed(), outputs);
+
+        Ok(())
+    }
+
+    /// Record the time spent finalising the outputs of a language.
+    fn record_emitting(&self, lang: &'static str, elapsed: Duration, outputs: &Outputs) {
+        self.record(|profile| {
+            let backend = profile.backend(lang);
+            backend.emitting += elapsed;
+            backend.files += outputs.len();
+        });
+    }
+
+    /// Update the profile, if profiling is enabled.
+    fn record<F: FnOnce(&mut Profile)>(&self, f: F) {
+        if let Some(ref profile) = self.profile {
+            f(&mut profile.borrow_mut());
+        }
+    }
+
+    /// Print the profile to stderr, if profiling is enabled.
+    fn print_profile(&self) {
+        if let Some(ref profile) = self.profile {
+            eprint!("{}", profile.borrow());
+        }
+    }
+
+    /// Arrange the finalised outputs of a language, apply the banner and the post-processing
+    /// hooks, then convert the line endings.
+    fn complete_outputs(&self, lang: &str, outputs: &mut Outputs) -> Result<(), Vec<Error>> {
+        if self.android_layout {
+            output::android_layout(&self.package.name.replace("-", "_"), outputs);
+        }
+
+        if self.banner {
+            self.add_banner(outputs);
+        }
+
+        self.apply_post_processors(lang, outputs)?;
+
+        if self.line_ending != LineEnding::Lf || self.bom {
+            for contents in outputs.values_mut() {
+                output::encode(contents, self.line_ending, self.bom);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate the `extern "C"` shims of the public functions marked `#[cheddar(shim)]` or
+    /// `#[cheddar(catch_panic = "ERR_PANICKED")]`.
+    ///
+    /// The functions excluded by the item filter or compiled for none of the targets are skipped,
+    /// and the shims keep the `#[cfg]` attributes of their functions.
+    ///
+    /// The shims convert `&str`, slices and `Result`s into FFI-safe parameters and error codes,
+    /// catch panics (returning the `catch_panic` code instead), and are declared in the outputs
+    /// of `compile` in place of the original functions. The returned Rust source has to be
+    /// included into the crate, e.g. by writing it into `OUT_DIR` from a build script and using
+    /// `include!(concat!(env!("OUT_DIR"), "/shims.rs"));`. The shims call the functions by their
+    /// path from the crate root, and can be included anywhere.
+    pub fn compile_shims(&self) -> Result<String, Vec<Error>> {
+        let mut source = String::new();
+        let mut errors = Vec::new();
+        let root = convert_lib_path_to_module(&self.input);
+
+        self.parse_modules(|module, module_path| {
+            let module_path: &[String] = if module_path == &root[..] {
+                &[]
+            } else {
+                module_path
+            };
+            let module = self.target_module(module);
+            let shims = module.items.iter().filter(|item| {
+                shim::is_shim(item)
+                    && parse::is_selected(&item.ident.name.as_str(), &item.attrs, &self.config)
+            });
+            for item in shims {
+                match shim::generate(item, module_path) {
+                    Ok(shim) => source.push_str(&shim.source),
+                    Err(error) => errors.push(error),
+                }
+            }
+            Ok(())
+        })?;
+
+        if errors.is_empty() {
+            Ok(source)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Pass the top level module and the FFI modules it imports to `f`, taking them from `krate`
+    /// if given, or else parsing the source files.
+    fn visit_modules<F>(
+        &self,
+        krate: Option<&syntax::ast::Crate>,
+        mut f: F,
+    ) -> Result<(), Vec<Error>>
+    where
+        F: FnMut(&syntax::ast::Mod, &[String]) -> Result<(), Vec<Error>>,
+    {
+        let krate = match krate {
+            Some(krate) => krate,
+            None => return self.parse_modules(f),
+        };
+
+        let module = convert_lib_path_to_module(&self.input);
+        f(&krate.module, &module)?;
+
+        for module in parse::imported_mods(&krate.module) {
+            match parse::find_module(&krate.module, &module) {
+                Some(found) => f(found, &module)?,
+                None => {
+                    return Err(vec![Error {
+                        level: Level::Error,
+                        span: None,
+                        message: format!("module `{}` not found in the crate", module.join("::")),
+                        help: None,
+                    }])
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the top level module and the FFI modules it imports, passing each of them to `f`
+    /// with its module path.
+    fn parse_modules<F>(&self, mut f: F) -> Result<(), Vec<Error>>
+    where
+        F: FnMut(&syntax::ast::Mod, &[String]) -> Result<(), Vec<Error>>,
+    {
         let base_path = self.input.parent().unwrap();
         let mod_path = unwrap!(self.input.to_str()).to_string();
 
         // Parse the top level mod.
+        let start = Instant::now();
         let krate = syntax::parse::parse_crate_from_file(&self.input, &self.session).unwrap();
+        self.record(|profile| profile.parsing += start.elapsed());
         let module = convert_lib_path_to_module(&PathBuf::from(mod_path.clone()));
-        eprintln!("Parsing {} ({:?})", module.join("::"), mod_path);
+        debug!("Parsing {} ({:?})", module.join("::"), mod_path);
 
-        parse::parse_mod(lang, &krate.module, &module, outputs)?;
+        f(&krate.module, &module)?;
 
         // Parse other mods.
         let modules = parse::imported_mods(&krate.module);
@@ -285,17 +1093,58 @@ impl Bindgen {
                 ));
             }
 
-            eprintln!("Parsing {} ({:?})", module.join("::"), mod_path);
+            debug!("Parsing {} ({:?})", module.join("::"), mod_path);
 
+            let start = Instant::now();
             let krate = syntax::parse::parse_crate_from_file(&mod_path, &self.session).unwrap();
-            parse::parse_mod(lang, &krate.module, &module, outputs)?;
+            self.record(|profile| profile.parsing += start.elapsed());
+            f(&krate.module, &module)?;
         }
 
-        if finalise {
-            lang.finalise_output(outputs)?;
+        Ok(())
+    }
+
+    /// Run the registered post-processing hooks on every output file.
+    fn apply_post_processors(&self, lang: &str, outputs: &mut Outputs) -> Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+
+        for (path, contents) in outputs.iter_mut() {
+            for hook in &self.post_processors {
+                if let Err(error) = hook(lang, path, contents) {
+                    errors.push(error);
+                }
+            }
         }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Prepend the "generated by" banner to every output file.
+    fn add_banner(&self, outputs: &mut Outputs) {
+        let mut text = format!(
+            "Generated by safe_bindgen v{} from crate {} v{}",
+            env!("CARGO_PKG_VERSION"),
+            self.package.name,
+            self.package.version
+        );
+
+        if self.timestamp {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0);
+            text.push_str(&format!(" on {}", output::format_timestamp(now)));
+        }
+
+        for (path, contents) in outputs.iter_mut() {
+            if let Some(banner) = output::comment(path, &text) {
+                contents.insert_str(0, &banner);
+            }
+        }
     }
 
     pub fn compile_or_panic<L: Lang>(&self, lang: &mut L, outputs: &mut Outputs, finalise: bool) {
@@ -310,21 +1159,29 @@ impl Bindgen {
 
     /// Writes virtual files to the file system
     pub fn write_outputs<P: AsRef<Path>>(&self, root: P, outputs: &Outputs) -> Result<(), IoError> {
-        let root = root.as_ref();
-
-        for (path, contents) in outputs {
-            let full_path = root.join(PathBuf::from(path));
-
-            if let Some(parent_dirs) = full_path.parent() {
-                fs::create_dir_all(parent_dirs)?;
-            }
-
-            let mut f = fs::File::create(full_path)?;
-            f.write_all(contents.as_bytes())?;
-            f.sync_all()?;
+        if self.dry_run {
+            return Ok(());
+        }
+        if self.stdout {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            return self.write_outputs_to(&mut stdout, outputs);
         }
 
-        Ok(())
+        let start = Instant::now();
+        let result = write_files(root.as_ref(), outputs);
+        self.record(|profile| profile.writing += start.elapsed());
+        result
+    }
+
+    /// Write the outputs one after the other into `writer` (sorted by path), each preceded by a
+    /// comment holding its path unless there is a single one.
+    pub fn write_outputs_to<W: Write>(
+        &self,
+        writer: &mut W,
+        outputs: &Outputs,
+    ) -> Result<(), IoError> {
+        output::write_concatenated(writer, outputs)
     }
 
     pub fn write_outputs_or_panic<P: AsRef<Path>>(&self, root: P, outputs: &Outputs) {
@@ -339,13 +1196,259 @@ impl Bindgen {
     /// This is a convenience method for use in build scripts. If errors occur during compilation
     /// they will be printed then the function will panic.
     ///
+    /// If targets are configured (see `targets`), the outputs of each target are written into a
+    /// subdirectory named after its triple.
+    ///
+    /// The output directory can be overridden with the `SAFE_BINDGEN_OUTPUT_DIR` environment
+    /// variable, and the languages generated by this method and the like restricted with
+    /// `SAFE_BINDGEN_LANGS` (e.g. `c,java`). `SAFE_BINDGEN_STRICT=1`, read on each compilation,
+    /// rejects `char` and makes the unsupported constructs fail even if they are to be skipped.
+    ///
     /// # Panics
     ///
     /// Panics on any compilation error so that the build script exits and prints output.
     pub fn run_build<P: AsRef<Path>, L: Lang>(&self, lang: &mut L, output_dir: P) {
+        if !lang_enabled(lang.name()) {
+            return;
+        }
+
+        let output_dir = target_dir(output_dir.as_ref(), lang.name(), false);
+        if self.targets.is_empty() {
+            let mut outputs = HashMap::new();
+            self.compile_or_panic(lang, &mut outputs, true);
+            self.write_outputs_or_panic(output_dir, &outputs);
+        } else {
+            match self.compile_targets(lang) {
+                Ok(outputs) => for (triple, outputs) in &outputs {
+                    self.write_outputs_or_panic(output_dir.join(triple), outputs);
+                },
+                Err(errors) => self.fail(&errors),
+            }
+        }
+        self.print_profile();
+    }
+
+    /// Write the outputs of several target languages, each into its own directory, parsing the
+    /// crate a single time (see `compile_all`).
+    ///
+    /// If targets are configured (see `targets`), the outputs of each target are written into a
+    /// subdirectory of the directory of the language, named after its triple.
+    ///
+    /// # Panics
+    ///
+    /// Panics on any compilation error so that the build script exits and prints output.
+    pub fn run_build_all<P: AsRef<Path>>(&self, targets: &mut [(&mut Lang, P)]) {
+        let mut targets: Vec<_> = targets
+            .iter_mut()
+            .filter(|&&mut (ref lang, _)| lang_enabled(lang.name()))
+            .collect();
+        let several = targets.len() > 1;
+        let dirs: Vec<_> = targets
+            .iter()
+            .map(|&&mut (ref lang, ref output_dir)| {
+                target_dir(output_dir.as_ref(), lang.name(), several)
+            })
+            .collect();
+
+        let result = {
+            let mut langs: Vec<&mut Lang> = targets
+                .iter_mut()
+                .map(|&mut &mut (ref mut lang, _)| &mut **lang)
+                .collect();
+            if self.targets.is_empty() {
+                self.compile_all(&mut langs).map(|outputs| vec![(None, outputs)])
+            } else {
+                self.compile_all_targets(&mut langs).map(|outputs| {
+                    outputs
+                        .into_iter()
+                        .map(|(triple, outputs)| (Some(triple), outputs))
+                        .collect()
+                })
+            }
+        };
+
+        match result {
+            Ok(outputs) => for (triple, outputs) in outputs {
+                for (dir, outputs) in dirs.iter().zip(&outputs) {
+                    match triple {
+                        Some(ref triple) => self.write_outputs_or_panic(dir.join(triple), outputs),
+                        None => self.write_outputs_or_panic(dir, outputs),
+                    }
+                }
+            },
+            Err(errors) => self.fail(&errors),
+        }
+
+        self.print_profile();
+    }
+
+    /// Like `run_build_all`, but finalises and writes the outputs of the languages concurrently,
+    /// on a thread per language, so adding languages doesn't add up their generation times.
+    ///
+    /// The crate is still parsed on the calling thread, as the syntax tree can't be shared
+    /// between threads, and the banner and the post-processing hooks are applied there too. The
+    /// targets (see `targets`) are generated one after the other.
+    ///
+    /// # Panics
+    ///
+    /// Panics on any compilation error so that the build script exits and prints output.
+    pub fn run_build_parallel<P>(&self, targets: Vec<(Box<Lang + Send>, P)>)
+    where
+        P: AsRef<Path>,
+    {
+        let (mut langs, dirs): (Vec<_>, Vec<_>) = targets
+            .into_iter()
+            .filter(|&(ref lang, _)| lang_enabled(lang.name()))
+            .unzip();
+        let several = langs.len() > 1;
+        let dirs: Vec<_> = langs
+            .iter()
+            .zip(dirs)
+            .map(|(lang, dir)| target_dir(dir.as_ref(), lang.name(), several))
+            .collect();
+
+        let mut errors = Vec::new();
+        let mut writing = Vec::new();
+        if self.targets.is_empty() {
+            let _ = self.build_parallel(langs, &dirs, &mut errors, &mut writing);
+        } else {
+            for target in &self.targets {
+                let dirs: Vec<_> = dirs.iter().map(|dir| dir.join(&target.triple)).collect();
+                langs = self.with_target(target, || {
+                    self.build_parallel(langs, &dirs, &mut errors, &mut writing)
+                });
+            }
+        }
+
+        for handle in writing {
+            let (result, elapsed) = unwrap!(handle.join());
+            if let Err(error) = result {
+                errors.push(From::from(error));
+            }
+            self.record(|profile| profile.writing += elapsed);
+        }
+        if !errors.is_empty() {
+            self.fail(&errors);
+        }
+
+        self.print_profile();
+    }
+
+    /// Parse the crate for the languages, then finalise their outputs concurrently and start
+    /// writing them into `dirs`, returning the languages for the next target.
+    fn build_parallel(
+        &self,
+        mut langs: Vec<Box<Lang + Send>>,
+        dirs: &[PathBuf],
+        errors: &mut Vec<Error>,
+        writing: &mut Vec<thread::JoinHandle<(Result<(), IoError>, Duration)>>,
+    ) -> Vec<Box<Lang + Send>> {
+        let result = {
+            let mut refs: Vec<&mut Lang> = Vec::with_capacity(langs.len());
+            for lang in &mut langs {
+                refs.push(&mut **lang);
+            }
+            self.parse_all(&mut refs)
+        };
+        let outputs = match result {
+            Ok(outputs) => outputs,
+            Err(errs) => self.fail(&errs),
+        };
+
+        let finalising: Vec<_> = langs
+            .into_iter()
+            .zip(outputs)
+            .map(|(mut lang, mut outputs)| {
+                thread::spawn(move || {
+                    let start = Instant::now();
+                    let result = lang.finalise_output(&mut outputs);
+                    (lang, outputs, result, start.elapsed())
+                })
+            })
+            .collect();
+
+        let mut langs = Vec::with_capacity(finalising.len());
+        for (handle, dir) in finalising.into_iter().zip(dirs) {
+            let (lang, mut outputs, result, elapsed) = unwrap!(handle.join());
+            let name = lang.name();
+            langs.push(lang);
+            if let Err(error) = result {
+                errors.push(error);
+                continue;
+            }
+            let completing = Instant::now();
+            if let Err(errs) = self.complete_outputs(name, &mut outputs) {
+                errors.extend(errs);
+                continue;
+            }
+            self.record_emitting(name, elapsed + completing.elapsed(), &outputs);
+            if self.dry_run {
+                continue;
+            }
+
+            if self.stdout {
+                if let Err(error) = self.write_outputs(dir, &outputs) {
+                    errors.push(From::from(error));
+                }
+                continue;
+            }
+            let dir = dir.clone();
+            writing.push(thread::spawn(move || {
+                let start = Instant::now();
+                (write_files(&dir, &outputs), start.elapsed())
+            }));
+        }
+
+        langs
+    }
+
+    /// Print the errors and panic.
+    fn fail(&self, errors: &[Error]) -> ! {
+        for error in errors {
+            self.print_error(error);
+        }
+
+        panic!("Failed to compile.");
+    }
+
+    /// Compare the ABI of the crate with the baseline description at `path` (as generated by
+    /// `LangAbi`, e.g. for the previous release) and return the changes.
+    ///
+    /// Fails if there are breaking changes (removed symbols or changed signatures) and the
+    /// version of the crate wasn't bumped accordingly.
+    pub fn check_abi<P: AsRef<Path>>(&self, path: P) -> Result<AbiReport, Vec<Error>> {
+        let mut baseline = String::new();
+        let _ = fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut baseline))
+            .map_err(Error::from)?;
+
+        let mut lang = LangAbi::new();
         let mut outputs = HashMap::new();
-        self.compile_or_panic(lang, &mut outputs, true);
-        self.write_outputs_or_panic(output_dir, &outputs);
+        self.compile(&mut lang, &mut outputs, false)?;
+        lang.finalise_output(&mut outputs)?;
+        let current = outputs
+            .into_iter()
+            .next()
+            .map(|(_, abi)| abi)
+            .unwrap_or_default();
+
+        let report = AbiReport::new(&baseline, &current);
+        report.check()?;
+
+        Ok(report)
+    }
+
+    /// Audit the FFI surface: find the exported functions whose signatures reference types for
+    /// which no bindings are generated (because they are private, hidden or filtered out), and
+    /// the types which are not reachable from any exported function or constant.
+    pub fn export_report(&self) -> Result<ExportReport, Vec<Error>> {
+        let mut graph = TypeGraph::default();
+        self.parse_modules(|module, _| {
+            graph.collect(&self.target_module(module), &self.config);
+            Ok(())
+        })?;
+
+        Ok(graph.report())
     }
 
     /// Print an error using the ParseSess stored in Cheddar.
@@ -354,6 +1457,40 @@ impl Bindgen {
     }
 }
 
+/// Write the files of `outputs` into `root`, creating the missing directories.
+///
+/// The files whose contents didn't change are left untouched, so the C builds including the
+/// headers of the unchanged modules aren't invalidated by their modification times.
+fn write_files(root: &Path, outputs: &Outputs) -> Result<(), IoError> {
+    for (path, contents) in outputs {
+        let full_path = root.join(PathBuf::from(path));
+        if is_unchanged(&full_path, contents) {
+            debug!("Leaving {} unchanged", full_path.display());
+            continue;
+        }
+
+        if let Some(parent_dirs) = full_path.parent() {
+            fs::create_dir_all(parent_dirs)?;
+        }
+
+        info!("Writing {}", full_path.display());
+        let mut f = fs::File::create(full_path)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Whether the file at `path` exists and holds `contents`.
+fn is_unchanged(path: &Path, contents: &str) -> bool {
+    let mut existing = String::new();
+    match fs::File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut existing).is_ok() && existing == contents,
+        Err(_) => false,
+    }
+}
+
 /// Convert a path into a top-level module name (e.g. `ffi_utils/src/lib.rs` -> `ffi_libs`)
 fn convert_lib_path_to_module<P: AsRef<Path>>(path: &P) -> Vec<String> {
     let mut res = Vec::new();
@@ -374,17 +1511,67 @@ fn convert_lib_path_to_module<P: AsRef<Path>>(path: &P) -> Vec<String> {
     res
 }
 
-/// Extract the path to the root source file from a `Cargo.toml`.
-fn source_file_from_cargo() -> Result<String, Error> {
+/// Environment variable overriding the output directory of `run_build` and the like.
+const OUTPUT_DIR_VAR: &str = "SAFE_BINDGEN_OUTPUT_DIR";
+/// Environment variable listing the languages `run_build` and the like generate (e.g. `c,java`).
+const LANGS_VAR: &str = "SAFE_BINDGEN_LANGS";
+/// Environment variable enabling the strict checks (see `Config::strict`).
+const STRICT_VAR: &str = "SAFE_BINDGEN_STRICT";
+
+/// Returns the directory to write the outputs of a language into: `dir`, unless overridden by
+/// `SAFE_BINDGEN_OUTPUT_DIR`, in which case the outputs of `several` languages go into
+/// subdirectories named after them.
+fn target_dir(dir: &Path, lang: &str, several: bool) -> PathBuf {
+    match env::var_os(OUTPUT_DIR_VAR) {
+        Some(ref root) if !root.is_empty() => {
+            let root = PathBuf::from(root);
+            if several {
+                root.join(lang)
+            } else {
+                root
+            }
+        }
+        _ => dir.to_path_buf(),
+    }
+}
+
+/// Whether to generate the bindings of a language, according to the comma-separated names of
+/// `SAFE_BINDGEN_LANGS` (all the languages if it is unset or empty).
+fn lang_enabled(lang: &str) -> bool {
+    match env::var(LANGS_VAR) {
+        Ok(ref langs) if !langs.trim().is_empty() => langs
+            .split(',')
+            .any(|name| name.trim().eq_ignore_ascii_case(lang)),
+        _ => true,
+    }
+}
+
+/// Whether the environment variable `name` is set to `1`, `true` or `yes`.
+fn env_flag(name: &str) -> bool {
+    match env::var(name) {
+        Ok(value) => match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" => true,
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Name and version of the crate being processed.
+struct Package {
+    name: String,
+    version: String,
+}
+
+/// Read and parse the `Cargo.toml` of the crate being processed, if there is one.
+fn read_cargo_manifest() -> Result<Option<toml::Value>, Error> {
     let cargo_toml = path::Path::new(
         &std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_else(|| std::ffi::OsString::from("")),
     ).join("Cargo.toml");
 
-    // If no `Cargo.toml` assume `src/lib.rs` until told otherwise.
-    let default = "src/lib.rs";
     let mut cargo_toml = match std::fs::File::open(&cargo_toml) {
         Ok(value) => value,
-        Err(..) => return Ok(default.to_owned()),
+        Err(..) => return Ok(None),
     };
 
     let mut buf = String::new();
@@ -395,26 +1582,211 @@ fn source_file_from_cargo() -> Result<String, Error> {
                 level: Level::Fatal,
                 span: None,
                 message: "could not read cargo manifest".into(),
+                help: None,
             })
         }
     };
 
-    let table = match (&buf).parse::<toml::Value>() {
-        Ok(value) => value,
-        Err(..) => {
-            return Err(Error {
-                level: Level::Fatal,
-                span: None,
-                message: "could not parse cargo manifest".into(),
-            })
-        }
-    };
+    match (&buf).parse::<toml::Value>() {
+        Ok(value) => Ok(Some(value)),
+        Err(..) => Err(Error {
+            level: Level::Fatal,
+            span: None,
+            message: "could not parse cargo manifest".into(),
+            help: None,
+        }),
+    }
+}
 
-    // If not explicitly stated then defaults to `src/lib.rs`.
-    Ok(table
-        .get("lib")
+/// Extract the path to the root source file from a `Cargo.toml`.
+fn source_file_from_cargo(manifest: Option<&toml::Value>) -> String {
+    // If no `Cargo.toml` or not explicitly stated then defaults to `src/lib.rs`.
+    manifest
+        .and_then(|t| t.get("lib"))
         .and_then(|t| t.get("path"))
         .and_then(|s| s.as_str())
-        .unwrap_or(default)
-        .into())
+        .unwrap_or("src/lib.rs")
+        .into()
+}
+
+/// Extract the package name and version from a `Cargo.toml`.
+fn package_from_cargo(manifest: Option<&toml::Value>) -> Package {
+    let package = manifest.and_then(|t| t.get("package"));
+    let field = |name: &str, default: &str| {
+        package
+            .and_then(|t| t.get(name))
+            .and_then(|s| s.as_str())
+            .unwrap_or(default)
+            .to_string()
+    };
+
+    Package {
+        name: field("name", "unknown"),
+        version: field("version", "0.0.0"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn compile_targets() {
+        let source = "
+            #[cfg(target_os = \"android\")]
+            #[cheddar(handle)]
+            pub struct Context;
+            #[cfg(not(target_os = \"android\"))]
+            #[cheddar(handle)]
+            pub struct Context;
+
+            #[cfg(target_pointer_width = \"64\")]
+            #[no_mangle]
+            pub extern \"C\" fn context_wide(context: *mut Context) {}
+            #[cfg(target_os = \"android\")]
+            #[no_mangle]
+            pub extern \"C\" fn context_jni(context: *mut Context) {}
+        ";
+        let (dir, path) = write_crate("compile_targets", source);
+
+        let mut bindgen = unwrap!(Bindgen::new());
+        let _ = bindgen
+            .source_file(path)
+            .banner(false)
+            .opaque_handles()
+            .targets(&["x86_64-unknown-linux-gnu", "armv7-linux-androideabi"]);
+
+        let mut lang = LangC::new();
+        lang.set_lib_name("targets");
+        lang.set_mock(true);
+        let outputs = bindgen.compile_targets(&mut lang);
+        unwrap!(fs::remove_dir_all(&dir));
+        let outputs = match outputs {
+            Ok(outputs) => outputs,
+            Err(errors) => panic!("unexpected errors: {:?}", errors),
+        };
+
+        let triples: Vec<_> = outputs.iter().map(|&(ref triple, _)| triple.as_str()).collect();
+        assert_eq!(triples, vec!["x86_64-unknown-linux-gnu", "armv7-linux-androideabi"]);
+
+        let desktop = &outputs[0].1["targets_mock.c"];
+        assert!(desktop.contains("context_wide"));
+        assert!(!desktop.contains("context_jni"));
+
+        let android = &outputs[1].1["targets_mock.c"];
+        assert!(android.contains("context_jni"));
+        assert!(!android.contains("context_wide"));
+    }
+
+    #[test]
+    fn compile_targets_java() {
+        let source = "
+            #[cfg(target_os = \"android\")]
+            #[no_mangle]
+            pub extern \"C\" fn app_jni(
+                user_data: *mut c_void,
+                o_cb: extern \"C\" fn(user_data: *mut c_void, result: *const FfiResult)
+            ) {}
+            #[cfg(not(target_os = \"android\"))]
+            #[no_mangle]
+            pub extern \"C\" fn app_desktop(
+                user_data: *mut c_void,
+                o_cb: extern \"C\" fn(user_data: *mut c_void, result: *const FfiResult)
+            ) {}
+        ";
+        let (dir, path) = write_crate("compile_targets_java", source);
+
+        let mut bindgen = unwrap!(Bindgen::new());
+        let _ = bindgen
+            .source_file(path)
+            .banner(false)
+            .targets(&["x86_64-unknown-linux-gnu", "armv7-linux-androideabi"]);
+
+        let mut lang = LangJava::new(HashMap::new());
+        lang.set_native_registration(true);
+        let outputs = bindgen.compile_targets(&mut lang);
+        unwrap!(fs::remove_dir_all(&dir));
+        let outputs = match outputs {
+            Ok(outputs) => outputs,
+            Err(errors) => panic!("unexpected errors: {:?}", errors),
+        };
+
+        // Each target gets its own callback trampolines and registers its own methods only.
+        let desktop = &outputs[0].1;
+        assert!(desktop["jni.rs"].contains("fn call_CallbackResult("));
+        assert_eq!(desktop["jni_onload.c"].matches("\"appDesktop\"").count(), 1);
+        assert!(!desktop["jni_onload.c"].contains("appJni"));
+
+        let android = &outputs[1].1;
+        assert!(android["jni.rs"].contains("fn call_CallbackResult("));
+        assert_eq!(android["jni_onload.c"].matches("\"appJni\"").count(), 1);
+        assert!(!android["jni_onload.c"].contains("appDesktop"));
+    }
+
+    #[test]
+    fn filtered_shims_and_report() {
+        let source = "
+            #[repr(C)]
+            struct Internal { len: usize }
+
+            #[no_mangle]
+            pub extern \"C\" fn app_internal(internal: *const Internal) {}
+            #[no_mangle]
+            pub extern \"C\" fn test_internal(internal: *const Internal) {}
+            #[cfg(target_os = \"ios\")]
+            #[no_mangle]
+            pub extern \"C\" fn ios_internal(internal: *const Internal) {}
+
+            #[cheddar(shim)]
+            pub fn test_fill(buffer: &mut [u8]) {}
+            #[cfg(target_os = \"ios\")]
+            #[cheddar(shim)]
+            pub fn ios_fill(buffer: &mut [u8]) {}
+            #[cfg(target_os = \"android\")]
+            #[cheddar(shim)]
+            pub fn android_fill(buffer: &mut [u8]) {}
+        ";
+        let (dir, path) = write_crate("filtered_shims_and_report", source);
+
+        let mut bindgen = unwrap!(Bindgen::new());
+        let _ = bindgen
+            .source_file(path)
+            .exclude("test_*")
+            .targets(&["x86_64-unknown-linux-gnu", "armv7-linux-androideabi"]);
+
+        let shims = bindgen.compile_shims();
+        let report = bindgen.export_report();
+        unwrap!(fs::remove_dir_all(&dir));
+        let shims = match shims {
+            Ok(shims) => shims,
+            Err(errors) => panic!("unexpected errors: {:?}", errors),
+        };
+        let report = match report {
+            Ok(report) => report,
+            Err(errors) => panic!("unexpected errors: {:?}", errors),
+        };
+
+        // Neither the excluded functions nor those compiled for other targets get a shim.
+        assert!(shims.contains(
+            "#[cfg(target_os = \"android\")]\n#[export_name = \"android_fill\"]\n"
+        ));
+        assert!(!shims.contains("test_fill"));
+        assert!(!shims.contains("ios_fill"));
+
+        let broken: Vec<_> = report.broken_functions.keys().map(|name| name.as_str()).collect();
+        assert_eq!(broken, vec!["app_internal"]);
+    }
+
+    /// Write `source` as the root module of a crate, into a directory unique to the test (and
+    /// the process) which the test removes, returning the directory and the root module.
+    fn write_crate(test: &str, source: &str) -> (PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("safe_bindgen_{}_{}", test, process::id()));
+        let src = dir.join("src");
+        unwrap!(fs::create_dir_all(&src));
+
+        let path = src.join("lib.rs");
+        unwrap!(unwrap!(fs::File::create(&path)).write_all(source.as_bytes()));
+        (dir, path)
+    }
 }
@@ -0,0 +1,173 @@
+//! Grouping of constructor/destructor function pairs (e.g. `app_new` and `app_free`).
+
+use std::collections::{HashMap, HashSet};
+use syntax::ast;
+use syntax::ptr::P;
+
+/// Naming convention of the functions creating and destroying an opaque type.
+pub struct Lifecycle {
+    /// Suffix of the constructors (e.g. `_new`).
+    pub constructor: String,
+    /// Suffix of the destructors (e.g. `_free`).
+    pub destructor: String,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle {
+            constructor: "_new".to_string(),
+            destructor: "_free".to_string(),
+        }
+    }
+}
+
+impl Lifecycle {
+    /// Reorder the items of a module so each destructor directly follows its constructor, and
+    /// the pair follows the declaration of the type returned by the constructor (if declared in
+    /// the same module).
+    ///
+    /// Prints a warning for every public constructor without a matching destructor.
+    pub fn group<'a>(&self, items: Vec<&'a P<ast::Item>>) -> Vec<&'a P<ast::Item>> {
+        let fns: HashMap<String, usize> = items
+            .iter()
+            .enumerate()
+            .filter(|&(_, item)| is_public_fn(item))
+            .map(|(index, item)| (item.ident.name.as_str().to_string(), index))
+            .collect();
+
+        // Pairs to emit after the item at a given index.
+        let mut anchors: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        let mut moved = HashSet::new();
+
+        for (index, item) in items.iter().enumerate() {
+            if !is_public_fn(item) {
+                continue;
+            }
+
+            let name = item.ident.name.as_str();
+            let name = &*name;
+            if !name.ends_with(&*self.constructor) {
+                continue;
+            }
+            let base = &name[..name.len() - self.constructor.len()];
+            let destructor_name = format!("{}{}", base, self.destructor);
+
+            let destructor = match fns.get(&destructor_name) {
+                Some(&destructor) => destructor,
+                None => {
+                    warn!(
+                        "constructor `{}` has no matching destructor `{}`",
+                        name, destructor_name
+                    );
+                    continue;
+                }
+            };
+
+            let owner = returned_type(item).and_then(|ty| {
+                items.iter().position(|item| match item.node {
+                    ast::ItemKind::Struct(..) | ast::ItemKind::Enum(..) | ast::ItemKind::Ty(..) => {
+                        item.ident.name == &*ty
+                    }
+                    _ => false,
+                })
+            });
+
+            let _ = moved.insert(index);
+            let _ = moved.insert(destructor);
+            anchors
+                .entry(owner.unwrap_or(index))
+                .or_insert_with(Vec::new)
+                .push((index, destructor));
+        }
+
+        let mut ordered = Vec::with_capacity(items.len());
+        for index in 0..items.len() {
+            if !moved.contains(&index) {
+                ordered.push(items[index]);
+            }
+            if let Some(pairs) = anchors.get(&index) {
+                for &(constructor, destructor) in pairs {
+                    ordered.push(items[constructor]);
+                    ordered.push(items[destructor]);
+                }
+            }
+        }
+
+        ordered
+    }
+}
+
+fn is_public_fn(item: &ast::Item) -> bool {
+    match (&item.vis, &item.node) {
+        (&ast::Visibility::Public, &ast::ItemKind::Fn(..)) => true,
+        _ => false,
+    }
+}
+
+/// Name of the type pointed to by the return value of a function (e.g. `App` for `*mut App`).
+fn returned_type(item: &ast::Item) -> Option<String> {
+    let ty = match item.node {
+        ast::ItemKind::Fn(ref decl, ..) => match decl.output {
+            ast::FunctionRetTy::Ty(ref ty) => ty,
+            ast::FunctionRetTy::Default(..) => return None,
+        },
+        _ => return None,
+    };
+
+    match ty.node {
+        ast::TyKind::Ptr(ref pointee) => match pointee.ty.node {
+            ast::TyKind::Path(None, ref path) => path.segments
+                .last()
+                .map(|segment| segment.identifier.name.as_str().to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse::{self, ParseSess};
+
+    #[test]
+    fn group() {
+        let source = "
+            pub extern \"C\" fn session_new() -> *mut Session {}
+            pub extern \"C\" fn session_open() {}
+            pub struct Session;
+            pub extern \"C\" fn log_new() {}
+            pub extern \"C\" fn session_free(session: *mut Session) {}
+            pub extern \"C\" fn buffer_new() -> *mut Buffer {}
+            pub extern \"C\" fn other() {}
+            pub extern \"C\" fn buffer_free(buffer: *mut Buffer) {}
+        ";
+
+        let session = ParseSess::new();
+        let krate = unwrap!(parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let names: Vec<_> = Lifecycle::default()
+            .group(krate.module.items.iter().collect())
+            .iter()
+            .map(|item| item.ident.name.as_str().to_string())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "session_open",
+                "Session",
+                "session_new",
+                "session_free",
+                "log_new",
+                "buffer_new",
+                "buffer_free",
+                "other",
+            ]
+        );
+    }
+}
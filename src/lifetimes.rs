@@ -0,0 +1,87 @@
+//! Support of the items generic only over lifetimes (e.g. `pub struct View<'a> { .. }`), which
+//! are declared like non-generic items.
+
+use syntax::ast;
+use syntax::fold::{self, Folder};
+use syntax::ptr::P;
+
+/// Remove the lifetime parameters of an item which has no type parameters, and the lifetimes of
+/// the types in the signature of a function, the fields of a struct or enum, or an aliased type.
+pub fn strip(item: ast::Item) -> ast::Item {
+    let mut folder = StripLifetimes;
+    let node = match item.node {
+        ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block) => {
+            ast::ItemKind::Fn(
+                folder.fold_fn_decl(fn_decl),
+                unsafety,
+                constness,
+                abi,
+                strip_generics(generics),
+                block,
+            )
+        }
+        ast::ItemKind::Struct(variants, generics) => {
+            ast::ItemKind::Struct(folder.fold_variant_data(variants), strip_generics(generics))
+        }
+        ast::ItemKind::Enum(mut definition, generics) => {
+            for var in &mut definition.variants {
+                var.node.data = folder.fold_variant_data(var.node.data.clone());
+            }
+            ast::ItemKind::Enum(definition, strip_generics(generics))
+        }
+        ast::ItemKind::Ty(ty, generics) => {
+            ast::ItemKind::Ty(folder.fold_ty(ty), strip_generics(generics))
+        }
+        node => node,
+    };
+
+    ast::Item { node, ..item }
+}
+
+/// Remove the lifetimes (and their bounds) from generics without type parameters.
+fn strip_generics(mut generics: ast::Generics) -> ast::Generics {
+    if generics.ty_params.is_empty() {
+        generics.lifetimes.clear();
+        generics.where_clause.predicates.clear();
+    }
+
+    generics
+}
+
+/// Removes the lifetimes of references (`&'a T`) and of the parameters of paths (`View<'a>`).
+struct StripLifetimes;
+
+impl Folder for StripLifetimes {
+    fn fold_ty(&mut self, ty: P<ast::Ty>) -> P<ast::Ty> {
+        fold::noop_fold_ty(ty, self).map(|mut ty| {
+            match ty.node {
+                ast::TyKind::Rptr(ref mut lifetime, _) => *lifetime = None,
+                ast::TyKind::Path(_, ref mut path) => {
+                    for segment in &mut path.segments {
+                        segment.parameters = segment.parameters.take().and_then(strip_parameters);
+                    }
+                }
+                _ => {}
+            }
+
+            ty
+        })
+    }
+}
+
+/// Remove the lifetimes of the parameters of a path segment, returning `None` if there are no
+/// parameters left.
+fn strip_parameters(parameters: P<ast::PathParameters>) -> Option<P<ast::PathParameters>> {
+    if let ast::PathParameters::AngleBracketed(ref data) = *parameters {
+        if data.types.is_empty() && data.bindings.is_empty() {
+            return None;
+        }
+    }
+
+    Some(parameters.map(|mut parameters| {
+        if let ast::PathParameters::AngleBracketed(ref mut data) = parameters {
+            data.lifetimes.clear();
+        }
+        parameters
+    }))
+}
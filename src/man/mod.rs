@@ -0,0 +1,345 @@
+//! Generation of section 3 man pages for the functions of the C API.
+
+use bitflags::BitFlags;
+use common::{check_no_mangle, is_extern, parse_attr, retrieve_doc_comment, Lang, Outputs};
+use lang_c::c_prototype;
+use std::collections::{BTreeMap, BTreeSet};
+use syntax::ast;
+use Error;
+use Level;
+
+/// Generates a roff man page per exported function (`man3/<function>.3`), with its prototype,
+/// its documentation (headings such as `# Safety` becoming sections) and references to the
+/// other functions mentioned in the documentation.
+pub struct LangMan {
+    lib_name: String,
+    version: String,
+    /// Prototype and documentation of the functions, indexed by name.
+    functions: BTreeMap<String, (String, String)>,
+}
+
+impl LangMan {
+    pub fn new() -> Self {
+        LangMan {
+            lib_name: "backend".to_owned(),
+            version: String::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// Set the name of the native library (and of its C header).
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Render the man page of a function.
+    fn page(&self, name: &str, prototype: &str, docs: &str) -> String {
+        let mut lines = docs.lines();
+        let summary = lines.next().unwrap_or("").trim();
+        let description = lines.collect::<Vec<_>>().join("\n");
+
+        let mut page = format!(
+            ".TH {} 3 \"\" \"{} {}\" \"{} Manual\"\n\
+             .SH NAME\n",
+            name,
+            self.lib_name,
+            self.version,
+            self.lib_name
+        );
+        if summary.is_empty() {
+            page.push_str(&format!("{}\n", name));
+        } else {
+            page.push_str(&format!("{} \\- {}\n", name, inline(summary)));
+        }
+        page.push_str(&format!(
+            ".SH SYNOPSIS\n\
+             .nf\n\
+             .B #include \"{}.h\"\n\
+             .PP\n\
+             {};\n\
+             .fi\n",
+            self.lib_name,
+            escape(prototype)
+        ));
+
+        let description = roff(description.trim());
+        if !description.is_empty() {
+            if !description.starts_with(".SH ") {
+                page.push_str(".SH DESCRIPTION\n");
+            }
+            page.push_str(&description);
+        }
+
+        // Code spans are the odd parts between backticks.
+        let references: BTreeSet<_> = docs.split('`')
+            .enumerate()
+            .filter(|&(index, _)| index % 2 == 1)
+            .map(|(_, code)| code.trim_right_matches("()"))
+            .filter(|code| *code != name && self.functions.contains_key(*code))
+            .collect();
+        if !references.is_empty() {
+            let references: Vec<_> = references
+                .iter()
+                .map(|reference| format!(".BR {} (3)", reference))
+                .collect();
+            page.push_str(&format!(".SH SEE ALSO\n{}\n", references.join(",\n")));
+        }
+
+        page
+    }
+}
+
+impl Default for LangMan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangMan {
+    fn name(&self) -> &'static str {
+        "man"
+    }
+
+    fn crate_version(&mut self, _name: &str, version: &str) {
+        self.version = version.to_string();
+    }
+
+    fn parse_const(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn parse_ty(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn parse_enum(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn parse_struct(
+        &mut self,
+        _item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        _flags: &BitFlags,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Record the prototype and the documentation of a `#[no_mangle] pub extern "C" fn`.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (no_mangle, docs) = parse_attr(&item.attrs, check_no_mangle, |attr| {
+            retrieve_doc_comment(attr, "", "")
+        });
+        if !no_mangle {
+            return Ok(());
+        }
+
+        let name = item.ident.name.as_str().to_string();
+        let prototype = match item.node {
+            ast::ItemKind::Fn(ref fn_decl, _, _, abi, ref generics, _) => {
+                if !is_extern(abi) {
+                    return Ok(());
+                }
+
+                if generics.is_parameterized() {
+                    return Err(Error {
+                        level: Level::Error,
+                        span: Some(item.span),
+                        message: "bindgen can not handle parameterized extern functions".into(),
+                        help: None,
+                    });
+                }
+
+                c_prototype(&name, fn_decl)?
+            }
+            _ => {
+                return Err(Error {
+                    level: Level::Bug,
+                    span: Some(item.span),
+                    message: "`parse_fn` called on wrong `Item_`".into(),
+                    help: None,
+                })
+            }
+        };
+
+        let _ = self.functions.insert(name, (prototype, docs));
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        for (name, &(ref prototype, ref docs)) in &self.functions {
+            let _ = outputs.insert(
+                format!("man3/{}.3", name),
+                self.page(name, prototype, docs),
+            );
+        }
+
+        self.functions.clear();
+
+        Ok(())
+    }
+}
+
+/// Translate Markdown documentation into roff: headings become sections, paragraphs are
+/// separated with `.PP`, list items are indented and code blocks are kept verbatim.
+fn roff(docs: &str) -> String {
+    let mut output = String::new();
+    let mut in_code = false;
+
+    for line in docs.lines() {
+        if line.starts_with("```") {
+            output.push_str(if in_code { ".fi\n.RE\n" } else { ".RS\n.nf\n" });
+            in_code = !in_code;
+        } else if in_code {
+            output.push_str(&format!("{}\n", escape(line)));
+        } else if line.starts_with('#') {
+            let heading = line.trim_left_matches('#').trim().to_uppercase();
+            output.push_str(&format!(".SH {}\n", escape(&heading)));
+        } else if line.trim().is_empty() {
+            let previous = output.lines().last().unwrap_or(".PP");
+            if previous != ".PP" && !previous.starts_with(".SH ") {
+                output.push_str(".PP\n");
+            }
+        } else if line.starts_with("- ") || line.starts_with("* ") {
+            output.push_str(&format!(".IP \\(bu 2\n{}\n", inline(&line[2..])));
+        } else {
+            output.push_str(&format!("{}\n", inline(line.trim())));
+        }
+    }
+
+    // Headings already start a new paragraph.
+    output.replace(".PP\n.SH ", ".SH ")
+}
+
+/// Escape a line of text, rendering `code` in bold.
+fn inline(text: &str) -> String {
+    escape(text)
+        .split('`')
+        .enumerate()
+        .map(|(index, part)| {
+            if index % 2 == 1 {
+                format!("\\fB{}\\fR", part)
+            } else {
+                part.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Escape the backslashes of `text`, and the dots and quotes starting a line (which would be
+/// read as requests).
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse;
+
+    #[test]
+    fn pages() {
+        let source = "
+            /// Length of an `App`.
+            ///
+            /// Returns 0 if the app is empty, see `app_clear()`.
+            ///
+            /// # Safety
+            ///
+            /// - `app` must be valid.
+            #[no_mangle]
+            pub extern \"C\" fn app_len(app: *const App) -> usize {}
+
+            #[no_mangle]
+            pub extern \"C\" fn app_clear(app: *mut App) {}
+        ";
+
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut lang = LangMan::new();
+        lang.set_lib_name("safe_app");
+        lang.crate_version("safe_app", "0.5.0");
+        let mut outputs = Outputs::default();
+        unwrap!(parse::parse_mod(
+            &mut lang,
+            &krate.module,
+            &["ffi".to_string()],
+            &Default::default(),
+            &mut outputs,
+        ));
+        unwrap!(lang.finalise_output(&mut outputs));
+
+        assert_eq!(
+            outputs["man3/app_len.3"],
+            ".TH app_len 3 \"\" \"safe_app 0.5.0\" \"safe_app Manual\"\n\
+             .SH NAME\n\
+             app_len \\- Length of an \\fBApp\\fR.\n\
+             .SH SYNOPSIS\n\
+             .nf\n\
+             .B #include \"safe_app.h\"\n\
+             .PP\n\
+             uintptr_t app_len(App const* app);\n\
+             .fi\n\
+             .SH DESCRIPTION\n\
+             Returns 0 if the app is empty, see \\fBapp_clear()\\fR.\n\
+             .SH SAFETY\n\
+             .IP \\(bu 2\n\
+             \\fBapp\\fR must be valid.\n\
+             .SH SEE ALSO\n\
+             .BR app_clear (3)\n"
+        );
+        assert_eq!(
+            outputs["man3/app_clear.3"],
+            ".TH app_clear 3 \"\" \"safe_app 0.5.0\" \"safe_app Manual\"\n\
+             .SH NAME\n\
+             app_clear\n\
+             .SH SYNOPSIS\n\
+             .nf\n\
+             .B #include \"safe_app.h\"\n\
+             .PP\n\
+             void app_clear(App* app);\n\
+             .fi\n"
+        );
+    }
+}
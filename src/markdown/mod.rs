@@ -0,0 +1,376 @@
+//! Generation of a Markdown API reference of the C API.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, doc_comments, extern_fn, literal_const, repr_c_enum, repr_c_struct, Lang, Outputs,
+    ReprCEnum, ReprCStruct,
+};
+use lang_c::{c_prototype, rust_to_c};
+use std::collections::BTreeMap;
+use syntax::ast;
+use Error;
+
+/// Generates an mdBook-ready API reference of the C API (as generated by `LangC`): a page per
+/// module (e.g. `ffi/app.md` for `ffi::app`) listing its constants, types and functions with
+/// their C declarations and documentation, and a `SUMMARY.md` linking the pages.
+pub struct LangMarkdown {
+    lib_name: String,
+    /// Sections of the pages, indexed by module path.
+    pages: BTreeMap<Vec<String>, Page>,
+}
+
+#[derive(Default)]
+struct Page {
+    constants: String,
+    types: String,
+    functions: String,
+}
+
+impl LangMarkdown {
+    pub fn new() -> Self {
+        LangMarkdown {
+            lib_name: "backend".to_owned(),
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Set the name of the native library, used as the title of the top-level module.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    fn page(&mut self, module: &[String]) -> &mut Page {
+        self.pages.entry(module.to_vec()).or_insert_with(Page::default)
+    }
+
+    fn page_name(&self, module: &[String]) -> String {
+        if module.is_empty() {
+            format!("{}.md", self.lib_name)
+        } else {
+            format!("{}.md", module.join("/"))
+        }
+    }
+
+    fn title(&self, module: &[String]) -> String {
+        if module.is_empty() {
+            self.lib_name.clone()
+        } else {
+            module.join("::")
+        }
+    }
+}
+
+impl Default for LangMarkdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangMarkdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    /// Document `pub const NAME: Type = value;` as `#define NAME value`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let value = match literal_const(item)? {
+            Some(constant) => constant.value,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let entry = entry(
+            &name,
+            &format!("#define {} {}", name, value),
+            &docs(&item.attrs),
+        );
+        self.page(module).constants.push_str(&entry);
+
+        Ok(())
+    }
+
+    /// Document `pub type A = B;` as `typedef B A;`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let declaration = match alias_type(item)? {
+            Some(ty) => format!("typedef {};", rust_to_c(ty, &name)?),
+            None => return Ok(()),
+        };
+
+        let entry = entry(&name, &declaration, &docs(&item.attrs));
+        self.page(module).types.push_str(&entry);
+
+        Ok(())
+    }
+
+    /// Document a `#[repr(C)]` enum, with the documentation of its variants.
+    ///
+    /// Enums with data-carrying variants are skipped.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (variants, values) = match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => (variants, values),
+            Some(ReprCEnum::Opaque) | None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let mut declaration = format!("typedef enum {} {{\n", name);
+        let mut members = String::new();
+        for (var, &(ref var_name, value)) in variants.iter().zip(&values) {
+            let var_name = format!("{}_{}", name, var_name);
+            declaration.push_str(&format!("\t{} = {},\n", var_name, value));
+            members.push_str(&member(&var_name, &var.node.attrs));
+        }
+        declaration.push_str(&format!("}} {};", name));
+
+        let entry = entry(&name, &declaration, &docs(&item.attrs));
+        let page = self.page(module);
+        page.types.push_str(&entry);
+        page.types.push_str(&members);
+
+        Ok(())
+    }
+
+    /// Document a `#[repr(C)]` struct, with the documentation of its fields.
+    ///
+    /// Tuple structs with a single field are documented as opaque types.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let mut members = String::new();
+        let declaration = match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => {
+                let mut declaration = format!("typedef struct {} {{\n", name);
+                for &(ref field_name, field) in &fields {
+                    declaration.push_str(&format!("\t{};\n", rust_to_c(&field.ty, field_name)?));
+                    members.push_str(&member(field_name, &field.attrs));
+                }
+                declaration.push_str(&format!("}} {};", name));
+                declaration
+            }
+            Some(ReprCStruct::Opaque) => format!("typedef struct {0} {0};", name),
+            None => return Ok(()),
+        };
+
+        let entry = entry(&name, &declaration, &docs(&item.attrs));
+        let page = self.page(module);
+        page.types.push_str(&entry);
+        page.types.push_str(&members);
+
+        Ok(())
+    }
+
+    /// Document a `bitflags!` flag set as a `typedef` and the `#define`s of its flags.
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut declaration = format!("typedef {};\n\n", rust_to_c(&flags.ty, &flags.name)?);
+        let mut members = String::new();
+        for flag in &flags.flags {
+            let flag_name = format!("{}_{}", flags.name, flag.name);
+            declaration.push_str(&format!("#define {} {:#x}\n", flag_name, flag.value));
+            members.push_str(&member(&flag_name, &flag.attrs));
+        }
+
+        let entry = entry(&flags.name, declaration.trim_right(), &docs(&flags.attrs));
+        let page = self.page(module);
+        page.types.push_str(&entry);
+        page.types.push_str(&members);
+
+        Ok(())
+    }
+
+    /// Document a `#[no_mangle] pub extern "C" fn` with its C prototype.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        _outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = item.ident.name.as_str();
+        let prototype = match extern_fn(item)? {
+            Some(fn_decl) => c_prototype(&name, fn_decl)?,
+            None => return Ok(()),
+        };
+
+        let entry = entry(&name, &format!("{};", prototype), &docs(&item.attrs));
+        self.page(module).functions.push_str(&entry);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let mut summary = String::from("# Summary\n\n");
+
+        for (module, page) in &self.pages {
+            let page_name = self.page_name(module);
+            let title = self.title(module);
+            summary.push_str(&format!("- [`{}`]({})\n", title, page_name));
+
+            let mut contents = format!("# `{}`\n", title);
+            for &(heading, ref section) in &[
+                ("Constants", &page.constants),
+                ("Types", &page.types),
+                ("Functions", &page.functions),
+            ] {
+                if !section.is_empty() {
+                    contents.push_str(&format!("\n## {}\n{}", heading, section));
+                }
+            }
+
+            let _ = outputs.insert(page_name, contents);
+        }
+
+        let _ = outputs.insert("SUMMARY.md".to_owned(), summary);
+        self.pages.clear();
+
+        Ok(())
+    }
+}
+
+/// A heading followed by the C declaration of an item and its documentation.
+fn entry(name: &str, declaration: &str, docs: &str) -> String {
+    let mut entry = format!("\n### `{}`\n\n```c\n{}\n```\n", name, declaration);
+    if !docs.is_empty() {
+        entry.push('\n');
+        entry.push_str(docs);
+    }
+    entry
+}
+
+/// A list item documenting a field, a variant or a flag (if it has documentation).
+fn member(name: &str, attrs: &[ast::Attribute]) -> String {
+    let docs = docs(attrs);
+    if docs.is_empty() {
+        return String::new();
+    }
+
+    let mut lines = docs.lines();
+    let mut member = format!("\n- `{}`: {}\n", name, lines.next().unwrap_or(""));
+    for line in lines {
+        if line.is_empty() {
+            member.push('\n');
+        } else {
+            member.push_str(&format!("  {}\n", line));
+        }
+    }
+    member
+}
+
+/// Collect the doc comments, demoting their headings (e.g. `# Safety`) below the heading of the
+/// item.
+fn docs(attrs: &[ast::Attribute]) -> String {
+    let docs = doc_comments(attrs, "", "");
+
+    let mut in_code = false;
+    let mut output = String::new();
+    for line in docs.lines() {
+        if line.starts_with("```") {
+            in_code = !in_code;
+        }
+        if !in_code && line.starts_with('#') {
+            output.push_str("###");
+        }
+        output.push_str(line);
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse;
+
+    #[test]
+    fn reference() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = 2,
+            }
+
+            /// Length of an app.
+            ///
+            /// # Safety
+            ///
+            /// `app` must be valid.
+            #[no_mangle]
+            pub extern \"C\" fn app_len(app: *const App) -> usize {}
+        ";
+
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut lang = LangMarkdown::new();
+        let mut outputs = Outputs::default();
+        unwrap!(parse::parse_mod(
+            &mut lang,
+            &krate.module,
+            &["ffi".to_string(), "app".to_string()],
+            &Default::default(),
+            &mut outputs,
+        ));
+        unwrap!(lang.finalise_output(&mut outputs));
+
+        assert_eq!(outputs["SUMMARY.md"], "# Summary\n\n- [`ffi::app`](ffi/app.md)\n");
+        assert_eq!(
+            outputs["ffi/app.md"],
+            "# `ffi::app`\n\n\
+             ## Constants\n\n\
+             ### `MAX_LEN`\n\n\
+             ```c\n\
+             #define MAX_LEN 16\n\
+             ```\n\n\
+             Maximum length.\n\n\
+             ## Types\n\n\
+             ### `Status`\n\n\
+             ```c\n\
+             typedef enum Status {\n\
+             \tStatus_Ok = 0,\n\
+             \tStatus_Failed = 2,\n\
+             } Status;\n\
+             ```\n\n\
+             - `Status_Ok`: Success.\n\n\
+             ## Functions\n\n\
+             ### `app_len`\n\n\
+             ```c\n\
+             uintptr_t app_len(App const* app);\n\
+             ```\n\n\
+             Length of an app.\n\n\
+             #### Safety\n\n\
+             `app` must be valid.\n"
+        );
+    }
+}
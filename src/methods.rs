@@ -0,0 +1,133 @@
+//! Grouping of the functions operating on a type (e.g. `app_len` for `App`), recognised by the
+//! `type_action` naming convention or declared in an `impl App` block.
+
+use inflector::Inflector;
+use std::collections::{HashMap, HashSet};
+use syntax::ast;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+/// Returns the type a function operates on, recognised by the prefix of its name (`app_` or
+/// `App_` for `App`). The longest match wins, so `app_key_len` belongs to `AppKey` rather than to
+/// `App`.
+pub fn owner<'a, I>(name: &str, types: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    types
+        .into_iter()
+        .filter(|ty| {
+            name.starts_with(&format!("{}_", ty.to_snake_case()))
+                || name.starts_with(&format!("{}_", ty))
+        })
+        .max_by_key(|ty| ty.len())
+        .cloned()
+}
+
+/// Reorder the items of a module so the functions operating on a type (and the `impl` blocks of
+/// the type) directly follow the declaration of the type, keeping their relative order.
+pub fn group<'a>(items: Vec<&'a P<ast::Item>>) -> Vec<&'a P<ast::Item>> {
+    let types: Vec<String> = items
+        .iter()
+        .filter_map(|item| match item.node {
+            ast::ItemKind::Struct(..) | ast::ItemKind::Enum(..) | ast::ItemKind::Ty(..) => {
+                Some(item.ident.name.as_str().to_string())
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Methods to emit after the declaration of each type.
+    let mut methods: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut moved = HashSet::new();
+
+    for (index, item) in items.iter().enumerate() {
+        let owner = match item.node {
+            ast::ItemKind::Fn(..) => owner(&item.ident.name.as_str(), &types),
+            ast::ItemKind::Impl(_, _, _, None, ref self_ty, _) => {
+                let name = pprust::ty_to_string(self_ty);
+                if types.contains(&name) {
+                    Some(name)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(owner) = owner {
+            let _ = moved.insert(index);
+            methods.entry(owner).or_insert_with(Vec::new).push(index);
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        if moved.contains(&index) {
+            continue;
+        }
+
+        ordered.push(*item);
+        if let Some(indices) = methods.get(&*item.ident.name.as_str()) {
+            match item.node {
+                ast::ItemKind::Struct(..) | ast::ItemKind::Enum(..) | ast::ItemKind::Ty(..) => {
+                    ordered.extend(indices.iter().map(|&index| items[index]));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse::{self, ParseSess};
+
+    #[test]
+    fn owners() {
+        let types = vec!["App".to_string(), "AppKey".to_string()];
+
+        assert_eq!(owner("app_len", &types), Some("App".to_string()));
+        assert_eq!(owner("App_len", &types), Some("App".to_string()));
+        assert_eq!(owner("app_key_len", &types), Some("AppKey".to_string()));
+        assert_eq!(owner("apply", &types), None);
+    }
+
+    #[test]
+    fn group_methods() {
+        let source = "
+            pub extern \"C\" fn app_len(app: *const App) -> usize {}
+            pub extern \"C\" fn init() {}
+            pub struct App;
+            impl Key {
+                pub extern \"C\" fn key_random() -> Key {}
+            }
+            pub extern \"C\" fn key_len(key: *const Key) -> usize {}
+            pub struct Key;
+            pub extern \"C\" fn app_clear(app: *mut App) {}
+        ";
+
+        let session = ParseSess::new();
+        let krate = unwrap!(parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let names: Vec<_> = group(krate.module.items.iter().collect())
+            .iter()
+            .map(|item| match item.node {
+                ast::ItemKind::Impl(..) => "impl".to_string(),
+                _ => item.ident.name.as_str().to_string(),
+            })
+            .collect();
+
+        assert_eq!(
+            names,
+            vec!["init", "App", "app_len", "app_clear", "Key", "impl", "key_len"]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Emission order of the items of a module.
+
+use bitflags::BitFlags;
+use std::collections::HashMap;
+use syntax::ast;
+use syntax::ptr::P;
+
+/// Kind of an item whose emission order is configurable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OrderedKind {
+    /// `pub const NAME: Type = value;`
+    Const,
+    /// `pub type A = B;`
+    Type,
+    /// `#[repr(C)]` enum.
+    Enum,
+    /// `#[repr(C)]` struct, or `bitflags!` struct.
+    Struct,
+    /// `#[no_mangle] pub extern "C" fn` declared at the top level of a module (the functions of
+    /// `impl` blocks stay together).
+    Function,
+}
+
+/// Order in which the items of a kind are emitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// The order of the Rust source (default), easier to read alongside the code.
+    Source,
+    /// Sorted by name, so the output stays stable when the source is rearranged.
+    Alphabetical,
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Order::Source
+    }
+}
+
+/// Reorder the items of a module according to the order of their kinds. Sorted items take the
+/// places of the items of the same kind, so the kinds remain interleaved as in the source.
+pub fn sort<'a>(
+    items: Vec<&'a P<ast::Item>>,
+    orders: &HashMap<OrderedKind, Order>,
+) -> Vec<&'a P<ast::Item>> {
+    let mut ordered = items.clone();
+
+    for (&kind, &order) in orders {
+        if order != Order::Alphabetical {
+            continue;
+        }
+
+        let mut named: Vec<(usize, String)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| name(item, kind).map(|name| (index, name)))
+            .collect();
+        let slots: Vec<usize> = named.iter().map(|&(index, _)| index).collect();
+        named.sort_by(|a, b| a.1.cmp(&b.1));
+
+        for (&slot, &(index, _)) in slots.iter().zip(&named) {
+            ordered[slot] = items[index];
+        }
+    }
+
+    ordered
+}
+
+/// Returns the name of the item if it is of the given kind.
+fn name(item: &ast::Item, kind: OrderedKind) -> Option<String> {
+    let matches = match (kind, &item.node) {
+        (OrderedKind::Const, &ast::ItemKind::Const(..))
+        | (OrderedKind::Type, &ast::ItemKind::Ty(..))
+        | (OrderedKind::Enum, &ast::ItemKind::Enum(..))
+        | (OrderedKind::Struct, &ast::ItemKind::Struct(..))
+        | (OrderedKind::Function, &ast::ItemKind::Fn(..)) => true,
+        (OrderedKind::Struct, &ast::ItemKind::Mac(..)) => {
+            return match BitFlags::from_item(item) {
+                Some(Ok(flags)) => Some(flags.name),
+                _ => None,
+            }
+        }
+        _ => false,
+    };
+
+    if matches {
+        Some(item.ident.name.as_str().to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parse::{self, ParseSess};
+
+    #[test]
+    fn alphabetical() {
+        let source = "
+            pub extern \"C\" fn session_open() {}
+            pub struct Session;
+            pub const MAX: u32 = 1;
+            pub extern \"C\" fn app_len() {}
+            pub struct App;
+            pub const LIMIT: u32 = 2;
+        ";
+
+        let session = ParseSess::new();
+        let krate = unwrap!(parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut orders = HashMap::new();
+        let _ = orders.insert(OrderedKind::Function, Order::Alphabetical);
+        let _ = orders.insert(OrderedKind::Struct, Order::Alphabetical);
+        let _ = orders.insert(OrderedKind::Const, Order::Source);
+
+        let names: Vec<_> = sort(krate.module.items.iter().collect(), &orders)
+            .iter()
+            .map(|item| item.ident.name.as_str().to_string())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec!["app_len", "App", "MAX", "session_open", "Session", "LIMIT"]
+        );
+    }
+}
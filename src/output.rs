@@ -1,5 +1,17 @@
+use common::Outputs;
+use java;
 use std::fmt::{self, Write};
+use std::io;
 use std::ops::Deref;
+use std::path::{Path, MAIN_SEPARATOR};
+
+/// Android ABIs and the Rust targets building the native library for them.
+const ANDROID_ABIS: &[(&str, &str)] = &[
+    ("arm64-v8a", "aarch64-linux-android"),
+    ("armeabi-v7a", "armv7-linux-androideabi"),
+    ("x86", "i686-linux-android"),
+    ("x86_64", "x86_64-linux-android"),
+];
 
 pub struct IndentedWriter {
     inner: String,
@@ -81,6 +93,175 @@ impl Deref for IndentedWriter {
     }
 }
 
+/// Format `text` as a comment suitable for the output file at `path`, based on its extension.
+///
+/// Returns `None` for file types we don't know the comment syntax of.
+pub fn comment(path: &str, text: &str) -> Option<String> {
+    let ext = Path::new(path).extension().and_then(|ext| ext.to_str());
+
+    match ext {
+        Some("h") | Some("c") => Some(format!("/* {} */\n", text)),
+        Some("cs") | Some("java") | Some("rs") | Some("zig") | Some("d") | Some("js") => {
+            Some(format!("// {}\n", text))
+        }
+        Some("pc") | Some("cmake") | Some("pxd") | Some("rb") | Some("abi") => {
+            Some(format!("# {}\n", text))
+        }
+        Some("hsc") | Some("lua") => Some(format!("-- {}\n", text)),
+        Some("md") => Some(format!("<!-- {} -->\n", text)),
+        Some("3") => Some(format!(".\\\" {}\n", text)),
+        _ => None,
+    }
+}
+
+/// Arrange the outputs into the layout of the `src/main` directory of an Android library module.
+///
+/// Java sources go into `java` (in the directories of their packages), C headers and sources into
+/// `cpp` and the Rust JNI glue into `rust`. A `jniLibs/README.md` lists where the native library
+/// has to be copied for every ABI. Outputs already in the layout are left untouched.
+pub fn android_layout(lib_name: &str, outputs: &mut Outputs) {
+    let root = format!("src{0}main{0}", MAIN_SEPARATOR);
+
+    let moves: Vec<_> = outputs
+        .iter()
+        .filter(|&(path, _)| !path.starts_with(&root))
+        .filter_map(|(path, contents)| {
+            let ext = Path::new(path).extension().and_then(|ext| ext.to_str());
+            let dir = match ext {
+                Some("java") => match java::package_dir(contents) {
+                    // Sources already in their package directories keep their path.
+                    Some(ref package) if !path.contains(MAIN_SEPARATOR) => {
+                        format!("java{}{}", MAIN_SEPARATOR, package)
+                    }
+                    _ => "java".to_owned(),
+                },
+                Some("h") | Some("c") => "cpp".to_owned(),
+                Some("rs") => "rust".to_owned(),
+                _ => return None,
+            };
+
+            Some((path.clone(), format!("{}{}{}{}", root, dir, MAIN_SEPARATOR, path)))
+        })
+        .collect();
+
+    for (from, to) in moves {
+        if let Some(contents) = outputs.remove(&from) {
+            let _ = outputs.insert(to, contents);
+        }
+    }
+
+    let _ = outputs.insert(
+        format!("{0}jniLibs{1}README.md", root, MAIN_SEPARATOR),
+        android_abi_notes(lib_name),
+    );
+}
+
+/// Notes on where to put the native library built for each Android ABI.
+fn android_abi_notes(lib_name: &str) -> String {
+    let mut notes = format!(
+        "# Native libraries\n\n\
+         Copy `lib{0}.so` built for each ABI into the matching directory, where Gradle packages it \
+         into the AAR. The library is then loaded with `System.loadLibrary(\"{0}\")`.\n\n\
+         | ABI | Rust target | Path |\n\
+         | --- | --- | --- |\n",
+        lib_name
+    );
+
+    for &(abi, target) in ANDROID_ABIS {
+        notes.push_str(&format!(
+            "| {0} | `{1}` | `src/main/jniLibs/{0}/lib{2}.so` |\n",
+            abi, target, lib_name
+        ));
+    }
+
+    notes
+}
+
+/// Write the outputs one after the other (sorted by path), e.g. to pipe them into other tools.
+///
+/// Unless there is a single output, each file is preceded by a comment holding its path.
+pub fn write_concatenated<W: io::Write>(writer: &mut W, outputs: &Outputs) -> io::Result<()> {
+    let mut paths: Vec<_> = outputs.keys().collect();
+    paths.sort();
+
+    for (index, path) in paths.into_iter().enumerate() {
+        if outputs.len() > 1 {
+            if index > 0 {
+                writer.write_all(b"\n")?;
+            }
+            let marker = comment(path, path).unwrap_or_else(|| format!("==> {} <==\n", path));
+            writer.write_all(marker.as_bytes())?;
+        }
+        writer.write_all(outputs[path].as_bytes())?;
+    }
+
+    writer.flush()
+}
+
+/// Line endings of the generated files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n` (default).
+    Lf,
+    /// `\r\n`, as expected by some Windows tools.
+    CrLf,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        LineEnding::Lf
+    }
+}
+
+/// Convert the line endings of a generated file and prepend the UTF-8 byte order mark if `bom`
+/// is set.
+pub fn encode(contents: &mut String, line_ending: LineEnding, bom: bool) {
+    if line_ending == LineEnding::CrLf {
+        let mut converted = String::with_capacity(contents.len() + contents.len() / 16);
+        let mut previous = None;
+        for c in contents.chars() {
+            // Line endings which are already converted are left alone.
+            if c == '\n' && previous != Some('\r') {
+                converted.push('\r');
+            }
+            converted.push(c);
+            previous = Some(c);
+        }
+        *contents = converted;
+    }
+
+    if bom && !contents.starts_with('\u{feff}') {
+        contents.insert(0, '\u{feff}');
+    }
+}
+
+/// Format a UNIX timestamp as a UTC date and time (`YYYY-MM-DD HH:MM:SS UTC`).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Convert days since the epoch into a civil date (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,4 +292,90 @@ mod tests {
         let expected = "    foo\n\n\n    bar";
         assert_eq!(&*output, expected);
     }
+
+    #[test]
+    fn comments() {
+        assert_eq!(comment("a/b.h", "hi"), Some("/* hi */\n".to_string()));
+        assert_eq!(comment("Types.cs", "hi"), Some("// hi\n".to_string()));
+        assert_eq!(comment("jni.rs", "hi"), Some("// hi\n".to_string()));
+        assert_eq!(comment("lib.pc", "hi"), Some("# hi\n".to_string()));
+        assert_eq!(comment("README", "hi"), None);
+    }
+
+    #[test]
+    fn android() {
+        let mut outputs = Outputs::default();
+        let _ = outputs.insert(
+            "NativeBindings.java".to_owned(),
+            "package com.example;\n".to_owned(),
+        );
+        let _ = outputs.insert("backend.h".to_owned(), String::new());
+        let _ = outputs.insert("jni.rs".to_owned(), String::new());
+        let _ = outputs.insert("Types.cs".to_owned(), String::new());
+
+        android_layout("backend", &mut outputs);
+
+        let mut paths: Vec<_> = outputs
+            .keys()
+            .map(|path| path.replace(MAIN_SEPARATOR, "/"))
+            .collect();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                "Types.cs",
+                "src/main/cpp/backend.h",
+                "src/main/java/com/example/NativeBindings.java",
+                "src/main/jniLibs/README.md",
+                "src/main/rust/jni.rs",
+            ]
+        );
+
+        let notes = &outputs[&format!("src{0}main{0}jniLibs{0}README.md", MAIN_SEPARATOR)];
+        assert!(notes.contains("| arm64-v8a | `aarch64-linux-android` |"));
+        assert!(notes.contains("`src/main/jniLibs/x86_64/libbackend.so`"));
+    }
+
+    #[test]
+    fn concatenated() {
+        let mut outputs = Outputs::default();
+        let _ = outputs.insert("backend.h".to_owned(), "void f(void);\n".to_owned());
+        let _ = outputs.insert("README".to_owned(), "Bindings\n".to_owned());
+
+        let mut buffer = Vec::new();
+        unwrap!(write_concatenated(&mut buffer, &outputs));
+        assert_eq!(
+            unwrap!(String::from_utf8(buffer)),
+            "==> README <==\nBindings\n\n/* backend.h */\nvoid f(void);\n"
+        );
+
+        let _ = outputs.remove("README");
+        let mut buffer = Vec::new();
+        unwrap!(write_concatenated(&mut buffer, &outputs));
+        assert_eq!(unwrap!(String::from_utf8(buffer)), "void f(void);\n");
+    }
+
+    #[test]
+    fn encoding() {
+        let mut contents = "#pragma once\n\r\nvoid f(void);\n".to_owned();
+        encode(&mut contents, LineEnding::CrLf, true);
+        assert_eq!(contents, "\u{feff}#pragma once\r\n\r\nvoid f(void);\r\n");
+
+        // Encoding twice changes nothing.
+        let encoded = contents.clone();
+        encode(&mut contents, LineEnding::CrLf, true);
+        assert_eq!(contents, encoded);
+
+        let mut contents = "a\nb\n".to_owned();
+        encode(&mut contents, LineEnding::Lf, false);
+        assert_eq!(contents, "a\nb\n");
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_792_057_688), "2026-10-15 09:48:08 UTC");
+    }
 }
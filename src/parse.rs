@@ -1,8 +1,124 @@
 //! Functions for actually parsing the source file.
 
-use common::{Lang, Outputs};
+use bitflags::BitFlags;
+use common::{
+    check_doc_hidden, check_no_mangle, check_repr_c, has_cheddar_flag, is_extern, parse_attr,
+    pointer_width, std_type_alternative, Lang, Outputs,
+};
+use config::Config;
+use docs;
+use instantiate;
+use lifetimes;
+use methods;
+use order;
+use references;
+use shim;
+use std::collections::HashMap;
 use syntax::ast;
+use syntax::fold::{self, Folder};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
 use Error;
+use Level;
+
+/// Hook invoked for every item emitted into an output file.
+///
+/// Receives the item and the path of the output file, and returns the text to inject (if any).
+pub type ItemHook = Box<Fn(&ast::Item, &str) -> Option<String>>;
+
+/// Kind of item which the target languages may be unable to handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Unsupported {
+    /// Generic struct, without `#[cheddar(instantiate(..))]`.
+    ParameterizedStruct,
+    /// Generic enum.
+    ParameterizedEnum,
+    /// Enum whose variants carry data.
+    DataEnum,
+    /// Generic type alias.
+    ParameterizedAlias,
+    /// Generic function.
+    ParameterizedFn,
+}
+
+impl Unsupported {
+    /// Returns the construct of an item, if it is one the target languages may be unable to
+    /// handle.
+    fn of(item: &ast::Item) -> Option<Self> {
+        match item.node {
+            ast::ItemKind::Struct(_, ref generics) if generics.is_parameterized() => {
+                Some(Unsupported::ParameterizedStruct)
+            }
+            ast::ItemKind::Enum(_, ref generics) if generics.is_parameterized() => {
+                Some(Unsupported::ParameterizedEnum)
+            }
+            ast::ItemKind::Enum(ref definition, _)
+                if definition.variants.iter().any(|var| !var.node.data.is_unit()) =>
+            {
+                Some(Unsupported::DataEnum)
+            }
+            ast::ItemKind::Ty(_, ref generics) if generics.is_parameterized() => {
+                Some(Unsupported::ParameterizedAlias)
+            }
+            ast::ItemKind::Fn(_, _, _, _, ref generics, _) if generics.is_parameterized() => {
+                Some(Unsupported::ParameterizedFn)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a suggestion on how to export an item of this construct.
+    fn suggestion(self) -> &'static str {
+        match self {
+            Unsupported::ParameterizedStruct => {
+                "list the concrete types with `#[cheddar(instantiate(..))]`, or wrap the struct \
+                 in an opaque handle"
+            }
+            Unsupported::ParameterizedEnum | Unsupported::DataEnum => {
+                "move the data into `#[repr(C)]` structs, or wrap the enum in an opaque handle"
+            }
+            Unsupported::ParameterizedAlias => {
+                "alias a concrete type instead (e.g. `pub type Bytes = FfiVec<u8>;`)"
+            }
+            Unsupported::ParameterizedFn => {
+                "export a non-generic `extern \"C\"` wrapper for each type"
+            }
+        }
+    }
+}
+
+/// Hooks injecting custom text around every emitted item.
+#[derive(Default)]
+pub struct ItemHooks {
+    /// Hooks whose output is inserted before the item.
+    pub pre: Vec<ItemHook>,
+    /// Hooks whose output is appended after the item.
+    pub post: Vec<ItemHook>,
+}
+
+impl ItemHooks {
+    fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+
+    /// Wrap the text emitted for `item` (everything past the lengths recorded in `before`) with
+    /// the output of the hooks.
+    fn apply(&self, item: &ast::Item, before: &HashMap<String, usize>, outputs: &mut Outputs) {
+        for (path, contents) in outputs.iter_mut() {
+            let start = before.get(path).cloned().unwrap_or(0);
+            if contents.len() == start {
+                continue;
+            }
+
+            let pre: String = self.pre.iter().filter_map(|hook| hook(item, path)).collect();
+            let post: String = self.post.iter().filter_map(|hook| hook(item, path)).collect();
+
+            contents.insert_str(start, &pre);
+            contents.push_str(&post);
+        }
+    }
+}
 
 /// Returns a list of FFI submodules imported in a top-level module
 pub fn imported_mods(module: &ast::Mod) -> Vec<Vec<String>> {
@@ -33,37 +149,115 @@ pub fn imported_mods(module: &ast::Mod) -> Vec<Vec<String>> {
     imported
 }
 
+/// Returns the module at `path` (e.g. `["ffi", "app"]`) inside `module`, if it is declared inline
+/// or was loaded by the parser.
+pub fn find_module<'a>(module: &'a ast::Mod, path: &[String]) -> Option<&'a ast::Mod> {
+    let (name, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Some(module),
+    };
+
+    module
+        .items
+        .iter()
+        .filter_map(|item| match item.node {
+            ast::ItemKind::Mod(ref inner) if item.ident.name == name.as_str() => Some(inner),
+            _ => None,
+        })
+        .next()
+        .and_then(|inner| find_module(inner, rest))
+}
+
 /// The manager of bindgen and entry point when the crate is the module.
 ///
 /// Iterates through all items in the module and dispatches to correct methods, then pulls all
 /// the results together into a header.
-pub fn parse_mod<L: Lang>(
+pub fn parse_mod<L: Lang + ?Sized>(
     lang: &mut L,
     module: &ast::Mod,
     module_path: &[String],
+    config: &Config,
     outputs: &mut Outputs,
 ) -> Result<(), Vec<Error>> {
     let mut errors = vec![];
 
-    for item in &module.items {
-        // If it's not visible it can't be called from C.
-        if let ast::Visibility::Inherited = item.vis {
-            continue;
-        }
+    let items = order::sort(module.items.iter().collect(), &config.order);
+    let items = match config.lifecycle {
+        Some(ref lifecycle) => lifecycle.group(items),
+        None => items,
+    };
+    let items = if config.group_methods {
+        methods::group(items)
+    } else {
+        items
+    };
+
+    // The structs declared inside their parent are parsed first, so that their fields are known
+    // when the parent is declared.
+    let (inline, items): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .partition(|item| item.attrs.iter().any(|attr| has_cheddar_flag(attr, "inline")));
+    let items = inline.into_iter().chain(items);
 
-        // Dispatch to correct method.
-        let res = match item.node {
-            ast::ItemKind::Const(..) => lang.parse_const(item, module_path, outputs),
-            ast::ItemKind::Ty(..) => lang.parse_ty(item, module_path, outputs),
-            ast::ItemKind::Enum(..) => lang.parse_enum(item, module_path, outputs),
-            ast::ItemKind::Struct(..) => lang.parse_struct(item, module_path, outputs),
-            ast::ItemKind::Fn(..) => lang.parse_fn(item, module_path, outputs),
-            _ => Ok(()),
+    for item in items {
+        let mut bitflags = None;
+        let items = match item.node {
+            // Functions can also be exported from inherent impl blocks.
+            ast::ItemKind::Impl(_, _, _, None, ref self_ty, ref impl_items) => {
+                let mut items = Vec::new();
+                for impl_item in impl_items {
+                    match impl_item_to_item(impl_item, self_ty, config) {
+                        Some(Ok(item)) => items.push(item),
+                        Some(Err(error)) => errors.push(error),
+                        None => {}
+                    }
+                }
+                items
+            }
+            ast::ItemKind::Mac(..) => match BitFlags::from_item(item) {
+                Some(Ok(flags)) => {
+                    let item = flags.to_item(item);
+                    bitflags = Some(flags);
+                    vec![item]
+                }
+                Some(Err(error)) => {
+                    errors.push(error);
+                    continue;
+                }
+                None => continue,
+            },
+            // Idiomatic functions are exported through their generated shims.
+            ast::ItemKind::Fn(..) if shim::is_shim(item) => {
+                match shim::generate(item, module_path) {
+                    Ok(shim) => vec![shim.item],
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    }
+                }
+            }
+            // Generic structs are declared once per requested instantiation.
+            ast::ItemKind::Struct(..) => match instantiate::instantiate(item) {
+                Some(Ok(items)) => items,
+                Some(Err(error)) => {
+                    errors.push(error);
+                    continue;
+                }
+                None => vec![(**item).clone()],
+            },
+            _ => vec![(**item).clone()],
         };
 
-        // Display any non-fatal errors, fatal errors are handled at cause.
-        if let Err(error) = res {
-            errors.push(error)
+        for item in items {
+            let item = instantiate::mangle(lifetimes::strip(item));
+            let item = config.type_overrides.apply(lang.name(), item);
+
+            // Display any non-fatal errors, fatal errors are handled at cause.
+            if let Err(error) =
+                parse_item(lang, &item, bitflags.as_ref(), module_path, config, outputs)
+            {
+                errors.push(error)
+            }
         }
     }
 
@@ -73,3 +267,457 @@ pub fn parse_mod<L: Lang>(
         Err(errors)
     }
 }
+
+/// Whether the item named `name` is kept by the item filter and compiled for the current target
+/// and pointer widths, if any.
+pub fn is_selected(name: &str, attrs: &[ast::Attribute], config: &Config) -> bool {
+    if !config.filter.matches(name) {
+        debug!("Skipping `{}`: filtered out", name);
+        return false;
+    }
+
+    // Items compiled for other targets.
+    if let Some(ref target) = *config.target.borrow() {
+        if !target.matches(attrs) {
+            debug!("Skipping `{}`: not compiled for `{}`", name, target.triple);
+            return false;
+        }
+    }
+
+    // Items for the pointer widths of other targets.
+    if let Some(width) = pointer_width(attrs) {
+        if !config.pointer_widths.is_empty() && !config.pointer_widths.contains(&width) {
+            debug!("Skipping `{}`: only for {}-bit targets", name, width);
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Filter a single item and dispatch it to the correct method.
+fn parse_item<L: Lang + ?Sized>(
+    lang: &mut L,
+    item: &ast::Item,
+    bitflags: Option<&BitFlags>,
+    module_path: &[String],
+    config: &Config,
+    outputs: &mut Outputs,
+) -> Result<(), Error> {
+    let name = item.ident.name.as_str();
+    trace!("Visiting `{}::{}`", module_path.join("::"), name);
+
+    // If it's not visible it can't be called from C.
+    if let ast::Visibility::Inherited = item.vis {
+        return Ok(());
+    }
+
+    if !is_selected(&name, &item.attrs, config) {
+        return Ok(());
+    }
+
+    // Types not reachable from the exported functions are only used inside the crate.
+    if let Some(ref pruning) = config.pruning {
+        match item.node {
+            ast::ItemKind::Ty(..)
+            | ast::ItemKind::Enum(..)
+            | ast::ItemKind::Struct(..)
+            | ast::ItemKind::Mac(..) => {
+                if !pruning.reachable.borrow().contains(&*name) {
+                    debug!("Skipping `{}`: not reachable from the exported functions", name);
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Hidden items are not part of the public API.
+    if !config.include_hidden && parse_attr(&item.attrs, check_doc_hidden, |_| None).0 {
+        debug!("Skipping `{}`: hidden", name);
+        return Ok(());
+    }
+
+    // Render the documentation carried by attributes.
+    let augmented;
+    let item = match docs::augment(item, config) {
+        Some(attrs) => {
+            augmented = ast::Item {
+                attrs,
+                ..item.clone()
+            };
+            &augmented
+        }
+        None => item,
+    };
+
+    check_ffi_types(item, config)?;
+
+    // References are passed as pointers (after documenting their requirements).
+    let converted;
+    let item = match references::to_pointers(item) {
+        Some(item) => {
+            converted = item;
+            &converted
+        }
+        None => item,
+    };
+
+    let hooks = &config.hooks;
+
+    // Remember where the output of this item is going to start.
+    let before: HashMap<String, usize> = if hooks.is_empty() {
+        HashMap::new()
+    } else {
+        outputs
+            .iter()
+            .map(|(path, contents)| (path.clone(), contents.len()))
+            .collect()
+    };
+
+    // Dispatch to correct method.
+    let result = match item.node {
+        ast::ItemKind::Const(..) => lang.parse_const(item, module_path, outputs),
+        ast::ItemKind::Ty(..) => lang.parse_ty(item, module_path, outputs),
+        ast::ItemKind::Enum(..) => lang.parse_enum(item, module_path, outputs),
+        ast::ItemKind::Struct(..) => lang.parse_struct(item, module_path, outputs),
+        ast::ItemKind::Fn(..) => lang.parse_fn(item, module_path, outputs),
+        ast::ItemKind::Mac(..) => match bitflags {
+            Some(flags) => lang.parse_bitflags(item, flags, module_path, outputs),
+            None => return Ok(()),
+        },
+        ast::ItemKind::Static(..) | ast::ItemKind::Trait(..) | ast::ItemKind::Union(..) => {
+            warn!("Skipping `{}`: no bindings are generated for this kind of item", name);
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    if let Err(mut error) = result {
+        if error.level == Level::Error {
+            if let Some(construct) = Unsupported::of(item) {
+                if !config.strict.get() && config.skip_unsupported.contains(&construct) {
+                    warn!("Skipping `{}`: {}", name, error.message);
+                    return Ok(());
+                }
+                if error.help.is_none() {
+                    error.help = Some(construct.suggestion().to_string());
+                }
+            }
+        }
+        return Err(error);
+    }
+
+    if !hooks.is_empty() {
+        hooks.apply(item, &before, outputs);
+    }
+
+    Ok(())
+}
+
+/// Reject the types which have no C equivalent in the signature of a function, the fields of a
+/// struct or an aliased type, suggesting alternatives:
+///
+/// - pointers to unsized types (`*const str`, `*const [T]` and trait objects), which are fat
+///   pointers,
+/// - the standard library types such as `String` or `Vec` (unless behind a pointer),
+/// - 128-bit integers,
+/// - `char`, if `config.strict_char` is set.
+///
+/// Only the items declared to the target languages are checked, the others being skipped.
+fn check_ffi_types(item: &ast::Item, config: &Config) -> Result<(), Error> {
+    if !is_exported(item) {
+        return Ok(());
+    }
+
+    let mut types = Vec::new();
+    match item.node {
+        ast::ItemKind::Fn(ref fn_decl, ..) => fn_decl_types(fn_decl, &mut types),
+        ast::ItemKind::Struct(ref variants, _) => {
+            types.extend(variants.fields().iter().map(|field| &*field.ty))
+        }
+        ast::ItemKind::Ty(ref ty, _) => types.push(ty),
+        _ => {}
+    }
+
+    for ty in types {
+        let found = find_type(ty, true, &unsized_pointer)
+            .or_else(|| find_type(ty, false, &std_type))
+            .or_else(|| find_type(ty, true, &int128))
+            .or_else(|| if config.strict_char || config.strict.get() {
+                find_type(ty, true, &char_type)
+            } else {
+                None
+            });
+
+        if let Some((ty, message)) = found {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(ty.span),
+                message: format!("`{}` {}", pprust::ty_to_string(ty), message),
+                help: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the item is declared to the target languages: `#[no_mangle]` functions with a C
+/// ABI, `#[repr(C)]` structs and non-generic type aliases.
+fn is_exported(item: &ast::Item) -> bool {
+    match item.node {
+        ast::ItemKind::Fn(_, _, _, abi, _, _) => {
+            is_extern(abi) && parse_attr(&item.attrs, check_no_mangle, |_| None).0
+        }
+        ast::ItemKind::Struct(..) => parse_attr(&item.attrs, check_repr_c, |_| None).0,
+        ast::ItemKind::Ty(_, ref generics) => !generics.is_parameterized(),
+        _ => false,
+    }
+}
+
+/// Add the types of the parameters and the return type of a function to `types`.
+fn fn_decl_types<'a>(fn_decl: &'a ast::FnDecl, types: &mut Vec<&'a ast::Ty>) {
+    types.extend(fn_decl.inputs.iter().map(|arg| &*arg.ty));
+    if let ast::FunctionRetTy::Ty(ref ty) = fn_decl.output {
+        types.push(ty);
+    }
+}
+
+/// Returns the first type found in `ty` for which `check` returns a message: `ty` itself, its
+/// generic arguments, the types of arrays, tuples and function pointers, and the pointees (if
+/// `pointees` is set).
+fn find_type<'a, F>(ty: &'a ast::Ty, pointees: bool, check: &F) -> Option<(&'a ast::Ty, String)>
+where
+    F: Fn(&ast::Ty) -> Option<String>,
+{
+    if let Some(message) = check(ty) {
+        return Some((ty, message));
+    }
+
+    let mut nested = Vec::new();
+    match ty.node {
+        ast::TyKind::Ptr(ref ptr) | ast::TyKind::Rptr(_, ref ptr) if pointees => {
+            nested.push(&*ptr.ty)
+        }
+        ast::TyKind::Path(_, ref path) => {
+            for segment in &path.segments {
+                if let Some(ref parameters) = segment.parameters {
+                    if let ast::PathParameters::AngleBracketed(ref data) = **parameters {
+                        nested.extend(data.types.iter().map(|ty| &**ty));
+                    }
+                }
+            }
+        }
+        ast::TyKind::Array(ref ty, _) | ast::TyKind::Paren(ref ty) => nested.push(ty),
+        ast::TyKind::Tup(ref tys) => nested.extend(tys.iter().map(|ty| &**ty)),
+        ast::TyKind::BareFn(ref bare_fn) => fn_decl_types(&bare_fn.decl, &mut nested),
+        _ => {}
+    }
+
+    nested
+        .into_iter()
+        .filter_map(|ty| find_type(ty, pointees, check))
+        .next()
+}
+
+/// Checks whether `ty` is a pointer to an unsized type.
+fn unsized_pointer(ty: &ast::Ty) -> Option<String> {
+    let pointee = match ty.node {
+        ast::TyKind::Ptr(ref ptr) | ast::TyKind::Rptr(_, ref ptr) => &*ptr.ty,
+        _ => return None,
+    };
+
+    let suggestion = match pointee.node {
+        ast::TyKind::Slice(..) => {
+            "pass a pointer to the first element and a `usize` length instead (see \
+             `#[cheddar(slice(ptr = \"..\", len = \"..\"))]`)"
+        }
+        ast::TyKind::Path(None, ref path)
+            if path.segments.len() == 1 && path.segments[0].identifier.name == "str" =>
+        {
+            "pass a `*const c_char` to a nul-terminated string, or a `*const u8` pointer and a \
+             `usize` length instead"
+        }
+        _ => {
+            let pointee = pprust::ty_to_string(pointee);
+            if !pointee.starts_with("dyn ") && !pointee.contains('+') {
+                return None;
+            }
+            "pass an opaque handle instead (e.g. a pointer to a struct owning a `Box` of the \
+             trait object)"
+        }
+    };
+
+    Some(format!(
+        "is a fat pointer to an unsized type, which can not be represented in C; {}",
+        suggestion
+    ))
+}
+
+/// Checks whether `ty` is a standard library type.
+fn std_type(ty: &ast::Ty) -> Option<String> {
+    let segment = match ty.node {
+        ast::TyKind::Path(_, ref path) => path.segments.last()?,
+        _ => return None,
+    };
+
+    std_type_alternative(&segment.identifier.name.as_str()).map(|alternative| {
+        format!(
+            "is a Rust standard library type, which can not be represented in C; {}",
+            alternative
+        )
+    })
+}
+
+/// Checks whether `ty` is a 128-bit integer.
+fn int128(ty: &ast::Ty) -> Option<String> {
+    match ty.node {
+        ast::TyKind::Path(None, ref path)
+            if path.segments.len() == 1
+                && (path.segments[0].identifier.name == "i128"
+                    || path.segments[0].identifier.name == "u128") =>
+        {
+            Some(
+                "is a 128-bit integer, which has no standard C equivalent (nor a stable ABI); \
+                 split it into two 64-bit integers or pass it as a `[u8; 16]` array instead"
+                    .to_string(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether `ty` is Rust's `char`.
+fn char_type(ty: &ast::Ty) -> Option<String> {
+    match ty.node {
+        ast::TyKind::Path(None, ref path)
+            if path.segments.len() == 1 && path.segments[0].identifier.name == "char" =>
+        {
+            Some(
+                "is a 32-bit Unicode scalar value, unlike C's `char`; use `u32` (or `c_char` for \
+                 a byte) instead"
+                    .to_string(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Turn a method of an inherent `impl` block into a free function item, so that
+/// `impl Foo { #[no_mangle] pub extern "C" fn foo_new() {} }` is exported like a top-level
+/// function.
+///
+/// `Self` is replaced with the type of the block, and exported methods taking `self` are
+/// rejected. Associated constants become constants prefixed with the type name (e.g. `Foo_BAR`)
+/// when enabled by the config.
+fn impl_item_to_item(
+    impl_item: &ast::ImplItem,
+    self_ty: &ast::Ty,
+    config: &Config,
+) -> Option<Result<ast::Item, Error>> {
+    let node = match impl_item.node {
+        ast::ImplItemKind::Method(ref sig, ref body) => {
+            let exported = is_extern(sig.abi)
+                && impl_item.attrs.iter().any(|attr| {
+                    check_no_mangle(attr) || attr.name() == "export_name"
+                });
+            if exported && sig.decl.has_self() {
+                return Some(Err(Error {
+                    level: Level::Error,
+                    span: Some(impl_item.span),
+                    message: format!(
+                        "`{}::{}` takes `self`, which can not be passed from C",
+                        pprust::ty_to_string(self_ty),
+                        impl_item.ident.name.as_str()
+                    ),
+                    help: Some(format!(
+                        "take a pointer instead (e.g. `this: *const {}`)",
+                        pprust::ty_to_string(self_ty)
+                    )),
+                }));
+            }
+
+            let mut replace_self = ReplaceSelf { self_ty: self_ty };
+            ast::ItemKind::Fn(
+                replace_self.fold_fn_decl(sig.decl.clone()),
+                sig.unsafety,
+                sig.constness,
+                sig.abi,
+                sig.generics.clone(),
+                body.clone(),
+            )
+        }
+        ast::ImplItemKind::Const(ref ty, ref expr) if config.export_assoc_consts => {
+            let node = ast::ItemKind::Const(ty.clone(), expr.clone());
+            return Some(Ok(ast::Item {
+                ident: ast::Ident::from_str(&format!(
+                    "{}_{}",
+                    pprust::ty_to_string(self_ty),
+                    impl_item.ident.name.as_str()
+                )),
+                attrs: impl_item.attrs.clone(),
+                id: impl_item.id,
+                node: node,
+                vis: impl_item.vis.clone(),
+                span: impl_item.span,
+            }));
+        }
+        _ => return None,
+    };
+
+    // The declared name has to be the exported symbol, so the methods are only prefixed when
+    // exported under the prefixed name (`#[export_name = "Foo_bar"]`), and then declared as if
+    // they were `#[no_mangle] fn Foo_bar()`.
+    let mut ident = impl_item.ident;
+    let mut attrs = impl_item.attrs.clone();
+    if config.prefix_impl_fns {
+        let prefixed = format!(
+            "{}_{}",
+            pprust::ty_to_string(self_ty),
+            impl_item.ident.name.as_str()
+        );
+        let export_name = attrs.iter_mut().find(|attr| {
+            attr.name() == "export_name"
+                && attr.value_str().map_or(false, |name| *name.as_str() == *prefixed)
+        });
+        if let Some(attr) = export_name {
+            attr.value = ast::MetaItem {
+                name: Symbol::intern("no_mangle"),
+                node: ast::MetaItemKind::Word,
+                span: attr.value.span,
+            };
+            ident = ast::Ident::from_str(&prefixed);
+        }
+    }
+
+    Some(Ok(ast::Item {
+        ident: ident,
+        attrs: attrs,
+        id: impl_item.id,
+        node: node,
+        vis: impl_item.vis.clone(),
+        span: impl_item.span,
+    }))
+}
+
+/// Replaces `Self` with the type of the `impl` block.
+struct ReplaceSelf<'a> {
+    self_ty: &'a ast::Ty,
+}
+
+impl<'a> Folder for ReplaceSelf<'a> {
+    fn fold_ty(&mut self, ty: P<ast::Ty>) -> P<ast::Ty> {
+        match ty.node {
+            ast::TyKind::Path(None, ref path)
+                if path.segments.len() == 1 && path.segments[0].parameters.is_none()
+                    && path.segments[0].identifier.name == "Self" =>
+            {
+                return P(self.self_ty.clone())
+            }
+            _ => {}
+        }
+
+        fold::noop_fold_ty(ty, self)
+    }
+}
@@ -0,0 +1,127 @@
+//! Timings and statistics of the generation, collected when profiling is enabled.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use syntax::ast;
+
+/// Time spent in each phase of the generation and number of items visited.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// Reading and parsing the source files.
+    pub parsing: Duration,
+    /// Collecting the information about the whole crate (thread safety, reachable types).
+    pub resolving: Duration,
+    /// Writing the output files.
+    pub writing: Duration,
+    /// Timings of each target language, by name.
+    pub backends: BTreeMap<&'static str, BackendProfile>,
+    /// Number of items of each kind (e.g. `"functions"`) in the parsed modules.
+    pub items: BTreeMap<&'static str, usize>,
+}
+
+/// Time spent by a target language.
+#[derive(Clone, Debug, Default)]
+pub struct BackendProfile {
+    /// Translating the items of the modules.
+    pub translating: Duration,
+    /// Finalising the outputs (including the banner and the post-processing hooks).
+    pub emitting: Duration,
+    /// Number of files generated.
+    pub files: usize,
+}
+
+impl Profile {
+    /// Count the items of a module by kind.
+    pub fn count_items(&mut self, module: &ast::Mod) {
+        for item in &module.items {
+            let kind = match item.node {
+                ast::ItemKind::Fn(..) => "functions",
+                ast::ItemKind::Struct(..) => "structs",
+                ast::ItemKind::Enum(..) => "enums",
+                ast::ItemKind::Const(..) => "constants",
+                ast::ItemKind::Ty(..) => "types",
+                ast::ItemKind::Impl(..) => "impls",
+                _ => "other",
+            };
+            *self.items.entry(kind).or_insert(0) += 1;
+        }
+    }
+
+    /// Returns the profile of a target language, adding it if missing.
+    pub fn backend(&mut self, lang: &'static str) -> &mut BackendProfile {
+        self.backends.entry(lang).or_insert_with(Default::default)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Generation profile:")?;
+        writeln!(f, "  parsing: {}", millis(self.parsing))?;
+        writeln!(f, "  resolving: {}", millis(self.resolving))?;
+        for (lang, backend) in &self.backends {
+            writeln!(
+                f,
+                "  {}: translating {}, emitting {}, {} file(s)",
+                lang,
+                millis(backend.translating),
+                millis(backend.emitting),
+                backend.files
+            )?;
+        }
+        writeln!(f, "  writing: {}", millis(self.writing))?;
+
+        let items: Vec<_> = self.items
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        writeln!(f, "  items: {}", items.join(", "))
+    }
+}
+
+/// Format a duration in milliseconds.
+fn millis(duration: Duration) -> String {
+    let millis =
+        duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0;
+    format!("{:.1} ms", millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let source = "
+            pub struct App;
+            pub enum Status { Ok }
+            pub extern \"C\" fn app_new() -> App {}
+            pub extern \"C\" fn app_free(app: App) {}
+        ";
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut profile = Profile::default();
+        profile.count_items(&krate.module);
+        profile.parsing = Duration::from_millis(12);
+        {
+            let backend = profile.backend("c");
+            backend.translating = Duration::new(1, 500_000);
+            backend.files = 1;
+        }
+
+        assert_eq!(
+            profile.to_string(),
+            "Generation profile:\n  \
+             parsing: 12.0 ms\n  \
+             resolving: 0.0 ms\n  \
+             c: translating 1000.5 ms, emitting 0.0 ms, 1 file(s)\n  \
+             writing: 0.0 ms\n  \
+             items: 1 enums, 2 functions, 1 structs\n"
+        );
+    }
+}
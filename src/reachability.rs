@@ -0,0 +1,365 @@
+//! Analysis of the types reachable from the exported functions.
+
+use bitflags::BitFlags;
+use common::{check_doc_hidden, check_no_mangle, is_extern, parse_attr};
+use config::Config;
+use instantiate::{instantiate, mangled_name};
+use parse::is_selected;
+use shim;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use syntax::abi::Abi;
+use syntax::ast;
+
+/// Settings of the pruning of the types which are not reachable from the exported functions.
+pub struct Pruning {
+    /// Types emitted even if they are not reachable.
+    pub keep: Vec<String>,
+    /// Types reachable from the exported functions (or kept), collected before parsing.
+    pub reachable: RefCell<BTreeSet<String>>,
+}
+
+/// References between the type definitions and the signatures of the exported functions.
+#[derive(Debug, Default)]
+pub struct TypeGraph {
+    /// Types referenced by the definition of each type (fields, aliased type or variants).
+    types: BTreeMap<String, BTreeSet<String>>,
+    /// Types referenced by the signature of each exported function.
+    functions: BTreeMap<String, BTreeSet<String>>,
+    /// Types of the public constants.
+    constants: BTreeSet<String>,
+    /// Types defined in the crate for which no bindings are generated (because they are private,
+    /// hidden or filtered out).
+    skipped: BTreeSet<String>,
+}
+
+/// Audit of the FFI surface: exported functions which can't be used because their signatures
+/// reference skipped types, and types which are not used by any exported function.
+#[derive(Debug, Default, PartialEq)]
+pub struct ExportReport {
+    /// Exported functions and the skipped types referenced by their signatures.
+    pub broken_functions: BTreeMap<String, BTreeSet<String>>,
+    /// Types not reachable from any exported function or constant.
+    pub unreferenced_types: BTreeSet<String>,
+}
+
+impl ExportReport {
+    pub fn is_empty(&self) -> bool {
+        self.broken_functions.is_empty() && self.unreferenced_types.is_empty()
+    }
+}
+
+impl fmt::Display for ExportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.broken_functions.is_empty() {
+            writeln!(f, "Exported functions referencing skipped types:")?;
+            for (name, types) in &self.broken_functions {
+                let types: Vec<_> = types.iter().map(|ty| ty.as_str()).collect();
+                writeln!(f, "  {}: {}", name, types.join(", "))?;
+            }
+        }
+        if !self.unreferenced_types.is_empty() {
+            writeln!(f, "Types not referenced by any exported function:")?;
+            for name in &self.unreferenced_types {
+                writeln!(f, "  {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TypeGraph {
+    /// Record the type definitions, the exported functions and the public constants of a module.
+    pub fn collect(&mut self, module: &ast::Mod, config: &Config) {
+        for item in &module.items {
+            let name = item.ident.name.as_str().to_string();
+            let mut names = BTreeSet::new();
+
+            match item.node {
+                ast::ItemKind::Const(ref ty, _) => {
+                    if let ast::Visibility::Public = item.vis {
+                        referenced_types(ty, &mut self.constants);
+                    }
+                    continue;
+                }
+                ast::ItemKind::Ty(ref ty, _) => referenced_types(ty, &mut names),
+                ast::ItemKind::Struct(ref variants, _) => {
+                    // The generic struct itself is not declared, only its instantiations.
+                    if let Some(Ok(items)) = instantiate(item) {
+                        for item in &items {
+                            let mut names = BTreeSet::new();
+                            if let ast::ItemKind::Struct(ref variants, _) = item.node {
+                                for field in variants.fields() {
+                                    referenced_types(&field.ty, &mut names);
+                                }
+                            }
+                            if is_skipped(item, config) {
+                                let _ = self.skipped.insert(item.ident.name.as_str().to_string());
+                            }
+                            let _ = self.types
+                                .insert(item.ident.name.as_str().to_string(), names);
+                        }
+                        continue;
+                    }
+
+                    for field in variants.fields() {
+                        referenced_types(&field.ty, &mut names);
+                    }
+                }
+                ast::ItemKind::Enum(ref definition, _) => {
+                    for var in &definition.variants {
+                        for field in var.node.data.fields() {
+                            referenced_types(&field.ty, &mut names);
+                        }
+                    }
+                }
+                ast::ItemKind::Mac(..) => {
+                    if let Some(Ok(flags)) = BitFlags::from_item(item) {
+                        let item = flags.to_item(item);
+                        if is_skipped(&item, config) {
+                            let _ = self.skipped.insert(flags.name.clone());
+                        }
+                        let _ = self.types.insert(flags.name, names);
+                    }
+                    continue;
+                }
+                ast::ItemKind::Fn(ref fn_decl, _, _, abi, _, _) => {
+                    if (is_exported(&item.attrs, abi) || shim::is_shim(item))
+                        && is_selected(&name, &item.attrs, config)
+                    {
+                        fn_decl_types(fn_decl, &mut names);
+                        let _ = self.functions.insert(name, names);
+                    }
+                    continue;
+                }
+                ast::ItemKind::Impl(_, _, _, None, _, ref impl_items) => {
+                    for impl_item in impl_items {
+                        if let ast::ImplItemKind::Method(ref sig, _) = impl_item.node {
+                            let name = impl_item.ident.name.as_str().to_string();
+                            if is_exported(&impl_item.attrs, sig.abi)
+                                && is_selected(&name, &impl_item.attrs, config)
+                            {
+                                let mut names = BTreeSet::new();
+                                fn_decl_types(&sig.decl, &mut names);
+                                let _ = self.functions.insert(name, names);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+
+            if is_skipped(item, config) {
+                let _ = self.skipped.insert(name.clone());
+            }
+            let _ = self.types.insert(name, names);
+        }
+    }
+
+    /// Returns the types referenced (directly or through other types) by the exported functions
+    /// or by the `keep` types.
+    pub fn reachable(&self, keep: &[String]) -> BTreeSet<String> {
+        let mut reachable = BTreeSet::new();
+        let mut pending: Vec<&String> = self.functions
+            .values()
+            .flat_map(|names| names.iter())
+            .chain(&self.constants)
+            .chain(keep)
+            .collect();
+
+        while let Some(name) = pending.pop() {
+            if !reachable.insert(name.clone()) {
+                continue;
+            }
+            if let Some(names) = self.types.get(name) {
+                pending.extend(names.iter());
+            }
+        }
+
+        reachable
+    }
+
+    /// Find the exported functions referencing skipped types and the (not skipped) types which
+    /// are not reachable from the exported functions.
+    pub fn report(&self) -> ExportReport {
+        let broken_functions = self.functions
+            .iter()
+            .filter_map(|(name, types)| {
+                let skipped: BTreeSet<_> = types.intersection(&self.skipped).cloned().collect();
+                if skipped.is_empty() {
+                    None
+                } else {
+                    Some((name.clone(), skipped))
+                }
+            })
+            .collect();
+
+        let reachable = self.reachable(&[]);
+        let unreferenced_types = self.types
+            .keys()
+            .filter(|name| !reachable.contains(*name) && !self.skipped.contains(*name))
+            .cloned()
+            .collect();
+
+        ExportReport {
+            broken_functions,
+            unreferenced_types,
+        }
+    }
+}
+
+/// Whether no bindings are generated for the type defined by `item`.
+fn is_skipped(item: &ast::Item, config: &Config) -> bool {
+    let public = match item.vis {
+        ast::Visibility::Public => true,
+        _ => false,
+    };
+
+    !public
+        || !is_selected(&item.ident.name.as_str(), &item.attrs, config)
+        || (!config.include_hidden && parse_attr(&item.attrs, check_doc_hidden, |_| None).0)
+}
+
+/// Whether a function with these attributes and ABI is exported (`#[no_mangle] extern "C"`).
+fn is_exported(attrs: &[ast::Attribute], abi: Abi) -> bool {
+    is_extern(abi) && parse_attr(attrs, check_no_mangle, |_| None).0
+}
+
+/// Add the names of the types referenced by the parameters and the return type of a function.
+fn fn_decl_types(fn_decl: &ast::FnDecl, names: &mut BTreeSet<String>) {
+    for arg in &fn_decl.inputs {
+        referenced_types(&arg.ty, names);
+    }
+    if let ast::FunctionRetTy::Ty(ref ty) = fn_decl.output {
+        referenced_types(ty, names);
+    }
+}
+
+/// Add the names of the types referenced by `ty` (including the generic arguments, pointees and
+/// the types of function pointers) to `names`.
+fn referenced_types(ty: &ast::Ty, names: &mut BTreeSet<String>) {
+    match ty.node {
+        ast::TyKind::Path(_, ref path) => {
+            for segment in &path.segments {
+                if let Some(ref parameters) = segment.parameters {
+                    if let ast::PathParameters::AngleBracketed(ref data) = **parameters {
+                        for ty in &data.types {
+                            referenced_types(ty, names);
+                        }
+                    }
+                }
+            }
+            if let Some(segment) = path.segments.last() {
+                let _ = names.insert(segment.identifier.name.as_str().to_string());
+            }
+            if let Some(name) = mangled_name(ty) {
+                let _ = names.insert(name);
+            }
+        }
+        ast::TyKind::Ptr(ref ptr) | ast::TyKind::Rptr(_, ref ptr) => {
+            referenced_types(&ptr.ty, names)
+        }
+        ast::TyKind::Slice(ref ty) | ast::TyKind::Array(ref ty, _) | ast::TyKind::Paren(ref ty) => {
+            referenced_types(ty, names)
+        }
+        ast::TyKind::Tup(ref tys) => {
+            for ty in tys {
+                referenced_types(ty, names);
+            }
+        }
+        ast::TyKind::BareFn(ref bare_fn) => fn_decl_types(&bare_fn.decl, names),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reachable() {
+        let source = "
+            #[repr(C)]
+            pub struct App { config: *const Config, cb: extern \"C\" fn(status: Status) }
+            #[repr(C)]
+            pub struct Config { name: [u8; 32] }
+            #[repr(C)]
+            pub enum Status { Ok, Failed }
+            #[repr(C)]
+            pub struct Internal { app: App }
+            #[repr(C)]
+            pub struct Cache { len: usize }
+            pub type Handle = *mut App;
+
+            #[no_mangle]
+            pub extern \"C\" fn app_new(out: *mut Option<Handle>) {}
+
+            pub fn helper(internal: Internal) {}
+        ";
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut graph = TypeGraph::default();
+        graph.collect(&krate.module, &Default::default());
+
+        let reachable = graph.reachable(&["Cache".to_string()]);
+        for name in &["App", "Config", "Status", "Handle", "Cache"] {
+            assert!(reachable.contains(*name), "{} should be reachable", name);
+        }
+        assert!(!reachable.contains("Internal"));
+    }
+
+    #[test]
+    fn report() {
+        let source = "
+            #[repr(C)]
+            pub struct App { config: *const Config }
+            #[repr(C)]
+            pub struct Config { len: usize }
+            #[repr(C)]
+            struct Internal { len: usize }
+            #[doc(hidden)]
+            #[repr(C)]
+            pub struct Hidden { len: usize }
+            #[repr(C)]
+            pub struct Cache { len: usize }
+            #[repr(C)]
+            pub struct Limits { max: usize }
+            pub const LIMITS: Limits = Limits { max: 1 };
+
+            #[no_mangle]
+            pub extern \"C\" fn app_new(config: *const Config) -> *mut App {}
+
+            #[no_mangle]
+            pub extern \"C\" fn app_debug(
+                app: *const App,
+                internal: *mut Internal,
+                hidden: Hidden,
+            ) {}
+        ";
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut graph = TypeGraph::default();
+        graph.collect(&krate.module, &Default::default());
+        let report = graph.report();
+
+        assert_eq!(
+            report.to_string(),
+            "Exported functions referencing skipped types:\n  \
+             app_debug: Hidden, Internal\n\
+             Types not referenced by any exported function:\n  \
+             Cache\n"
+        );
+    }
+}
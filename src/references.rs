@@ -0,0 +1,62 @@
+//! Translation of the references of the exported functions (`&T` and `&mut T`), both
+//! parameters and return types, into pointers (`*const T` and `*mut T`).
+
+use syntax::ast;
+use syntax::ptr::P;
+
+/// Returns the function with its references replaced by pointers, or `None` if the item isn't a
+/// function taking or returning references.
+pub fn to_pointers(item: &ast::Item) -> Option<ast::Item> {
+    match item.node {
+        ast::ItemKind::Fn(ref fn_decl, ..)
+            if fn_decl.inputs.iter().any(|arg| is_reference(&arg.ty))
+                || returns_reference(fn_decl) => {}
+        _ => return None,
+    }
+
+    let mut item = item.clone();
+    item.node = match item.node {
+        ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block) => {
+            let fn_decl = fn_decl.map(|mut fn_decl| {
+                for arg in &mut fn_decl.inputs {
+                    arg.ty = to_pointer(arg.ty.clone());
+                }
+                fn_decl.output = match fn_decl.output {
+                    ast::FunctionRetTy::Ty(ty) => ast::FunctionRetTy::Ty(to_pointer(ty)),
+                    output => output,
+                };
+                fn_decl
+            });
+            ast::ItemKind::Fn(fn_decl, unsafety, constness, abi, generics, block)
+        }
+        node => node,
+    };
+
+    Some(item)
+}
+
+/// Whether the type is a reference (`&T` or `&mut T`).
+pub fn is_reference(ty: &ast::Ty) -> bool {
+    match ty.node {
+        ast::TyKind::Rptr(..) => true,
+        _ => false,
+    }
+}
+
+/// Whether the function returns a reference (`&T` or `&mut T`).
+pub fn returns_reference(fn_decl: &ast::FnDecl) -> bool {
+    match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) => is_reference(ty),
+        ast::FunctionRetTy::Default(..) => false,
+    }
+}
+
+fn to_pointer(ty: P<ast::Ty>) -> P<ast::Ty> {
+    ty.map(|mut ty| {
+        ty.node = match ty.node {
+            ast::TyKind::Rptr(_, mut_ty) => ast::TyKind::Ptr(mut_ty),
+            node => node,
+        };
+        ty
+    })
+}
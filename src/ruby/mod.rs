@@ -0,0 +1,600 @@
+//! Generation of Ruby bindings using the `ffi` gem.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, extern_fn, literal_const, repr_c_enum,
+    repr_c_struct, repr_layout, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use inflector::Inflector;
+use lang_c::option_inner;
+use std::collections::HashSet;
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "  ";
+
+/// Generates a `<lib_name>.rb` file declaring a module which extends `FFI::Library`, with
+/// `attach_function` declarations of the functions, `FFI::Struct` layouts of the structs and
+/// enum maps.
+pub struct LangRuby {
+    lib_name: String,
+    module: Option<String>,
+    /// Names of the structs declared so far, passed `by_ref`/`by_value`.
+    structs: HashSet<String>,
+}
+
+impl LangRuby {
+    pub fn new() -> Self {
+        LangRuby {
+            lib_name: "backend".to_owned(),
+            module: None,
+            structs: HashSet::new(),
+        }
+    }
+
+    /// Set the name of the native library loaded with `ffi_lib`.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    /// Set the name of the generated module. Defaults to the name of the native library in
+    /// PascalCase.
+    pub fn set_module<T: Into<String>>(&mut self, module: T) {
+        self.module = Some(module.into());
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}.rb", self.lib_name)
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &self.output_name(), outputs);
+    }
+
+    /// Convert a Rust type into an FFI type, declaring the callbacks it uses (named after
+    /// `assoc`) into `callbacks`.
+    fn rust_to_ruby(
+        &self,
+        ty: &ast::Ty,
+        assoc: &str,
+        callbacks: &mut String,
+    ) -> Result<String, Error> {
+        // Nullable pointers map to plain pointers.
+        if let Some(inner) = option_inner(ty) {
+            return self.rust_to_ruby(inner, assoc, callbacks);
+        }
+
+        match ty.node {
+            ast::TyKind::BareFn(ref bare_fn) => {
+                let name = assoc.to_snake_case();
+                let (args, output) = self.fn_decl_to_ruby(&bare_fn.decl, &name, callbacks)?;
+                callbacks.push_str(&format!(
+                    "{}callback :{}, [{}], {}\n",
+                    INDENT, name, args, output
+                ));
+                Ok(format!(":{}", name))
+            }
+            ast::TyKind::Array(ref inner, ref len) => Ok(format!(
+                "[{}, {}]",
+                self.rust_to_ruby(inner, assoc, callbacks)?,
+                pprust::expr_to_string(len)
+            )),
+            ast::TyKind::Ptr(ref ptr) => match ptr.ty.node {
+                ast::TyKind::Path(None, ref path) => {
+                    let name = path_name(path);
+                    if self.structs.contains(&name) {
+                        Ok(format!("{}.by_ref", name))
+                    } else if name == "c_char" && ptr.mutbl == ast::Mutability::Immutable {
+                        Ok(":string".to_owned())
+                    } else {
+                        Ok(":pointer".to_owned())
+                    }
+                }
+                _ => Ok(":pointer".to_owned()),
+            },
+            ast::TyKind::Path(None, ref path) => self.path_to_ruby(path),
+            _ => {
+                let new_type = pprust::ty_to_string(ty);
+                if new_type == "()" {
+                    Ok(":void".to_owned())
+                } else {
+                    Err(Error {
+                        level: Level::Error,
+                        span: Some(ty.span),
+                        message: format!("bindgen can not handle the type `{}`", new_type),
+                        help: None,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Convert the parameters and the return type of a function into FFI types (e.g.
+    /// `:int32, :pointer` and `:void`).
+    fn fn_decl_to_ruby(
+        &self,
+        fn_decl: &ast::FnDecl,
+        name: &str,
+        callbacks: &mut String,
+    ) -> Result<(String, String), Error> {
+        let mut args = Vec::new();
+        for arg in &fn_decl.inputs {
+            let arg_name = pprust::pat_to_string(&arg.pat);
+            args.push(self.rust_to_ruby(
+                &arg.ty,
+                &format!("{}_{}", name, arg_name),
+                callbacks,
+            )?);
+        }
+
+        let output = match fn_decl.output {
+            ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: "panics across a C boundary are naughty!".into(),
+                    help: None,
+                });
+            }
+            ast::FunctionRetTy::Default(..) => ":void".to_owned(),
+            ast::FunctionRetTy::Ty(ref ty) => {
+                self.rust_to_ruby(ty, &format!("{}_result", name), callbacks)?
+            }
+        };
+
+        Ok((args.join(", "), output))
+    }
+
+    /// Convert a Rust path type into an FFI type, supporting the types of `libc` and
+    /// `std::os::raw`.
+    fn path_to_ruby(&self, path: &ast::Path) -> Result<String, Error> {
+        let module: Vec<_> = match path.segments.split_last() {
+            Some((_, module)) => module
+                .iter()
+                .map(|segment| segment.identifier.name.as_str().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        match &*module.join("::") {
+            "" | "libc" | "std::os::raw" => {}
+            _ => {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(path.span),
+                    message: "can not handle types in other modules (except `libc` and \
+                              `std::os::raw`)"
+                        .into(),
+                    help: None,
+                })
+            }
+        }
+
+        let name = path_name(path);
+        let ty = match &*name {
+            "c_void" => "void",
+            "i8" | "c_schar" => "int8",
+            "i16" => "int16",
+            "i32" => "int32",
+            "i64" => "int64",
+            "isize" => "intptr_t",
+            "u8" | "c_uchar" => "uint8",
+            "u16" => "uint16",
+            "u32" => "uint32",
+            "u64" => "uint64",
+            "usize" => "uintptr_t",
+            "f32" | "c_float" => "float",
+            "f64" | "c_double" => "double",
+            "bool" => "bool",
+            "c_char" => "char",
+            "c_short" => "short",
+            "c_ushort" => "ushort",
+            "c_int" => "int",
+            "c_uint" => "uint",
+            "c_long" => "long",
+            "c_ulong" => "ulong",
+            "c_longlong" => "long_long",
+            "c_ulonglong" => "ulong_long",
+            // Structs are passed by value, other user-defined types are registered as types.
+            _ if self.structs.contains(&name) => return Ok(format!("{}.by_value", name)),
+            _ => return Ok(format!(":{}", name)),
+        };
+
+        Ok(format!(":{}", ty))
+    }
+}
+
+impl Default for LangRuby {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangRuby {
+    fn name(&self) -> &'static str {
+        "ruby"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into a Ruby constant.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let value = match literal_const(item)? {
+            Some(constant) => constant.value,
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!(
+            "{}{} = {}\n\n",
+            INDENT,
+            constant_name(&item.ident.name.as_str()),
+            value
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `typedef :b, :A`, or a `callback` for function pointers.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let ty = match alias_type(item)? {
+            Some(ty) => ty,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        let mut callbacks = String::new();
+
+        match ty.node {
+            ast::TyKind::BareFn(ref bare_fn) => {
+                let (args, output) = self.fn_decl_to_ruby(&bare_fn.decl, &name, &mut callbacks)?;
+                buffer.push_str(&callbacks);
+                buffer.push_str(&format!(
+                    "{}callback :{}, [{}], {}\n",
+                    INDENT, name, args, output
+                ));
+            }
+            _ => {
+                let ty = self.rust_to_ruby(ty, &name, &mut callbacks)?;
+                buffer.push_str(&callbacks);
+                buffer.push_str(&format!("{}typedef {}, :{}\n", INDENT, ty, name));
+            }
+        }
+
+        buffer.push('\n');
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into an FFI enum.
+    ///
+    /// Enums with data-carrying variants are skipped, so they can only be used through pointers.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (variants, values) = match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => (variants, values),
+            Some(ReprCEnum::Opaque) | None => return Ok(()),
+        };
+
+        let variants: Vec<_> = variants
+            .iter()
+            .zip(&values)
+            .map(|(var, &(ref var_name, value))| {
+                format!(
+                    "{}{}:{}, {}",
+                    doc_comments(&var.node.attrs, &INDENT.repeat(2), "# "),
+                    INDENT.repeat(2),
+                    var_name,
+                    value
+                )
+            })
+            .collect();
+
+        let mut buffer = doc_comments(&item.attrs, INDENT, "# ");
+        buffer.push_str(&format!(
+            "{0}enum :{1}, [\n{2}\n{0}]\n\n",
+            INDENT,
+            item.ident.name.as_str(),
+            variants.join(",\n")
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into an `FFI::Struct` with the same layout.
+    ///
+    /// Tuple structs with a single field are skipped, so they can only be used through pointers.
+    /// `#[repr(packed)]` and `#[repr(align(N))]` are kept with `pack` and `aligned`.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let struct_fields = match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => fields,
+            Some(ReprCStruct::Opaque) | None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str().to_string();
+        let mut callbacks = String::new();
+        let mut fields = Vec::new();
+
+        for &(ref field_name, field) in &struct_fields {
+            let ty = self.rust_to_ruby(
+                &field.ty,
+                &format!("{}_{}", name, field_name),
+                &mut callbacks,
+            )?;
+            fields.push(format!(
+                "{}{}:{}, {}",
+                doc_comments(&field.attrs, &INDENT.repeat(3), "# "),
+                INDENT.repeat(3),
+                field_name,
+                ty
+            ));
+        }
+
+        let mut buffer = callbacks;
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&doc_comments(&item.attrs, INDENT, "# "));
+        buffer.push_str(&format!("{}class {} < FFI::Struct\n", INDENT, name));
+        let layout = repr_layout(&item.attrs);
+        if let Some(packed) = layout.packed {
+            buffer.push_str(&format!("{0}{0}pack {1}\n", INDENT, packed));
+        }
+        if let Some(align) = layout.align {
+            buffer.push_str(&format!("{0}{0}aligned {1}\n", INDENT, align));
+        }
+        if !fields.is_empty() {
+            buffer.push_str(&format!(
+                "{0}{0}layout(\n{1}\n{0}{0})\n",
+                INDENT,
+                fields.join(",\n")
+            ));
+        }
+        buffer.push_str(&format!("{}end\n\n", INDENT));
+        self.append(buffer, outputs);
+
+        let _ = self.structs.insert(name);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into a `typedef` of the underlying integer type and a
+    /// constant for every flag (e.g. `Flags_READ`).
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut callbacks = String::new();
+        let ty = self.rust_to_ruby(&flags.ty, &flags.name, &mut callbacks)?;
+
+        let mut buffer = doc_comments(&flags.attrs, INDENT, "# ");
+        buffer.push_str(&format!("{}typedef {}, :{}\n\n", INDENT, ty, flags.name));
+
+        for flag in &flags.flags {
+            buffer.push_str(&doc_comments(&flag.attrs, INDENT, "# "));
+            buffer.push_str(&format!(
+                "{}{} = {:#x}\n",
+                INDENT,
+                constant_name(&format!("{}_{}", flags.name, flag.name)),
+                flag.value
+            ));
+        }
+
+        buffer.push('\n');
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into an `attach_function` declaration.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let fn_decl = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl,
+            None => return Ok(()),
+        };
+
+        let name = item.ident.name.as_str();
+        let mut callbacks = String::new();
+        let (args, output) = self.fn_decl_to_ruby(fn_decl, &name, &mut callbacks)?;
+
+        let mut buffer = callbacks;
+        buffer.push_str(&doc_comments(&item.attrs, INDENT, "# "));
+        buffer.push_str(&format!(
+            "{}attach_function :{}, [{}], {}\n\n",
+            INDENT, name, args, output
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let body = outputs.remove(&self.output_name()).unwrap_or_default();
+        let module = self.module
+            .clone()
+            .unwrap_or_else(|| self.lib_name.to_pascal_case());
+
+        let _ = outputs.insert(
+            self.output_name(),
+            format!(
+                "require 'ffi'\n\n\
+                 module {0}\n\
+                 {1}extend FFI::Library\n\
+                 {1}ffi_lib '{2}'\n\n\
+                 {3}\
+                 end\n",
+                module, INDENT, self.lib_name, body
+            ),
+        );
+
+        self.structs.clear();
+
+        Ok(())
+    }
+}
+
+/// The last segment of a path (e.g. `c_int` for `libc::c_int`).
+fn path_name(path: &ast::Path) -> String {
+    path.segments
+        .last()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .unwrap_or_default()
+}
+
+/// Ruby constants have to start with an uppercase letter.
+fn constant_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            pub type Id = u64;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = 2,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                /// Vertical.
+                y: i32,
+                tags: [u8; 4],
+            }
+
+            bitflags! {
+                pub struct Flags: u32 {
+                    const READ = 1;
+                }
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_name(
+                point: *const Point,
+                user_data: *mut c_void,
+                cb: extern \"C\" fn(user_data: *mut c_void, name: *const c_char, status: Status),
+            ) -> bool {}
+        ";
+
+        let mut lang = LangRuby::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.rb"],
+            "require 'ffi'\n\n\
+             module SafeApp\n  \
+             extend FFI::Library\n  \
+             ffi_lib 'safe_app'\n\n  \
+             # Maximum length.\n  \
+             MAX_LEN = 16\n\n  \
+             typedef :uint64, :Id\n\n  \
+             enum :Status, [\n    \
+             # Success.\n    \
+             :Ok, 0,\n    \
+             :Failed, 2\n  \
+             ]\n\n  \
+             class Point < FFI::Struct\n    \
+             layout(\n      \
+             :x, :int32,\n      \
+             # Vertical.\n      \
+             :y, :int32,\n      \
+             :tags, [:uint8, 4]\n    \
+             )\n  \
+             end\n\n  \
+             typedef :uint32, :Flags\n\n  \
+             Flags_READ = 0x1\n\n  \
+             callback :point_name_cb, [:pointer, :string, :Status], :void\n  \
+             attach_function :point_name, [Point.by_ref, :pointer, :point_name_cb], :bool\n\n\
+             end\n"
+        );
+    }
+
+    #[test]
+    fn struct_layout() {
+        let source = "
+            #[repr(C, packed)]
+            pub struct Packed {
+                tag: u8,
+                value: u32,
+            }
+
+            #[repr(C, align(16))]
+            pub struct Aligned {
+                tag: u8,
+            }
+        ";
+
+        let mut lang = LangRuby::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.rb"],
+            "require 'ffi'\n\n\
+             module SafeApp\n  \
+             extend FFI::Library\n  \
+             ffi_lib 'safe_app'\n\n  \
+             class Packed < FFI::Struct\n    \
+             pack 1\n    \
+             layout(\n      \
+             :tag, :uint8,\n      \
+             :value, :uint32\n    \
+             )\n  \
+             end\n\n  \
+             class Aligned < FFI::Struct\n    \
+             aligned 16\n    \
+             layout(\n      \
+             :tag, :uint8\n    \
+             )\n  \
+             end\n\n\
+             end\n"
+        );
+    }
+}
@@ -0,0 +1,518 @@
+//! Generation of `extern "C"` shims for idiomatic Rust functions marked `#[cheddar(shim)]` or
+//! `#[cheddar(catch_panic = "ERR_PANICKED")]`.
+//!
+//! The shims convert the parameters which can not cross the FFI boundary:
+//!
+//! - `&str` is passed as a nul-terminated `*const c_char` (invalid UTF-8 is replaced),
+//! - `&[T]` and `&mut [T]` are passed as a pointer and a `<name>_len` length,
+//! - a `Result<T, E>` is returned as an `i32` error code (`0` on success, `i32::from(error)`
+//!   otherwise), with the value written into the `o_result` out-parameter. Panics are caught
+//!   and reported as `-1`, or as the `catch_panic` code, and null strings or `o_result` as
+//!   `-2`. Only the functions returning a `Result` can take a `&str`.
+//!
+//! All other parameter and return types are passed through unchanged. Panics in functions
+//! returning such a type are only caught with `catch_panic`, in which case the code is returned
+//! instead of the value.
+//!
+//! The shims are named `<function>_shim` in Rust, so they can be included next to the original
+//! functions, and exported under the name of the function.
+
+use common::{check_no_mangle, has_cheddar_flag, parse_attr, retrieve_catch_panic};
+use syntax::ast;
+use syntax::parse::{self, ParseSess};
+use syntax::print::pprust;
+use Error;
+use Level;
+
+/// Error code returned by the shims of `Result`-returning functions when a panic is caught.
+const PANIC_CODE: &str = "-1";
+
+/// Error code returned by the shims of `Result`-returning functions when a string or the
+/// `o_result` pointer is null.
+const NULL_CODE: &str = "-2";
+
+/// A generated shim.
+pub struct Shim {
+    /// Declaration of the shim, exported in place of the original function.
+    pub item: ast::Item,
+    /// Rust source of the shim.
+    pub source: String,
+}
+
+/// Check whether a shim should be generated for the item.
+pub fn is_shim(item: &ast::Item) -> bool {
+    match (&item.vis, &item.node) {
+        (&ast::Visibility::Public, &ast::ItemKind::Fn(..)) => {
+            let (shim, panic_code) = parse_attr(
+                &item.attrs,
+                |attr| has_cheddar_flag(attr, "shim"),
+                retrieve_catch_panic,
+            );
+            shim || !panic_code.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Generate the shim of a function declared in `module_path`.
+pub fn generate(item: &ast::Item, module_path: &[String]) -> Result<Shim, Error> {
+    let decl = match item.node {
+        ast::ItemKind::Fn(ref decl, _, _, _, ref generics, _) => {
+            if generics.is_parameterized() {
+                return Err(Error {
+                    level: Level::Error,
+                    span: Some(item.span),
+                    message: "bindgen can not generate shims for generic functions".into(),
+                    help: None,
+                });
+            }
+            decl
+        }
+        _ => {
+            return Err(Error {
+                level: Level::Bug,
+                span: Some(item.span),
+                message: "`shim::generate` called on wrong `Item_`".into(),
+                help: None,
+            })
+        }
+    };
+
+    let (_, panic_code) = parse_attr(&item.attrs, |_| false, retrieve_catch_panic);
+    let catch_panic = !panic_code.is_empty();
+
+    // The wrapper is exported under the name of the function.
+    if catch_panic && parse_attr(&item.attrs, check_no_mangle, |_| None).0 {
+        return Err(Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: "functions marked `#[cheddar(catch_panic)]` are exported through their \
+                      generated wrapper and can not be `#[no_mangle]`"
+                .into(),
+            help: None,
+        });
+    }
+
+    let name = item.ident.name.as_str();
+    let mut params = Vec::new();
+    let mut null_checks = Vec::new();
+    let mut conversions = Vec::new();
+    let mut args = Vec::new();
+
+    for arg in &decl.inputs {
+        let arg_name = pprust::pat_to_string(&arg.pat);
+
+        match param_kind(&arg.ty) {
+            ParamKind::Str => {
+                params.push(format!("{}: *const ::std::os::raw::c_char", arg_name));
+                null_checks.push((arg_name.clone(), arg.ty.span));
+                conversions.push(format!(
+                    "let {0} = ::std::ffi::CStr::from_ptr({0}).to_string_lossy();",
+                    arg_name
+                ));
+                args.push(format!("&{}", arg_name));
+            }
+            ParamKind::Slice(ty, mutable) => {
+                let ty = pprust::ty_to_string(ty);
+                let (pointer, reference, from_raw_parts) = if mutable {
+                    ("*mut", "&mut ", "from_raw_parts_mut")
+                } else {
+                    ("*const", "&", "from_raw_parts")
+                };
+
+                params.push(format!("{}: {} {}", arg_name, pointer, ty));
+                params.push(format!("{}_len: usize", arg_name));
+                // `from_raw_parts` requires a non-null pointer, even for empty slices.
+                conversions.push(format!(
+                    "let {0}: {1}[{2}] = if {0}_len == 0 {{ {1}[] }} else {{ \
+                     ::std::slice::{3}({0}, {0}_len) }};",
+                    arg_name, reference, ty, from_raw_parts
+                ));
+                args.push(arg_name);
+            }
+            ParamKind::Value => {
+                params.push(format!("{}: {}", arg_name, pprust::ty_to_string(&arg.ty)));
+                args.push(arg_name);
+            }
+        }
+    }
+
+    let call = format!("{}{}({})", fn_path(module_path), name, args.join(", "));
+
+    let result = match decl.output {
+        ast::FunctionRetTy::Ty(ref ty) => result_inner(ty),
+        ast::FunctionRetTy::Default(..) => None,
+    };
+    if let (None, Some(&(_, span))) = (result, null_checks.first()) {
+        return Err(Error {
+            level: Level::Error,
+            span: Some(span),
+            message: "the shims of functions taking a `&str` have to return a `Result`, to report \
+                      null pointers"
+                .into(),
+            help: None,
+        });
+    }
+
+    let (ret, body) = match decl.output {
+        ast::FunctionRetTy::Ty(ref ty) => match result {
+            Some(ok) => {
+                let success = if is_unit(ok) {
+                    "Ok(Ok(())) => 0,".to_string()
+                } else {
+                    params.push(format!("o_result: *mut {}", pprust::ty_to_string(ok)));
+                    null_checks.push(("o_result".to_string(), ty.span));
+                    "Ok(Ok(value)) => {\n            *o_result = value;\n            0\n        }"
+                        .to_string()
+                };
+                let panicked = if catch_panic {
+                    panic_code.as_str()
+                } else {
+                    PANIC_CODE
+                };
+
+                let arms = [
+                    success,
+                    "Ok(Err(error)) => i32::from(error),".to_string(),
+                    format!("Err(_) => {},", panicked),
+                ];
+                let mut body = String::new();
+                for &(ref pointer, _) in &null_checks {
+                    body.push_str(&format!(
+                        "    if {}.is_null() {{\n        return {};\n    }}\n",
+                        pointer, NULL_CODE
+                    ));
+                }
+                body.push_str(&caught_body(&conversions, &call, &arms));
+                (" -> i32".to_string(), body)
+            }
+            None if catch_panic => {
+                let arms = [
+                    "Ok(value) => value,".to_string(),
+                    format!("Err(_) => {},", panic_code),
+                ];
+                (
+                    format!(" -> {}", pprust::ty_to_string(ty)),
+                    caught_body(&conversions, &call, &arms),
+                )
+            }
+            None => (
+                format!(" -> {}", pprust::ty_to_string(ty)),
+                plain_body(&conversions, &call),
+            ),
+        },
+        ast::FunctionRetTy::Default(..) if catch_panic => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(item.span),
+                message: "`#[cheddar(catch_panic)]` requires a function returning an error code"
+                    .into(),
+                help: None,
+            });
+        }
+        ast::FunctionRetTy::Default(..) => (String::new(), plain_body(&conversions, &call)),
+    };
+
+    let mut source = String::new();
+    // The shim is compiled for the same targets and features as the function it calls.
+    for attr in item.attrs.iter().filter(|attr| attr.name() == "doc" || attr.name() == "cfg") {
+        source.push_str(&format!("{}\n", pprust::attr_to_string(attr)));
+    }
+    // A distinct name in Rust, so the shim neither clashes with nor calls the function.
+    source.push_str(&format!(
+        "#[export_name = \"{0}\"]\npub unsafe extern \"C\" fn {0}_shim({1}){2} {{\n{3}}}\n\n",
+        name,
+        params.join(", "),
+        ret,
+        body
+    ));
+
+    // The declaration of the exported symbol.
+    let declaration = format!(
+        "#[no_mangle]\npub unsafe extern \"C\" fn {}({}){} {{}}",
+        name,
+        params.join(", "),
+        ret
+    );
+    let session = ParseSess::new();
+    let parsed = match parse::parse_item_from_source_str(
+        "shim".to_string(),
+        declaration,
+        &session,
+    ) {
+        Ok(parsed) => parsed,
+        Err(mut err) => {
+            err.cancel();
+            None
+        }
+    };
+    let mut shim = match parsed {
+        Some(parsed) => (*parsed).clone(),
+        None => {
+            return Err(Error {
+                level: Level::Bug,
+                span: Some(item.span),
+                message: "bindgen generated an invalid shim".into(),
+                help: None,
+            })
+        }
+    };
+
+    // The declaration keeps the attributes of the original function (e.g. `#[cheddar(..)]`).
+    shim.attrs.extend(item.attrs.iter().cloned());
+    shim.span = item.span;
+
+    Ok(Shim { item: shim, source })
+}
+
+enum ParamKind<'a> {
+    /// `&str`.
+    Str,
+    /// `&[T]` or `&mut [T]` (if `true`).
+    Slice(&'a ast::Ty, bool),
+    /// Passed through unchanged.
+    Value,
+}
+
+fn param_kind(ty: &ast::Ty) -> ParamKind {
+    match ty.node {
+        ast::TyKind::Rptr(_, ref referent) => match referent.ty.node {
+            ast::TyKind::Path(None, ref path)
+                if referent.mutbl == ast::Mutability::Immutable
+                    && pprust::path_to_string(path) == "str" =>
+            {
+                ParamKind::Str
+            }
+            ast::TyKind::Slice(ref ty) => {
+                ParamKind::Slice(ty, referent.mutbl == ast::Mutability::Mutable)
+            }
+            _ => ParamKind::Value,
+        },
+        _ => ParamKind::Value,
+    }
+}
+
+/// Returns the success type of a `Result<T, E>` (or a `Result<T>` alias), or `None` if the type
+/// is not a `Result`.
+fn result_inner(ty: &ast::Ty) -> Option<&ast::Ty> {
+    let segment = match ty.node {
+        ast::TyKind::Path(None, ref path) => path.segments.last()?,
+        _ => return None,
+    };
+    if segment.identifier.name != "Result" {
+        return None;
+    }
+
+    match segment.parameters {
+        Some(ref parameters) => match **parameters {
+            ast::PathParameters::AngleBracketed(ref data)
+                if data.types.len() == 1 || data.types.len() == 2 =>
+            {
+                Some(&*data.types[0])
+            }
+            _ => None,
+        },
+        None => None,
+    }
+}
+
+fn is_unit(ty: &ast::Ty) -> bool {
+    match ty.node {
+        ast::TyKind::Tup(ref types) => types.is_empty(),
+        _ => false,
+    }
+}
+
+/// Path of the functions declared in the module (e.g. `["ffi", "app"]`, or empty for the crate
+/// root), relative to the crate root.
+fn fn_path(module_path: &[String]) -> String {
+    let mut path: String = module_path
+        .iter()
+        .map(|module| format!("::{}", module))
+        .collect();
+    path.push_str("::");
+    path
+}
+
+/// Body calling the function within `catch_unwind`, then matching its `result` with `arms`.
+fn caught_body(conversions: &[String], call: &str, arms: &[String]) -> String {
+    let mut body = String::from(
+        "    let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {\n",
+    );
+    for conversion in conversions {
+        body.push_str(&format!("        {}\n", conversion));
+    }
+    body.push_str(&format!("        {}\n    }}));\n    match result {{\n", call));
+    for arm in arms {
+        body.push_str(&format!("        {}\n", arm));
+    }
+    body.push_str("    }\n");
+    body
+}
+
+fn plain_body(conversions: &[String], call: &str) -> String {
+    let mut body = String::new();
+    for conversion in conversions {
+        body.push_str(&format!("    {}\n", conversion));
+    }
+    body.push_str(&format!("    {}\n", call));
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shim(source: &str) -> Shim {
+        unwrap!(try_shim_in(source, &["ffi", "app"]))
+    }
+
+    fn try_shim_in(source: &str, module_path: &[&str]) -> Result<Shim, Error> {
+        let session = ParseSess::new();
+        let krate = unwrap!(parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let item = &krate.module.items[0];
+        assert!(is_shim(item));
+        let module_path: Vec<_> = module_path.iter().map(|module| module.to_string()).collect();
+        generate(item, &module_path)
+    }
+
+    #[test]
+    fn result() {
+        let shim = shim(
+            "
+            /// Open a file.
+            #[cheddar(shim)]
+            pub fn open(name: &str, data: &[u8], flags: u32) -> Result<Handle, Error> {}
+            ",
+        );
+
+        assert_eq!(
+            shim.source,
+            "/// Open a file.\n\
+             #[export_name = \"open\"]\n\
+             pub unsafe extern \"C\" fn open_shim(name: *const ::std::os::raw::c_char, \
+             data: *const u8, data_len: usize, flags: u32, o_result: *mut Handle) -> i32 {\n\
+             \x20   if name.is_null() {\n\
+             \x20       return -2;\n\
+             \x20   }\n\
+             \x20   if o_result.is_null() {\n\
+             \x20       return -2;\n\
+             \x20   }\n\
+             \x20   let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {\n\
+             \x20       let name = ::std::ffi::CStr::from_ptr(name).to_string_lossy();\n\
+             \x20       let data: &[u8] = if data_len == 0 { &[] } else { \
+             ::std::slice::from_raw_parts(data, data_len) };\n\
+             \x20       ::ffi::app::open(&name, data, flags)\n\
+             \x20   }));\n\
+             \x20   match result {\n\
+             \x20       Ok(Ok(value)) => {\n\
+             \x20           *o_result = value;\n\
+             \x20           0\n\
+             \x20       }\n\
+             \x20       Ok(Err(error)) => i32::from(error),\n\
+             \x20       Err(_) => -1,\n\
+             \x20   }\n\
+             }\n\n"
+        );
+        assert_eq!(shim.item.ident.name.as_str(), "open");
+        assert!(parse_attr(&shim.item.attrs, check_no_mangle, |_| None).0);
+    }
+
+    #[test]
+    fn catch_panic() {
+        let shim = shim(
+            "
+            #[cheddar(catch_panic = \"ERR_PANICKED\")]
+            pub fn compute(value: u32) -> i32 {}
+            ",
+        );
+
+        assert_eq!(
+            shim.source,
+            "#[export_name = \"compute\"]\n\
+             pub unsafe extern \"C\" fn compute_shim(value: u32) -> i32 {\n\
+             \x20   let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {\n\
+             \x20       ::ffi::app::compute(value)\n\
+             \x20   }));\n\
+             \x20   match result {\n\
+             \x20       Ok(value) => value,\n\
+             \x20       Err(_) => ERR_PANICKED,\n\
+             \x20   }\n\
+             }\n\n"
+        );
+    }
+
+    #[test]
+    fn plain() {
+        let shim = shim(
+            "
+            #[cheddar(shim)]
+            pub fn fill(buffer: &mut [u8]) {}
+            ",
+        );
+
+        assert_eq!(
+            shim.source,
+            "#[export_name = \"fill\"]\n\
+             pub unsafe extern \"C\" fn fill_shim(buffer: *mut u8, buffer_len: usize) {\n\
+             \x20   let buffer: &mut [u8] = if buffer_len == 0 { &mut [] } else { \
+             ::std::slice::from_raw_parts_mut(buffer, buffer_len) };\n\
+             \x20   ::ffi::app::fill(buffer)\n\
+             }\n\n"
+        );
+    }
+
+    #[test]
+    fn module_paths() {
+        let source = "
+            #[cheddar(shim)]
+            pub fn reset(flags: u32) {}
+        ";
+
+        let shim = unwrap!(try_shim_in(source, &["api", "session"]));
+        assert!(shim.source.contains("    ::api::session::reset(flags)\n"));
+
+        // Functions of the crate root are called by the shim included next to them.
+        let shim = unwrap!(try_shim_in(source, &[]));
+        assert!(shim.source.contains("pub unsafe extern \"C\" fn reset_shim(flags: u32) {\n"));
+        assert!(shim.source.contains("    ::reset(flags)\n"));
+    }
+
+    #[test]
+    fn cfg() {
+        let shim = shim(
+            "
+            #[cfg(target_os = \"android\")]
+            #[cheddar(shim)]
+            pub fn attach(flags: u32) {}
+            ",
+        );
+
+        assert!(shim.source.starts_with(
+            "#[cfg(target_os = \"android\")]\n#[export_name = \"attach\"]\n"
+        ));
+    }
+
+    #[test]
+    fn str_without_result() {
+        let source = "
+            #[cheddar(shim)]
+            pub fn greet(name: &str) {}
+        ";
+
+        let error = match try_shim_in(source, &["ffi"]) {
+            Ok(shim) => panic!("unexpected shim: {}", shim.source),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.message,
+            "the shims of functions taking a `&str` have to return a `Result`, to report null \
+             pointers"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Target triples (e.g. `aarch64-linux-android`) and the evaluation of the `#[cfg(..)]`
+//! attributes of the items for them.
+
+use syntax::ast;
+
+/// Vendors which can appear between the architecture and the OS of a triple.
+const VENDORS: &[&str] = &["apple", "fortanix", "nvidia", "pc", "sun", "unknown", "uwp", "wrs"];
+
+/// Suffixes naming the ABI after the environment (e.g. `gnueabihf`), which `target_env` omits.
+const ABI_SUFFIXES: &[&str] = &["eabihf", "eabi", "abi64"];
+
+/// Configuration of a target, as tested by `#[cfg(target_os = "android")]` and the like.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    /// The target triple.
+    pub triple: String,
+    /// `target_arch` (e.g. `arm` for `armv7-linux-androideabi`).
+    pub arch: String,
+    /// `target_vendor`.
+    pub vendor: String,
+    /// `target_os`.
+    pub os: String,
+    /// `target_env` (empty if there is none).
+    pub env: String,
+    /// `target_pointer_width`.
+    pub pointer_width: u32,
+}
+
+impl Target {
+    /// Read the configuration of a target triple.
+    pub fn parse(triple: &str) -> Self {
+        let parts: Vec<_> = triple.split('-').collect();
+        let arch = match parts[0] {
+            arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+            "i386" | "i586" | "i686" => "x86",
+            arch => arch,
+        };
+
+        let rest = &parts[1..];
+        let (vendor, rest) = match rest.split_first() {
+            Some((vendor, rest)) if VENDORS.contains(vendor) && !rest.is_empty() => (*vendor, rest),
+            _ => ("unknown", rest),
+        };
+        let (os, env) = match rest.split_first() {
+            Some((&"darwin", env)) => ("macos", strip_abi(&env.join("-"))),
+            // e.g. `linux-androideabi`.
+            Some((_, env)) if env.first().map_or(false, |env| env.starts_with("android")) => {
+                ("android", String::new())
+            }
+            Some((os, env)) => (*os, strip_abi(&env.join("-"))),
+            None => ("none", String::new()),
+        };
+
+        let pointer_width = match arch {
+            "aarch64" | "mips64" | "mips64el" | "powerpc64" | "powerpc64le" | "riscv64gc"
+            | "s390x" | "sparc64" | "sparcv9" | "wasm64" | "x86_64" => 64,
+            "msp430" => 16,
+            _ => 32,
+        };
+
+        Target {
+            triple: triple.to_string(),
+            arch: arch.to_string(),
+            vendor: vendor.to_string(),
+            os: os.to_string(),
+            env,
+            pointer_width,
+        }
+    }
+
+    /// `target_family` of the target, if any.
+    fn family(&self) -> Option<&'static str> {
+        match &*self.os {
+            "windows" => Some("windows"),
+            "none" | "unknown" | "cuda" => None,
+            _ => Some("unix"),
+        }
+    }
+
+    /// Whether an item with these attributes is compiled for the target.
+    ///
+    /// Predicates which don't depend on the target (e.g. `feature = "mock"`) are assumed to hold.
+    pub fn matches(&self, attrs: &[ast::Attribute]) -> bool {
+        attrs.iter().all(|attr| match attr.value.node {
+            ast::MetaItemKind::List(ref items) if attr.name() == "cfg" && items.len() == 1 => {
+                self.eval(&items[0]) != Some(false)
+            }
+            _ => true,
+        })
+    }
+
+    /// Evaluate a predicate, or `None` if it doesn't depend on the target.
+    fn eval(&self, predicate: &ast::NestedMetaItem) -> Option<bool> {
+        let item = match predicate.node {
+            ast::NestedMetaItemKind::MetaItem(ref item) => item,
+            ast::NestedMetaItemKind::Literal(_) => return None,
+        };
+
+        match item.node {
+            ast::MetaItemKind::Word => match &*item.name.as_str() {
+                "unix" => Some(self.family() == Some("unix")),
+                "windows" => Some(self.family() == Some("windows")),
+                _ => None,
+            },
+            ast::MetaItemKind::NameValue(_) => {
+                let value = item.value_str()?.as_str().to_string();
+                match &*item.name.as_str() {
+                    "target_arch" => Some(value == self.arch),
+                    "target_vendor" => Some(value == self.vendor),
+                    "target_os" => Some(value == self.os),
+                    "target_env" => Some(value == self.env),
+                    "target_family" => Some(self.family() == Some(&*value)),
+                    "target_pointer_width" => Some(value == self.pointer_width.to_string()),
+                    _ => None,
+                }
+            }
+            ast::MetaItemKind::List(ref items) => {
+                let values: Vec<_> = items.iter().map(|item| self.eval(item)).collect();
+                match &*item.name.as_str() {
+                    "all" if values.contains(&Some(false)) => Some(false),
+                    "all" if values.iter().all(Option::is_some) => Some(true),
+                    "any" if values.contains(&Some(true)) => Some(true),
+                    "any" if values.iter().all(Option::is_some) => Some(false),
+                    "not" if values.len() == 1 => values[0].map(|value| !value),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Returns the environment of a triple without its ABI (e.g. `gnu` for `gnueabihf`).
+fn strip_abi(env: &str) -> String {
+    for suffix in ABI_SUFFIXES {
+        if env.ends_with(*suffix) {
+            return env[..env.len() - suffix.len()].to_string();
+        }
+    }
+    env.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let android = Target::parse("armv7-linux-androideabi");
+        assert_eq!(
+            (&*android.arch, &*android.os, android.pointer_width),
+            ("arm", "android", 32)
+        );
+
+        let desktop = Target::parse("x86_64-unknown-linux-gnu");
+        assert_eq!(
+            (&*desktop.arch, &*desktop.vendor, &*desktop.os, &*desktop.env),
+            ("x86_64", "unknown", "linux", "gnu")
+        );
+        assert_eq!(desktop.pointer_width, 64);
+
+        let ios = Target::parse("aarch64-apple-ios");
+        assert_eq!((&*ios.vendor, &*ios.os, &*ios.env), ("apple", "ios", ""));
+
+        let raspberry = Target::parse("armv7-unknown-linux-gnueabihf");
+        assert_eq!((&*raspberry.arch, &*raspberry.env), ("arm", "gnu"));
+
+        let embedded = Target::parse("thumbv7em-none-eabihf");
+        assert_eq!((&*embedded.os, &*embedded.env), ("none", ""));
+    }
+
+    #[test]
+    fn matches() {
+        let source = "
+            #[cfg(target_os = \"android\")]
+            pub struct Jni;
+            #[cfg(all(unix, not(target_os = \"android\")))]
+            pub struct Desktop;
+            #[cfg(any(target_pointer_width = \"64\", feature = \"large\"))]
+            pub struct Large;
+            #[cfg(feature = \"mock\")]
+            pub struct Mock;
+        ";
+        let session = ::syntax::parse::ParseSess::new();
+        let krate = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let compiled = |triple: &str| -> Vec<String> {
+            let target = Target::parse(triple);
+            krate
+                .module
+                .items
+                .iter()
+                .filter(|item| target.matches(&item.attrs))
+                .map(|item| item.ident.name.as_str().to_string())
+                .collect()
+        };
+
+        assert_eq!(compiled("armv7-linux-androideabi"), vec!["Jni", "Large", "Mock"]);
+        assert_eq!(compiled("x86_64-apple-darwin"), vec!["Desktop", "Large", "Mock"]);
+        assert_eq!(compiled("x86_64-pc-windows-msvc"), vec!["Large", "Mock"]);
+    }
+}
@@ -0,0 +1,380 @@
+//! Template-based code generation.
+//!
+//! Instead of using one of the built-in emitters, every exported item is fed into a user supplied
+//! [Tera](https://tera.netlify.com/) template, selected by the kind of the item.
+
+use common::{
+    append_output, check_no_mangle, check_repr_c, is_extern, parse_attr, wrong_item, Lang, Outputs,
+};
+use std::collections::{BTreeMap, HashMap};
+use syntax::ast;
+use syntax::print::pprust;
+use tera::{Context, Tera};
+use Error;
+use Level;
+
+/// Kind of an item that can be rendered using a template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ItemKind {
+    /// `pub const NAME: Type = value;`
+    ///
+    /// Variables: `name`, `docs`, `module`, `ty`, `value`.
+    Const,
+    /// `pub type A = B;`
+    ///
+    /// Variables: `name`, `docs`, `module`, `ty`.
+    Type,
+    /// `#[repr(C)]` enum.
+    ///
+    /// Variables: `name`, `docs`, `module`, `variants` (each with `name`, `docs` and an optional
+    /// `value`).
+    Enum,
+    /// `#[repr(C)]` struct.
+    ///
+    /// Variables: `name`, `docs`, `module`, `fields` (each with `name`, `docs` and `ty`).
+    Struct,
+    /// `#[no_mangle] pub extern "C" fn`.
+    ///
+    /// Variables: `name`, `docs`, `module`, `inputs` (each with `name` and `ty`), `output`.
+    Function,
+    /// Wrapper around the whole output file, applied when the output is finalised.
+    ///
+    /// Variables: `body` (all the rendered items).
+    File,
+}
+
+impl ItemKind {
+    fn template_name(&self) -> &'static str {
+        match *self {
+            ItemKind::Const => "const",
+            ItemKind::Type => "type",
+            ItemKind::Enum => "enum",
+            ItemKind::Struct => "struct",
+            ItemKind::Function => "function",
+            ItemKind::File => "file",
+        }
+    }
+}
+
+/// Generates bindings using user supplied templates.
+///
+/// Items whose kind has no template are skipped.
+pub struct LangTemplate {
+    tera: Tera,
+    templates: HashMap<ItemKind, String>,
+    output: String,
+}
+
+impl LangTemplate {
+    /// Create a new template backend writing everything into the `output` file.
+    pub fn new<T: Into<String>>(output: T) -> Self {
+        LangTemplate {
+            tera: Tera::default(),
+            templates: HashMap::new(),
+            output: output.into(),
+        }
+    }
+
+    /// Set the template used to render items of the given kind.
+    pub fn set_template(&mut self, kind: ItemKind, source: &str) -> Result<(), Error> {
+        let name = kind.template_name();
+
+        self.tera
+            .add_raw_template(name, source)
+            .map_err(|err| Error::error(&format!("invalid `{}` template: {}", name, err)))?;
+        let _ = self.templates.insert(kind, name.to_string());
+
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        kind: ItemKind,
+        item: &ast::Item,
+        mut context: Context,
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = match self.templates.get(&kind) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        let docs = docs(&item.attrs);
+        context.add("docs", &docs);
+        context.add("name", &item.ident.name.as_str().to_string());
+
+        let text = self.tera.render(name, &context).map_err(|err| Error {
+            level: Level::Error,
+            span: Some(item.span),
+            message: format!("failed to render `{}` template: {}", name, err),
+            help: None,
+        })?;
+        append_output(text, &self.output, outputs);
+
+        Ok(())
+    }
+}
+
+impl Lang for LangTemplate {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut context = module_context(module);
+
+        match item.node {
+            ast::ItemKind::Const(ref ty, ref expr) => {
+                context.add("ty", &pprust::ty_to_string(ty));
+                context.add("value", &pprust::expr_to_string(expr));
+            }
+            _ => return Err(wrong_item(item, "parse_const")),
+        }
+
+        self.render(ItemKind::Const, item, context, outputs)
+    }
+
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut context = module_context(module);
+
+        match item.node {
+            ast::ItemKind::Ty(ref ty, ref generics) => {
+                if generics.is_parameterized() {
+                    return Ok(());
+                }
+
+                context.add("ty", &pprust::ty_to_string(ty));
+            }
+            _ => return Err(wrong_item(item, "parse_ty")),
+        }
+
+        self.render(ItemKind::Type, item, context, outputs)
+    }
+
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (repr_c, _) = parse_attr(&item.attrs, check_repr_c, |_| None);
+        if !repr_c {
+            return Ok(());
+        }
+
+        let mut context = module_context(module);
+
+        match item.node {
+            ast::ItemKind::Enum(ref definition, _) => {
+                let variants: Vec<_> = definition
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let mut entry = BTreeMap::new();
+                        let _ = entry.insert("name", variant.node.name.name.as_str().to_string());
+                        let _ = entry.insert("docs", docs(&variant.node.attrs));
+                        if let Some(ref expr) = variant.node.disr_expr {
+                            let _ = entry.insert("value", pprust::expr_to_string(expr));
+                        }
+                        entry
+                    })
+                    .collect();
+
+                context.add("variants", &variants);
+            }
+            _ => return Err(wrong_item(item, "parse_enum")),
+        }
+
+        self.render(ItemKind::Enum, item, context, outputs)
+    }
+
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (repr_c, _) = parse_attr(&item.attrs, check_repr_c, |_| None);
+        if !repr_c {
+            return Ok(());
+        }
+
+        let mut context = module_context(module);
+
+        match item.node {
+            ast::ItemKind::Struct(ref variants, _) => {
+                let fields: Vec<_> = variants
+                    .fields()
+                    .iter()
+                    .filter_map(|field| {
+                        field.ident.map(|ident| {
+                            let mut entry = BTreeMap::new();
+                            let _ = entry.insert("name", ident.name.as_str().to_string());
+                            let _ = entry.insert("docs", docs(&field.attrs));
+                            let _ = entry.insert("ty", pprust::ty_to_string(&field.ty));
+                            entry
+                        })
+                    })
+                    .collect();
+
+                context.add("fields", &fields);
+            }
+            _ => return Err(wrong_item(item, "parse_struct")),
+        }
+
+        self.render(ItemKind::Struct, item, context, outputs)
+    }
+
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let (no_mangle, _) = parse_attr(&item.attrs, check_no_mangle, |_| None);
+        if !no_mangle {
+            return Ok(());
+        }
+
+        let mut context = module_context(module);
+
+        match item.node {
+            ast::ItemKind::Fn(ref decl, _, _, abi, _, _) => {
+                if !is_extern(abi) {
+                    return Ok(());
+                }
+
+                let inputs: Vec<_> = decl.inputs
+                    .iter()
+                    .map(|arg| {
+                        let mut entry = BTreeMap::new();
+                        let _ = entry.insert("name", pprust::pat_to_string(&arg.pat));
+                        let _ = entry.insert("ty", pprust::ty_to_string(&arg.ty));
+                        entry
+                    })
+                    .collect();
+                let output = match decl.output {
+                    ast::FunctionRetTy::Ty(ref ty) => pprust::ty_to_string(ty),
+                    ast::FunctionRetTy::Default(..) => "()".to_string(),
+                };
+
+                context.add("inputs", &inputs);
+                context.add("output", &output);
+            }
+            _ => return Err(wrong_item(item, "parse_fn")),
+        }
+
+        self.render(ItemKind::Function, item, context, outputs)
+    }
+
+    fn finalise_output(&mut self, outputs: &mut Outputs) -> Result<(), Error> {
+        let name = match self.templates.get(&ItemKind::File) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        let body = outputs.remove(&self.output).unwrap_or_default();
+        let mut context = Context::new();
+        context.add("body", &body);
+
+        let text = self.tera.render(name, &context).map_err(|err| {
+            Error::error(&format!("failed to render `{}` template: {}", name, err))
+        })?;
+        let _ = outputs.insert(self.output.clone(), text);
+
+        Ok(())
+    }
+}
+
+/// Create a template context holding the module path of an item.
+fn module_context(module: &[String]) -> Context {
+    let mut context = Context::new();
+    context.add("module", &module.join("::"));
+    context
+}
+
+/// Collect the doc comments of an item, without the comment markers.
+fn docs(attrs: &[ast::Attribute]) -> String {
+    let (_, docs) = parse_attr(attrs, |_| false, |attr| {
+        if attr.is_sugared_doc {
+            attr.value_str().map(|docs| {
+                let docs = docs.as_str();
+                let line = docs.trim_left_matches("///").trim_left_matches("//!");
+                format!("{}\n", line.trim())
+            })
+        } else {
+            None
+        }
+    });
+
+    docs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse;
+
+    #[test]
+    fn renders_items_and_file() {
+        let mut lang = LangTemplate::new("bindings.txt");
+        unwrap!(lang.set_template(ItemKind::Const, "const {{ name }}: {{ ty }}\n"));
+        unwrap!(lang.set_template(
+            ItemKind::Struct,
+            "struct {{ name }}{% for field in fields %} {{ field.name }}{% endfor %}\n",
+        ));
+        unwrap!(lang.set_template(
+            ItemKind::Function,
+            "fn {{ name }} -> {{ output }} ({{ docs | trim }})\n",
+        ));
+        unwrap!(lang.set_template(ItemKind::File, "BEGIN\n{{ body }}END\n"));
+
+        let source = "
+            pub const LIMIT: u32 = 10;
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                y: i32,
+            }
+
+            /// Does nothing.
+            #[no_mangle]
+            pub extern \"C\" fn noop() {}
+
+            pub type Ignored = u8;
+        ";
+
+        let session = ::syntax::parse::ParseSess::new();
+        let ast = unwrap!(::syntax::parse::parse_crate_from_source_str(
+            "lib.rs".to_string(),
+            source.to_string(),
+            &session,
+        ));
+
+        let mut outputs = Outputs::default();
+        unwrap!(parse::parse_mod(
+            &mut lang,
+            &ast.module,
+            &[Default::default()],
+            &Default::default(),
+            &mut outputs
+        ));
+        unwrap!(lang.finalise_output(&mut outputs));
+
+        assert_eq!(
+            outputs["bindings.txt"],
+            "BEGIN\nconst LIMIT: u32\nstruct Point x y\nfn noop -> () (Does nothing.)\nEND\n"
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Generation of Zig `extern` declarations.
+
+use bitflags::BitFlags;
+use common::{
+    alias_type, append_output, doc_comments, escape_keyword, extern_fn, literal_const,
+    repr_c_enum, repr_c_struct, repr_layout, Lang, Outputs, ReprCEnum, ReprCStruct,
+};
+use lang_c::option_inner;
+use syntax::ast;
+use syntax::print::pprust;
+use Error;
+use Level;
+
+const INDENT: &str = "    ";
+
+/// Zig keywords, which have to be quoted (`@"type"`) when used as identifiers.
+const KEYWORDS: &[&str] = &[
+    "addrspace", "align", "allowzero", "and", "anyframe", "anytype", "asm", "async", "await",
+    "break", "callconv", "catch", "comptime", "const", "continue", "defer", "else", "enum",
+    "errdefer", "error", "export", "extern", "fn", "for", "if", "inline", "linksection",
+    "noalias", "noinline", "nosuspend", "opaque", "or", "orelse", "packed", "pub", "resume",
+    "return", "struct", "suspend", "switch", "test", "threadlocal", "try", "type", "union",
+    "unreachable", "usingnamespace", "var", "volatile", "while",
+];
+
+/// Generates a `<lib_name>.zig` file with `extern` declarations of the functions and
+/// `extern struct` definitions of the types, keeping the doc comments.
+pub struct LangZig {
+    lib_name: String,
+}
+
+impl LangZig {
+    pub fn new() -> Self {
+        LangZig {
+            lib_name: "backend".to_owned(),
+        }
+    }
+
+    /// Set the name of the native library, which is also the name of the generated file.
+    pub fn set_lib_name<T: Into<String>>(&mut self, name: T) {
+        self.lib_name = name.into();
+    }
+
+    fn append(&self, buffer: String, outputs: &mut Outputs) {
+        append_output(buffer, &format!("{}.zig", self.lib_name), outputs);
+    }
+}
+
+impl Default for LangZig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lang for LangZig {
+    fn name(&self) -> &'static str {
+        "zig"
+    }
+
+    /// Convert `pub const NAME: Type = value;` into `pub const NAME: Type = value;`.
+    fn parse_const(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let constant = match literal_const(item)? {
+            Some(constant) => constant,
+            None => return Ok(()),
+        };
+
+        let name = ident(&item.ident.name.as_str());
+        let decl = match constant.lit {
+            Some(&ast::LitKind::Str(..)) => format!("pub const {} = {};", name, constant.value),
+            _ => format!(
+                "pub const {}: {} = {};",
+                name,
+                rust_to_zig(constant.ty)?,
+                constant.value
+            ),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!("{}\n\n", decl));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert `pub type A = B;` into `pub const A = B;`.
+    fn parse_ty(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let ty = match alias_type(item)? {
+            Some(ty) => rust_to_zig(ty)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!(
+            "pub const {} = {};\n\n",
+            ident(&item.ident.name.as_str()),
+            ty
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` enum into an `enum(c_int)`.
+    ///
+    /// Enums with data-carrying variants are declared as opaque types.
+    fn parse_enum(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = ident(&item.ident.name.as_str());
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+
+        match repr_c_enum(item)? {
+            Some(ReprCEnum::Fieldless(variants, values)) => {
+                buffer.push_str(&format!("pub const {} = enum(c_int) {{\n", name));
+                for (var, &(ref var_name, value)) in variants.iter().zip(&values) {
+                    buffer.push_str(&doc_comments(&var.node.attrs, INDENT, "/// "));
+                    buffer.push_str(&format!("{}{} = {},\n", INDENT, ident(var_name), value));
+                }
+                buffer.push_str("};\n\n");
+            }
+            Some(ReprCEnum::Opaque) => {
+                buffer.push_str(&format!("pub const {} = opaque {{}};\n\n", name))
+            }
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[repr(C)]` struct into an `extern struct`.
+    ///
+    /// Tuple structs with a single field are declared as opaque types. The layout of
+    /// `#[repr(packed)]` and `#[repr(align(N))]` structs is kept with `align` field attributes.
+    fn parse_struct(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let name = ident(&item.ident.name.as_str());
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+
+        match repr_c_struct(item)? {
+            Some(ReprCStruct::Fields(fields)) => {
+                let layout = repr_layout(&item.attrs);
+
+                buffer.push_str(&format!("pub const {} = extern struct {{\n", name));
+                for (index, &(ref field_name, field)) in fields.iter().enumerate() {
+                    let ty = rust_to_zig(&field.ty)?;
+
+                    // Zig has no packed or aligned `extern struct`, so the alignment of the
+                    // fields is lowered (`packed`) or the first one is raised (`align`).
+                    let align = match (layout.packed, layout.align) {
+                        (Some(1), _) => " align(1)".to_owned(),
+                        (Some(packed), _) => format!(" align(@min({}, @alignOf({})))", packed, ty),
+                        (None, Some(align)) if index == 0 => {
+                            format!(" align(@max({}, @alignOf({})))", align, ty)
+                        }
+                        _ => String::new(),
+                    };
+
+                    buffer.push_str(&doc_comments(&field.attrs, INDENT, "/// "));
+                    buffer.push_str(&format!(
+                        "{}{}: {}{},\n",
+                        INDENT,
+                        ident(field_name),
+                        ty,
+                        align
+                    ));
+                }
+                buffer.push_str("};\n\n");
+            }
+            Some(ReprCStruct::Opaque) => {
+                buffer.push_str(&format!("pub const {} = opaque {{}};\n\n", name))
+            }
+            None => return Ok(()),
+        }
+
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `bitflags!` flag set into an alias of the underlying integer type and a
+    /// constant for every flag (e.g. `Flags_READ`).
+    fn parse_bitflags(
+        &mut self,
+        _item: &ast::Item,
+        flags: &BitFlags,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let mut buffer = doc_comments(&flags.attrs, "", "/// ");
+        buffer.push_str(&format!(
+            "pub const {} = {};\n\n",
+            flags.name,
+            rust_to_zig(&flags.ty)?
+        ));
+
+        for flag in &flags.flags {
+            buffer.push_str(&doc_comments(&flag.attrs, "", "/// "));
+            buffer.push_str(&format!(
+                "pub const {0}_{1}: {0} = {2:#x};\n",
+                flags.name, flag.name, flag.value
+            ));
+        }
+
+        buffer.push('\n');
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+
+    /// Convert a `#[no_mangle] pub extern "C" fn` into a `pub extern fn` declaration.
+    fn parse_fn(
+        &mut self,
+        item: &ast::Item,
+        _module: &[String],
+        outputs: &mut Outputs,
+    ) -> Result<(), Error> {
+        let decl = match extern_fn(item)? {
+            Some(fn_decl) => fn_decl_to_zig(fn_decl)?,
+            None => return Ok(()),
+        };
+
+        let mut buffer = doc_comments(&item.attrs, "", "/// ");
+        buffer.push_str(&format!(
+            "pub extern fn {}({}) {};\n\n",
+            ident(&item.ident.name.as_str()),
+            decl.0,
+            decl.1
+        ));
+        self.append(buffer, outputs);
+
+        Ok(())
+    }
+}
+
+/// Convert the parameters and the return type of a function into Zig (e.g. `a: i32` and `void`).
+fn fn_decl_to_zig(fn_decl: &ast::FnDecl) -> Result<(String, String), Error> {
+    let mut args = Vec::new();
+    for arg in &fn_decl.inputs {
+        args.push(format!(
+            "{}: {}",
+            ident(&pprust::pat_to_string(&arg.pat)),
+            rust_to_zig(&arg.ty)?
+        ));
+    }
+
+    let output = match fn_decl.output {
+        ast::FunctionRetTy::Ty(ref ty) if ty.node == ast::TyKind::Never => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(ty.span),
+                message: "panics across a C boundary are naughty!".into(),
+                help: None,
+            });
+        }
+        ast::FunctionRetTy::Default(..) => "void".to_owned(),
+        ast::FunctionRetTy::Ty(ref ty) => rust_to_zig(ty)?,
+    };
+
+    Ok((args.join(", "), output))
+}
+
+/// Convert a Rust type into a Zig type.
+///
+/// Pointers are converted into C pointers (`[*c]T`), except pointers to `c_void` which become
+/// optional pointers to `anyopaque`. Function pointers are non-null unless wrapped in an `Option`.
+fn rust_to_zig(ty: &ast::Ty) -> Result<String, Error> {
+    if let Some(inner) = option_inner(ty) {
+        let inner = rust_to_zig(inner)?;
+        return Ok(if inner.starts_with('?') || inner.starts_with("[*c]") {
+            inner
+        } else {
+            format!("?{}", inner)
+        });
+    }
+
+    match ty.node {
+        ast::TyKind::BareFn(ref bare_fn) => {
+            let (args, output) = fn_decl_to_zig(&bare_fn.decl)?;
+            Ok(format!("*const fn ({}) callconv(.C) {}", args, output))
+        }
+        ast::TyKind::Array(ref inner, ref len) => Ok(format!(
+            "[{}]{}",
+            pprust::expr_to_string(len),
+            rust_to_zig(inner)?
+        )),
+        ast::TyKind::Ptr(ref ptr) => {
+            let inner = rust_to_zig(&ptr.ty)?;
+            let constness = match ptr.mutbl {
+                ast::Mutability::Immutable => "const ",
+                ast::Mutability::Mutable => "",
+            };
+
+            if inner == "anyopaque" {
+                Ok(format!("?*{}anyopaque", constness))
+            } else {
+                Ok(format!("[*c]{}{}", constness, inner))
+            }
+        }
+        ast::TyKind::Path(None, ref path) => path_to_zig(path),
+        _ => {
+            let new_type = pprust::ty_to_string(ty);
+            if new_type == "()" {
+                Ok("void".to_owned())
+            } else {
+                Err(Error {
+                    level: Level::Error,
+                    span: Some(ty.span),
+                    message: format!("bindgen can not handle the type `{}`", new_type),
+                    help: None,
+                })
+            }
+        }
+    }
+}
+
+/// Convert a Rust path type into a Zig type, supporting the types of `libc` and `std::os::raw`.
+fn path_to_zig(path: &ast::Path) -> Result<String, Error> {
+    let (ty, module) = match path.segments.split_last() {
+        Some(segments) => segments,
+        None => {
+            return Err(Error {
+                level: Level::Bug,
+                span: Some(path.span),
+                message: "invalid type".into(),
+                help: None,
+            })
+        }
+    };
+
+    let module: Vec<_> = module
+        .iter()
+        .map(|segment| segment.identifier.name.as_str().to_string())
+        .collect();
+    match &*module.join("::") {
+        "" | "libc" | "std::os::raw" => {}
+        _ => {
+            return Err(Error {
+                level: Level::Error,
+                span: Some(path.span),
+                message: "can not handle types in other modules (except `libc` and `std::os::raw`)"
+                    .into(),
+                help: None,
+            })
+        }
+    }
+
+    let ty = match &*ty.identifier.name.as_str() {
+        "c_void" => "anyopaque",
+        "c_char" => "u8",
+        "c_schar" => "i8",
+        "c_uchar" => "u8",
+        "c_float" => "f32",
+        "c_double" => "f64",
+        "c_short" => "c_short",
+        "c_ushort" => "c_ushort",
+        "c_int" => "c_int",
+        "c_uint" => "c_uint",
+        "c_long" => "c_long",
+        "c_ulong" => "c_ulong",
+        "c_longlong" => "c_longlong",
+        "c_ulonglong" => "c_ulonglong",
+        // A Unicode scalar value.
+        "char" => "u32",
+        // Primitive and user-defined types have the same name in Zig.
+        ty => return Ok(ident(ty)),
+    };
+
+    Ok(ty.to_owned())
+}
+
+/// Quote the identifier if it is a Zig keyword.
+fn ident(name: &str) -> String {
+    escape_keyword(name, KEYWORDS, "@\"{}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::compile_source;
+
+    #[test]
+    fn declarations() {
+        let source = "
+            /// Maximum length.
+            pub const MAX_LEN: u32 = 16;
+
+            pub const NAME: &'static str = \"safe\";
+
+            pub type Id = u64;
+
+            #[repr(C)]
+            pub enum Status {
+                /// Success.
+                Ok,
+                Failed = 2,
+            }
+
+            #[repr(C)]
+            pub struct Point {
+                x: i32,
+                /// Vertical.
+                y: i32,
+                tags: [u8; 4],
+                initial: char,
+            }
+
+            bitflags! {
+                pub struct Flags: u32 {
+                    const READ = 1;
+                    const WRITE = 2;
+                }
+            }
+
+            #[no_mangle]
+            pub extern \"C\" fn point_name(
+                point: *const Point,
+                user_data: *mut c_void,
+                cb: extern \"C\" fn(user_data: *mut c_void, name: *const c_char),
+                test: Option<extern \"C\" fn()>,
+            ) -> bool {}
+        ";
+
+        let mut lang = LangZig::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.zig"],
+            "/// Maximum length.\n\
+             pub const MAX_LEN: u32 = 16;\n\n\
+             pub const NAME = \"safe\";\n\n\
+             pub const Id = u64;\n\n\
+             pub const Status = enum(c_int) {\n    \
+             /// Success.\n    \
+             Ok = 0,\n    \
+             Failed = 2,\n\
+             };\n\n\
+             pub const Point = extern struct {\n    \
+             x: i32,\n    \
+             /// Vertical.\n    \
+             y: i32,\n    \
+             tags: [4]u8,\n    \
+             initial: u32,\n\
+             };\n\n\
+             pub const Flags = u32;\n\n\
+             pub const Flags_READ: Flags = 0x1;\n\
+             pub const Flags_WRITE: Flags = 0x2;\n\n\
+             pub extern fn point_name(point: [*c]const Point, user_data: ?*anyopaque, \
+             cb: *const fn (user_data: ?*anyopaque, name: [*c]const u8) callconv(.C) void, \
+             @\"test\": ?*const fn () callconv(.C) void) bool;\n\n"
+        );
+    }
+
+    #[test]
+    fn string_constants() {
+        let source = "pub const GREETING: &'static str = \"h\\u{e9}llo\\t\\u{1}\";";
+
+        let mut lang = LangZig::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.zig"],
+            "pub const GREETING = \"h\u{e9}llo\\t\\u{1}\";\n\n"
+        );
+    }
+
+    #[test]
+    fn struct_layout() {
+        let source = "
+            #[repr(C, packed)]
+            pub struct Packed {
+                tag: u8,
+                value: u32,
+            }
+
+            #[repr(C, packed(2))]
+            pub struct Packed2 {
+                tag: u8,
+                value: u32,
+            }
+
+            #[repr(C, align(16))]
+            pub struct Aligned {
+                tag: u8,
+                value: u32,
+            }
+        ";
+
+        let mut lang = LangZig::new();
+        lang.set_lib_name("safe_app");
+        let outputs = compile_source(&mut lang, source);
+
+        assert_eq!(
+            outputs["safe_app.zig"],
+            "pub const Packed = extern struct {\n    \
+             tag: u8 align(1),\n    \
+             value: u32 align(1),\n\
+             };\n\n\
+             pub const Packed2 = extern struct {\n    \
+             tag: u8 align(@min(2, @alignOf(u8))),\n    \
+             value: u32 align(@min(2, @alignOf(u32))),\n\
+             };\n\n\
+             pub const Aligned = extern struct {\n    \
+             tag: u8 align(@max(16, @alignOf(u8))),\n    \
+             value: u32,\n\
+             };\n\n"
+        );
+    }
+}